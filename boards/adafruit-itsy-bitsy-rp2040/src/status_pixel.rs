@@ -0,0 +1,103 @@
+//! The on-board NeoPixel, and the pin that powers it.
+//!
+//! The NeoPixel stays dark until `neopixel_power` is driven high.
+//! [`StatusPixel`] takes care of that, and can cut its power again to save
+//! energy.
+
+use embedded_hal::digital::v2::OutputPin;
+use fugit::HertzU32;
+use hal::gpio::bank0::{Gpio16, Gpio17};
+use hal::gpio::{Function, FunctionConfig, Pin, PinId, PushPullOutput, ValidPinMode};
+use hal::pio::{PIOExt, StateMachineIndex, UninitStateMachine, PIO};
+use smart_leds::{SmartLedsWrite, RGB8};
+use ws2812_pio::Ws2812Direct;
+
+const OFF: RGB8 = RGB8 { r: 0, g: 0, b: 0 };
+
+/// Drives the on-board NeoPixel through a PIO state machine.
+///
+/// The NeoPixel needs about 300µs between two color changes to latch the
+/// new color.
+pub struct StatusPixel<P, SM>
+where
+    P: PIOExt + FunctionConfig,
+    SM: StateMachineIndex,
+    Function<P>: ValidPinMode<Gpio17>,
+{
+    power: Pin<Gpio16, PushPullOutput>,
+    ws: Ws2812Direct<P, SM, Gpio17>,
+    color: RGB8,
+    powered: bool,
+}
+
+impl<P, SM> StatusPixel<P, SM>
+where
+    P: PIOExt + FunctionConfig,
+    SM: StateMachineIndex,
+    Function<P>: ValidPinMode<Gpio17>,
+{
+    /// Power up the NeoPixel, initially off.
+    ///
+    /// `clock_freq` is the frequency the PIO is clocked from, which is the
+    /// system clock.
+    pub fn new(
+        neopixel_power: Pin<Gpio16, <Gpio16 as PinId>::Reset>,
+        neopixel_data: Pin<Gpio17, Function<P>>,
+        pio: &mut PIO<P>,
+        sm: UninitStateMachine<(P, SM)>,
+        clock_freq: HertzU32,
+    ) -> Self {
+        let mut pixel = StatusPixel {
+            power: neopixel_power.into_push_pull_output(),
+            ws: Ws2812Direct::new(neopixel_data, pio, sm, clock_freq),
+            color: OFF,
+            powered: false,
+        };
+        pixel.power_on();
+        pixel
+    }
+
+    /// Show `color`, powering the NeoPixel back up if it was shut down.
+    pub fn set(&mut self, color: RGB8) {
+        self.power_on();
+        self.color = color;
+        // Writing to the PIO FIFO can't fail
+        let _ = self.ws.write(core::iter::once(color));
+    }
+
+    /// Turn the NeoPixel off, but keep it powered.
+    ///
+    /// A dark NeoPixel still draws about 1mA; use
+    /// [`StatusPixel::shutdown`] to save that too.
+    pub fn off(&mut self) {
+        self.set(OFF);
+    }
+
+    /// The color last set.
+    pub fn color(&self) -> RGB8 {
+        self.color
+    }
+
+    /// Cut the power of the NeoPixel.
+    ///
+    /// It comes back on at the next [`StatusPixel::set`] or
+    /// [`StatusPixel::off`].
+    pub fn shutdown(&mut self) {
+        // Setting GPIO outputs can't fail
+        let _ = self.power.set_low();
+        self.powered = false;
+        self.color = OFF;
+    }
+
+    /// Whether the NeoPixel is powered.
+    pub fn is_powered(&self) -> bool {
+        self.powered
+    }
+
+    fn power_on(&mut self) {
+        if !self.powered {
+            let _ = self.power.set_high();
+            self.powered = true;
+        }
+    }
+}
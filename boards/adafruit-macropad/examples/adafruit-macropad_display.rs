@@ -0,0 +1,126 @@
+//! Shows the keys pressed on the OLED display of a Adafruit MacroPad board
+//!
+//! This draws the 3×4 grid of keys on the display, filling in the ones held
+//! down. Only the pages of the display that changed are sent over SPI, so
+//! the display is updated quickly on every key press.
+#![no_std]
+#![no_main]
+
+use adafruit_macropad::{
+    hal::{
+        clocks::{init_clocks_and_plls, Clock},
+        pac,
+        watchdog::Watchdog,
+        Sio,
+    },
+    keypad::{key_position, NUM_KEYS},
+    Keypad, Macropad, Pins, XOSC_CRYSTAL_FREQ,
+};
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyleBuilder, Rectangle},
+    text::Text,
+};
+use panic_halt as _;
+
+/// Size of each key drawn, in pixels.
+const KEY_SIZE: u32 = 12;
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[rp2040_hal::entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables and the spinlock are initialised.
+#[rp2040_hal::entry]
+fn main() -> ! {
+    let mut pac = pac::Peripherals::take().unwrap();
+    let core = pac::CorePeripherals::take().unwrap();
+
+    let mut watchdog = Watchdog::new(pac.WATCHDOG);
+
+    let clocks = init_clocks_and_plls(
+        XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+
+    let sio = Sio::new(pac.SIO);
+    let pins = Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    let mut display = Macropad::display(
+        pac.SPI1,
+        pins.sclk,
+        pins.mosi,
+        pins.oled_cs,
+        pins.oled_dc,
+        pins.oled_reset,
+        &mut pac.RESETS,
+        clocks.peripheral_clock.freq(),
+        &mut delay,
+    )
+    .unwrap();
+    display.set_contrast(0x80).unwrap();
+
+    let mut keypad = Keypad::new(
+        pins.key1,
+        pins.key2,
+        pins.key3,
+        pins.key4,
+        pins.key5,
+        pins.key6,
+        pins.key7,
+        pins.key8,
+        pins.key9,
+        pins.key10,
+        pins.key11,
+        pins.key12,
+        pins.button,
+    );
+
+    let style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+    Text::new("MacroPad", Point::new(72, 12), style)
+        .draw(&mut display)
+        .unwrap();
+
+    loop {
+        keypad.poll();
+        for key in 0..NUM_KEYS {
+            let (row, column) = key_position(key).unwrap();
+            let top_left = Point::new(
+                (u32::from(column) * (KEY_SIZE + 2)) as i32,
+                (u32::from(row) * (KEY_SIZE + 2)) as i32 + 8,
+            );
+            // Pixels drawn the same as before don't mark the display dirty
+            let fill = if keypad.is_pressed(key) {
+                BinaryColor::On
+            } else {
+                BinaryColor::Off
+            };
+            let style = PrimitiveStyleBuilder::new()
+                .stroke_color(BinaryColor::On)
+                .stroke_width(1)
+                .fill_color(fill)
+                .build();
+            Rectangle::new(top_left, Size::new_equal(KEY_SIZE))
+                .into_styled(style)
+                .draw(&mut display)
+                .unwrap();
+        }
+        display.flush().unwrap();
+        delay.delay_ms(1);
+    }
+}
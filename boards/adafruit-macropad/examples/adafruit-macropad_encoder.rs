@@ -0,0 +1,84 @@
+//! Reads the rotary encoder on a Adafruit MacroPad board
+//!
+//! This will dim the on-board LED as the encoder knob turns counter-clockwise,
+//! and brighten it as it turns clockwise. The edges of the encoder are counted
+//! by a PIO state machine.
+#![no_std]
+#![no_main]
+
+use adafruit_macropad::{
+    hal::{
+        clocks::{init_clocks_and_plls, Clock},
+        pac,
+        pio::PIOExt,
+        pwm::Slices,
+        watchdog::Watchdog,
+        Sio,
+    },
+    Encoder, Pins, PioBackend, ENCODER_EDGES_PER_DETENT, XOSC_CRYSTAL_FREQ,
+};
+use embedded_hal::PwmPin;
+use panic_halt as _;
+
+/// Number of detents from off to full brightness.
+const STEPS: i32 = 20;
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[rp2040_hal::entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables and the spinlock are initialised.
+#[rp2040_hal::entry]
+fn main() -> ! {
+    let mut pac = pac::Peripherals::take().unwrap();
+    let core = pac::CorePeripherals::take().unwrap();
+
+    let mut watchdog = Watchdog::new(pac.WATCHDOG);
+
+    let clocks = init_clocks_and_plls(
+        XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+
+    let sio = Sio::new(pac.SIO);
+    let pins = Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // The LED is on channel B of PWM6
+    let mut pwm_slices = Slices::new(pac.PWM, &mut pac.RESETS);
+    let pwm = &mut pwm_slices.pwm6;
+    pwm.enable();
+    let led = &mut pwm.channel_b;
+    led.output_to(pins.led);
+
+    let (mut pio, sm0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
+    let backend = PioBackend::new(
+        pins.encoder_rota.into_pull_up_input(),
+        pins.encoder_rotb.into_pull_up_input(),
+        &mut pio,
+        sm0,
+        clocks.system_clock.freq(),
+    )
+    .unwrap();
+    let mut encoder = Encoder::new(backend, ENCODER_EDGES_PER_DETENT);
+
+    let mut brightness = STEPS / 2;
+    loop {
+        brightness = (brightness + encoder.delta()).clamp(0, STEPS);
+        let duty = (brightness * brightness * i32::from(u16::MAX) / (STEPS * STEPS)) as u16;
+        led.set_duty(duty);
+        delay.delay_ms(10);
+    }
+}
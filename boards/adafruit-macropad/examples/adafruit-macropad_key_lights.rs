@@ -0,0 +1,133 @@
+//! Lights up the keys on a Adafruit MacroPad board
+//!
+//! This will light each key in the colors of a scheme, and flash keys white
+//! while they are held, fading them back once released. The encoder button
+//! switches between the schemes.
+#![no_std]
+#![no_main]
+
+use adafruit_macropad::{
+    hal::{
+        clocks::{init_clocks_and_plls, Clock},
+        pac,
+        pio::PIOExt,
+        watchdog::Watchdog,
+        Sio,
+    },
+    key_colors::{Flash, NUM_LEDS},
+    keypad::ENCODER_BUTTON,
+    KeyEvent, KeyLights, Keypad, Pins, XOSC_CRYSTAL_FREQ,
+};
+use panic_halt as _;
+use smart_leds::RGB8;
+
+const RED: RGB8 = RGB8::new(255, 0, 0);
+const ORANGE: RGB8 = RGB8::new(255, 96, 0);
+const GREEN: RGB8 = RGB8::new(0, 255, 0);
+const TEAL: RGB8 = RGB8::new(0, 160, 128);
+const BLUE: RGB8 = RGB8::new(0, 0, 255);
+const PURPLE: RGB8 = RGB8::new(160, 0, 255);
+
+/// Colors of the keys, one row of the grid after the other.
+const SCHEMES: [[RGB8; NUM_LEDS as usize]; 2] = [
+    [
+        RED, RED, RED, ORANGE, ORANGE, ORANGE, GREEN, GREEN, GREEN, BLUE, BLUE, BLUE,
+    ],
+    [
+        PURPLE, TEAL, PURPLE, TEAL, PURPLE, TEAL, PURPLE, TEAL, PURPLE, TEAL, PURPLE, TEAL,
+    ],
+];
+
+/// The LEDs are very bright at full power.
+const BRIGHTNESS: u8 = 64;
+
+/// Milliseconds between two updates of the LEDs.
+const FRAME_MS: u32 = 10;
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[rp2040_hal::entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables and the spinlock are initialised.
+#[rp2040_hal::entry]
+fn main() -> ! {
+    let mut pac = pac::Peripherals::take().unwrap();
+    let core = pac::CorePeripherals::take().unwrap();
+
+    let mut watchdog = Watchdog::new(pac.WATCHDOG);
+
+    let clocks = init_clocks_and_plls(
+        XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+
+    let sio = Sio::new(pac.SIO);
+    let pins = Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    let mut keypad = Keypad::new(
+        pins.key1,
+        pins.key2,
+        pins.key3,
+        pins.key4,
+        pins.key5,
+        pins.key6,
+        pins.key7,
+        pins.key8,
+        pins.key9,
+        pins.key10,
+        pins.key11,
+        pins.key12,
+        pins.button,
+    );
+
+    let (mut pio, sm0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
+    let mut lights = KeyLights::new(
+        pins.neopixel.into_mode(),
+        &mut pio,
+        sm0,
+        clocks.system_clock.freq(),
+    );
+    let mut scheme = 0;
+    let colors = lights.colors_mut();
+    colors.set_layer(&SCHEMES, scheme);
+    colors.set_brightness(BRIGHTNESS);
+    colors.set_flash(Some(Flash {
+        color: RGB8::new(255, 255, 255),
+        fade_ms: 300,
+    }));
+    lights.show();
+
+    let mut elapsed_ms = 0;
+    loop {
+        let events = keypad.poll();
+        if events
+            .clone()
+            .any(|event| event == KeyEvent::Pressed(ENCODER_BUTTON))
+        {
+            scheme = (scheme + 1) % SCHEMES.len() as u8;
+            lights.colors_mut().set_layer(&SCHEMES, scheme);
+        }
+        lights.update(events);
+
+        delay.delay_ms(1);
+        elapsed_ms += 1;
+        if elapsed_ms == FRAME_MS {
+            lights.tick(elapsed_ms);
+            lights.show();
+            elapsed_ms = 0;
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Reads the keys on a Adafruit MacroPad board
+//!
+//! This will toggle the on-board LED on each press of a key in the left
+//! column, and light it while the encoder button is held down.
+#![no_std]
+#![no_main]
+
+use adafruit_macropad::{
+    hal::{
+        clocks::{init_clocks_and_plls, Clock},
+        pac,
+        watchdog::Watchdog,
+        Sio,
+    },
+    keypad::{key_position, ENCODER_BUTTON},
+    KeyEvent, Keypad, Pins, XOSC_CRYSTAL_FREQ,
+};
+use embedded_hal::digital::v2::{OutputPin, ToggleableOutputPin};
+use panic_halt as _;
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[rp2040_hal::entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables and the spinlock are initialised.
+#[rp2040_hal::entry]
+fn main() -> ! {
+    let mut pac = pac::Peripherals::take().unwrap();
+    let core = pac::CorePeripherals::take().unwrap();
+
+    let mut watchdog = Watchdog::new(pac.WATCHDOG);
+
+    let clocks = init_clocks_and_plls(
+        XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+
+    let sio = Sio::new(pac.SIO);
+    let pins = Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+    let mut led_pin = pins.led.into_push_pull_output();
+
+    let mut keypad = Keypad::new(
+        pins.key1,
+        pins.key2,
+        pins.key3,
+        pins.key4,
+        pins.key5,
+        pins.key6,
+        pins.key7,
+        pins.key8,
+        pins.key9,
+        pins.key10,
+        pins.key11,
+        pins.key12,
+        pins.button,
+    );
+
+    loop {
+        for event in keypad.poll() {
+            match event {
+                KeyEvent::Pressed(ENCODER_BUTTON) => led_pin.set_high().unwrap(),
+                KeyEvent::Released(ENCODER_BUTTON) => led_pin.set_low().unwrap(),
+                KeyEvent::Pressed(key) if matches!(key_position(key), Some((_, 0))) => {
+                    led_pin.toggle().unwrap()
+                }
+                _ => {}
+            }
+        }
+        delay.delay_ms(1);
+    }
+}
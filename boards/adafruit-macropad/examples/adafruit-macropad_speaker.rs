@@ -0,0 +1,110 @@
+//! Plays melodies on the speaker of a Adafruit MacroPad board
+//!
+//! Each of the first three keys plays a melody, and the other keys play a
+//! note of a scale while held. The melodies play without blocking, so the
+//! keys are read all along and pressing another key stops the melody.
+#![no_std]
+#![no_main]
+
+use adafruit_macropad::{
+    hal::{
+        clocks::{init_clocks_and_plls, Clock},
+        pac,
+        pwm::Slices,
+        watchdog::Watchdog,
+        Sio,
+    },
+    KeyEvent, Keypad, Melody, Pins, Speaker, XOSC_CRYSTAL_FREQ,
+};
+use panic_halt as _;
+
+const MELODIES: [&str; 3] = [
+    "Scale:d=8,o=5,b=160:c,d,e,f,g,a,b,4c6",
+    "Tetris:d=4,o=5,b=160:e6,8b,8c6,8d6,16e6,16d6,8c6,8b,a,8a,8c6,e6,8d6,8c6,b,8b,8c6,d6,e6,c6,a,2a",
+    "Mario:d=4,o=5,b=100:16e6,16e6,32p,8e6,16c6,8e6,8g6,8p,8g,8p",
+];
+
+/// Notes of the keys without a melody, in Hz.
+const NOTES: [u32; 9] = [262, 294, 330, 349, 392, 440, 494, 523, 587];
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[rp2040_hal::entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables and the spinlock are initialised.
+#[rp2040_hal::entry]
+fn main() -> ! {
+    let mut pac = pac::Peripherals::take().unwrap();
+    let core = pac::CorePeripherals::take().unwrap();
+
+    let mut watchdog = Watchdog::new(pac.WATCHDOG);
+
+    let clocks = init_clocks_and_plls(
+        XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+
+    let sio = Sio::new(pac.SIO);
+    let pins = Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    let mut keypad = Keypad::new(
+        pins.key1,
+        pins.key2,
+        pins.key3,
+        pins.key4,
+        pins.key5,
+        pins.key6,
+        pins.key7,
+        pins.key8,
+        pins.key9,
+        pins.key10,
+        pins.key11,
+        pins.key12,
+        pins.button,
+    );
+
+    // The speaker is on channel A of PWM0
+    let pwm_slices = Slices::new(pac.PWM, &mut pac.RESETS);
+    let mut speaker = Speaker::new(
+        pwm_slices.pwm0,
+        pins.speaker,
+        pins.speaker_shutdown,
+        clocks.system_clock.freq(),
+    );
+
+    // Say hello
+    speaker.beep(880, 100, &mut delay);
+
+    loop {
+        for event in keypad.poll() {
+            match event {
+                KeyEvent::Pressed(key) if usize::from(key) < MELODIES.len() => {
+                    let melody = Melody::parse(MELODIES[usize::from(key)]).unwrap();
+                    speaker.play(melody);
+                }
+                KeyEvent::Pressed(key) => {
+                    if let Some(&note) = NOTES.get(usize::from(key) - MELODIES.len()) {
+                        speaker.tone(note);
+                    }
+                }
+                KeyEvent::Released(_) if !speaker.is_playing() => speaker.stop(),
+                KeyEvent::Released(_) => {}
+            }
+        }
+        speaker.tick(1);
+        delay.delay_ms(1);
+    }
+}
@@ -0,0 +1,181 @@
+//! Runs a USB macro keyboard from a keymap stored in flash on a Adafruit MacroPad board
+//!
+//! The keymap is read from the last sector of the flash. If there is none
+//! yet, or it is corrupted, a default keymap is stored there first. A tool on
+//! the host can then write another keymap there in the same format, to change
+//! the macros without rebuilding the firmware.
+#![no_std]
+#![no_main]
+
+use adafruit_macropad::{
+    config::LayerConfig,
+    hal::{
+        self,
+        clocks::{init_clocks_and_plls, Clock},
+        pac,
+        pio::PIOExt,
+        watchdog::Watchdog,
+        Sio, Timer,
+    },
+    macro_keyboard::{Action, Layer, Report},
+    usage::{consumer, key, modifier},
+    Encoder, FlashSector, Keypad, MacroKeyboard, Pins, PioBackend, ENCODER_EDGES_PER_DETENT,
+    XOSC_CRYSTAL_FREQ,
+};
+use embedded_hal::timer::CountDown;
+use fugit::ExtU32;
+use panic_halt as _;
+use smart_leds::RGB8;
+use usb_device::{class_prelude::*, prelude::*};
+use usbd_hid::descriptor::generator_prelude::*;
+use usbd_hid::descriptor::{KeyboardReport, MediaKeyboardReport};
+use usbd_hid::hid_class::HIDClass;
+
+const NONE: Action = Action::None;
+
+/// A single layer with copy and paste, and the volume on the encoder.
+const DEFAULT_KEYMAP: [LayerConfig; 1] = [LayerConfig {
+    layer: Layer {
+        keys: [
+            Action::Keys {
+                modifiers: modifier::LEFT_CTRL,
+                keys: &[key::C],
+            },
+            Action::Keys {
+                modifiers: modifier::LEFT_CTRL,
+                keys: &[key::V],
+            },
+            Action::Consumer(consumer::PLAY_PAUSE),
+            NONE,
+            NONE,
+            NONE,
+            NONE,
+            NONE,
+            NONE,
+            NONE,
+            NONE,
+            Action::Text("Stored in flash\n"),
+        ],
+        clockwise: Action::Consumer(consumer::VOLUME_UP),
+        counter_clockwise: Action::Consumer(consumer::VOLUME_DOWN),
+    },
+    colors: [RGB8 { r: 0, g: 0, b: 32 }; 12],
+}];
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[rp2040_hal::entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables and the spinlock are initialised.
+#[rp2040_hal::entry]
+fn main() -> ! {
+    let mut pac = pac::Peripherals::take().unwrap();
+
+    let mut watchdog = Watchdog::new(pac.WATCHDOG);
+
+    let clocks = init_clocks_and_plls(
+        XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    let sio = Sio::new(pac.SIO);
+    let pins = Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // Store the default keymap if there isn't a valid one yet
+    let mut sector = FlashSector::last();
+    if sector.load().is_err() {
+        // Safety: the other core isn't running
+        unsafe { sector.store(&DEFAULT_KEYMAP) }.unwrap();
+    }
+    let config = sector.load().unwrap();
+
+    let mut keypad = Keypad::new(
+        pins.key1,
+        pins.key2,
+        pins.key3,
+        pins.key4,
+        pins.key5,
+        pins.key6,
+        pins.key7,
+        pins.key8,
+        pins.key9,
+        pins.key10,
+        pins.key11,
+        pins.key12,
+        pins.button,
+    );
+
+    let (mut pio, sm0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
+    let backend = PioBackend::new(
+        pins.encoder_rota.into_pull_up_input(),
+        pins.encoder_rotb.into_pull_up_input(),
+        &mut pio,
+        sm0,
+        clocks.system_clock.freq(),
+    )
+    .unwrap();
+    let mut encoder = Encoder::new(backend, ENCODER_EDGES_PER_DETENT);
+
+    // Set up the USB driver, with a keyboard and a consumer control interface
+    let usb_bus = UsbBusAllocator::new(hal::usb::UsbBus::new(
+        pac.USBCTRL_REGS,
+        pac.USBCTRL_DPRAM,
+        clocks.usb_clock,
+        true,
+        &mut pac.RESETS,
+    ));
+    let mut keyboard_hid = HIDClass::new(&usb_bus, KeyboardReport::desc(), 10);
+    let mut consumer_hid = HIDClass::new(&usb_bus, MediaKeyboardReport::desc(), 10);
+
+    // Create a USB device with a fake VID and PID
+    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16c0, 0x27da))
+        .manufacturer("Fake company")
+        .product("MacroPad")
+        .serial_number("TEST")
+        .device_class(0)
+        .build();
+
+    let mut macro_keyboard = MacroKeyboard::new(config);
+    let mut report = None;
+
+    let timer = Timer::new(pac.TIMER, &mut pac.RESETS);
+    let mut scan = timer.count_down();
+    scan.start(1.millis());
+    loop {
+        usb_dev.poll(&mut [&mut keyboard_hid, &mut consumer_hid]);
+
+        if scan.wait().is_err() {
+            continue;
+        }
+        for event in keypad.poll() {
+            macro_keyboard.key_event(event);
+        }
+        macro_keyboard.encoder(encoder.delta());
+
+        // Keep trying to send a report while the endpoint is busy
+        if report.is_none() {
+            report = macro_keyboard.next_report();
+        }
+        let sent = match report {
+            Some(Report::Keyboard(keys)) => keyboard_hid.push_input(&KeyboardReport::from(keys)),
+            Some(Report::Consumer(usage_id)) => {
+                consumer_hid.push_input(&MediaKeyboardReport { usage_id })
+            }
+            None => continue,
+        };
+        if sent.is_ok() {
+            report = None;
+        }
+    }
+}
@@ -0,0 +1,610 @@
+//! A compact binary format for keymaps, so they can be changed without
+//! rebuilding the firmware.
+//!
+//! A configuration holds the layers of a [`Keymap`], each with the actions of
+//! the 12 keys and both directions of the encoder, and the color of the LED
+//! under each key. [`serialize`] writes one out and [`Config::parse`] checks
+//! one and reads from it without copying. It doesn't touch any hardware.
+//!
+//! All numbers are little-endian. Version 1 of the format is laid out as:
+//!
+//! | Offset | Size | Contents                                       |
+//! |--------|------|------------------------------------------------|
+//! | 0      | 4    | [`MAGIC`]                                      |
+//! | 4      | 1    | [`VERSION`]                                    |
+//! | 5      | 1    | Number of layers                               |
+//! | 6      | 2    | Size of the text area, in bytes                |
+//! | 8      | 148  | Each layer in turn                             |
+//! |        |      | The text area, holding UTF-8 text              |
+//! |        | 4    | CRC-32 (IEEE 802.3) of all the bytes before it |
+//!
+//! A layer holds 14 actions of 8 bytes, for the keys 0 to 11, then the
+//! encoder turning clockwise and counter-clockwise, followed by the 12 colors
+//! of the keys as red, green and blue bytes. The first byte of an action is
+//! its kind, followed by its arguments and zeros:
+//!
+//! | Kind | Action               | Arguments                                  |
+//! |------|----------------------|--------------------------------------------|
+//! | 0    | [`Action::None`]     |                                            |
+//! | 1    | [`Action::Keys`]     | Modifiers, then up to 6 keys               |
+//! | 2    | [`Action::Consumer`] | Consumer control, on 2 bytes               |
+//! | 3    | [`Action::Text`]     | Offset in the text area, then size, on 2 bytes each |
+//! | 4    | [`Action::Layer`]    | Layer                                      |
+
+use smart_leds::RGB8;
+
+use crate::key_colors::ColorSchemes;
+use crate::keypad::NUM_KEYS;
+use crate::macro_keyboard::{Action, Input, Keymap, Layer};
+
+/// The first bytes of a configuration.
+pub const MAGIC: [u8; 4] = *b"MPKM";
+/// The version of the format written by [`serialize`].
+pub const VERSION: u8 = 1;
+
+const HEADER_SIZE: usize = 8;
+const CRC_SIZE: usize = 4;
+const ACTION_SIZE: usize = 8;
+/// Actions of a layer: the keys, then both directions of the encoder.
+const ACTIONS: usize = NUM_KEYS as usize + 2;
+const LAYER_SIZE: usize = ACTIONS * ACTION_SIZE + NUM_KEYS as usize * 3;
+
+const KIND_NONE: u8 = 0;
+const KIND_KEYS: u8 = 1;
+const KIND_CONSUMER: u8 = 2;
+const KIND_TEXT: u8 = 3;
+const KIND_LAYER: u8 = 4;
+
+/// Why a configuration can't be read or written.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConfigError {
+    /// The data ends before the end of the configuration.
+    Truncated,
+    /// The data doesn't start with [`MAGIC`], as with erased flash.
+    BadMagic,
+    /// The configuration is in a version of the format this crate can't
+    /// read.
+    UnsupportedVersion(u8),
+    /// The configuration was corrupted.
+    BadChecksum,
+    /// The action bound to `input` on `layer` is unknown or out of range.
+    InvalidAction { layer: u8, input: Input },
+    /// There are more than 255 layers.
+    TooManyLayers,
+    /// The texts take more than 65535 bytes in total.
+    TextTooLong,
+    /// The buffer is too small for the configuration.
+    BufferTooSmall,
+}
+
+/// The actions and LED colors of one layer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LayerConfig<'a> {
+    pub layer: Layer<'a>,
+    /// The color of the LED under each key.
+    pub colors: [RGB8; NUM_KEYS as usize],
+}
+
+/// A configuration checked by [`Config::parse`], read in place.
+#[derive(Clone, Copy, Debug)]
+pub struct Config<'a> {
+    bytes: &'a [u8],
+    layers: &'a [u8],
+    texts: &'a [u8],
+    layer_count: u8,
+}
+
+impl<'a> Config<'a> {
+    /// Check the configuration at the start of `data`. Bytes after the end of
+    /// the configuration are ignored.
+    pub fn parse(data: &'a [u8]) -> Result<Self, ConfigError> {
+        let header = data.get(..HEADER_SIZE).ok_or(ConfigError::Truncated)?;
+        if header[..4] != MAGIC {
+            return Err(ConfigError::BadMagic);
+        }
+        if header[4] != VERSION {
+            return Err(ConfigError::UnsupportedVersion(header[4]));
+        }
+        let layer_count = header[5];
+        let text_size = usize::from(u16::from_le_bytes([header[6], header[7]]));
+        let texts_start = HEADER_SIZE + usize::from(layer_count) * LAYER_SIZE;
+        let crc_start = texts_start + text_size;
+        let bytes = data
+            .get(..crc_start + CRC_SIZE)
+            .ok_or(ConfigError::Truncated)?;
+
+        let crc = &bytes[crc_start..];
+        if crc32(&bytes[..crc_start]) != u32::from_le_bytes([crc[0], crc[1], crc[2], crc[3]]) {
+            return Err(ConfigError::BadChecksum);
+        }
+
+        let config = Config {
+            bytes,
+            layers: &bytes[HEADER_SIZE..texts_start],
+            texts: &bytes[texts_start..crc_start],
+            layer_count,
+        };
+        for layer in 0..layer_count {
+            for slot in 0..ACTIONS {
+                if config.decode_action(layer, slot).is_none() {
+                    let input = slot_input(slot);
+                    return Err(ConfigError::InvalidAction { layer, input });
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    /// The whole configuration, including its header and checksum.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn layer_count(&self) -> u8 {
+        self.layer_count
+    }
+
+    /// The actions and LED colors of `layer`, or `None` past the last layer.
+    pub fn layer(&self, layer: u8) -> Option<LayerConfig<'a>> {
+        if layer >= self.layer_count {
+            return None;
+        }
+        let mut keys = [Action::None; NUM_KEYS as usize];
+        for (key, action) in keys.iter_mut().enumerate() {
+            *action = self.action(layer, Input::Key(key as u8));
+        }
+        Some(LayerConfig {
+            layer: Layer {
+                keys,
+                clockwise: self.action(layer, Input::Clockwise),
+                counter_clockwise: self.action(layer, Input::CounterClockwise),
+            },
+            colors: self.colors(layer)?,
+        })
+    }
+
+    /// The action bound to `input` on `layer`, which is [`Action::None`] for
+    /// layers past the last.
+    pub fn action(&self, layer: u8, input: Input) -> Action<'a> {
+        match input_slot(input) {
+            Some(slot) if layer < self.layer_count => {
+                self.decode_action(layer, slot).unwrap_or(Action::None)
+            }
+            _ => Action::None,
+        }
+    }
+
+    /// The LED colors of the keys on `layer`, or `None` past the last layer.
+    pub fn colors(&self, layer: u8) -> Option<[RGB8; NUM_KEYS as usize]> {
+        if layer >= self.layer_count {
+            return None;
+        }
+        let start = usize::from(layer) * LAYER_SIZE + ACTIONS * ACTION_SIZE;
+        let bytes = &self.layers[start..start + NUM_KEYS as usize * 3];
+        let mut colors = [RGB8::default(); NUM_KEYS as usize];
+        for (color, rgb) in colors.iter_mut().zip(bytes.chunks_exact(3)) {
+            *color = RGB8::new(rgb[0], rgb[1], rgb[2]);
+        }
+        Some(colors)
+    }
+
+    /// The action in `slot` of `layer`, or `None` if it isn't valid.
+    fn decode_action(&self, layer: u8, slot: usize) -> Option<Action<'a>> {
+        let start = usize::from(layer) * LAYER_SIZE + slot * ACTION_SIZE;
+        let record = &self.layers[start..start + ACTION_SIZE];
+        let word =
+            |index: usize| usize::from(u16::from_le_bytes([record[index], record[index + 1]]));
+        match record[0] {
+            KIND_NONE => Some(Action::None),
+            KIND_KEYS => {
+                let keys = &record[2..];
+                let count = keys.iter().position(|&key| key == 0).unwrap_or(keys.len());
+                Some(Action::Keys {
+                    modifiers: record[1],
+                    keys: &keys[..count],
+                })
+            }
+            KIND_CONSUMER => Some(Action::Consumer(word(1) as u16)),
+            KIND_TEXT => {
+                let text = self.texts.get(word(1)..word(1) + word(3))?;
+                core::str::from_utf8(text).ok().map(Action::Text)
+            }
+            KIND_LAYER if record[1] < self.layer_count => Some(Action::Layer(record[1])),
+            _ => None,
+        }
+    }
+}
+
+impl Keymap for Config<'_> {
+    fn layer_count(&self) -> u8 {
+        self.layer_count
+    }
+
+    fn action(&self, layer: u8, input: Input) -> Action<'_> {
+        Config::action(self, layer, input)
+    }
+}
+
+impl ColorSchemes for Config<'_> {
+    fn colors(&self, layer: u8) -> Option<[RGB8; NUM_KEYS as usize]> {
+        Config::colors(self, layer)
+    }
+}
+
+/// The size of the configuration holding `layers`.
+pub fn serialized_size(layers: &[LayerConfig<'_>]) -> Result<usize, ConfigError> {
+    if layers.len() > usize::from(u8::MAX) {
+        return Err(ConfigError::TooManyLayers);
+    }
+    Ok(HEADER_SIZE + layers.len() * LAYER_SIZE + text_size(layers)? + CRC_SIZE)
+}
+
+/// Write the configuration holding `layers` at the start of `buffer`, and
+/// return its size.
+pub fn serialize(layers: &[LayerConfig<'_>], buffer: &mut [u8]) -> Result<usize, ConfigError> {
+    let size = serialized_size(layers)?;
+    let bytes = buffer.get_mut(..size).ok_or(ConfigError::BufferTooSmall)?;
+    let layer_count = layers.len() as u8;
+    let text_size = text_size(layers)?;
+
+    bytes[..4].copy_from_slice(&MAGIC);
+    bytes[4] = VERSION;
+    bytes[5] = layer_count;
+    bytes[6..8].copy_from_slice(&(text_size as u16).to_le_bytes());
+
+    let texts_start = HEADER_SIZE + layers.len() * LAYER_SIZE;
+    let mut text_offset = 0;
+    for (index, config) in layers.iter().enumerate() {
+        let layer_start = HEADER_SIZE + index * LAYER_SIZE;
+        for slot in 0..ACTIONS {
+            let input = slot_input(slot);
+            let invalid = ConfigError::InvalidAction {
+                layer: index as u8,
+                input,
+            };
+            let start = layer_start + slot * ACTION_SIZE;
+            let record = &mut bytes[start..start + ACTION_SIZE];
+            record.fill(0);
+            match config.layer.action(input) {
+                Action::None => record[0] = KIND_NONE,
+                Action::Keys { modifiers, keys } => {
+                    if keys.len() > ACTION_SIZE - 2 || keys.contains(&0) {
+                        return Err(invalid);
+                    }
+                    record[0] = KIND_KEYS;
+                    record[1] = modifiers;
+                    record[2..2 + keys.len()].copy_from_slice(keys);
+                }
+                Action::Consumer(usage) => {
+                    record[0] = KIND_CONSUMER;
+                    record[1..3].copy_from_slice(&usage.to_le_bytes());
+                }
+                Action::Text(text) => {
+                    record[0] = KIND_TEXT;
+                    record[1..3].copy_from_slice(&(text_offset as u16).to_le_bytes());
+                    record[3..5].copy_from_slice(&(text.len() as u16).to_le_bytes());
+                    let start = texts_start + text_offset;
+                    bytes[start..start + text.len()].copy_from_slice(text.as_bytes());
+                    text_offset += text.len();
+                }
+                Action::Layer(layer) => {
+                    if layer >= layer_count {
+                        return Err(invalid);
+                    }
+                    record[0] = KIND_LAYER;
+                    record[1] = layer;
+                }
+            }
+        }
+
+        let colors_start = layer_start + ACTIONS * ACTION_SIZE;
+        let colors = &mut bytes[colors_start..colors_start + NUM_KEYS as usize * 3];
+        for (rgb, color) in colors.chunks_exact_mut(3).zip(config.colors.iter()) {
+            rgb.copy_from_slice(&[color.r, color.g, color.b]);
+        }
+    }
+
+    let crc_start = size - CRC_SIZE;
+    let crc = crc32(&bytes[..crc_start]);
+    bytes[crc_start..].copy_from_slice(&crc.to_le_bytes());
+    Ok(size)
+}
+
+/// The size of all the texts of `layers`.
+fn text_size(layers: &[LayerConfig<'_>]) -> Result<usize, ConfigError> {
+    let mut size = 0;
+    for config in layers {
+        for slot in 0..ACTIONS {
+            if let Action::Text(text) = config.layer.action(slot_input(slot)) {
+                size += text.len();
+            }
+        }
+    }
+    if size > usize::from(u16::MAX) {
+        return Err(ConfigError::TextTooLong);
+    }
+    Ok(size)
+}
+
+/// The input whose action is in `slot` of a layer.
+fn slot_input(slot: usize) -> Input {
+    match slot {
+        slot if slot < NUM_KEYS as usize => Input::Key(slot as u8),
+        slot if slot == NUM_KEYS as usize => Input::Clockwise,
+        _ => Input::CounterClockwise,
+    }
+}
+
+/// The slot holding the action of `input` in a layer.
+fn input_slot(input: Input) -> Option<usize> {
+    match input {
+        Input::Key(key) if key < NUM_KEYS => Some(usize::from(key)),
+        Input::Key(_) => None,
+        Input::Clockwise => Some(NUM_KEYS as usize),
+        Input::CounterClockwise => Some(NUM_KEYS as usize + 1),
+    }
+}
+
+/// CRC-32 as used by Ethernet and zip files.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usage::{consumer, key, modifier};
+
+    const NONE: Action<'static> = Action::None;
+
+    fn layers() -> [LayerConfig<'static>; 2] {
+        [
+            LayerConfig {
+                layer: Layer {
+                    keys: [
+                        Action::Keys {
+                            modifiers: modifier::LEFT_CTRL,
+                            keys: &[key::C],
+                        },
+                        Action::Text("Hi!\n"),
+                        Action::Consumer(consumer::MUTE),
+                        Action::Layer(1),
+                        Action::Text("héllo"),
+                        NONE,
+                        NONE,
+                        NONE,
+                        NONE,
+                        NONE,
+                        NONE,
+                        Action::Keys {
+                            modifiers: 0,
+                            keys: &[key::A, key::B, key::C, key::D, key::E, key::F],
+                        },
+                    ],
+                    clockwise: Action::Consumer(consumer::VOLUME_UP),
+                    counter_clockwise: Action::Text("ab"),
+                },
+                colors: [RGB8::new(1, 2, 3); NUM_KEYS as usize],
+            },
+            LayerConfig {
+                layer: Layer {
+                    keys: [NONE; NUM_KEYS as usize],
+                    clockwise: Action::Layer(0),
+                    counter_clockwise: NONE,
+                },
+                colors: [RGB8::new(9, 8, 7); NUM_KEYS as usize],
+            },
+        ]
+    }
+
+    /// Serialize `layers()` into `buffer`, and return its size.
+    fn serialize_layers(buffer: &mut [u8]) -> usize {
+        serialize(&layers(), buffer).unwrap()
+    }
+
+    /// Write the checksum again after changing the configuration in `bytes`.
+    fn reseal(bytes: &mut [u8]) {
+        let crc_start = bytes.len() - CRC_SIZE;
+        let crc = crc32(&bytes[..crc_start]);
+        bytes[crc_start..].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    /// The offset of the action of `slot` on `layer`.
+    fn action_start(layer: usize, slot: usize) -> usize {
+        HEADER_SIZE + layer * LAYER_SIZE + slot * ACTION_SIZE
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn round_trip() {
+        let layers = layers();
+        let mut buffer = [0xFF; 1024];
+        let size = serialize(&layers, &mut buffer).unwrap();
+        assert_eq!(size, serialized_size(&layers).unwrap());
+        assert_eq!(size, HEADER_SIZE + 2 * LAYER_SIZE + 12 + CRC_SIZE);
+
+        let config = Config::parse(&buffer).unwrap();
+        assert_eq!(config.as_bytes(), &buffer[..size]);
+        assert_eq!(config.layer_count(), 2);
+        for (index, layer) in layers.iter().enumerate() {
+            assert_eq!(config.layer(index as u8), Some(*layer));
+        }
+        assert_eq!(config.layer(2), None);
+        assert_eq!(config.colors(2), None);
+        assert_eq!(config.action(2, Input::Key(0)), Action::None);
+        assert_eq!(config.action(0, Input::Key(NUM_KEYS)), Action::None);
+    }
+
+    #[test]
+    fn round_trip_without_layers() {
+        let mut buffer = [0; 16];
+        let size = serialize(&[], &mut buffer).unwrap();
+        assert_eq!(size, HEADER_SIZE + CRC_SIZE);
+        let config = Config::parse(&buffer).unwrap();
+        assert_eq!(config.layer_count(), 0);
+        assert_eq!(config.layer(0), None);
+    }
+
+    #[test]
+    fn flipped_bits_are_caught() {
+        let mut buffer = [0; 1024];
+        let size = serialize_layers(&mut buffer);
+        // Anywhere past the header, whose fields are checked first
+        for index in HEADER_SIZE..size {
+            let mut bytes = buffer;
+            bytes[index] ^= 0x10;
+            assert_eq!(
+                Config::parse(&bytes[..size]).err(),
+                Some(ConfigError::BadChecksum),
+                "{}",
+                index
+            );
+        }
+    }
+
+    #[test]
+    fn erased_flash_has_bad_magic() {
+        assert_eq!(
+            Config::parse(&[0xFF; 256]).err(),
+            Some(ConfigError::BadMagic)
+        );
+    }
+
+    #[test]
+    fn short_data_is_truncated() {
+        let mut buffer = [0; 1024];
+        let size = serialize_layers(&mut buffer);
+        for size in [0, HEADER_SIZE - 1, HEADER_SIZE, size - 1] {
+            assert_eq!(
+                Config::parse(&buffer[..size]).err(),
+                Some(ConfigError::Truncated)
+            );
+        }
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut buffer = [0; 1024];
+        let size = serialize_layers(&mut buffer);
+        buffer[4] = VERSION + 1;
+        reseal(&mut buffer[..size]);
+        assert_eq!(
+            Config::parse(&buffer).err(),
+            Some(ConfigError::UnsupportedVersion(VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn layers_past_the_last_are_invalid() {
+        let mut buffer = [0; 1024];
+        let size = serialize_layers(&mut buffer);
+        buffer[action_start(0, 3) + 1] = 2;
+        reseal(&mut buffer[..size]);
+        assert_eq!(
+            Config::parse(&buffer).err(),
+            Some(ConfigError::InvalidAction {
+                layer: 0,
+                input: Input::Key(3)
+            })
+        );
+    }
+
+    #[test]
+    fn texts_past_the_text_area_are_invalid() {
+        let mut buffer = [0; 1024];
+        let size = serialize_layers(&mut buffer);
+        // The size of the text of the encoder turning counter-clockwise
+        buffer[action_start(0, NUM_KEYS as usize + 1) + 3] = 3;
+        reseal(&mut buffer[..size]);
+        assert_eq!(
+            Config::parse(&buffer).err(),
+            Some(ConfigError::InvalidAction {
+                layer: 0,
+                input: Input::CounterClockwise
+            })
+        );
+    }
+
+    #[test]
+    fn texts_must_be_utf8() {
+        let mut buffer = [0; 1024];
+        let size = serialize_layers(&mut buffer);
+        // Split the 'é' of "héllo"
+        let start = action_start(0, 4);
+        buffer[start + 3] = 2;
+        reseal(&mut buffer[..size]);
+        assert_eq!(
+            Config::parse(&buffer).err(),
+            Some(ConfigError::InvalidAction {
+                layer: 0,
+                input: Input::Key(4)
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_actions_are_invalid() {
+        let mut buffer = [0; 1024];
+        let size = serialize_layers(&mut buffer);
+        buffer[action_start(1, 5)] = KIND_LAYER + 1;
+        reseal(&mut buffer[..size]);
+        assert_eq!(
+            Config::parse(&buffer).err(),
+            Some(ConfigError::InvalidAction {
+                layer: 1,
+                input: Input::Key(5)
+            })
+        );
+    }
+
+    #[test]
+    fn serialize_checks_the_actions() {
+        let mut buffer = [0; 1024];
+        let mut layers = layers();
+        layers[1].layer.keys[0] = Action::Layer(2);
+        assert_eq!(
+            serialize(&layers, &mut buffer),
+            Err(ConfigError::InvalidAction {
+                layer: 1,
+                input: Input::Key(0)
+            })
+        );
+
+        let mut layers = self::layers();
+        layers[0].layer.clockwise = Action::Keys {
+            modifiers: 0,
+            keys: &[key::A; 7],
+        };
+        assert_eq!(
+            serialize(&layers, &mut buffer),
+            Err(ConfigError::InvalidAction {
+                layer: 0,
+                input: Input::Clockwise
+            })
+        );
+    }
+
+    #[test]
+    fn serialize_needs_room() {
+        let layers = layers();
+        let size = serialized_size(&layers).unwrap();
+        let mut buffer = [0; 1024];
+        assert_eq!(
+            serialize(&layers, &mut buffer[..size - 1]),
+            Err(ConfigError::BufferTooSmall)
+        );
+        assert_eq!(serialize(&layers, &mut buffer[..size]), Ok(size));
+    }
+}
@@ -0,0 +1,229 @@
+//! Debounce key states scanned into a bitmask.
+//!
+//! [`Debouncer`] takes raw scans of up to 16 keys, one bit per key with a set
+//! bit for a pressed key, and only reports a key as changed once it reads the
+//! same for a number of scans in a row. Each key is debounced on its own, so
+//! a bouncing key doesn't hold back the others. It doesn't touch any
+//! hardware.
+
+/// A key changing state, with the index of the key.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyEvent {
+    Pressed(u8),
+    Released(u8),
+}
+
+impl KeyEvent {
+    /// The index of the key.
+    pub fn key(&self) -> u8 {
+        match *self {
+            KeyEvent::Pressed(key) | KeyEvent::Released(key) => key,
+        }
+    }
+}
+
+/// Debounces up to 16 keys.
+#[derive(Clone, Debug)]
+pub struct Debouncer {
+    state: u16,
+    counters: [u8; 16],
+    samples: u8,
+}
+
+impl Debouncer {
+    /// Debounce keys which start released, reporting a change once a key
+    /// reads the same for `samples` scans in a row. A `samples` of 0 or 1
+    /// reports every change at once.
+    pub const fn new(samples: u8) -> Self {
+        Debouncer {
+            state: 0,
+            counters: [0; 16],
+            samples,
+        }
+    }
+
+    /// The debounced state, with a set bit for each pressed key.
+    pub fn state(&self) -> u16 {
+        self.state
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool {
+        key < 16 && self.state & (1 << key) != 0
+    }
+
+    pub fn samples(&self) -> u8 {
+        self.samples
+    }
+
+    /// Change the number of scans a key must read the same for. Changes
+    /// counted so far are kept.
+    pub fn set_samples(&mut self, samples: u8) {
+        self.samples = samples;
+    }
+
+    /// Feed a raw scan, and get the keys that changed.
+    pub fn update(&mut self, raw: u16) -> KeyEvents {
+        let mut changed = 0;
+        let differs = raw ^ self.state;
+        for (key, counter) in self.counters.iter_mut().enumerate() {
+            if differs & (1 << key) == 0 {
+                *counter = 0;
+                continue;
+            }
+            *counter = counter.saturating_add(1);
+            if *counter >= self.samples {
+                *counter = 0;
+                changed |= 1 << key;
+            }
+        }
+        self.state ^= changed;
+        KeyEvents {
+            pressed: changed & self.state,
+            released: changed & !self.state,
+        }
+    }
+
+    /// Forget all keys, as if they were all released.
+    pub fn reset(&mut self) {
+        self.state = 0;
+        self.counters = [0; 16];
+    }
+}
+
+/// The keys changed by a scan, pressed keys first, each in order of index.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct KeyEvents {
+    pressed: u16,
+    released: u16,
+}
+
+impl KeyEvents {
+    /// The keys pressed by the scan, one bit per key.
+    pub fn pressed(&self) -> u16 {
+        self.pressed
+    }
+
+    /// The keys released by the scan, one bit per key.
+    pub fn released(&self) -> u16 {
+        self.released
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pressed == 0 && self.released == 0
+    }
+}
+
+impl Iterator for KeyEvents {
+    type Item = KeyEvent;
+
+    fn next(&mut self) -> Option<KeyEvent> {
+        if self.pressed != 0 {
+            let key = self.pressed.trailing_zeros() as u8;
+            self.pressed &= self.pressed - 1;
+            Some(KeyEvent::Pressed(key))
+        } else if self.released != 0 {
+            let key = self.released.trailing_zeros() as u8;
+            self.released &= self.released - 1;
+            Some(KeyEvent::Released(key))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `raw`, and get the only event it gives, if any.
+    fn update(debouncer: &mut Debouncer, raw: u16) -> Option<KeyEvent> {
+        let mut events = debouncer.update(raw);
+        let event = events.next();
+        assert_eq!(events.next(), None);
+        event
+    }
+
+    #[test]
+    fn press_after_enough_samples() {
+        let mut debouncer = Debouncer::new(3);
+        assert_eq!(update(&mut debouncer, 0b100), None);
+        assert_eq!(update(&mut debouncer, 0b100), None);
+        assert!(!debouncer.is_pressed(2));
+        assert_eq!(update(&mut debouncer, 0b100), Some(KeyEvent::Pressed(2)));
+        assert!(debouncer.is_pressed(2));
+        assert_eq!(debouncer.state(), 0b100);
+        // Only once
+        assert_eq!(update(&mut debouncer, 0b100), None);
+    }
+
+    #[test]
+    fn release_after_enough_samples() {
+        let mut debouncer = Debouncer::new(2);
+        update(&mut debouncer, 0b1);
+        assert_eq!(update(&mut debouncer, 0b1), Some(KeyEvent::Pressed(0)));
+        assert_eq!(update(&mut debouncer, 0), None);
+        assert!(debouncer.is_pressed(0));
+        assert_eq!(update(&mut debouncer, 0), Some(KeyEvent::Released(0)));
+        assert!(!debouncer.is_pressed(0));
+    }
+
+    #[test]
+    fn bounces_are_suppressed() {
+        let mut debouncer = Debouncer::new(3);
+        for raw in [1, 0, 1, 1, 0, 1, 1] {
+            assert_eq!(update(&mut debouncer, raw), None);
+        }
+        assert_eq!(update(&mut debouncer, 1), Some(KeyEvent::Pressed(0)));
+        // Bouncing on release doesn't press the key again
+        for raw in [0, 1, 0, 0, 1] {
+            assert_eq!(update(&mut debouncer, raw), None);
+        }
+        assert!(debouncer.is_pressed(0));
+    }
+
+    #[test]
+    fn keys_are_debounced_on_their_own() {
+        let mut debouncer = Debouncer::new(2);
+        assert!(debouncer.update(0b01).is_empty());
+        // Key 1 bouncing doesn't hold back key 0
+        let events = debouncer.update(0b11);
+        assert_eq!(events.pressed(), 0b01);
+        assert_eq!(update(&mut debouncer, 0b01), None);
+        assert_eq!(update(&mut debouncer, 0b11), None);
+        assert_eq!(update(&mut debouncer, 0b11), Some(KeyEvent::Pressed(1)));
+    }
+
+    #[test]
+    fn events_come_pressed_first_in_order() {
+        let mut debouncer = Debouncer::new(1);
+        debouncer.update(0b0000_0000_0000_0101);
+        let mut events = debouncer.update(0b1000_0000_0001_0010);
+        assert_eq!(events.pressed(), 0b1000_0000_0001_0010);
+        assert_eq!(events.released(), 0b0101);
+        assert_eq!(events.next(), Some(KeyEvent::Pressed(1)));
+        assert_eq!(events.next(), Some(KeyEvent::Pressed(4)));
+        assert_eq!(events.next(), Some(KeyEvent::Pressed(15)));
+        assert_eq!(events.next(), Some(KeyEvent::Released(0)));
+        assert_eq!(events.next(), Some(KeyEvent::Released(2)));
+        assert_eq!(events.next(), None);
+        assert_eq!(KeyEvent::Released(2).key(), 2);
+    }
+
+    #[test]
+    fn zero_samples_reports_at_once() {
+        let mut debouncer = Debouncer::new(0);
+        assert_eq!(update(&mut debouncer, 0b1000), Some(KeyEvent::Pressed(3)));
+        assert_eq!(update(&mut debouncer, 0), Some(KeyEvent::Released(3)));
+    }
+
+    #[test]
+    fn reset_releases_all_keys() {
+        let mut debouncer = Debouncer::new(1);
+        debouncer.update(0xFFFF);
+        assert_eq!(debouncer.state(), 0xFFFF);
+        debouncer.reset();
+        assert_eq!(debouncer.state(), 0);
+        assert!(!debouncer.is_pressed(16));
+        assert_eq!(debouncer.update(0xFFFF).count(), 16);
+    }
+}
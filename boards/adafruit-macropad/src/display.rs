@@ -0,0 +1,350 @@
+//! The 1.3" 128×64 OLED display, driven by a SH1106 controller.
+//!
+//! [`MacropadDisplay`] draws into a [`Framebuffer`] in RAM, and sends the
+//! pages that changed to the display on [`MacropadDisplay::flush`].
+//! [`Macropad::display`] sets up SPI1 and the display in one go.
+//!
+//! The chip select of the display isn't on a pin SPI1 can drive, so it is
+//! driven as a GPIO around each transfer.
+
+use core::convert::Infallible;
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Size},
+    pixelcolor::BinaryColor,
+    Pixel,
+};
+use embedded_hal::{
+    blocking::{delay::DelayUs, spi::Write},
+    digital::v2::OutputPin,
+};
+use fugit::{HertzU32, RateExtU32};
+use hal::gpio::bank0::{Gpio22, Gpio23, Gpio24, Gpio26, Gpio27};
+use hal::gpio::{FunctionSpi, Pin, PinId, PinState, PushPullOutput};
+use hal::pac;
+use hal::spi::{Enabled, Spi};
+
+use crate::framebuffer::{Framebuffer, Rotation, PAGES};
+use crate::Macropad;
+
+/// SH1106 commands. Those with an argument in their low bits take it ORed
+/// in, the others take it in the next byte.
+mod command {
+    pub const SET_LOW_COLUMN: u8 = 0x00;
+    pub const SET_HIGH_COLUMN: u8 = 0x10;
+    pub const SET_PUMP_VOLTAGE: u8 = 0x30;
+    pub const SET_START_LINE: u8 = 0x40;
+    pub const SET_CONTRAST: u8 = 0x81;
+    pub const SET_SEGMENT_REMAP: u8 = 0xA1;
+    pub const DISPLAY_FROM_RAM: u8 = 0xA4;
+    pub const SET_NORMAL: u8 = 0xA6;
+    pub const SET_INVERTED: u8 = 0xA7;
+    pub const SET_MULTIPLEX: u8 = 0xA8;
+    pub const SET_DC_DC: u8 = 0xAD;
+    pub const DISPLAY_OFF: u8 = 0xAE;
+    pub const DISPLAY_ON: u8 = 0xAF;
+    pub const SET_PAGE: u8 = 0xB0;
+    pub const SET_COM_SCAN_DESCENDING: u8 = 0xC8;
+    pub const SET_DISPLAY_OFFSET: u8 = 0xD3;
+    pub const SET_CLOCK: u8 = 0xD5;
+    pub const SET_PRECHARGE: u8 = 0xD9;
+    pub const SET_COM_PINS: u8 = 0xDA;
+    pub const SET_VCOM_DESELECT: u8 = 0xDB;
+}
+
+/// The SH1106 has 132 columns of RAM, of which the panel shows the middle
+/// 128.
+const COLUMN_OFFSET: u8 = 2;
+
+/// Contrast set by [`MacropadDisplay::init`].
+pub const DEFAULT_CONTRAST: u8 = 0xFF;
+
+/// Fastest SPI clock the SH1106 takes.
+pub const MAX_SPI_FREQ_HZ: u32 = 4_000_000;
+
+/// The SPI bus of the display, as set up by [`Macropad::display`].
+pub type DisplaySpi = Spi<Enabled, pac::SPI1, 8>;
+
+/// Drives the OLED display over SPI.
+///
+/// `SPI` is the SPI1 bus on the `sclk` and `mosi` pins, in mode 0 at up to
+/// [`MAX_SPI_FREQ_HZ`]. Everything drawn through [`DrawTarget`] goes to the
+/// framebuffer only, and shows up on the next flush.
+pub struct MacropadDisplay<SPI> {
+    bus: Interface<SPI>,
+    reset: Pin<Gpio23, PushPullOutput>,
+    framebuffer: Framebuffer,
+}
+
+impl<SPI> MacropadDisplay<SPI>
+where
+    SPI: Write<u8>,
+{
+    /// Take control of the display. It must be set up with
+    /// [`MacropadDisplay::init`] before it shows anything.
+    pub fn new(
+        spi: SPI,
+        oled_cs: Pin<Gpio22, <Gpio22 as PinId>::Reset>,
+        oled_dc: Pin<Gpio24, <Gpio24 as PinId>::Reset>,
+        oled_reset: Pin<Gpio23, <Gpio23 as PinId>::Reset>,
+    ) -> Self {
+        MacropadDisplay {
+            bus: Interface {
+                spi,
+                cs: oled_cs.into_push_pull_output_in_state(PinState::High),
+                dc: oled_dc.into_push_pull_output(),
+            },
+            reset: oled_reset.into_push_pull_output_in_state(PinState::High),
+            framebuffer: Framebuffer::new(),
+        }
+    }
+
+    /// Reset the controller, set it up with [`DEFAULT_CONTRAST`], send it the
+    /// whole framebuffer and switch the display on.
+    pub fn init(&mut self, delay: &mut impl DelayUs<u32>) -> Result<(), SPI::Error> {
+        // Setting GPIO outputs can't fail
+        let _ = self.reset.set_high();
+        delay.delay_us(1_000);
+        let _ = self.reset.set_low();
+        delay.delay_us(1_000);
+        let _ = self.reset.set_high();
+        delay.delay_us(1_000);
+
+        self.bus.commands(&[
+            command::DISPLAY_OFF,
+            // Default oscillator frequency, with no divider
+            command::SET_CLOCK,
+            0x80,
+            // All 64 rows, from the first line of RAM
+            command::SET_MULTIPLEX,
+            0x3F,
+            command::SET_DISPLAY_OFFSET,
+            0x00,
+            command::SET_START_LINE,
+            // Internal DC-DC converter on
+            command::SET_DC_DC,
+            0x8B,
+            // Columns and rows scanned backwards, as the panel is mounted
+            // upside down
+            command::SET_SEGMENT_REMAP,
+            command::SET_COM_SCAN_DESCENDING,
+            command::SET_COM_PINS,
+            0x12,
+            command::SET_CONTRAST,
+            DEFAULT_CONTRAST,
+            // Precharge of 2 clocks, discharge of 1
+            command::SET_PRECHARGE,
+            0x1F,
+            command::SET_VCOM_DESELECT,
+            0x20,
+            // 9V for the panel
+            command::SET_PUMP_VOLTAGE | 0x03,
+            command::SET_NORMAL,
+            command::DISPLAY_FROM_RAM,
+        ])?;
+        self.flush_all()?;
+        self.bus.commands(&[command::DISPLAY_ON])
+    }
+
+    /// Set the brightness of lit pixels, from 0 to 255.
+    pub fn set_contrast(&mut self, contrast: u8) -> Result<(), SPI::Error> {
+        self.bus.commands(&[command::SET_CONTRAST, contrast])
+    }
+
+    /// Show lit pixels as dark and dark pixels as lit, or back to normal.
+    pub fn set_inverted(&mut self, inverted: bool) -> Result<(), SPI::Error> {
+        let command = if inverted {
+            command::SET_INVERTED
+        } else {
+            command::SET_NORMAL
+        };
+        self.bus.commands(&[command])
+    }
+
+    /// Switch the display on or off. It keeps its contents while off, and
+    /// can still be flushed.
+    pub fn set_display_on(&mut self, on: bool) -> Result<(), SPI::Error> {
+        let command = if on {
+            command::DISPLAY_ON
+        } else {
+            command::DISPLAY_OFF
+        };
+        self.bus.commands(&[command])
+    }
+
+    pub fn rotation(&self) -> Rotation {
+        self.framebuffer.rotation()
+    }
+
+    /// Draw in the orientation `rotation` from now on.
+    ///
+    /// Pixels already drawn stay where they are on the display, so clear it
+    /// and draw it again after changing the rotation.
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.framebuffer.set_rotation(rotation);
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    pub fn framebuffer_mut(&mut self) -> &mut Framebuffer {
+        &mut self.framebuffer
+    }
+
+    /// Send the pages of the framebuffer that changed since the last flush.
+    ///
+    /// A page stays dirty if sending it fails, so it is sent again on the
+    /// next flush.
+    pub fn flush(&mut self) -> Result<(), SPI::Error> {
+        for page in 0..PAGES {
+            if self.framebuffer.dirty_pages() & (1 << page) != 0 {
+                self.bus.commands(&[
+                    command::SET_PAGE | page,
+                    command::SET_LOW_COLUMN | (COLUMN_OFFSET & 0x0F),
+                    command::SET_HIGH_COLUMN | (COLUMN_OFFSET >> 4),
+                ])?;
+                self.bus.data(self.framebuffer.page(page))?;
+                self.framebuffer.mark_clean(page);
+            }
+        }
+        Ok(())
+    }
+
+    /// Send the whole framebuffer, whether it changed or not.
+    pub fn flush_all(&mut self) -> Result<(), SPI::Error> {
+        self.framebuffer.mark_all_dirty();
+        self.flush()
+    }
+
+    /// Release the bus and the pins.
+    #[allow(clippy::type_complexity)]
+    pub fn free(
+        self,
+    ) -> (
+        SPI,
+        Pin<Gpio22, PushPullOutput>,
+        Pin<Gpio24, PushPullOutput>,
+        Pin<Gpio23, PushPullOutput>,
+    ) {
+        let Interface { spi, cs, dc } = self.bus;
+        (spi, cs, dc, self.reset)
+    }
+}
+
+impl MacropadDisplay<DisplaySpi> {
+    /// Set up SPI1 and the display, and switch it on with a blank image, as
+    /// [`Macropad::display`] does.
+    ///
+    /// `peripheral_clock` is the frequency SPI1 is clocked from. Writing to
+    /// the SPI peripheral can't fail, so neither can this.
+    #[allow(clippy::too_many_arguments)]
+    pub fn with_spi1(
+        spi1: pac::SPI1,
+        sclk: Pin<Gpio26, <Gpio26 as PinId>::Reset>,
+        mosi: Pin<Gpio27, <Gpio27 as PinId>::Reset>,
+        oled_cs: Pin<Gpio22, <Gpio22 as PinId>::Reset>,
+        oled_dc: Pin<Gpio24, <Gpio24 as PinId>::Reset>,
+        oled_reset: Pin<Gpio23, <Gpio23 as PinId>::Reset>,
+        resets: &mut pac::RESETS,
+        peripheral_clock: HertzU32,
+        delay: &mut impl DelayUs<u32>,
+    ) -> Result<Self, Infallible> {
+        let _ = sclk.into_mode::<FunctionSpi>();
+        let _ = mosi.into_mode::<FunctionSpi>();
+        let spi = Spi::<_, _, 8>::new(spi1).init(
+            resets,
+            peripheral_clock,
+            MAX_SPI_FREQ_HZ.Hz(),
+            &embedded_hal::spi::MODE_0,
+        );
+        let mut display = MacropadDisplay::new(spi, oled_cs, oled_dc, oled_reset);
+        display.init(delay)?;
+        Ok(display)
+    }
+}
+
+impl Macropad {
+    /// Set up SPI1 and the display, and switch it on with a blank image.
+    ///
+    /// `peripheral_clock` is the frequency SPI1 is clocked from. Writing to
+    /// the SPI peripheral can't fail, so neither can this.
+    #[allow(clippy::too_many_arguments)]
+    pub fn display(
+        spi1: pac::SPI1,
+        sclk: Pin<Gpio26, <Gpio26 as PinId>::Reset>,
+        mosi: Pin<Gpio27, <Gpio27 as PinId>::Reset>,
+        oled_cs: Pin<Gpio22, <Gpio22 as PinId>::Reset>,
+        oled_dc: Pin<Gpio24, <Gpio24 as PinId>::Reset>,
+        oled_reset: Pin<Gpio23, <Gpio23 as PinId>::Reset>,
+        resets: &mut pac::RESETS,
+        peripheral_clock: HertzU32,
+        delay: &mut impl DelayUs<u32>,
+    ) -> Result<MacropadDisplay<DisplaySpi>, Infallible> {
+        MacropadDisplay::with_spi1(
+            spi1,
+            sclk,
+            mosi,
+            oled_cs,
+            oled_dc,
+            oled_reset,
+            resets,
+            peripheral_clock,
+            delay,
+        )
+    }
+}
+
+/// The SPI bus and the pins framing its transfers.
+struct Interface<SPI> {
+    spi: SPI,
+    cs: Pin<Gpio22, PushPullOutput>,
+    dc: Pin<Gpio24, PushPullOutput>,
+}
+
+impl<SPI> Interface<SPI>
+where
+    SPI: Write<u8>,
+{
+    /// Send command bytes, along with their arguments.
+    fn commands(&mut self, bytes: &[u8]) -> Result<(), SPI::Error> {
+        let _ = self.dc.set_low();
+        self.transfer(bytes)
+    }
+
+    /// Send bytes to the RAM of the display.
+    fn data(&mut self, bytes: &[u8]) -> Result<(), SPI::Error> {
+        let _ = self.dc.set_high();
+        self.transfer(bytes)
+    }
+
+    fn transfer(&mut self, bytes: &[u8]) -> Result<(), SPI::Error> {
+        let _ = self.cs.set_low();
+        let result = self.spi.write(bytes);
+        let _ = self.cs.set_high();
+        result
+    }
+}
+
+impl<SPI> OriginDimensions for MacropadDisplay<SPI> {
+    fn size(&self) -> Size {
+        self.framebuffer.size()
+    }
+}
+
+impl<SPI> DrawTarget for MacropadDisplay<SPI> {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        self.framebuffer.draw_iter(pixels)
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.framebuffer.clear(color)
+    }
+}
@@ -0,0 +1,519 @@
+//! Decode a quadrature rotary encoder.
+//!
+//! The two signals of an encoder, A and B, follow a Gray code as the shaft
+//! turns, with one edge on either signal at each step. [`Encoder`] counts
+//! those edges through an [`EncoderBackend`], and turns them into a position
+//! in detents, the clicks felt when turning the knob.
+//!
+//! Two backends are available:
+//!
+//! - [`PollingBackend`] reads the pins whenever it is polled, so it must be
+//!   polled more often than the signals change;
+//! - [`PioBackend`] has a PIO state machine count the edges on its own, and
+//!   only needs reading when the position is wanted.
+//!
+//! Counts go up when A leads B, which is clockwise on most encoders. Both
+//! backends work with any pair of pins, as long as both signals are pulled
+//! up and, for [`PioBackend`], B is on the pin after A.
+
+use embedded_hal::digital::v2::InputPin;
+use fugit::HertzU32;
+use hal::gpio::{Function, FunctionConfig, Pin, PinId, PullUpInput, ValidPinMode};
+use hal::pio::{
+    InstallError, PIOBuilder, PIOExt, PinDir, Running, Rx, ShiftDirection, StateMachine,
+    StateMachineIndex, UninitStateMachine, PIO,
+};
+
+/// A change of the two signals between two readings.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Transition {
+    /// Neither signal changed.
+    None,
+    /// One signal changed, with A leading B.
+    Forward,
+    /// One signal changed, with B leading A.
+    Backward,
+    /// Both signals changed at once, so the direction is unknown. This
+    /// happens when a step was missed, or with electrical noise.
+    Invalid,
+}
+
+impl Transition {
+    /// The transition between the states `from` and `to` of the signals,
+    /// with A in bit 0 and B in bit 1.
+    pub fn between(from: u8, to: u8) -> Self {
+        // Position of each state along the Gray code sequence
+        const STEP: [u8; 4] = [0, 1, 3, 2];
+        let from = STEP[usize::from(from & 0b11)];
+        let to = STEP[usize::from(to & 0b11)];
+        match to.wrapping_sub(from) & 0b11 {
+            0 => Transition::None,
+            1 => Transition::Forward,
+            3 => Transition::Backward,
+            _ => Transition::Invalid,
+        }
+    }
+}
+
+/// Counts edges from successive readings of the signals, rejecting invalid
+/// transitions. It doesn't touch any hardware.
+#[derive(Clone, Debug)]
+pub struct QuadratureDecoder {
+    state: u8,
+    edges: i32,
+    invalid: u32,
+}
+
+impl QuadratureDecoder {
+    /// Start counting from the current `state` of the signals, with A in bit
+    /// 0 and B in bit 1.
+    pub const fn new(state: u8) -> Self {
+        QuadratureDecoder {
+            state: state & 0b11,
+            edges: 0,
+            invalid: 0,
+        }
+    }
+
+    /// Feed a new reading of the signals.
+    ///
+    /// An invalid transition doesn't change the count, but the reading is
+    /// kept so the next transitions are decoded from it.
+    pub fn update(&mut self, state: u8) -> Transition {
+        let transition = Transition::between(self.state, state);
+        match transition {
+            Transition::None => {}
+            Transition::Forward => self.edges = self.edges.wrapping_add(1),
+            Transition::Backward => self.edges = self.edges.wrapping_sub(1),
+            Transition::Invalid => self.invalid = self.invalid.wrapping_add(1),
+        }
+        self.state = state & 0b11;
+        transition
+    }
+
+    /// The last reading of the signals.
+    pub fn state(&self) -> u8 {
+        self.state
+    }
+
+    /// Edges counted since the start.
+    pub fn edges(&self) -> i32 {
+        self.edges
+    }
+
+    /// Number of invalid transitions seen since the start.
+    pub fn invalid_transitions(&self) -> u32 {
+        self.invalid
+    }
+}
+
+/// Counts the edges of an encoder.
+pub trait EncoderBackend {
+    /// Edges counted since the backend was created, going up when A leads B.
+    fn edges(&mut self) -> i32;
+}
+
+/// Counts edges by reading the pins on each poll.
+pub struct PollingBackend<A, B> {
+    a: A,
+    b: B,
+    decoder: QuadratureDecoder,
+}
+
+impl<A, B> PollingBackend<A, B>
+where
+    A: InputPin,
+    B: InputPin,
+{
+    /// Start counting from the current state of the pins, which must be
+    /// pulled up.
+    pub fn new(a: A, b: B) -> Self {
+        let mut backend = PollingBackend {
+            a,
+            b,
+            decoder: QuadratureDecoder::new(0),
+        };
+        backend.decoder = QuadratureDecoder::new(backend.read_state());
+        backend
+    }
+
+    /// Read the pins and count the edge since the last poll, if any.
+    ///
+    /// Call this at least once between two edges, such as from a timer
+    /// interrupt every few hundred microseconds, for no step to be missed.
+    pub fn poll(&mut self) -> Transition {
+        let state = self.read_state();
+        self.decoder.update(state)
+    }
+
+    pub fn decoder(&self) -> &QuadratureDecoder {
+        &self.decoder
+    }
+
+    /// Release the pins.
+    pub fn free(self) -> (A, B) {
+        (self.a, self.b)
+    }
+
+    fn read_state(&self) -> u8 {
+        // A pin that can't be read counts as low
+        let a = self.a.is_high().unwrap_or(false);
+        let b = self.b.is_high().unwrap_or(false);
+        u8::from(a) | u8::from(b) << 1
+    }
+}
+
+impl<A, B> EncoderBackend for PollingBackend<A, B>
+where
+    A: InputPin,
+    B: InputPin,
+{
+    /// Poll the pins, and return the edges counted.
+    fn edges(&mut self) -> i32 {
+        self.poll();
+        self.decoder.edges()
+    }
+}
+
+/// Frequency the PIO program runs at, sampling the pins about every 7
+/// cycles.
+const PIO_FREQ_HZ: u32 = 1_000_000;
+
+/// Counts edges with a PIO state machine, without any help from the CPU.
+///
+/// The program takes 26 instructions, and must be installed at the start of
+/// the PIO memory.
+pub struct PioBackend<P, SM, A, B>
+where
+    P: PIOExt + FunctionConfig,
+    SM: StateMachineIndex,
+    A: PinId,
+    B: PinId,
+    Function<P>: ValidPinMode<A> + ValidPinMode<B>,
+{
+    _sm: StateMachine<(P, SM), Running>,
+    rx: Rx<(P, SM)>,
+    _a: Pin<A, Function<P>>,
+    _b: Pin<B, Function<P>>,
+    start: u32,
+}
+
+impl<P, SM, A, B> PioBackend<P, SM, A, B>
+where
+    P: PIOExt + FunctionConfig,
+    SM: StateMachineIndex,
+    A: PinId,
+    B: PinId,
+    Function<P>: ValidPinMode<A> + ValidPinMode<B>,
+{
+    /// Start counting from the current state of the pins.
+    ///
+    /// `system_clock` is the frequency the PIO is clocked from.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `b` isn't the pin after `a`.
+    pub fn new(
+        a: Pin<A, PullUpInput>,
+        b: Pin<B, PullUpInput>,
+        pio: &mut PIO<P>,
+        sm: UninitStateMachine<(P, SM)>,
+        system_clock: HertzU32,
+    ) -> Result<Self, InstallError> {
+        assert_eq!(A::DYN.num + 1, B::DYN.num, "B must be on the pin after A");
+
+        // The state machine keeps the count in Y. Each sample of the pins is
+        // appended to the previous one, and the 4 bits jump into the table
+        // at the start of the program, which updates Y for the transition.
+        // The count is pushed all the time, and dropped while the FIFO is
+        // full.
+        let program = pio_proc::pio_asm!(
+            ".origin 0",
+            "    jmp update",    // 00 -> 00
+            "    jmp increment", // 00 -> 01
+            "    jmp decrement", // 00 -> 10
+            "    jmp update",    // 00 -> 11, invalid
+            "    jmp decrement", // 01 -> 00
+            "    jmp update",    // 01 -> 01
+            "    jmp update",    // 01 -> 10, invalid
+            "    jmp increment", // 01 -> 11
+            "    jmp increment", // 10 -> 00
+            "    jmp update",    // 10 -> 01, invalid
+            "    jmp update",    // 10 -> 10
+            "    jmp decrement", // 10 -> 11
+            "    jmp update",    // 11 -> 00, invalid
+            "    jmp decrement", // 11 -> 01
+            "    jmp increment", // 11 -> 10
+            "    jmp update",    // 11 -> 11
+            "decrement:",
+            "    jmp y--, update",
+            ".wrap_target",
+            "update:",
+            "    mov isr, y",
+            "    push noblock",
+            "    out isr, 2",
+            "    in pins, 2",
+            "    mov osr, isr",
+            "    mov pc, isr",
+            "increment:",
+            "    mov y, ~y",
+            "    jmp y--, increment_cont",
+            "increment_cont:",
+            "    mov y, ~y",
+            ".wrap",
+        )
+        .program;
+        let installed = pio.install(&program)?;
+
+        let a = a.into_mode();
+        let b = b.into_mode();
+        let divisor = system_clock.to_Hz() as f32 / PIO_FREQ_HZ as f32;
+        let (mut sm, rx, _) = PIOBuilder::from_program(installed)
+            .in_pin_base(A::DYN.num)
+            .in_shift_direction(ShiftDirection::Left)
+            .out_shift_direction(ShiftDirection::Right)
+            .clock_divisor(divisor)
+            .build(sm);
+        sm.set_pindirs([(A::DYN.num, PinDir::Input), (B::DYN.num, PinDir::Input)]);
+
+        let mut backend = PioBackend {
+            _sm: sm.start(),
+            rx,
+            _a: a,
+            _b: b,
+            start: 0,
+        };
+        // Y isn't cleared when the state machine starts, so count from the
+        // first value pushed
+        backend.start = backend.read_count();
+        Ok(backend)
+    }
+
+    /// The latest count pushed by the state machine.
+    fn read_count(&mut self) -> u32 {
+        // Drop the counts queued up in the FIFO, which may be stale, and wait
+        // for a fresh one
+        while self.rx.read().is_some() {}
+        loop {
+            if let Some(count) = self.rx.read() {
+                return count;
+            }
+        }
+    }
+}
+
+impl<P, SM, A, B> EncoderBackend for PioBackend<P, SM, A, B>
+where
+    P: PIOExt + FunctionConfig,
+    SM: StateMachineIndex,
+    A: PinId,
+    B: PinId,
+    Function<P>: ValidPinMode<A> + ValidPinMode<B>,
+{
+    fn edges(&mut self) -> i32 {
+        self.read_count().wrapping_sub(self.start) as i32
+    }
+}
+
+/// A rotary encoder with detents.
+///
+/// The position only changes once the count of edges reaches the next
+/// detent, so it doesn't flicker while the knob sits between two of them.
+/// The encoder is expected to rest in a detent when the backend is created.
+pub struct Encoder<E> {
+    backend: E,
+    edges_per_detent: i32,
+    edges: i32,
+    position: i32,
+    last_read: i32,
+}
+
+impl<E: EncoderBackend> Encoder<E> {
+    /// An encoder with `edges_per_detent` edges from one detent to the next,
+    /// usually 4, 2 or 1. A value of 0 counts as 1.
+    pub fn new(backend: E, edges_per_detent: u8) -> Self {
+        Encoder {
+            backend,
+            edges_per_detent: i32::from(edges_per_detent.max(1)),
+            edges: 0,
+            position: 0,
+            last_read: 0,
+        }
+    }
+
+    /// Read the backend, and return the position in detents.
+    pub fn update(&mut self) -> i32 {
+        self.edges = self.backend.edges();
+        if self.edges.rem_euclid(self.edges_per_detent) == 0 {
+            self.position = self.edges.div_euclid(self.edges_per_detent);
+        }
+        self.position
+    }
+
+    /// The position in detents, as of the last update.
+    pub fn position(&self) -> i32 {
+        self.position
+    }
+
+    /// Edges counted, as of the last update.
+    pub fn edges(&self) -> i32 {
+        self.edges
+    }
+
+    /// Update the position, and return how many detents it moved by since
+    /// the last call to `delta`.
+    pub fn delta(&mut self) -> i32 {
+        let position = self.update();
+        let delta = position.wrapping_sub(self.last_read);
+        self.last_read = position;
+        delta
+    }
+
+    pub fn backend(&self) -> &E {
+        &self.backend
+    }
+
+    pub fn backend_mut(&mut self) -> &mut E {
+        &mut self.backend
+    }
+
+    /// Release the backend.
+    pub fn free(self) -> E {
+        self.backend
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ENCODER_EDGES_PER_DETENT;
+
+    /// The states of the signals along the Gray code, with A leading B.
+    const FORWARD: [u8; 4] = [0b00, 0b01, 0b11, 0b10];
+
+    /// A backend whose edges are set by the test.
+    struct Edges(i32);
+
+    impl EncoderBackend for Edges {
+        fn edges(&mut self) -> i32 {
+            self.0
+        }
+    }
+
+    /// Move `encoder` through each count of `edges`, and return the
+    /// positions after each update.
+    fn positions<const N: usize>(encoder: &mut Encoder<Edges>, edges: [i32; N]) -> [i32; N] {
+        let mut positions = [0; N];
+        for (position, edges) in positions.iter_mut().zip(edges.iter()) {
+            encoder.backend_mut().0 = *edges;
+            *position = encoder.update();
+        }
+        positions
+    }
+
+    #[test]
+    fn forward_gray_code() {
+        for step in 0..4 {
+            let from = FORWARD[step];
+            let to = FORWARD[(step + 1) % 4];
+            assert_eq!(Transition::between(from, to), Transition::Forward);
+        }
+    }
+
+    #[test]
+    fn backward_gray_code() {
+        for step in 0..4 {
+            let from = FORWARD[(step + 1) % 4];
+            let to = FORWARD[step];
+            assert_eq!(Transition::between(from, to), Transition::Backward);
+        }
+    }
+
+    #[test]
+    fn no_change() {
+        for state in 0..4 {
+            assert_eq!(Transition::between(state, state), Transition::None);
+        }
+    }
+
+    #[test]
+    fn double_steps_are_invalid() {
+        for step in 0..4 {
+            let from = FORWARD[step];
+            let to = FORWARD[(step + 2) % 4];
+            assert_eq!(Transition::between(from, to), Transition::Invalid);
+        }
+    }
+
+    #[test]
+    fn only_the_two_low_bits_count() {
+        assert_eq!(Transition::between(0b100, 0b1001), Transition::Forward);
+    }
+
+    #[test]
+    fn decoder_counts_both_ways() {
+        let mut decoder = QuadratureDecoder::new(0b00);
+        for &state in FORWARD[1..].iter().chain(FORWARD.iter()) {
+            assert_eq!(decoder.update(state), Transition::Forward);
+        }
+        assert_eq!(decoder.edges(), 7);
+        assert_eq!(decoder.state(), 0b10);
+        for &state in [0b11, 0b01, 0b00, 0b10].iter() {
+            assert_eq!(decoder.update(state), Transition::Backward);
+        }
+        assert_eq!(decoder.edges(), 3);
+        assert_eq!(decoder.invalid_transitions(), 0);
+    }
+
+    #[test]
+    fn decoder_skips_invalid_transitions() {
+        let mut decoder = QuadratureDecoder::new(0b00);
+        assert_eq!(decoder.update(0b11), Transition::Invalid);
+        assert_eq!(decoder.edges(), 0);
+        assert_eq!(decoder.invalid_transitions(), 1);
+        // Decoding goes on from the new state
+        assert_eq!(decoder.state(), 0b11);
+        assert_eq!(decoder.update(0b10), Transition::Forward);
+        assert_eq!(decoder.edges(), 1);
+    }
+
+    #[test]
+    fn partial_detents_keep_the_position() {
+        let mut encoder = Encoder::new(Edges(0), ENCODER_EDGES_PER_DETENT);
+        assert_eq!(positions(&mut encoder, [1, 2, 3, 4]), [0, 0, 0, 1]);
+        let mut encoder = Encoder::new(Edges(0), ENCODER_EDGES_PER_DETENT);
+        assert_eq!(positions(&mut encoder, [-1, -2, -3, -4]), [0, 0, 0, -1]);
+        assert_eq!(encoder.edges(), -4);
+        assert_eq!(encoder.position(), -1);
+    }
+
+    #[test]
+    fn reversing_mid_detent() {
+        let mut encoder = Encoder::new(Edges(0), ENCODER_EDGES_PER_DETENT);
+        // Half way to the next detent and back
+        assert_eq!(positions(&mut encoder, [1, 2, 1, 0]), [0, 0, 0, 0]);
+        // Past a detent, then back before the next one
+        assert_eq!(positions(&mut encoder, [4, 5, 6, 5, 4]), [1, 1, 1, 1, 1]);
+        // And all the way back
+        assert_eq!(positions(&mut encoder, [3, 2, 1, 0]), [1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn delta_counts_detents_between_calls() {
+        let mut encoder = Encoder::new(Edges(0), ENCODER_EDGES_PER_DETENT);
+        assert_eq!(encoder.delta(), 0);
+        encoder.backend_mut().0 = 9;
+        assert_eq!(encoder.delta(), 0);
+        encoder.backend_mut().0 = 8;
+        assert_eq!(encoder.delta(), 2);
+        assert_eq!(encoder.delta(), 0);
+        encoder.backend_mut().0 = -4;
+        assert_eq!(encoder.delta(), -3);
+    }
+
+    #[test]
+    fn zero_edges_per_detent_counts_as_one() {
+        let mut encoder = Encoder::new(Edges(0), 0);
+        assert_eq!(positions(&mut encoder, [1, 2, -1]), [1, 2, -1]);
+    }
+}
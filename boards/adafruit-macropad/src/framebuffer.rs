@@ -0,0 +1,324 @@
+//! The contents of the OLED display, kept in RAM.
+//!
+//! [`Framebuffer`] holds one bit per pixel, laid out in pages of 8 rows the
+//! way the SH1106 controller expects them, and remembers which pages changed
+//! since they were last sent. It doesn't touch any hardware, and implements
+//! [`DrawTarget`](embedded_graphics::draw_target::DrawTarget) so anything from
+//! `embedded-graphics` can be drawn into it.
+
+use core::convert::Infallible;
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Point, Size},
+    pixelcolor::BinaryColor,
+    Pixel,
+};
+
+/// Width of the display in pixels, with the keys below it.
+pub const WIDTH: u32 = 128;
+/// Height of the display in pixels.
+pub const HEIGHT: u32 = 64;
+/// Number of pages of 8 rows.
+pub const PAGES: u8 = (HEIGHT / 8) as u8;
+
+/// How far the picture is turned clockwise, from the display held with the
+/// keys below it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rotation {
+    /// 128×64, with the keys below.
+    Rotate0,
+    /// 64×128, with the keys on the right.
+    Rotate90,
+    /// 128×64, with the keys above.
+    Rotate180,
+    /// 64×128, with the keys on the left.
+    Rotate270,
+}
+
+/// A 128×64 monochrome image.
+///
+/// [`BinaryColor::On`] is a lit pixel. Pixels are stored in pages of 8 rows
+/// from the top, each page column by column from the left, with the top row
+/// in the least significant bit.
+///
+/// Coordinates are in the orientation set by [`Framebuffer::set_rotation`],
+/// and the size of the image follows it.
+#[derive(Clone)]
+pub struct Framebuffer {
+    pages: [[u8; WIDTH as usize]; PAGES as usize],
+    dirty: u8,
+    rotation: Rotation,
+}
+
+impl Framebuffer {
+    /// A blank image, with every page dirty so all of it gets sent at first.
+    pub const fn new() -> Self {
+        Framebuffer {
+            pages: [[0; WIDTH as usize]; PAGES as usize],
+            dirty: 0xFF,
+            rotation: Rotation::Rotate0,
+        }
+    }
+
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    /// Draw in the orientation `rotation` from now on.
+    ///
+    /// Pixels already drawn stay where they are on the display, so clear the
+    /// image and draw it again after changing the rotation.
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.rotation = rotation;
+    }
+
+    /// The color of the pixel at (`x`, `y`), or `None` outside the image.
+    pub fn pixel(&self, x: u32, y: u32) -> Option<BinaryColor> {
+        let (page, column, mask) = self.locate(x, y)?;
+        Some(BinaryColor::from(self.pages[page][column] & mask != 0))
+    }
+
+    /// Set the pixel at (`x`, `y`) to `color`, marking its page dirty if it
+    /// changes. Pixels outside the image are ignored.
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: BinaryColor) {
+        if let Some((page, column, mask)) = self.locate(x, y) {
+            let byte = &mut self.pages[page][column];
+            let old = *byte;
+            match color {
+                BinaryColor::On => *byte |= mask,
+                BinaryColor::Off => *byte &= !mask,
+            }
+            if *byte != old {
+                self.dirty |= 1 << page;
+            }
+        }
+    }
+
+    /// The raw contents of `page`, from the left of the display held with the
+    /// keys below it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `page` is not below [`PAGES`].
+    pub fn page(&self, page: u8) -> &[u8; WIDTH as usize] {
+        &self.pages[usize::from(page)]
+    }
+
+    /// The pages changed since they were last marked clean, one bit per
+    /// page.
+    pub fn dirty_pages(&self) -> u8 {
+        self.dirty
+    }
+
+    /// Whether any page changed since it was last marked clean.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty != 0
+    }
+
+    /// Mark `page` as matching the display.
+    pub fn mark_clean(&mut self, page: u8) {
+        self.dirty &= !(1 << page);
+    }
+
+    /// Mark every page as changed, so all of them get sent again.
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty = 0xFF;
+    }
+
+    /// Page, column and bit mask of the pixel at (`x`, `y`) in the current
+    /// orientation.
+    fn locate(&self, x: u32, y: u32) -> Option<(usize, usize, u8)> {
+        let size = self.size();
+        if x >= size.width || y >= size.height {
+            return None;
+        }
+        let (x, y) = match self.rotation {
+            Rotation::Rotate0 => (x, y),
+            Rotation::Rotate90 => (WIDTH - 1 - y, x),
+            Rotation::Rotate180 => (WIDTH - 1 - x, HEIGHT - 1 - y),
+            Rotation::Rotate270 => (y, HEIGHT - 1 - x),
+        };
+        Some((y as usize / 8, x as usize, 1 << (y % 8)))
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        match self.rotation {
+            Rotation::Rotate0 | Rotation::Rotate180 => Size::new(WIDTH, HEIGHT),
+            Rotation::Rotate90 | Rotation::Rotate270 => Size::new(HEIGHT, WIDTH),
+        }
+    }
+}
+
+impl DrawTarget for Framebuffer {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(Point { x, y }, color) in pixels {
+            // Negative coordinates wrap to large ones, which are ignored
+            self.set_pixel(x as u32, y as u32, color);
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        let fill = match color {
+            BinaryColor::On => 0xFF,
+            BinaryColor::Off => 0x00,
+        };
+        for (index, page) in self.pages.iter_mut().enumerate() {
+            if page.iter().any(|&byte| byte != fill) {
+                *page = [fill; WIDTH as usize];
+                self.dirty |= 1 << index;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The page, column and bit mask of the only lit pixel, as stored.
+    fn lit(framebuffer: &Framebuffer) -> Option<(u8, usize, u8)> {
+        let mut found = None;
+        for page in 0..PAGES {
+            for (column, &byte) in framebuffer.page(page).iter().enumerate() {
+                if byte != 0 {
+                    assert!(found.is_none() && byte.is_power_of_two());
+                    found = Some((page, column, byte));
+                }
+            }
+        }
+        found
+    }
+
+    /// Where lighting (`x`, `y`) in `rotation` lands.
+    fn light(rotation: Rotation, x: u32, y: u32) -> Option<(u8, usize, u8)> {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.set_rotation(rotation);
+        framebuffer.set_pixel(x, y, BinaryColor::On);
+        assert_eq!(framebuffer.pixel(x, y), Some(BinaryColor::On));
+        lit(&framebuffer)
+    }
+
+    #[test]
+    fn rotate0_is_the_layout_of_the_display() {
+        assert_eq!(light(Rotation::Rotate0, 0, 0), Some((0, 0, 1)));
+        assert_eq!(light(Rotation::Rotate0, 10, 20), Some((2, 10, 1 << 4)));
+        assert_eq!(light(Rotation::Rotate0, 127, 63), Some((7, 127, 1 << 7)));
+    }
+
+    #[test]
+    fn rotate90() {
+        // (x, y) lands on (WIDTH - 1 - y, x)
+        assert_eq!(light(Rotation::Rotate90, 0, 0), Some((0, 127, 1)));
+        assert_eq!(light(Rotation::Rotate90, 10, 20), Some((1, 107, 1 << 2)));
+        assert_eq!(light(Rotation::Rotate90, 63, 127), Some((7, 0, 1 << 7)));
+    }
+
+    #[test]
+    fn rotate180() {
+        assert_eq!(light(Rotation::Rotate180, 0, 0), Some((7, 127, 1 << 7)));
+        assert_eq!(light(Rotation::Rotate180, 10, 20), Some((5, 117, 1 << 3)));
+    }
+
+    #[test]
+    fn rotate270() {
+        // (x, y) lands on (y, HEIGHT - 1 - x)
+        assert_eq!(light(Rotation::Rotate270, 0, 0), Some((7, 0, 1 << 7)));
+        assert_eq!(light(Rotation::Rotate270, 10, 20), Some((6, 20, 1 << 5)));
+        assert_eq!(light(Rotation::Rotate270, 63, 127), Some((0, 127, 1)));
+    }
+
+    #[test]
+    fn size_follows_the_rotation() {
+        let mut framebuffer = Framebuffer::new();
+        assert_eq!(framebuffer.size(), Size::new(128, 64));
+        framebuffer.set_rotation(Rotation::Rotate90);
+        assert_eq!(framebuffer.size(), Size::new(64, 128));
+        framebuffer.set_rotation(Rotation::Rotate180);
+        assert_eq!(framebuffer.size(), Size::new(128, 64));
+        framebuffer.set_rotation(Rotation::Rotate270);
+        assert_eq!(framebuffer.size(), Size::new(64, 128));
+    }
+
+    #[test]
+    fn pixels_outside_are_ignored() {
+        for (rotation, x, y) in [
+            (Rotation::Rotate0, WIDTH, 0),
+            (Rotation::Rotate0, 0, HEIGHT),
+            (Rotation::Rotate90, HEIGHT, 0),
+            (Rotation::Rotate90, 0, WIDTH),
+            (Rotation::Rotate270, HEIGHT, 0),
+            (Rotation::Rotate270, 0, WIDTH),
+        ] {
+            let mut framebuffer = Framebuffer::new();
+            framebuffer.set_rotation(rotation);
+            framebuffer.set_pixel(x, y, BinaryColor::On);
+            assert_eq!(framebuffer.pixel(x, y), None);
+            assert_eq!(lit(&framebuffer), None);
+        }
+
+        let mut framebuffer = Framebuffer::new();
+        let pixels = [
+            Pixel(Point::new(-1, 0), BinaryColor::On),
+            Pixel(Point::new(0, -1), BinaryColor::On),
+        ];
+        framebuffer.draw_iter(pixels.iter().copied()).unwrap();
+        assert_eq!(lit(&framebuffer), None);
+    }
+
+    #[test]
+    fn all_pages_start_dirty() {
+        let framebuffer = Framebuffer::new();
+        assert_eq!(framebuffer.dirty_pages(), 0xFF);
+        assert!(framebuffer.is_dirty());
+    }
+
+    #[test]
+    fn flushed_pages_are_clean() {
+        let mut framebuffer = Framebuffer::new();
+        for page in 0..PAGES {
+            framebuffer.mark_clean(page);
+        }
+        assert_eq!(framebuffer.dirty_pages(), 0);
+        assert!(!framebuffer.is_dirty());
+
+        framebuffer.set_pixel(5, 20, BinaryColor::On);
+        framebuffer.set_pixel(6, 63, BinaryColor::On);
+        assert_eq!(framebuffer.dirty_pages(), (1 << 2) | (1 << 7));
+        framebuffer.mark_clean(2);
+        assert_eq!(framebuffer.dirty_pages(), 1 << 7);
+
+        framebuffer.mark_all_dirty();
+        assert_eq!(framebuffer.dirty_pages(), 0xFF);
+    }
+
+    #[test]
+    fn unchanged_pixels_keep_pages_clean() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.set_pixel(5, 20, BinaryColor::On);
+        for page in 0..PAGES {
+            framebuffer.mark_clean(page);
+        }
+        framebuffer.set_pixel(5, 20, BinaryColor::On);
+        framebuffer.set_pixel(5, 30, BinaryColor::Off);
+        framebuffer.clear(BinaryColor::Off).unwrap();
+        // Only the page that had a lit pixel changed
+        assert_eq!(framebuffer.dirty_pages(), 1 << 2);
+    }
+}
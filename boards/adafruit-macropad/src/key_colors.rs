@@ -0,0 +1,393 @@
+//! Colors of the LEDs under the keys, and how they react to presses.
+//!
+//! Each of the 12 keys has an RGB LED underneath it, chained on the
+//! `neopixel` pin in the same order as the keys: LED `n` lights key `n`, as
+//! numbered in [`keypad`](crate::keypad), so [`led_index`] and [`led_at`]
+//! only check their arguments. The encoder button has no LED.
+//!
+//! [`KeyColors`] keeps the color of each key, taken from the layers of a
+//! [`ColorSchemes`] or set one by one, and can flash keys while they are held
+//! and fade them back afterwards, driven by [`KeyColors::tick`]. It works out
+//! the colors to send to the LEDs, scaled by a brightness, but doesn't touch
+//! any hardware.
+
+use led_effects::color::{blend, scale};
+use smart_leds::RGB8;
+
+use crate::debounce::KeyEvent;
+use crate::keypad::{key_at, NUM_KEYS};
+
+/// Number of LEDs under the keys.
+pub const NUM_LEDS: u8 = NUM_KEYS;
+
+const OFF: RGB8 = RGB8 { r: 0, g: 0, b: 0 };
+
+/// The index in the chain of the LED under `key`, or `None` for the encoder
+/// button.
+pub fn led_index(key: u8) -> Option<u8> {
+    if key < NUM_KEYS {
+        Some(key)
+    } else {
+        None
+    }
+}
+
+/// The index in the chain of the LED under the key at `row` and `column` of
+/// the grid, or `None` outside of it.
+pub fn led_at(row: u8, column: u8) -> Option<u8> {
+    key_at(row, column).and_then(led_index)
+}
+
+/// Colors of the keys, on a number of layers.
+pub trait ColorSchemes {
+    /// The colors of the keys on `layer`, or `None` past the last layer.
+    fn colors(&self, layer: u8) -> Option<[RGB8; NUM_LEDS as usize]>;
+}
+
+impl ColorSchemes for [[RGB8; NUM_LEDS as usize]] {
+    fn colors(&self, layer: u8) -> Option<[RGB8; NUM_LEDS as usize]> {
+        self.get(usize::from(layer)).copied()
+    }
+}
+
+impl<const N: usize> ColorSchemes for [[RGB8; NUM_LEDS as usize]; N] {
+    fn colors(&self, layer: u8) -> Option<[RGB8; NUM_LEDS as usize]> {
+        self[..].colors(layer)
+    }
+}
+
+impl<S: ColorSchemes + ?Sized> ColorSchemes for &S {
+    fn colors(&self, layer: u8) -> Option<[RGB8; NUM_LEDS as usize]> {
+        (**self).colors(layer)
+    }
+}
+
+/// Lights a key in `color` while it is held, then fades it back to its own
+/// color over `fade_ms`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Flash {
+    pub color: RGB8,
+    pub fade_ms: u32,
+}
+
+/// The colors of the LEDs under the keys.
+#[derive(Clone, Debug)]
+pub struct KeyColors {
+    colors: [RGB8; NUM_LEDS as usize],
+    flash: Option<Flash>,
+    /// Keys held down, one bit per key.
+    held: u16,
+    /// Time left until each key is back to its own color.
+    fade_left_ms: [u32; NUM_LEDS as usize],
+    brightness: u8,
+}
+
+impl KeyColors {
+    /// All keys off, without flashes, at full brightness.
+    pub const fn new() -> Self {
+        KeyColors {
+            colors: [OFF; NUM_LEDS as usize],
+            flash: None,
+            held: 0,
+            fade_left_ms: [0; NUM_LEDS as usize],
+            brightness: 255,
+        }
+    }
+
+    /// Set the color of the LED under `key`. The encoder button is ignored.
+    pub fn set_key(&mut self, key: u8, color: RGB8) {
+        if let Some(led) = led_index(key) {
+            self.colors[usize::from(led)] = color;
+        }
+    }
+
+    /// Set the color of the LED under the key at `row` and `column` of the
+    /// grid. Positions outside of the grid are ignored.
+    pub fn set_at(&mut self, row: u8, column: u8, color: RGB8) {
+        if let Some(led) = led_at(row, column) {
+            self.colors[usize::from(led)] = color;
+        }
+    }
+
+    /// Set the color of all keys.
+    pub fn set_all(&mut self, color: RGB8) {
+        self.colors = [color; NUM_LEDS as usize];
+    }
+
+    /// Set the color of each key, in the order of the keys.
+    pub fn set_colors(&mut self, colors: [RGB8; NUM_LEDS as usize]) {
+        self.colors = colors;
+    }
+
+    /// Take the colors of the keys from `layer` of `schemes`, and return
+    /// whether it has that layer. The colors are left alone if it doesn't.
+    pub fn set_layer<S: ColorSchemes + ?Sized>(&mut self, schemes: &S, layer: u8) -> bool {
+        match schemes.colors(layer) {
+            Some(colors) => {
+                self.colors = colors;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The color of the LED under `key`, before flashes and brightness are
+    /// applied, or `None` for the encoder button.
+    pub fn key(&self, key: u8) -> Option<RGB8> {
+        led_index(key).map(|led| self.colors[usize::from(led)])
+    }
+
+    /// The colors of the keys, before flashes and brightness are applied.
+    pub fn colors(&self) -> &[RGB8; NUM_LEDS as usize] {
+        &self.colors
+    }
+
+    /// Flash keys as they are pressed, or stop flashing them with `None`.
+    /// Stopping ends the flashes under way too.
+    pub fn set_flash(&mut self, flash: Option<Flash>) {
+        if flash.is_none() {
+            self.fade_left_ms = [0; NUM_LEDS as usize];
+        }
+        self.flash = flash;
+    }
+
+    pub fn flash(&self) -> Option<Flash> {
+        self.flash
+    }
+
+    /// Scale all colors by `brightness` / 255 in [`KeyColors::frame`].
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Flash `key` until it is released. The encoder button is ignored.
+    pub fn press(&mut self, key: u8) {
+        if let Some(led) = led_index(key) {
+            self.held |= 1 << led;
+            self.fade_left_ms[usize::from(led)] = 0;
+        }
+    }
+
+    /// Start fading `key` back to its own color.
+    pub fn release(&mut self, key: u8) {
+        if let Some(led) = led_index(key) {
+            if self.held & 1 << led != 0 {
+                self.held &= !(1 << led);
+                self.fade_left_ms[usize::from(led)] = self.flash.map_or(0, |flash| flash.fade_ms);
+            }
+        }
+    }
+
+    /// Follow the keys pressed and released in `events`, such as the ones
+    /// from [`Keypad::poll`](crate::Keypad::poll).
+    pub fn update(&mut self, events: impl IntoIterator<Item = KeyEvent>) {
+        for event in events {
+            match event {
+                KeyEvent::Pressed(key) => self.press(key),
+                KeyEvent::Released(key) => self.release(key),
+            }
+        }
+    }
+
+    /// Move the fades along by `elapsed_ms`.
+    pub fn tick(&mut self, elapsed_ms: u32) {
+        for fade_left_ms in self.fade_left_ms.iter_mut() {
+            *fade_left_ms = fade_left_ms.saturating_sub(elapsed_ms);
+        }
+    }
+
+    /// Whether a key is flashing or fading, so the colors change as time
+    /// passes.
+    pub fn is_animating(&self) -> bool {
+        self.flash.is_some() && (self.held != 0 || self.fade_left_ms.iter().any(|&ms| ms > 0))
+    }
+
+    /// The colors to send to the LEDs, in the order of the chain.
+    pub fn frame(&self) -> [RGB8; NUM_LEDS as usize] {
+        let mut frame = self.colors;
+        for (led, color) in frame.iter_mut().enumerate() {
+            let flashed = match self.flash {
+                Some(flash) => blend(*color, flash.color, self.flash_amount(led, flash)),
+                None => *color,
+            };
+            *color = scale(flashed, self.brightness);
+        }
+        frame
+    }
+
+    /// How much of the flash color shows on `led`, from 0 to 255.
+    fn flash_amount(&self, led: usize, flash: Flash) -> u8 {
+        if self.held & 1 << led != 0 {
+            255
+        } else if flash.fade_ms == 0 {
+            0
+        } else {
+            let fade_left_ms = u64::from(self.fade_left_ms[led].min(flash.fade_ms));
+            (fade_left_ms * 255 / u64::from(flash.fade_ms)) as u8
+        }
+    }
+}
+
+impl Default for KeyColors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keypad::{COLUMNS, ROWS};
+
+    #[test]
+    fn each_key_has_its_own_led() {
+        for key in 0..NUM_KEYS {
+            assert_eq!(led_index(key), Some(key));
+        }
+    }
+
+    #[test]
+    fn leds_follow_the_grid() {
+        let mut seen = [false; NUM_LEDS as usize];
+        for row in 0..ROWS {
+            for column in 0..COLUMNS {
+                // Keys are numbered left to right, then top to bottom
+                let led = led_at(row, column).unwrap();
+                assert_eq!(led, row * COLUMNS + column);
+                assert!(!seen[usize::from(led)], "{}", led);
+                seen[usize::from(led)] = true;
+            }
+        }
+        assert!(seen.iter().all(|&seen| seen));
+    }
+
+    #[test]
+    fn no_led_out_of_range() {
+        assert_eq!(led_index(NUM_KEYS), None);
+        assert_eq!(led_index(u8::MAX), None);
+        assert_eq!(led_at(ROWS, 0), None);
+        assert_eq!(led_at(0, COLUMNS), None);
+        assert_eq!(led_at(u8::MAX, u8::MAX), None);
+    }
+
+    const BLUE: RGB8 = RGB8 { r: 0, g: 0, b: 200 };
+    const ORANGE: RGB8 = RGB8 {
+        r: 200,
+        g: 100,
+        b: 0,
+    };
+    const FLASH: Flash = Flash {
+        color: ORANGE,
+        fade_ms: 100,
+    };
+
+    fn flashing() -> KeyColors {
+        let mut colors = KeyColors::new();
+        colors.set_flash(Some(FLASH));
+        colors
+    }
+
+    #[test]
+    fn held_keys_are_the_flash_color() {
+        let mut colors = flashing();
+        colors.set_all(BLUE);
+        assert!(!colors.is_animating());
+        colors.update([KeyEvent::Pressed(3), KeyEvent::Pressed(NUM_KEYS)]);
+        assert!(colors.is_animating());
+
+        let frame = colors.frame();
+        assert_eq!(frame[3], ORANGE);
+        for (led, &color) in frame.iter().enumerate() {
+            if led != 3 {
+                assert_eq!(color, BLUE, "{}", led);
+            }
+        }
+        // Held keys don't fade
+        colors.tick(1000);
+        assert_eq!(colors.frame()[3], ORANGE);
+        assert_eq!(colors.key(3), Some(BLUE));
+    }
+
+    #[test]
+    fn released_keys_fade_back() {
+        let mut colors = flashing();
+        colors.press(3);
+        colors.release(3);
+        assert_eq!(colors.frame()[3], ORANGE);
+
+        // Half way, 127 / 255 of the flash color is left
+        colors.tick(50);
+        assert_eq!(colors.frame()[3], RGB8::new(100, 50, 0));
+        assert!(colors.is_animating());
+
+        colors.tick(49);
+        assert_eq!(colors.frame()[3], RGB8::new(2, 1, 0));
+        colors.tick(1);
+        assert_eq!(colors.frame()[3], OFF);
+        assert!(!colors.is_animating());
+    }
+
+    #[test]
+    fn pressing_again_restarts_the_flash() {
+        let mut colors = flashing();
+        colors.press(3);
+        colors.release(3);
+        colors.tick(50);
+        colors.press(3);
+        assert_eq!(colors.frame()[3], ORANGE);
+        // Releasing a key that isn't held doesn't start a fade
+        colors.release(4);
+        assert_eq!(colors.frame()[4], OFF);
+    }
+
+    #[test]
+    fn stopping_flashes_cancels_fades() {
+        let mut colors = flashing();
+        colors.set_all(BLUE);
+        colors.press(3);
+        colors.release(3);
+        colors.set_flash(None);
+        assert_eq!(colors.frame(), [BLUE; NUM_LEDS as usize]);
+        assert!(!colors.is_animating());
+
+        // The fade doesn't come back with the next flash
+        colors.set_flash(Some(FLASH));
+        assert_eq!(colors.frame(), [BLUE; NUM_LEDS as usize]);
+        assert!(!colors.is_animating());
+    }
+
+    #[test]
+    fn layers() {
+        let schemes = [[BLUE; NUM_LEDS as usize], [ORANGE; NUM_LEDS as usize]];
+        let mut colors = KeyColors::new();
+        assert!(colors.set_layer(&schemes, 1));
+        assert_eq!(colors.colors(), &[ORANGE; NUM_LEDS as usize]);
+        assert!(colors.set_layer(&schemes[..], 0));
+        assert_eq!(colors.colors(), &[BLUE; NUM_LEDS as usize]);
+
+        // A missing layer leaves the colors alone
+        assert!(!colors.set_layer(&schemes, 2));
+        assert_eq!(colors.colors(), &[BLUE; NUM_LEDS as usize]);
+    }
+
+    #[test]
+    fn brightness_scales_the_frame() {
+        let mut colors = flashing();
+        colors.set_all(RGB8::new(255, 100, 0));
+        colors.set_brightness(128);
+        colors.press(0);
+
+        let frame = colors.frame();
+        assert_eq!(frame[0], RGB8::new(100, 50, 0));
+        assert_eq!(frame[1], RGB8::new(128, 50, 0));
+        // The colors themselves are left alone
+        assert_eq!(colors.key(1), Some(RGB8::new(255, 100, 0)));
+
+        colors.set_brightness(0);
+        assert_eq!(colors.frame(), [OFF; NUM_LEDS as usize]);
+    }
+}
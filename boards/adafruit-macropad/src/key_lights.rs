@@ -0,0 +1,82 @@
+//! The RGB LEDs under the keys, on the `neopixel` pin.
+//!
+//! [`KeyLights`] keeps the colors of the keys in a [`KeyColors`], and sends
+//! them to the LEDs through a PIO state machine on [`KeyLights::show`].
+
+use fugit::HertzU32;
+use hal::gpio::bank0::Gpio19;
+use hal::gpio::{Function, FunctionConfig, Pin, ValidPinMode};
+use hal::pio::{PIOExt, StateMachineIndex, UninitStateMachine, PIO};
+use smart_leds::SmartLedsWrite;
+use ws2812_pio::Ws2812Direct;
+
+use crate::debounce::KeyEvent;
+use crate::key_colors::KeyColors;
+
+/// Drives the LEDs under the keys.
+///
+/// The LEDs need about 300µs between two calls to [`KeyLights::show`] to
+/// latch their colors; a show every 10ms or so is plenty for fades.
+pub struct KeyLights<P, SM>
+where
+    P: PIOExt + FunctionConfig,
+    SM: StateMachineIndex,
+    Function<P>: ValidPinMode<Gpio19>,
+{
+    ws: Ws2812Direct<P, SM, Gpio19>,
+    colors: KeyColors,
+}
+
+impl<P, SM> KeyLights<P, SM>
+where
+    P: PIOExt + FunctionConfig,
+    SM: StateMachineIndex,
+    Function<P>: ValidPinMode<Gpio19>,
+{
+    /// Take control of the LEDs, initially off at full brightness.
+    ///
+    /// `clock_freq` is the frequency the PIO is clocked from, which is the
+    /// system clock.
+    pub fn new(
+        neopixel: Pin<Gpio19, Function<P>>,
+        pio: &mut PIO<P>,
+        sm: UninitStateMachine<(P, SM)>,
+        clock_freq: HertzU32,
+    ) -> Self {
+        let mut lights = KeyLights {
+            ws: Ws2812Direct::new(neopixel, pio, sm, clock_freq),
+            colors: KeyColors::new(),
+        };
+        // The LEDs keep their colors through a reset of the microcontroller
+        lights.show();
+        lights
+    }
+
+    /// The colors of the keys, and their flashes.
+    pub fn colors(&self) -> &KeyColors {
+        &self.colors
+    }
+
+    /// Change the colors of the keys, shown on the next
+    /// [`KeyLights::show`].
+    pub fn colors_mut(&mut self) -> &mut KeyColors {
+        &mut self.colors
+    }
+
+    /// Flash the keys pressed and released in `events`, if flashes are
+    /// enabled with [`KeyColors::set_flash`].
+    pub fn update(&mut self, events: impl IntoIterator<Item = KeyEvent>) {
+        self.colors.update(events);
+    }
+
+    /// Move the fades along by `elapsed_ms`.
+    pub fn tick(&mut self, elapsed_ms: u32) {
+        self.colors.tick(elapsed_ms);
+    }
+
+    /// Send the colors to the LEDs.
+    pub fn show(&mut self) {
+        // Writing to the PIO FIFO can't fail
+        let _ = self.ws.write(self.colors.frame().iter().copied());
+    }
+}
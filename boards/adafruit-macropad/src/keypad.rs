@@ -0,0 +1,165 @@
+//! The twelve keys, and the button of the rotary encoder.
+//!
+//! Each key connects its own pin to ground when pressed. [`Keypad`] reads
+//! them all into a bitmask and debounces it, turning scans into
+//! [`KeyEvent`]s.
+//!
+//! Keys are numbered from 0 to 11 along the rows of the 3×4 grid, starting
+//! at the top left with the encoder away from you: `key1` is key 0 and
+//! `key12` is key 11. The encoder button is key [`ENCODER_BUTTON`].
+
+use embedded_hal::digital::v2::InputPin;
+use hal::gpio::bank0::{
+    Gpio0, Gpio1, Gpio10, Gpio11, Gpio12, Gpio2, Gpio3, Gpio4, Gpio5, Gpio6, Gpio7, Gpio8, Gpio9,
+};
+use hal::gpio::{Pin, PinId, PullUpInput};
+
+use crate::debounce::{Debouncer, KeyEvents};
+
+/// Number of keys in the grid.
+pub const NUM_KEYS: u8 = 12;
+/// Number of columns of the grid.
+pub const COLUMNS: u8 = 3;
+/// Number of rows of the grid.
+pub const ROWS: u8 = 4;
+/// Index of the encoder button, after the keys of the grid.
+pub const ENCODER_BUTTON: u8 = NUM_KEYS;
+
+/// Scans a key must read the same for before it changes, when scanning
+/// every millisecond or so.
+pub const DEFAULT_DEBOUNCE_SAMPLES: u8 = 5;
+
+/// The index of the key at `row` and `column` of the grid, or `None` outside
+/// of it.
+pub fn key_at(row: u8, column: u8) -> Option<u8> {
+    if row < ROWS && column < COLUMNS {
+        Some(row * COLUMNS + column)
+    } else {
+        None
+    }
+}
+
+/// The row and column of `key` in the grid, or `None` for the encoder
+/// button.
+pub fn key_position(key: u8) -> Option<(u8, u8)> {
+    if key < NUM_KEYS {
+        Some((key / COLUMNS, key % COLUMNS))
+    } else {
+        None
+    }
+}
+
+/// Reads and debounces the keys and the encoder button.
+pub struct Keypad {
+    keys: KeyPins,
+    button: Pin<Gpio0, PullUpInput>,
+    debouncer: Debouncer,
+}
+
+struct KeyPins {
+    key1: Pin<Gpio1, PullUpInput>,
+    key2: Pin<Gpio2, PullUpInput>,
+    key3: Pin<Gpio3, PullUpInput>,
+    key4: Pin<Gpio4, PullUpInput>,
+    key5: Pin<Gpio5, PullUpInput>,
+    key6: Pin<Gpio6, PullUpInput>,
+    key7: Pin<Gpio7, PullUpInput>,
+    key8: Pin<Gpio8, PullUpInput>,
+    key9: Pin<Gpio9, PullUpInput>,
+    key10: Pin<Gpio10, PullUpInput>,
+    key11: Pin<Gpio11, PullUpInput>,
+    key12: Pin<Gpio12, PullUpInput>,
+}
+
+impl Keypad {
+    /// Set up the pins, with [`DEFAULT_DEBOUNCE_SAMPLES`]. All keys start
+    /// released, so keys held down show up as pressed once debounced.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        key1: Pin<Gpio1, <Gpio1 as PinId>::Reset>,
+        key2: Pin<Gpio2, <Gpio2 as PinId>::Reset>,
+        key3: Pin<Gpio3, <Gpio3 as PinId>::Reset>,
+        key4: Pin<Gpio4, <Gpio4 as PinId>::Reset>,
+        key5: Pin<Gpio5, <Gpio5 as PinId>::Reset>,
+        key6: Pin<Gpio6, <Gpio6 as PinId>::Reset>,
+        key7: Pin<Gpio7, <Gpio7 as PinId>::Reset>,
+        key8: Pin<Gpio8, <Gpio8 as PinId>::Reset>,
+        key9: Pin<Gpio9, <Gpio9 as PinId>::Reset>,
+        key10: Pin<Gpio10, <Gpio10 as PinId>::Reset>,
+        key11: Pin<Gpio11, <Gpio11 as PinId>::Reset>,
+        key12: Pin<Gpio12, <Gpio12 as PinId>::Reset>,
+        button: Pin<Gpio0, <Gpio0 as PinId>::Reset>,
+    ) -> Self {
+        Keypad {
+            keys: KeyPins {
+                key1: key1.into_pull_up_input(),
+                key2: key2.into_pull_up_input(),
+                key3: key3.into_pull_up_input(),
+                key4: key4.into_pull_up_input(),
+                key5: key5.into_pull_up_input(),
+                key6: key6.into_pull_up_input(),
+                key7: key7.into_pull_up_input(),
+                key8: key8.into_pull_up_input(),
+                key9: key9.into_pull_up_input(),
+                key10: key10.into_pull_up_input(),
+                key11: key11.into_pull_up_input(),
+                key12: key12.into_pull_up_input(),
+            },
+            button: button.into_pull_up_input(),
+            debouncer: Debouncer::new(DEFAULT_DEBOUNCE_SAMPLES),
+        }
+    }
+
+    /// Change the number of scans a key must read the same for before it
+    /// changes.
+    pub fn set_debounce_samples(&mut self, samples: u8) {
+        self.debouncer.set_samples(samples);
+    }
+
+    /// Read the keys as they are, with a set bit for each key held down.
+    pub fn scan_raw(&self) -> u16 {
+        // Reading GPIO inputs can't fail
+        let k = &self.keys;
+        let levels = [
+            k.key1.is_low(),
+            k.key2.is_low(),
+            k.key3.is_low(),
+            k.key4.is_low(),
+            k.key5.is_low(),
+            k.key6.is_low(),
+            k.key7.is_low(),
+            k.key8.is_low(),
+            k.key9.is_low(),
+            k.key10.is_low(),
+            k.key11.is_low(),
+            k.key12.is_low(),
+            self.button.is_low(),
+        ];
+        let mut mask = 0;
+        for (key, level) in levels.iter().enumerate() {
+            if let Ok(true) = level {
+                mask |= 1 << key;
+            }
+        }
+        mask
+    }
+
+    /// Scan the keys and debounce them, and get the keys that changed.
+    ///
+    /// Call this regularly, about every millisecond with the default
+    /// debounce.
+    pub fn poll(&mut self) -> KeyEvents {
+        let raw = self.scan_raw();
+        self.debouncer.update(raw)
+    }
+
+    /// The debounced keys held down, with a set bit for each.
+    pub fn pressed(&self) -> u16 {
+        self.debouncer.state()
+    }
+
+    /// Whether `key` is held down, once debounced.
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.debouncer.is_pressed(key)
+    }
+}
@@ -19,6 +19,32 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
 pub use hal::pac;
 
+pub mod config;
+pub use config::Config;
+pub mod debounce;
+pub use debounce::{Debouncer, KeyEvent, KeyEvents};
+pub mod display;
+pub use display::MacropadDisplay;
+pub mod encoder;
+pub use encoder::{Encoder, PioBackend, PollingBackend};
+pub mod framebuffer;
+pub use framebuffer::{Framebuffer, Rotation};
+pub mod key_colors;
+pub use key_colors::KeyColors;
+pub mod key_lights;
+pub use key_lights::KeyLights;
+pub mod keypad;
+pub use keypad::Keypad;
+pub mod macro_keyboard;
+pub use macro_keyboard::MacroKeyboard;
+pub mod rtttl;
+pub use rtttl::Melody;
+pub mod speaker;
+pub use speaker::Speaker;
+pub mod storage;
+pub use storage::FlashSector;
+pub mod usage;
+
 hal::bsp_pins!(
     Gpio0 { name: button },
     Gpio1 { name: key1 },
@@ -46,6 +72,7 @@ hal::bsp_pins!(
         name: encoder_rotb,
         aliases: { PullUpInput: RotaryB }
     },
+    // The 12 RGB LEDs under the keys, see `key_lights`
     Gpio19 { name: neopixel },
     Gpio20 {
         name: sda,
@@ -74,3 +101,10 @@ hal::bsp_pins!(
 );
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+/// Edges between two detents of the rotary encoder.
+pub const ENCODER_EDGES_PER_DETENT: u8 = 4;
+
+/// Sets up the peripherals of the board, such as its
+/// [display](Macropad::display).
+pub struct Macropad;
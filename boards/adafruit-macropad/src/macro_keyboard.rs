@@ -0,0 +1,646 @@
+//! Turn the keys and the encoder into USB HID keyboard reports.
+//!
+//! A [`Keymap`] binds an [`Action`] to each key and to both directions of the
+//! encoder, on each of its layers. [`MacroKeyboard`] takes the key events
+//! from [`Keypad`](crate::Keypad) and the turns of the
+//! [`Encoder`](crate::Encoder), and works out the [`Report`]s to send to the
+//! host, one at a time. Pressing the encoder button moves on to the next
+//! layer. It doesn't touch any hardware.
+//!
+//! Keys bound to keyboard keys or consumer controls hold them for as long as
+//! they are held. Turning the encoder taps its action once per detent, and
+//! text is typed one character after the other, each pressed then released.
+
+use usbd_hid::descriptor::KeyboardReport;
+
+use crate::debounce::KeyEvent;
+use crate::keypad::{ENCODER_BUTTON, NUM_KEYS};
+use crate::usage::{ascii_key, key};
+
+/// Something a [`Keymap`] binds an action to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Input {
+    /// One of the keys of the grid, numbered as in [`keypad`](crate::keypad).
+    Key(u8),
+    /// The encoder turning clockwise.
+    Clockwise,
+    /// The encoder turning counter-clockwise.
+    CounterClockwise,
+}
+
+/// What an [`Input`] does.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action<'a> {
+    None,
+    /// Keyboard keys from [`usage::key`](crate::usage::key), pressed along
+    /// with the [`usage::modifier`](crate::usage::modifier) bits.
+    Keys {
+        modifiers: u8,
+        keys: &'a [u8],
+    },
+    /// A control from [`usage::consumer`](crate::usage::consumer), such as a
+    /// media key.
+    Consumer(u16),
+    /// Characters typed on a US keyboard layout. Those it doesn't have are
+    /// skipped.
+    Text(&'a str),
+    /// Switch to another layer.
+    Layer(u8),
+}
+
+/// The actions of all inputs on one layer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Layer<'a> {
+    pub keys: [Action<'a>; NUM_KEYS as usize],
+    pub clockwise: Action<'a>,
+    pub counter_clockwise: Action<'a>,
+}
+
+impl<'a> Layer<'a> {
+    /// The action bound to `input`.
+    pub fn action(&self, input: Input) -> Action<'a> {
+        match input {
+            Input::Key(key) => self
+                .keys
+                .get(usize::from(key))
+                .copied()
+                .unwrap_or(Action::None),
+            Input::Clockwise => self.clockwise,
+            Input::CounterClockwise => self.counter_clockwise,
+        }
+    }
+}
+
+/// Actions bound to the inputs, on a number of layers.
+pub trait Keymap {
+    fn layer_count(&self) -> u8;
+
+    /// The action bound to `input` on `layer`, which is [`Action::None`] for
+    /// layers past the last.
+    fn action(&self, layer: u8, input: Input) -> Action<'_>;
+}
+
+impl Keymap for [Layer<'_>] {
+    fn layer_count(&self) -> u8 {
+        self.len().min(usize::from(u8::MAX)) as u8
+    }
+
+    fn action(&self, layer: u8, input: Input) -> Action<'_> {
+        match self.get(usize::from(layer)) {
+            Some(layer) => layer.action(input),
+            None => Action::None,
+        }
+    }
+}
+
+impl<const N: usize> Keymap for [Layer<'_>; N] {
+    fn layer_count(&self) -> u8 {
+        self[..].layer_count()
+    }
+
+    fn action(&self, layer: u8, input: Input) -> Action<'_> {
+        self[..].action(layer, input)
+    }
+}
+
+impl<K: Keymap + ?Sized> Keymap for &K {
+    fn layer_count(&self) -> u8 {
+        (**self).layer_count()
+    }
+
+    fn action(&self, layer: u8, input: Input) -> Action<'_> {
+        (**self).action(layer, input)
+    }
+}
+
+/// The modifiers and keys of a keyboard report.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct KeyboardKeys {
+    pub modifiers: u8,
+    /// Up to 6 keys, with unused slots set to 0.
+    pub keys: [u8; 6],
+}
+
+impl KeyboardKeys {
+    /// Add `modifiers` and `keys` to the ones pressed. With more than 6 keys,
+    /// all slots report [`key::ERROR_ROLL_OVER`].
+    pub fn press(&mut self, modifiers: u8, keys: &[u8]) {
+        self.modifiers |= modifiers;
+        for &usage in keys {
+            if usage == 0 || self.keys.contains(&usage) {
+                continue;
+            }
+            match self.keys.iter_mut().find(|slot| **slot == 0) {
+                Some(slot) => *slot = usage,
+                None => self.keys = [key::ERROR_ROLL_OVER; 6],
+            }
+        }
+    }
+}
+
+impl From<KeyboardKeys> for KeyboardReport {
+    fn from(keys: KeyboardKeys) -> Self {
+        KeyboardReport {
+            modifier: keys.modifiers,
+            reserved: 0,
+            leds: 0,
+            keycodes: keys.keys,
+        }
+    }
+}
+
+/// A report to send to the host.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Report {
+    /// For a keyboard interface, such as one described by
+    /// [`KeyboardReport`].
+    Keyboard(KeyboardKeys),
+    /// The consumer control held, or 0 for none, for a consumer control
+    /// interface such as one described by
+    /// [`MediaKeyboardReport`](usbd_hid::descriptor::MediaKeyboardReport).
+    Consumer(u16),
+}
+
+/// Number of taps and texts that can wait to be sent.
+const TAP_QUEUE_SIZE: usize = 8;
+
+/// An action to tap `count` times, as bound to `input` on `layer`.
+#[derive(Clone, Copy)]
+struct Tap {
+    layer: u8,
+    input: Input,
+    count: u16,
+}
+
+impl Tap {
+    const EMPTY: Tap = Tap {
+        layer: 0,
+        input: Input::Key(0),
+        count: 0,
+    };
+}
+
+/// Works out the reports to send from the key events and encoder turns.
+pub struct MacroKeyboard<K> {
+    keymap: K,
+    layer: u8,
+    /// The layer each key was pressed on, while it is held.
+    held: [Option<u8>; NUM_KEYS as usize],
+    /// Taps waiting to be sent, starting at `first_tap`.
+    taps: [Tap; TAP_QUEUE_SIZE],
+    first_tap: usize,
+    queued_taps: usize,
+    /// Byte offset of the character being typed, for a text.
+    offset: usize,
+    /// Whether the step of the tap being sent is pressed or released.
+    tap_pressed: bool,
+    sent_keyboard: KeyboardKeys,
+    sent_consumer: u16,
+}
+
+impl<K: Keymap> MacroKeyboard<K> {
+    /// Start on the first layer of `keymap`, with nothing pressed.
+    pub fn new(keymap: K) -> Self {
+        MacroKeyboard {
+            keymap,
+            layer: 0,
+            held: [None; NUM_KEYS as usize],
+            taps: [Tap::EMPTY; TAP_QUEUE_SIZE],
+            first_tap: 0,
+            queued_taps: 0,
+            offset: 0,
+            tap_pressed: false,
+            sent_keyboard: KeyboardKeys::default(),
+            sent_consumer: 0,
+        }
+    }
+
+    pub fn keymap(&self) -> &K {
+        &self.keymap
+    }
+
+    /// The layer new key presses and turns are looked up on.
+    pub fn layer(&self) -> u8 {
+        self.layer
+    }
+
+    /// Switch to `layer`, if the keymap has it. Keys held keep the action
+    /// they were pressed with.
+    pub fn set_layer(&mut self, layer: u8) {
+        if layer < self.keymap.layer_count() {
+            self.layer = layer;
+        }
+    }
+
+    /// Switch to the next layer, going back to the first after the last.
+    pub fn next_layer(&mut self) {
+        let count = self.keymap.layer_count();
+        if count > 0 {
+            self.layer = (self.layer + 1) % count;
+        }
+    }
+
+    /// Handle a key pressed or released, from
+    /// [`Keypad::poll`](crate::Keypad::poll).
+    pub fn key_event(&mut self, event: KeyEvent) {
+        match event {
+            KeyEvent::Pressed(ENCODER_BUTTON) => self.next_layer(),
+            KeyEvent::Pressed(key) if key < NUM_KEYS => {
+                let input = Input::Key(key);
+                match self.keymap.action(self.layer, input) {
+                    Action::None => {}
+                    Action::Keys { .. } | Action::Consumer(_) => {
+                        self.held[usize::from(key)] = Some(self.layer);
+                    }
+                    Action::Text(_) => self.queue_tap(input, 1),
+                    Action::Layer(layer) => self.set_layer(layer),
+                }
+            }
+            KeyEvent::Released(key) if key < NUM_KEYS => self.held[usize::from(key)] = None,
+            _ => {}
+        }
+    }
+
+    /// Handle the encoder turning by `detents`, from
+    /// [`Encoder::delta`](crate::Encoder::delta). Positive values are
+    /// clockwise.
+    pub fn encoder(&mut self, detents: i32) {
+        let input = match detents {
+            0 => return,
+            d if d > 0 => Input::Clockwise,
+            _ => Input::CounterClockwise,
+        };
+        match self.keymap.action(self.layer, input) {
+            Action::None => {}
+            Action::Layer(layer) => self.set_layer(layer),
+            _ => {
+                let count = detents.unsigned_abs().min(u32::from(u16::MAX)) as u16;
+                self.queue_tap(input, count);
+            }
+        }
+    }
+
+    /// Whether no taps or text are waiting to be sent.
+    pub fn is_idle(&self) -> bool {
+        self.queued_taps == 0
+    }
+
+    /// Release all keys, and drop the taps and text waiting to be sent.
+    pub fn release_all(&mut self) {
+        self.held = [None; NUM_KEYS as usize];
+        self.queued_taps = 0;
+    }
+
+    /// The next report to send, or `None` if the host is up to date.
+    ///
+    /// Send each report before asking for the next one, trying again for as
+    /// long as the endpoint is busy: taps and text move on by one step with
+    /// each report.
+    pub fn next_report(&mut self) -> Option<Report> {
+        loop {
+            let (keyboard, consumer) = self.pressed();
+            if keyboard != self.sent_keyboard {
+                self.sent_keyboard = keyboard;
+                return Some(Report::Keyboard(keyboard));
+            }
+            if consumer != self.sent_consumer {
+                self.sent_consumer = consumer;
+                return Some(Report::Consumer(consumer));
+            }
+            if !self.advance_tap() {
+                return None;
+            }
+        }
+    }
+
+    /// The keys and consumer control the host should see as pressed now.
+    fn pressed(&self) -> (KeyboardKeys, u16) {
+        let mut keyboard = KeyboardKeys::default();
+        let mut consumer = 0;
+        let mut press = |action: Action<'_>, c: Option<char>| match action {
+            Action::Keys { modifiers, keys } => keyboard.press(modifiers, keys),
+            Action::Consumer(usage) if consumer == 0 => consumer = usage,
+            Action::Text(_) => {
+                if let Some((modifiers, key)) = c.and_then(ascii_key) {
+                    keyboard.press(modifiers, &[key]);
+                }
+            }
+            _ => {}
+        };
+
+        // A tapped consumer control goes over the one of a key held
+        if !self.is_idle() && self.tap_pressed {
+            let tap = self.taps[self.first_tap];
+            let action = self.keymap.action(tap.layer, tap.input);
+            let c = match action {
+                Action::Text(text) => text[self.offset..].chars().next(),
+                _ => None,
+            };
+            press(action, c);
+        }
+        for (key, layer) in self.held.iter().enumerate() {
+            if let Some(layer) = *layer {
+                press(self.keymap.action(layer, Input::Key(key as u8)), None);
+            }
+        }
+        (keyboard, consumer)
+    }
+
+    /// Move on to the next step of the taps, and return whether there was
+    /// one.
+    fn advance_tap(&mut self) -> bool {
+        if self.is_idle() {
+            return false;
+        }
+        if self.tap_pressed {
+            self.tap_pressed = false;
+            return true;
+        }
+
+        // Texts take one step per character, other actions a single one
+        let tap = &mut self.taps[self.first_tap];
+        let text_left = match self.keymap.action(tap.layer, tap.input) {
+            Action::Text(text) => {
+                self.offset += text[self.offset..].chars().next().map_or(0, char::len_utf8);
+                self.offset < text.len()
+            }
+            _ => false,
+        };
+        if !text_left {
+            self.offset = 0;
+            tap.count -= 1;
+            if tap.count == 0 {
+                self.first_tap = (self.first_tap + 1) % TAP_QUEUE_SIZE;
+                self.queued_taps -= 1;
+            }
+        }
+        self.tap_pressed = !self.is_idle();
+        self.tap_pressed
+    }
+
+    /// Queue the action of `input` on the current layer to be tapped `count`
+    /// times. It is dropped if the queue is full.
+    fn queue_tap(&mut self, input: Input, count: u16) {
+        let layer = self.layer;
+        if self.is_idle() {
+            self.offset = 0;
+            self.tap_pressed = true;
+        } else {
+            // Repeated turns of the encoder add up with the last tap queued
+            let last = (self.first_tap + self.queued_taps - 1) % TAP_QUEUE_SIZE;
+            let last = &mut self.taps[last];
+            if last.layer == layer && last.input == input {
+                last.count = last.count.saturating_add(count);
+                return;
+            }
+        }
+        if self.queued_taps < TAP_QUEUE_SIZE {
+            let next = (self.first_tap + self.queued_taps) % TAP_QUEUE_SIZE;
+            self.taps[next] = Tap {
+                layer,
+                input,
+                count,
+            };
+            self.queued_taps += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usage::{consumer, modifier};
+
+    const NONE: Action<'static> = Action::None;
+
+    static KEYMAP: [Layer<'static>; 2] = [
+        Layer {
+            keys: [
+                Action::Keys {
+                    modifiers: modifier::LEFT_CTRL,
+                    keys: &[key::C],
+                },
+                Action::Text("aA!"),
+                Action::Consumer(consumer::MUTE),
+                Action::Layer(1),
+                NONE,
+                NONE,
+                NONE,
+                NONE,
+                NONE,
+                NONE,
+                NONE,
+                NONE,
+            ],
+            clockwise: Action::Consumer(consumer::VOLUME_UP),
+            counter_clockwise: Action::Consumer(consumer::VOLUME_DOWN),
+        },
+        Layer {
+            keys: [
+                Action::Keys {
+                    modifiers: 0,
+                    keys: &[key::X],
+                },
+                NONE,
+                NONE,
+                Action::Layer(0),
+                NONE,
+                NONE,
+                NONE,
+                NONE,
+                NONE,
+                NONE,
+                NONE,
+                NONE,
+            ],
+            clockwise: Action::Text("ll"),
+            counter_clockwise: Action::Keys {
+                modifiers: 0,
+                keys: &[key::LEFT],
+            },
+        },
+    ];
+
+    fn keys(modifiers: u8, pressed: &[u8]) -> Report {
+        let mut keys = KeyboardKeys::default();
+        keys.press(modifiers, pressed);
+        Report::Keyboard(keys)
+    }
+
+    /// Check that the reports sent next are `expected`, and no others.
+    fn assert_reports<K: Keymap>(keyboard: &mut MacroKeyboard<K>, expected: &[Report]) {
+        for report in expected {
+            assert_eq!(keyboard.next_report().as_ref(), Some(report));
+        }
+        assert_eq!(keyboard.next_report(), None);
+    }
+
+    #[test]
+    fn nothing_to_send_at_first() {
+        let mut keyboard = MacroKeyboard::new(&KEYMAP);
+        assert_reports(&mut keyboard, &[]);
+        assert!(keyboard.is_idle());
+    }
+
+    #[test]
+    fn keys_are_held_until_released() {
+        let mut keyboard = MacroKeyboard::new(&KEYMAP);
+        keyboard.key_event(KeyEvent::Pressed(0));
+        assert_reports(&mut keyboard, &[keys(modifier::LEFT_CTRL, &[key::C])]);
+        keyboard.key_event(KeyEvent::Released(0));
+        assert_reports(&mut keyboard, &[keys(0, &[])]);
+    }
+
+    #[test]
+    fn consumer_controls_are_held_until_released() {
+        let mut keyboard = MacroKeyboard::new(&KEYMAP);
+        keyboard.key_event(KeyEvent::Pressed(2));
+        assert_reports(&mut keyboard, &[Report::Consumer(consumer::MUTE)]);
+        keyboard.key_event(KeyEvent::Released(2));
+        assert_reports(&mut keyboard, &[Report::Consumer(0)]);
+    }
+
+    #[test]
+    fn text_is_typed_one_character_at_a_time() {
+        let mut keyboard = MacroKeyboard::new(&KEYMAP);
+        keyboard.key_event(KeyEvent::Pressed(1));
+        assert!(!keyboard.is_idle());
+        assert_reports(
+            &mut keyboard,
+            &[
+                keys(0, &[key::A]),
+                keys(0, &[]),
+                keys(modifier::LEFT_SHIFT, &[key::A]),
+                keys(0, &[]),
+                keys(modifier::LEFT_SHIFT, &[key::N1]),
+                keys(0, &[]),
+            ],
+        );
+        assert!(keyboard.is_idle());
+        // Releasing the key doesn't send anything more
+        keyboard.key_event(KeyEvent::Released(1));
+        assert_reports(&mut keyboard, &[]);
+    }
+
+    #[test]
+    fn repeated_characters_are_released_in_between() {
+        let mut keyboard = MacroKeyboard::new(&KEYMAP);
+        keyboard.set_layer(1);
+        keyboard.encoder(1);
+        assert_reports(
+            &mut keyboard,
+            &[
+                keys(0, &[key::L]),
+                keys(0, &[]),
+                keys(0, &[key::L]),
+                keys(0, &[]),
+            ],
+        );
+    }
+
+    #[test]
+    fn encoder_taps_once_per_detent() {
+        let mut keyboard = MacroKeyboard::new(&KEYMAP);
+        keyboard.encoder(2);
+        assert_reports(
+            &mut keyboard,
+            &[
+                Report::Consumer(consumer::VOLUME_UP),
+                Report::Consumer(0),
+                Report::Consumer(consumer::VOLUME_UP),
+                Report::Consumer(0),
+            ],
+        );
+        keyboard.encoder(-1);
+        assert_reports(
+            &mut keyboard,
+            &[Report::Consumer(consumer::VOLUME_DOWN), Report::Consumer(0)],
+        );
+        keyboard.encoder(0);
+        assert_reports(&mut keyboard, &[]);
+    }
+
+    #[test]
+    fn encoder_turns_add_up() {
+        let mut keyboard = MacroKeyboard::new(&KEYMAP);
+        keyboard.set_layer(1);
+        keyboard.encoder(-1);
+        keyboard.encoder(-1);
+        assert_reports(
+            &mut keyboard,
+            &[
+                keys(0, &[key::LEFT]),
+                keys(0, &[]),
+                keys(0, &[key::LEFT]),
+                keys(0, &[]),
+            ],
+        );
+    }
+
+    #[test]
+    fn encoder_button_moves_on_to_the_next_layer() {
+        let mut keyboard = MacroKeyboard::new(&KEYMAP);
+        keyboard.key_event(KeyEvent::Pressed(ENCODER_BUTTON));
+        assert_eq!(keyboard.layer(), 1);
+        keyboard.key_event(KeyEvent::Released(ENCODER_BUTTON));
+        assert_eq!(keyboard.layer(), 1);
+        // Back to the first after the last
+        keyboard.key_event(KeyEvent::Pressed(ENCODER_BUTTON));
+        assert_eq!(keyboard.layer(), 0);
+        assert_reports(&mut keyboard, &[]);
+    }
+
+    #[test]
+    fn layer_keys_switch_layers() {
+        let mut keyboard = MacroKeyboard::new(&KEYMAP);
+        keyboard.key_event(KeyEvent::Pressed(3));
+        assert_eq!(keyboard.layer(), 1);
+        keyboard.key_event(KeyEvent::Released(3));
+        keyboard.key_event(KeyEvent::Pressed(0));
+        assert_reports(&mut keyboard, &[keys(0, &[key::X])]);
+        keyboard.key_event(KeyEvent::Released(0));
+        keyboard.key_event(KeyEvent::Pressed(3));
+        assert_eq!(keyboard.layer(), 0);
+        assert_reports(&mut keyboard, &[keys(0, &[])]);
+    }
+
+    #[test]
+    fn held_keys_keep_the_action_they_were_pressed_with() {
+        let mut keyboard = MacroKeyboard::new(&KEYMAP);
+        keyboard.key_event(KeyEvent::Pressed(0));
+        assert_reports(&mut keyboard, &[keys(modifier::LEFT_CTRL, &[key::C])]);
+        keyboard.key_event(KeyEvent::Pressed(ENCODER_BUTTON));
+        assert_reports(&mut keyboard, &[]);
+        keyboard.key_event(KeyEvent::Released(0));
+        assert_reports(&mut keyboard, &[keys(0, &[])]);
+    }
+
+    #[test]
+    fn missing_layers_are_ignored() {
+        let mut keyboard = MacroKeyboard::new(&KEYMAP);
+        keyboard.set_layer(2);
+        assert_eq!(keyboard.layer(), 0);
+    }
+
+    #[test]
+    fn release_all() {
+        let mut keyboard = MacroKeyboard::new(&KEYMAP);
+        keyboard.key_event(KeyEvent::Pressed(0));
+        keyboard.encoder(3);
+        keyboard.release_all();
+        assert!(keyboard.is_idle());
+        assert_reports(&mut keyboard, &[]);
+    }
+
+    #[test]
+    fn too_many_keys_roll_over() {
+        let mut keys = KeyboardKeys::default();
+        keys.press(0, &[key::A, key::B, key::C, key::D, key::E, key::F]);
+        assert_eq!(keys.keys, [key::A, key::B, key::C, key::D, key::E, key::F]);
+        // Keys already pressed don't take another slot
+        keys.press(0, &[key::A]);
+        assert_eq!(keys.keys[0], key::A);
+        keys.press(0, &[key::G]);
+        assert_eq!(keys.keys, [key::ERROR_ROLL_OVER; 6]);
+    }
+}
@@ -0,0 +1,371 @@
+//! Parse melodies in the Ring Tone Text Transfer Language.
+//!
+//! An RTTTL melody has three sections separated by colons: a name, the
+//! default duration, octave and tempo of the notes, and the notes:
+//!
+//! ```text
+//! scale:d=4,o=5,b=120:c,d,e,f,g,a,b,c6,2p,8c.6
+//! ```
+//!
+//! Each note is made of an optional duration (1 for a whole note, 4 for a
+//! quarter note...), a letter from `a` to `g`, or `p` for a pause, an
+//! optional `#` for a sharp, an optional octave and an optional `.` making it
+//! half as long again. [`Melody`] checks a whole melody, and turns its notes
+//! into frequencies and durations. It doesn't touch any hardware.
+
+/// Duration of notes without one, when the melody doesn't set it.
+const DEFAULT_DURATION: u8 = 4;
+/// Octave of notes without one, when the melody doesn't set it.
+const DEFAULT_OCTAVE: u8 = 6;
+/// Tempo in beats per minute, when the melody doesn't set it.
+const DEFAULT_BPM: u16 = 63;
+
+/// Frequencies of the notes from C to B of the fourth octave, in mHz.
+const OCTAVE_4_MILLIHERTZ: [u32; 12] = [
+    261_626, 277_183, 293_665, 311_127, 329_628, 349_228, 369_994, 391_995, 415_305, 440_000,
+    466_164, 493_883,
+];
+
+/// Why a melody can't be parsed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParseError {
+    /// The melody doesn't have three sections separated by colons.
+    MissingSection,
+    /// The defaults section has an unknown key, or a value out of range.
+    InvalidDefaults,
+    /// The note at `index`, counting from 0, isn't valid.
+    InvalidNote { index: usize },
+}
+
+/// A note to play, or a pause.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Note {
+    /// Frequency in Hz, or `None` for a pause.
+    pub frequency_hz: Option<u32>,
+    pub duration_ms: u32,
+}
+
+/// A melody checked by [`Melody::parse`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Melody<'a> {
+    name: &'a str,
+    notes: &'a str,
+    defaults: Defaults,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Defaults {
+    duration: u8,
+    octave: u8,
+    bpm: u16,
+}
+
+impl<'a> Melody<'a> {
+    /// Check all of `text`, so the notes can be played without errors.
+    pub fn parse(text: &'a str) -> Result<Self, ParseError> {
+        let mut sections = text.splitn(3, ':');
+        let name = sections.next().ok_or(ParseError::MissingSection)?;
+        let defaults = sections.next().ok_or(ParseError::MissingSection)?;
+        let notes = sections.next().ok_or(ParseError::MissingSection)?;
+
+        let melody = Melody {
+            name: name.trim(),
+            notes,
+            defaults: parse_defaults(defaults).ok_or(ParseError::InvalidDefaults)?,
+        };
+        for (index, note) in notes.split(',').enumerate() {
+            if !is_empty(note) && parse_note(note, melody.defaults).is_none() {
+                return Err(ParseError::InvalidNote { index });
+            }
+        }
+        Ok(melody)
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// The tempo, in beats per minute.
+    pub fn bpm(&self) -> u16 {
+        self.defaults.bpm
+    }
+
+    /// The notes of the melody, in order.
+    pub fn notes(&self) -> Notes<'a> {
+        Notes {
+            notes: self.notes.split(','),
+            defaults: self.defaults,
+        }
+    }
+
+    /// How long the melody lasts, in milliseconds.
+    pub fn duration_ms(&self) -> u32 {
+        self.notes().map(|note| note.duration_ms).sum()
+    }
+}
+
+/// The notes of a [`Melody`].
+#[derive(Clone, Debug)]
+pub struct Notes<'a> {
+    notes: core::str::Split<'a, char>,
+    defaults: Defaults,
+}
+
+impl Iterator for Notes<'_> {
+    type Item = Note;
+
+    fn next(&mut self) -> Option<Note> {
+        // Notes were checked when parsing the melody, and empty ones skipped
+        let defaults = self.defaults;
+        self.notes
+            .by_ref()
+            .filter(|note| !is_empty(note))
+            .find_map(|note| parse_note(note, defaults))
+    }
+}
+
+/// Whether `note` is blank, as with a trailing comma.
+fn is_empty(note: &str) -> bool {
+    note.trim().is_empty()
+}
+
+fn parse_defaults(text: &str) -> Option<Defaults> {
+    let mut defaults = Defaults {
+        duration: DEFAULT_DURATION,
+        octave: DEFAULT_OCTAVE,
+        bpm: DEFAULT_BPM,
+    };
+    for setting in text.split(',').filter(|setting| !is_empty(setting)) {
+        let mut parts = setting.splitn(2, '=');
+        let key = parts.next()?.trim();
+        let value: u16 = parts.next()?.trim().parse().ok()?;
+        match key {
+            "d" | "D" if is_duration(value) => defaults.duration = value as u8,
+            "o" | "O" if value <= 9 => defaults.octave = value as u8,
+            "b" | "B" if value > 0 => defaults.bpm = value,
+            _ => return None,
+        }
+    }
+    Some(defaults)
+}
+
+/// Whether `value` is the duration of a note, from a whole note to a 64th.
+fn is_duration(value: u16) -> bool {
+    value.is_power_of_two() && value <= 64
+}
+
+/// Parse a note such as `8c#.6`, or `None` if it isn't valid.
+fn parse_note(text: &str, defaults: Defaults) -> Option<Note> {
+    let mut rest = text.trim().as_bytes();
+
+    let digits = rest.iter().take_while(|c| c.is_ascii_digit()).count();
+    let duration = match digits {
+        0 => u16::from(defaults.duration),
+        _ => core::str::from_utf8(&rest[..digits]).ok()?.parse().ok()?,
+    };
+    if !is_duration(duration) {
+        return None;
+    }
+    rest = &rest[digits..];
+
+    let (letter, after) = rest.split_first()?;
+    rest = after;
+    let mut semitone = match letter.to_ascii_lowercase() {
+        b'c' => Some(0),
+        b'd' => Some(2),
+        b'e' => Some(4),
+        b'f' => Some(5),
+        b'g' => Some(7),
+        b'a' => Some(9),
+        b'b' | b'h' => Some(11),
+        b'p' => None,
+        _ => return None,
+    };
+    if let [b'#', after @ ..] = rest {
+        semitone = semitone.map(|semitone| semitone + 1);
+        rest = after;
+    }
+    let mut dotted = false;
+    if let [b'.', after @ ..] = rest {
+        dotted = true;
+        rest = after;
+    }
+    let mut octave = defaults.octave;
+    if let [digit @ b'0'..=b'9', after @ ..] = rest {
+        octave = digit - b'0';
+        rest = after;
+    }
+    if let [b'.', after @ ..] = rest {
+        dotted = true;
+        rest = after;
+    }
+    if !rest.is_empty() {
+        return None;
+    }
+
+    // A whole note lasts four beats
+    let mut duration_ms = 240_000 / (u32::from(defaults.bpm) * u32::from(duration));
+    if dotted {
+        duration_ms += duration_ms / 2;
+    }
+    Some(Note {
+        frequency_hz: semitone.map(|semitone| frequency_hz(semitone, octave)),
+        duration_ms,
+    })
+}
+
+/// The frequency of the note `semitone` semitones above C in `octave`,
+/// rounded to the nearest Hz.
+fn frequency_hz(semitone: u8, octave: u8) -> u32 {
+    // B# is the C of the next octave
+    let (semitone, octave) = if semitone >= 12 {
+        (semitone - 12, octave + 1)
+    } else {
+        (semitone, octave)
+    };
+    let millihertz = OCTAVE_4_MILLIHERTZ[usize::from(semitone)] << octave >> 4;
+    (millihertz + 500) / 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(frequency_hz: u32, duration_ms: u32) -> Note {
+        Note {
+            frequency_hz: Some(frequency_hz),
+            duration_ms,
+        }
+    }
+
+    fn pause(duration_ms: u32) -> Note {
+        Note {
+            frequency_hz: None,
+            duration_ms,
+        }
+    }
+
+    /// Check that the notes of `text` are `expected`.
+    fn assert_notes(text: &str, expected: &[Note]) {
+        let melody = Melody::parse(text).unwrap();
+        assert!(
+            melody.notes().eq(expected.iter().copied()),
+            "{:?}",
+            melody.notes()
+        );
+    }
+
+    #[test]
+    fn sections() {
+        let melody = Melody::parse(" scale :d=4,o=5,b=120:c").unwrap();
+        assert_eq!(melody.name(), "scale");
+        assert_eq!(melody.bpm(), 120);
+        assert_eq!(Melody::parse("scale"), Err(ParseError::MissingSection));
+        assert_eq!(Melody::parse("scale:d=4"), Err(ParseError::MissingSection));
+    }
+
+    #[test]
+    fn default_values() {
+        let melody = Melody::parse("::a").unwrap();
+        assert_eq!(melody.name(), "");
+        assert_eq!(melody.bpm(), 63);
+        // A quarter note of the sixth octave, at 63 bpm
+        assert_notes("::a", &[note(1760, 952)]);
+    }
+
+    #[test]
+    fn notes_override_the_defaults() {
+        assert_notes(
+            "x:d=8,o=4,b=120:a,2a,a5,c,16g7",
+            &[
+                note(440, 250),
+                note(440, 1000),
+                note(880, 250),
+                note(262, 250),
+                note(3136, 125),
+            ],
+        );
+    }
+
+    #[test]
+    fn dotted_notes() {
+        // The dot may come before or after the octave
+        assert_notes(
+            "x:d=4,o=5,b=120:c.,8c.6,8c6.",
+            &[note(523, 750), note(1047, 375), note(1047, 375)],
+        );
+    }
+
+    #[test]
+    fn sharps() {
+        assert_notes(
+            "x:d=4,o=4,b=60:a#,c#5,e#",
+            &[note(466, 1000), note(554, 1000), note(349, 1000)],
+        );
+    }
+
+    #[test]
+    fn b_sharp_is_the_c_of_the_next_octave() {
+        assert_notes(
+            "x:d=4,o=5,b=60:b#,c6",
+            &[note(1047, 1000), note(1047, 1000)],
+        );
+    }
+
+    #[test]
+    fn pauses_and_trailing_commas() {
+        assert_notes(
+            "x:d=4,o=5,b=120:2p,c, p.,",
+            &[pause(1000), note(523, 500), pause(750)],
+        );
+        assert_notes("x:d=4,o=5,b=120:", &[]);
+    }
+
+    #[test]
+    fn case_and_spaces_are_ignored() {
+        assert_notes(
+            "x: D=8, O=5, B=120 : A , H",
+            &[note(880, 250), note(988, 250)],
+        );
+    }
+
+    #[test]
+    fn invalid_defaults() {
+        for defaults in ["d=3", "d=128", "o=10", "b=0", "x=1", "d", "d=a"] {
+            let text = ["x:", defaults, ":c"].concat();
+            assert_eq!(
+                Melody::parse(&text),
+                Err(ParseError::InvalidDefaults),
+                "{}",
+                defaults
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_notes() {
+        for (text, index) in [
+            ("x::c,x", 1),
+            ("x::3c", 0),
+            ("x::c,d,128e", 2),
+            ("x::c,,c10", 2),
+            ("x::c#x", 0),
+            ("x::c6..", 0),
+            ("x::8", 0),
+        ] {
+            assert_eq!(
+                Melody::parse(text),
+                Err(ParseError::InvalidNote { index }),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn duration() {
+        let melody = Melody::parse("x:d=4,o=5,b=120:c,2p,8c.6,").unwrap();
+        assert_eq!(melody.duration_ms(), 500 + 1000 + 375);
+        assert_eq!(Melody::parse("x::").unwrap().duration_ms(), 0);
+    }
+}
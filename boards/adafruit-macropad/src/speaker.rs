@@ -0,0 +1,314 @@
+//! Play tones and melodies on the speaker.
+//!
+//! The speaker is driven by an amplifier, fed a square wave by channel A of
+//! PWM slice 0 on the `speaker` pin. [`Speaker`] switches the amplifier on
+//! with `speaker_shutdown` only while something plays, as it hisses and
+//! draws current otherwise.
+//!
+//! Melodies play without blocking: [`Speaker::play`] starts one, and
+//! [`Speaker::tick`] moves it along as time passes, keeping time with a
+//! [`Schedule`].
+
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::PwmPin;
+use fugit::HertzU32;
+use hal::gpio::bank0::{Gpio14, Gpio16};
+use hal::gpio::{FunctionPwm, Pin, PinId, PinState, PushPullOutput};
+use hal::pwm::{FreeRunning, Pwm0, Slice};
+
+use crate::rtttl::{Melody, Notes};
+
+/// Part of each note of a melody left silent, so repeated notes can be told
+/// apart.
+const NOTE_GAP_FRACTION: u32 = 8;
+
+/// The integer clock divider and the top of the counter of a PWM slice
+/// clocked at `clock_hz`, to get as close to `frequency_hz` as possible, or
+/// `None` if the frequency is out of reach.
+pub fn pwm_settings(clock_hz: u32, frequency_hz: u32) -> Option<(u8, u16)> {
+    if frequency_hz == 0 {
+        return None;
+    }
+    // The counter wraps after `top + 1` cycles of the divided clock
+    let cycles = clock_hz / frequency_hz;
+    let divider = cycles.div_ceil(0x1_0000).max(1);
+    if divider > u32::from(u8::MAX) || cycles / divider < 2 {
+        return None;
+    }
+    Some((divider as u8, (cycles / divider - 1) as u16))
+}
+
+/// What plays at some point of a melody.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sound {
+    /// A tone at a frequency in Hz.
+    Tone(u32),
+    /// Silence, for a rest or the gap at the end of a note.
+    Rest,
+    /// The melody is over.
+    End,
+}
+
+/// Keeps time through the notes of a melody, without touching any hardware.
+#[derive(Clone, Debug)]
+pub struct Schedule<'a> {
+    notes: Notes<'a>,
+    /// Time left until the next note.
+    note_left_ms: u32,
+    /// Time left in the note when its tone stops.
+    gap_ms: u32,
+    sound: Sound,
+}
+
+impl<'a> Schedule<'a> {
+    /// Start at the first note of `melody`.
+    pub fn new(melody: Melody<'a>) -> Self {
+        let mut schedule = Schedule {
+            notes: melody.notes(),
+            note_left_ms: 0,
+            gap_ms: 0,
+            sound: Sound::Rest,
+        };
+        schedule.tick(0);
+        schedule
+    }
+
+    /// What plays now.
+    pub fn sound(&self) -> Sound {
+        self.sound
+    }
+
+    /// Move along by `elapsed_ms`, skipping any notes that ended in between,
+    /// and return what plays then.
+    pub fn tick(&mut self, elapsed_ms: u32) -> Sound {
+        let mut elapsed_ms = elapsed_ms;
+        while self.sound != Sound::End {
+            if self.note_left_ms > elapsed_ms {
+                self.note_left_ms -= elapsed_ms;
+                if self.note_left_ms <= self.gap_ms {
+                    self.sound = Sound::Rest;
+                }
+                break;
+            }
+            elapsed_ms -= self.note_left_ms;
+            match self.notes.next() {
+                Some(note) => {
+                    self.note_left_ms = note.duration_ms;
+                    self.gap_ms = note.duration_ms / NOTE_GAP_FRACTION;
+                    self.sound = note.frequency_hz.map_or(Sound::Rest, Sound::Tone);
+                }
+                None => self.sound = Sound::End,
+            }
+        }
+        self.sound
+    }
+}
+
+/// Plays square waves on the speaker.
+pub struct Speaker<'a> {
+    pwm: Slice<Pwm0, FreeRunning>,
+    speaker: Pin<Gpio16, FunctionPwm>,
+    enable: Pin<Gpio14, PushPullOutput>,
+    clock_hz: u32,
+    melody: Option<Schedule<'a>>,
+}
+
+impl<'a> Speaker<'a> {
+    /// Set up the speaker, silent with the amplifier off.
+    ///
+    /// `clock` is the frequency the PWM is clocked from, which is the system
+    /// clock.
+    pub fn new(
+        mut pwm: Slice<Pwm0, FreeRunning>,
+        speaker: Pin<Gpio16, <Gpio16 as PinId>::Reset>,
+        speaker_shutdown: Pin<Gpio14, <Gpio14 as PinId>::Reset>,
+        clock: HertzU32,
+    ) -> Self {
+        pwm.channel_a.set_duty(0);
+        pwm.enable();
+        let speaker = pwm.channel_a.output_to(speaker);
+        Speaker {
+            pwm,
+            speaker,
+            enable: speaker_shutdown.into_push_pull_output_in_state(PinState::Low),
+            clock_hz: clock.to_Hz(),
+            melody: None,
+        }
+    }
+
+    /// Play a tone at `frequency_hz` until stopped, stopping any melody.
+    /// Frequencies out of reach of the PWM are silent.
+    pub fn tone(&mut self, frequency_hz: u32) {
+        self.melody = None;
+        self.start_tone(frequency_hz);
+    }
+
+    /// Play a tone at `frequency_hz` for `duration_ms`, and wait for it to
+    /// end.
+    pub fn beep(&mut self, frequency_hz: u32, duration_ms: u32, delay: &mut impl DelayMs<u32>) {
+        self.tone(frequency_hz);
+        delay.delay_ms(duration_ms);
+        self.stop();
+    }
+
+    /// Start playing `melody`, stopping anything playing. Call
+    /// [`Speaker::tick`] as time passes for it to move along.
+    pub fn play(&mut self, melody: Melody<'a>) {
+        let schedule = Schedule::new(melody);
+        let sound = schedule.sound();
+        self.melody = Some(schedule);
+        self.play_sound(sound);
+    }
+
+    /// Play `melody`, and wait for it to end.
+    pub fn play_blocking(&mut self, melody: Melody<'a>, delay: &mut impl DelayMs<u32>) {
+        self.play(melody);
+        while self.is_playing() {
+            delay.delay_ms(1);
+            self.tick(1);
+        }
+    }
+
+    /// Move the melody along by `elapsed_ms`, and return whether it is still
+    /// playing.
+    pub fn tick(&mut self, elapsed_ms: u32) -> bool {
+        let schedule = match &mut self.melody {
+            Some(schedule) => schedule,
+            None => return false,
+        };
+        let before = schedule.sound();
+        let sound = schedule.tick(elapsed_ms);
+        if sound != before {
+            self.play_sound(sound);
+        }
+        self.is_playing()
+    }
+
+    /// Whether a melody is playing.
+    pub fn is_playing(&self) -> bool {
+        self.melody.is_some()
+    }
+
+    /// Stop playing, and switch the amplifier off.
+    pub fn stop(&mut self) {
+        self.melody = None;
+        self.silence();
+        // Setting GPIO outputs can't fail
+        let _ = self.enable.set_low();
+    }
+
+    /// Release the PWM slice and the pins.
+    pub fn free(
+        mut self,
+    ) -> (
+        Slice<Pwm0, FreeRunning>,
+        Pin<Gpio16, FunctionPwm>,
+        Pin<Gpio14, PushPullOutput>,
+    ) {
+        self.stop();
+        (self.pwm, self.speaker, self.enable)
+    }
+
+    fn play_sound(&mut self, sound: Sound) {
+        match sound {
+            Sound::Tone(frequency_hz) => self.start_tone(frequency_hz),
+            Sound::Rest => self.silence(),
+            Sound::End => self.stop(),
+        }
+    }
+
+    fn start_tone(&mut self, frequency_hz: u32) {
+        let (divider, top) = match pwm_settings(self.clock_hz, frequency_hz) {
+            Some(settings) => settings,
+            None => return self.silence(),
+        };
+        let _ = self.enable.set_high();
+        self.pwm.set_div_int(divider);
+        self.pwm.set_div_frac(0);
+        self.pwm.set_top(top);
+        // Half of the period high, for a square wave
+        self.pwm.channel_a.set_duty(top / 2 + 1);
+    }
+
+    /// Keep the output low. The slice keeps running, so the output goes low
+    /// at the end of the period instead of staying where it is.
+    fn silence(&mut self) {
+        self.pwm.channel_a.set_duty(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLOCK_HZ: u32 = 125_000_000;
+
+    #[test]
+    fn pwm_settings_at_the_system_clock() {
+        assert_eq!(pwm_settings(CLOCK_HZ, 20), Some((96, 65103)));
+        assert_eq!(pwm_settings(CLOCK_HZ, 440), Some((5, 56817)));
+        assert_eq!(pwm_settings(CLOCK_HZ, CLOCK_HZ / 2), Some((1, 1)));
+    }
+
+    #[test]
+    fn pwm_settings_out_of_reach() {
+        assert_eq!(pwm_settings(CLOCK_HZ, 0), None);
+        assert_eq!(pwm_settings(CLOCK_HZ, CLOCK_HZ / 2 + 1), None);
+        assert_eq!(pwm_settings(CLOCK_HZ, CLOCK_HZ), None);
+        // Would need a divider above 255
+        assert_eq!(pwm_settings(CLOCK_HZ, 7), None);
+    }
+
+    #[test]
+    fn pwm_settings_stay_close() {
+        for frequency_hz in (8..20_000).step_by(7) {
+            let (divider, top) = pwm_settings(CLOCK_HZ, frequency_hz).unwrap();
+            let period = u32::from(divider) * (u32::from(top) + 1);
+            let actual_hz = CLOCK_HZ / period;
+            // Within the divider of the cycles of a period
+            assert!(CLOCK_HZ / frequency_hz - period < u32::from(divider));
+            assert!(actual_hz >= frequency_hz);
+            assert!(actual_hz - frequency_hz <= frequency_hz / 100 + 1);
+        }
+    }
+
+    /// Quarter notes at 60 bpm last 1000 ms, with a gap of 125 ms.
+    const MELODY: &str = "x:d=4,o=4,b=60:a,p,8b";
+
+    #[test]
+    fn notes_end_with_a_gap() {
+        let mut schedule = Schedule::new(Melody::parse(MELODY).unwrap());
+        assert_eq!(schedule.sound(), Sound::Tone(440));
+        assert_eq!(schedule.tick(874), Sound::Tone(440));
+        assert_eq!(schedule.tick(1), Sound::Rest);
+        assert_eq!(schedule.tick(124), Sound::Rest);
+        // The pause
+        assert_eq!(schedule.tick(1), Sound::Rest);
+        assert_eq!(schedule.tick(999), Sound::Rest);
+        // The eighth note, with a gap of 62 ms
+        assert_eq!(schedule.tick(1), Sound::Tone(494));
+        assert_eq!(schedule.tick(437), Sound::Tone(494));
+        assert_eq!(schedule.tick(1), Sound::Rest);
+        assert_eq!(schedule.tick(61), Sound::Rest);
+        assert_eq!(schedule.tick(1), Sound::End);
+        assert_eq!(schedule.tick(1000), Sound::End);
+    }
+
+    #[test]
+    fn long_ticks_skip_notes() {
+        let mut schedule = Schedule::new(Melody::parse(MELODY).unwrap());
+        assert_eq!(schedule.tick(2100), Sound::Tone(494));
+        assert_eq!(schedule.tick(350), Sound::Rest);
+
+        let mut schedule = Schedule::new(Melody::parse(MELODY).unwrap());
+        assert_eq!(schedule.tick(2500), Sound::End);
+    }
+
+    #[test]
+    fn repeated_notes_are_apart() {
+        let mut schedule = Schedule::new(Melody::parse("x:d=4,o=4,b=60:a,a").unwrap());
+        assert_eq!(schedule.tick(900), Sound::Rest);
+        assert_eq!(schedule.tick(100), Sound::Tone(440));
+    }
+}
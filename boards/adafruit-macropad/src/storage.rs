@@ -0,0 +1,156 @@
+//! Keep a [`Config`] in a sector of the flash.
+//!
+//! The flash holding the program can't be read while it is erased or
+//! programmed, so [`FlashSector`] does it with interrupts disabled, from a
+//! function in RAM calling the routines of the boot ROM.
+
+use hal::rom_data;
+
+use crate::config::{serialize, Config, ConfigError, LayerConfig};
+
+/// Size of the W25Q64JV flash, in bytes.
+pub const FLASH_SIZE: u32 = 8 * 1024 * 1024;
+/// Smallest area of the flash that can be erased, in bytes.
+pub const SECTOR_SIZE: u32 = 4096;
+/// Smallest area of the flash that can be programmed, in bytes.
+const PAGE_SIZE: usize = 256;
+
+/// Address the flash is read from, through the execute-in-place cache.
+const XIP_BASE: u32 = 0x1000_0000;
+/// Size of the second stage bootloader, at the start of the flash.
+const BOOT2_SIZE: usize = 256;
+
+/// Largest area erased with a single command, when aligned.
+const BLOCK_SIZE: u32 = 1 << 16;
+/// Command erasing [`BLOCK_SIZE`] bytes.
+const BLOCK_ERASE: u8 = 0xD8;
+
+/// A sector of the flash set aside to store a configuration.
+pub struct FlashSector {
+    offset: u32,
+}
+
+impl FlashSector {
+    /// The sector `offset` bytes from the start of the flash. It must not
+    /// hold any of the program.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `offset` isn't at the start of a sector of the flash.
+    pub fn new(offset: u32) -> Self {
+        assert!(offset.is_multiple_of(SECTOR_SIZE) && offset < FLASH_SIZE);
+        FlashSector { offset }
+    }
+
+    /// The last sector of the flash, far away from the program.
+    pub fn last() -> Self {
+        Self::new(FLASH_SIZE - SECTOR_SIZE)
+    }
+
+    /// Offset of the sector from the start of the flash.
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// The contents of the sector.
+    pub fn read(&self) -> &[u8] {
+        let address = (XIP_BASE + self.offset) as *const u8;
+        // Safety: the flash is mapped there, and is only changed through
+        // `&mut self`
+        unsafe { core::slice::from_raw_parts(address, SECTOR_SIZE as usize) }
+    }
+
+    /// Check and read the configuration in the sector.
+    pub fn load(&self) -> Result<Config<'_>, ConfigError> {
+        Config::parse(self.read())
+    }
+
+    /// Store the configuration holding `layers` in the sector, replacing its
+    /// contents.
+    ///
+    /// # Safety
+    ///
+    /// The other core must not run from flash, or read from it, until this
+    /// returns.
+    pub unsafe fn store(&mut self, layers: &[LayerConfig<'_>]) -> Result<(), ConfigError> {
+        let mut buffer = [0xFF; SECTOR_SIZE as usize];
+        let size = serialize(layers, &mut buffer)?;
+        self.program(&buffer, size);
+        Ok(())
+    }
+
+    /// Erase the sector, and program `data` at its start.
+    ///
+    /// # Safety
+    ///
+    /// The other core must not run from flash, or read from it, until this
+    /// returns.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` doesn't fit in a sector.
+    pub unsafe fn write(&mut self, data: &[u8]) {
+        // The data may be in flash, where it can't be read from while
+        // programming
+        let mut buffer = [0xFF; SECTOR_SIZE as usize];
+        buffer[..data.len()].copy_from_slice(data);
+        self.program(&buffer, data.len());
+    }
+
+    /// Erase the sector, and program the first `size` bytes of `buffer`,
+    /// which is in RAM.
+    unsafe fn program(&mut self, buffer: &[u8; SECTOR_SIZE as usize], size: usize) {
+        // Keep the second stage bootloader in RAM, to set up fast reads of
+        // the flash again afterwards
+        let mut boot2 = [0u32; BOOT2_SIZE / 4];
+        core::ptr::copy_nonoverlapping(XIP_BASE as *const u32, boot2.as_mut_ptr(), boot2.len());
+
+        let rom = RomFunctions {
+            connect_internal_flash: rom_data::connect_internal_flash::ptr(),
+            flash_exit_xip: rom_data::flash_exit_xip::ptr(),
+            flash_range_erase: rom_data::flash_range_erase::ptr(),
+            flash_range_program: rom_data::flash_range_program::ptr(),
+            flash_flush_cache: rom_data::flash_flush_cache::ptr(),
+        };
+        // Programming works on whole pages
+        let size = size.div_ceil(PAGE_SIZE) * PAGE_SIZE;
+        cortex_m::interrupt::free(|_| {
+            write_flash(&rom, self.offset, buffer.as_ptr(), size, boot2.as_ptr());
+        });
+    }
+}
+
+/// The routines of the boot ROM used to write the flash, looked up
+/// beforehand as the lookup runs from flash.
+struct RomFunctions {
+    connect_internal_flash: unsafe extern "C" fn(),
+    flash_exit_xip: unsafe extern "C" fn(),
+    flash_range_erase: unsafe extern "C" fn(u32, usize, u32, u8),
+    flash_range_program: unsafe extern "C" fn(u32, *const u8, usize),
+    flash_flush_cache: unsafe extern "C" fn(),
+}
+
+/// Erase the sector at `offset` and program `size` bytes of `data` into it,
+/// then run the second stage bootloader copied at `boot2`.
+///
+/// This runs from RAM, and must not call anything in flash.
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+unsafe fn write_flash(
+    rom: &RomFunctions,
+    offset: u32,
+    data: *const u8,
+    size: usize,
+    boot2: *const u32,
+) {
+    (rom.connect_internal_flash)();
+    (rom.flash_exit_xip)();
+    (rom.flash_range_erase)(offset, SECTOR_SIZE as usize, BLOCK_SIZE, BLOCK_ERASE);
+    if size > 0 {
+        (rom.flash_range_program)(offset, data, size);
+    }
+    (rom.flash_flush_cache)();
+    // The bootloader is Thumb code, and returns when called
+    let boot2: unsafe extern "C" fn() = core::mem::transmute(boot2 as usize + 1);
+    boot2();
+}
@@ -0,0 +1,168 @@
+//! USB HID usage IDs, for keyboard keys and consumer controls.
+//!
+//! Keys are from the Keyboard/Keypad page, and consumer controls from the
+//! Consumer page, of the HID Usage Tables. Characters are typed as on a US
+//! keyboard layout.
+
+/// Keys of the Keyboard/Keypad usage page.
+pub mod key {
+    pub const A: u8 = 0x04;
+    pub const B: u8 = 0x05;
+    pub const C: u8 = 0x06;
+    pub const D: u8 = 0x07;
+    pub const E: u8 = 0x08;
+    pub const F: u8 = 0x09;
+    pub const G: u8 = 0x0A;
+    pub const H: u8 = 0x0B;
+    pub const I: u8 = 0x0C;
+    pub const J: u8 = 0x0D;
+    pub const K: u8 = 0x0E;
+    pub const L: u8 = 0x0F;
+    pub const M: u8 = 0x10;
+    pub const N: u8 = 0x11;
+    pub const O: u8 = 0x12;
+    pub const P: u8 = 0x13;
+    pub const Q: u8 = 0x14;
+    pub const R: u8 = 0x15;
+    pub const S: u8 = 0x16;
+    pub const T: u8 = 0x17;
+    pub const U: u8 = 0x18;
+    pub const V: u8 = 0x19;
+    pub const W: u8 = 0x1A;
+    pub const X: u8 = 0x1B;
+    pub const Y: u8 = 0x1C;
+    pub const Z: u8 = 0x1D;
+    pub const N1: u8 = 0x1E;
+    pub const N2: u8 = 0x1F;
+    pub const N3: u8 = 0x20;
+    pub const N4: u8 = 0x21;
+    pub const N5: u8 = 0x22;
+    pub const N6: u8 = 0x23;
+    pub const N7: u8 = 0x24;
+    pub const N8: u8 = 0x25;
+    pub const N9: u8 = 0x26;
+    pub const N0: u8 = 0x27;
+    pub const ENTER: u8 = 0x28;
+    pub const ESCAPE: u8 = 0x29;
+    pub const BACKSPACE: u8 = 0x2A;
+    pub const TAB: u8 = 0x2B;
+    pub const SPACE: u8 = 0x2C;
+    pub const MINUS: u8 = 0x2D;
+    pub const EQUAL: u8 = 0x2E;
+    pub const LEFT_BRACKET: u8 = 0x2F;
+    pub const RIGHT_BRACKET: u8 = 0x30;
+    pub const BACKSLASH: u8 = 0x31;
+    pub const SEMICOLON: u8 = 0x33;
+    pub const QUOTE: u8 = 0x34;
+    pub const GRAVE: u8 = 0x35;
+    pub const COMMA: u8 = 0x36;
+    pub const DOT: u8 = 0x37;
+    pub const SLASH: u8 = 0x38;
+    pub const CAPS_LOCK: u8 = 0x39;
+    pub const F1: u8 = 0x3A;
+    pub const F2: u8 = 0x3B;
+    pub const F3: u8 = 0x3C;
+    pub const F4: u8 = 0x3D;
+    pub const F5: u8 = 0x3E;
+    pub const F6: u8 = 0x3F;
+    pub const F7: u8 = 0x40;
+    pub const F8: u8 = 0x41;
+    pub const F9: u8 = 0x42;
+    pub const F10: u8 = 0x43;
+    pub const F11: u8 = 0x44;
+    pub const F12: u8 = 0x45;
+    pub const PRINT_SCREEN: u8 = 0x46;
+    pub const SCROLL_LOCK: u8 = 0x47;
+    pub const PAUSE: u8 = 0x48;
+    pub const INSERT: u8 = 0x49;
+    pub const HOME: u8 = 0x4A;
+    pub const PAGE_UP: u8 = 0x4B;
+    pub const DELETE: u8 = 0x4C;
+    pub const END: u8 = 0x4D;
+    pub const PAGE_DOWN: u8 = 0x4E;
+    pub const RIGHT: u8 = 0x4F;
+    pub const LEFT: u8 = 0x50;
+    pub const DOWN: u8 = 0x51;
+    pub const UP: u8 = 0x52;
+    /// Reported in every slot when too many keys are held.
+    pub const ERROR_ROLL_OVER: u8 = 0x01;
+}
+
+/// Modifier keys, as bits of the first byte of a keyboard report.
+pub mod modifier {
+    pub const LEFT_CTRL: u8 = 0x01;
+    pub const LEFT_SHIFT: u8 = 0x02;
+    pub const LEFT_ALT: u8 = 0x04;
+    pub const LEFT_GUI: u8 = 0x08;
+    pub const RIGHT_CTRL: u8 = 0x10;
+    pub const RIGHT_SHIFT: u8 = 0x20;
+    pub const RIGHT_ALT: u8 = 0x40;
+    pub const RIGHT_GUI: u8 = 0x80;
+}
+
+/// Controls of the Consumer usage page.
+pub mod consumer {
+    pub const BRIGHTNESS_UP: u16 = 0x006F;
+    pub const BRIGHTNESS_DOWN: u16 = 0x0070;
+    pub const PLAY: u16 = 0x00B0;
+    pub const PAUSE: u16 = 0x00B1;
+    pub const RECORD: u16 = 0x00B2;
+    pub const FAST_FORWARD: u16 = 0x00B3;
+    pub const REWIND: u16 = 0x00B4;
+    pub const NEXT_TRACK: u16 = 0x00B5;
+    pub const PREVIOUS_TRACK: u16 = 0x00B6;
+    pub const STOP: u16 = 0x00B7;
+    pub const PLAY_PAUSE: u16 = 0x00CD;
+    pub const MUTE: u16 = 0x00E2;
+    pub const VOLUME_UP: u16 = 0x00E9;
+    pub const VOLUME_DOWN: u16 = 0x00EA;
+}
+
+/// The modifiers and key typing `c` on a US keyboard layout, or `None` for
+/// characters it doesn't have.
+pub fn ascii_key(c: char) -> Option<(u8, u8)> {
+    const SHIFT: u8 = modifier::LEFT_SHIFT;
+    let key = match c {
+        'a'..='z' => (0, key::A + (c as u8 - b'a')),
+        'A'..='Z' => (SHIFT, key::A + (c as u8 - b'A')),
+        '1'..='9' => (0, key::N1 + (c as u8 - b'1')),
+        '0' => (0, key::N0),
+        '!' => (SHIFT, key::N1),
+        '@' => (SHIFT, key::N2),
+        '#' => (SHIFT, key::N3),
+        '$' => (SHIFT, key::N4),
+        '%' => (SHIFT, key::N5),
+        '^' => (SHIFT, key::N6),
+        '&' => (SHIFT, key::N7),
+        '*' => (SHIFT, key::N8),
+        '(' => (SHIFT, key::N9),
+        ')' => (SHIFT, key::N0),
+        '\n' => (0, key::ENTER),
+        '\t' => (0, key::TAB),
+        ' ' => (0, key::SPACE),
+        '-' => (0, key::MINUS),
+        '_' => (SHIFT, key::MINUS),
+        '=' => (0, key::EQUAL),
+        '+' => (SHIFT, key::EQUAL),
+        '[' => (0, key::LEFT_BRACKET),
+        '{' => (SHIFT, key::LEFT_BRACKET),
+        ']' => (0, key::RIGHT_BRACKET),
+        '}' => (SHIFT, key::RIGHT_BRACKET),
+        '\\' => (0, key::BACKSLASH),
+        '|' => (SHIFT, key::BACKSLASH),
+        ';' => (0, key::SEMICOLON),
+        ':' => (SHIFT, key::SEMICOLON),
+        '\'' => (0, key::QUOTE),
+        '"' => (SHIFT, key::QUOTE),
+        '`' => (0, key::GRAVE),
+        '~' => (SHIFT, key::GRAVE),
+        ',' => (0, key::COMMA),
+        '<' => (SHIFT, key::COMMA),
+        '.' => (0, key::DOT),
+        '>' => (SHIFT, key::DOT),
+        '/' => (0, key::SLASH),
+        '?' => (SHIFT, key::SLASH),
+        _ => return None,
+    };
+    Some(key)
+}
@@ -3,23 +3,20 @@
 #![no_main]
 
 use adafruit_qt_py_rp2040::entry;
-use core::iter::once;
 use embedded_hal::timer::CountDown;
 use fugit::ExtU32;
+use led_effects::color::{scale, wheel};
 use panic_halt as _;
-use smart_leds::{brightness, SmartLedsWrite, RGB8};
-use ws2812_pio::Ws2812;
 
 use adafruit_qt_py_rp2040::{
     hal::{
         clocks::{init_clocks_and_plls, Clock},
-        gpio::PinState,
         pac,
         pio::PIOExt,
         watchdog::Watchdog,
         Sio, Timer,
     },
-    Pins, XOSC_CRYSTAL_FREQ,
+    Pins, StatusPixel, XOSC_CRYSTAL_FREQ,
 };
 
 #[entry]
@@ -48,48 +45,24 @@ fn main() -> ! {
         &mut pac.RESETS,
     );
 
-    let led = pins.neopixel_data.into_mode();
-
-    pins.neopixel_power
-        .into_push_pull_output_in_state(PinState::High);
-
     let timer = Timer::new(pac.TIMER, &mut pac.RESETS);
     let mut delay = timer.count_down();
 
     let (mut pio, sm0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
-    let mut ws = Ws2812::new(
-        led,
+    let mut pixel = StatusPixel::new(
+        pins.neopixel_power,
+        pins.neopixel_data.into_mode(),
         &mut pio,
         sm0,
-        clocks.peripheral_clock.freq(),
-        timer.count_down(),
+        clocks.system_clock.freq(),
     );
 
     let mut n: u8 = 128;
     loop {
-        ws.write(brightness(once(wheel(n)), 32)).unwrap();
+        pixel.set(scale(wheel(n), 32));
         n = n.wrapping_add(1);
 
         delay.start(25u32.millis());
         let _ = nb::block!(delay.wait());
     }
 }
-
-/// Convert a number from `0..=255` to an RGB color triplet.
-///
-/// The colours are a transition from red, to green, to blue and back to red.
-fn wheel(mut wheel_pos: u8) -> RGB8 {
-    wheel_pos = 255 - wheel_pos;
-    if wheel_pos < 85 {
-        // No green in this sector - red and blue only
-        (255 - (wheel_pos * 3), 0, wheel_pos * 3).into()
-    } else if wheel_pos < 170 {
-        // No red in this sector - green and blue only
-        wheel_pos -= 85;
-        (0, wheel_pos * 3, 255 - (wheel_pos * 3)).into()
-    } else {
-        // No blue in this sector - red and green only
-        wheel_pos -= 170;
-        (wheel_pos * 3, 255 - (wheel_pos * 3), 0).into()
-    }
-}
@@ -17,6 +17,9 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_GD25Q64CS;
 
 pub use hal::pac;
 
+pub mod status_pixel;
+pub use status_pixel::StatusPixel;
+
 hal::bsp_pins!(
     Gpio3 {
         name: mosi,
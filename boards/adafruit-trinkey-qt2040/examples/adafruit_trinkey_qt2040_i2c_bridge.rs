@@ -0,0 +1,129 @@
+//! # USB to I2C Bridge Example for the Adafruit Trinkey QT2040
+//!
+//! Lets a host drive the devices plugged into the STEMMA QT port, over USB
+//! Serial.
+//!
+//! The Trinkey shows up as a serial port, such as `/dev/ttyACM0` on Linux,
+//! and answers the requests of the `i2c-bridge` protocol: scanning the bus,
+//! writing, reading, and setting the speed of the bus and the timeout of
+//! transfers. The `i2c-bridge-host` crate speaks that protocol on the host.
+
+#![no_std]
+#![no_main]
+
+use adafruit_trinkey_qt2040::entry;
+use panic_halt as _;
+
+use adafruit_trinkey_qt2040::{
+    hal::{
+        self,
+        clocks::{init_clocks_and_plls, Clock},
+        pac,
+        timer::Timer,
+        watchdog::Watchdog,
+        Sio,
+    },
+    i2c_bridge::{Device, MAX_FRAME_SIZE},
+    BridgeBus, XOSC_CRYSTAL_FREQ,
+};
+use usb_device::{class_prelude::*, prelude::*};
+use usbd_serial::SerialPort;
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[entry]` macro ensures the Cortex-M start-up code calls this
+/// function as soon as all global variables are initialised.
+///
+/// The function configures the RP2040 peripherals, then answers the requests
+/// received over USB Serial in an infinite loop.
+#[entry]
+fn main() -> ! {
+    // Configure the RP2040 peripherals
+
+    let mut pac = pac::Peripherals::take().unwrap();
+    let mut watchdog = Watchdog::new(pac.WATCHDOG);
+
+    let clocks = init_clocks_and_plls(
+        XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    let sio = Sio::new(pac.SIO);
+
+    let pins = adafruit_trinkey_qt2040::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // Configure the I2C bus of the STEMMA QT port
+
+    let timer = Timer::new(pac.TIMER, &mut pac.RESETS);
+    let bus = BridgeBus::new(
+        pac.I2C0,
+        pins.sda.into_mode(),
+        pins.scl.into_mode(),
+        &mut pac.RESETS,
+        clocks.system_clock.freq(),
+        timer,
+    );
+    let mut device = Device::new(bus);
+
+    // Configure the USB Serial port
+
+    let usb_bus = UsbBusAllocator::new(hal::usb::UsbBus::new(
+        pac.USBCTRL_REGS,
+        pac.USBCTRL_DPRAM,
+        clocks.usb_clock,
+        true,
+        &mut pac.RESETS,
+    ));
+    let mut serial = SerialPort::new(&usb_bus);
+    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16c0, 0x27dd))
+        .manufacturer("Adafruit")
+        .product("Trinkey QT2040 I2C bridge")
+        .serial_number("TEST")
+        .device_class(2) // from: https://www.usb.org/defined-class-codes
+        .build();
+
+    // Answer requests as they come in
+
+    let mut response = [0; MAX_FRAME_SIZE];
+    loop {
+        if !usb_dev.poll(&mut [&mut serial]) {
+            continue;
+        }
+        let mut buf = [0u8; 64];
+        let count = match serial.read(&mut buf) {
+            Ok(count) => count,
+            Err(_) => continue,
+        };
+        for &byte in &buf[..count] {
+            let size = match device.receive(byte, &mut response) {
+                Some(size) => size,
+                None => continue,
+            };
+            // The host waits for the whole response before sending the next
+            // request
+            let mut pending = &response[..size];
+            while !pending.is_empty() {
+                match serial.write(pending) {
+                    Ok(len) => pending = &pending[len..],
+                    Err(UsbError::WouldBlock) => {
+                        usb_dev.poll(&mut [&mut serial]);
+                    }
+                    // The host went away, drop the response
+                    Err(_) => break,
+                }
+            }
+        }
+    }
+}
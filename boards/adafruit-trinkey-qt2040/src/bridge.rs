@@ -0,0 +1,301 @@
+//! Drive the STEMMA QT port from a USB host.
+//!
+//! [`BridgeBus`] runs the I2C0 controller on the `sda` and `scl` pins for an
+//! [`i2c_bridge::Device`], which answers the requests of a host speaking the
+//! `i2c-bridge` protocol. Unlike the I2C driver of the HAL, it can change the
+//! speed of the bus as it runs, and gives up on transfers taking too long, as
+//! when a device holds the clock low.
+
+use fugit::HertzU32;
+use hal::gpio::bank0::{Gpio16, Gpio17};
+use hal::gpio::{FunctionI2C, Pin};
+use hal::Timer;
+use i2c_bridge::{Bus, ErrorCode};
+
+use crate::pac;
+
+/// Speed of the bus until the host changes it.
+pub const DEFAULT_FREQUENCY_HZ: u32 = 100_000;
+/// Timeout of transfers until the host changes it.
+pub const DEFAULT_TIMEOUT_MS: u16 = 100;
+/// Fastest speed of the bus, in Fast-mode Plus.
+pub const MAX_FREQUENCY_HZ: u32 = 1_000_000;
+
+/// Entries of the transmit FIFO.
+const TX_FIFO_DEPTH: u32 = 16;
+/// Time left for the controller to stop after aborting a transfer.
+const ABORT_GRACE_US: u32 = 1_000;
+
+// Bits of IC_CON
+const CON_MASTER_MODE: u32 = 1 << 0;
+const CON_SPEED_FAST: u32 = 2 << 1;
+const CON_RESTART_EN: u32 = 1 << 5;
+const CON_SLAVE_DISABLE: u32 = 1 << 6;
+const CON_TX_EMPTY_CTRL: u32 = 1 << 8;
+
+// Bits of IC_ENABLE
+const ENABLE: u32 = 1 << 0;
+const ENABLE_ABORT: u32 = 1 << 1;
+
+// Bits of IC_DATA_CMD, above the data byte
+const DATA_CMD_READ: u32 = 1 << 8;
+const DATA_CMD_STOP: u32 = 1 << 9;
+const DATA_CMD_RESTART: u32 = 1 << 10;
+
+// Bits of IC_RAW_INTR_STAT
+const INTR_TX_ABRT: u32 = 1 << 6;
+const INTR_STOP_DET: u32 = 1 << 9;
+
+// Bits of IC_TX_ABRT_SOURCE
+const ABRT_7B_ADDR_NOACK: u32 = 1 << 0;
+const ABRT_TXDATA_NOACK: u32 = 1 << 3;
+const ABRT_ARB_LOST: u32 = 1 << 12;
+
+/// Counts of the system clock for the high and low periods of the bus clock
+/// at `frequency_hz`, then for the hold time of the data, or `None` if the
+/// frequency is out of reach.
+fn timing(system_clock_hz: u32, frequency_hz: u32) -> Option<(u32, u32, u32)> {
+    if frequency_hz == 0 || frequency_hz > MAX_FREQUENCY_HZ {
+        return None;
+    }
+    // The clock is low for 60% of the period, as the specification asks for
+    // a longer low period at the faster speeds
+    let period = (system_clock_hz + frequency_hz / 2) / frequency_hz;
+    let low = period * 3 / 5;
+    let high = period - low;
+    // Hold the data for 300ns after the falling edge of the clock, or 120ns
+    // in Fast-mode Plus
+    let hold = if frequency_hz < MAX_FREQUENCY_HZ {
+        system_clock_hz * 3 / 10_000_000 + 1
+    } else {
+        system_clock_hz * 3 / 25_000_000 + 1
+    };
+    if high < 8 || low < 8 || high > 0xFFFF || low > 0xFFFF || hold > low - 2 {
+        return None;
+    }
+    Some((high, low, hold))
+}
+
+/// The I2C bus of the STEMMA QT port, for an [`i2c_bridge::Device`].
+pub struct BridgeBus {
+    i2c: pac::I2C0,
+    sda: Pin<Gpio16, FunctionI2C>,
+    scl: Pin<Gpio17, FunctionI2C>,
+    timer: Timer,
+    system_clock_hz: u32,
+    timeout_us: u32,
+}
+
+impl BridgeBus {
+    /// Set up the bus at [`DEFAULT_FREQUENCY_HZ`], with a timeout of
+    /// [`DEFAULT_TIMEOUT_MS`].
+    ///
+    /// `system_clock` is the frequency the I2C controller is clocked from.
+    /// The devices on the bus, or the port, must pull the lines up.
+    pub fn new(
+        i2c: pac::I2C0,
+        sda: Pin<Gpio16, FunctionI2C>,
+        scl: Pin<Gpio17, FunctionI2C>,
+        resets: &mut pac::RESETS,
+        system_clock: HertzU32,
+        timer: Timer,
+    ) -> Self {
+        resets.reset.modify(|_, w| w.i2c0().set_bit());
+        resets.reset.modify(|_, w| w.i2c0().clear_bit());
+        while resets.reset_done.read().i2c0().bit_is_clear() {}
+
+        let mut bus = BridgeBus {
+            i2c,
+            sda,
+            scl,
+            timer,
+            system_clock_hz: system_clock.to_Hz(),
+            timeout_us: u32::from(DEFAULT_TIMEOUT_MS) * 1000,
+        };
+        // The default speed is in reach of any usable system clock
+        let _ = bus.configure(DEFAULT_FREQUENCY_HZ);
+        bus
+    }
+
+    /// Release the controller and the pins.
+    pub fn free(
+        self,
+    ) -> (
+        pac::I2C0,
+        Pin<Gpio16, FunctionI2C>,
+        Pin<Gpio17, FunctionI2C>,
+    ) {
+        self.disable();
+        (self.i2c, self.sda, self.scl)
+    }
+
+    fn configure(&mut self, frequency_hz: u32) -> Result<(), ErrorCode> {
+        let (high, low, hold) =
+            timing(self.system_clock_hz, frequency_hz).ok_or(ErrorCode::UnsupportedSpeed)?;
+        self.disable();
+        // Fast mode timings are used at every speed
+        let con = CON_MASTER_MODE
+            | CON_SPEED_FAST
+            | CON_RESTART_EN
+            | CON_SLAVE_DISABLE
+            | CON_TX_EMPTY_CTRL;
+        let spike_length = if low < 16 { 1 } else { low / 16 };
+        // Safety: the values fit the fields
+        unsafe {
+            self.i2c.ic_con.write(|w| w.bits(con));
+            self.i2c.ic_fs_scl_hcnt.write(|w| w.bits(high));
+            self.i2c.ic_fs_scl_lcnt.write(|w| w.bits(low));
+            self.i2c.ic_fs_spklen.write(|w| w.bits(spike_length));
+            self.i2c
+                .ic_sda_hold
+                .modify(|r, w| w.bits((r.bits() & !0xFFFF) | hold));
+        }
+        Ok(())
+    }
+
+    fn disable(&self) {
+        // Safety: clearing the enable bit is always valid
+        self.i2c.ic_enable.write(|w| unsafe { w.bits(0) });
+    }
+
+    /// Write `write`, then read into `read` after a repeated start, from the
+    /// device at `address`. Either may be empty, but not both.
+    fn transfer(&mut self, address: u8, write: &[u8], read: &mut [u8]) -> Result<(), ErrorCode> {
+        if write.is_empty() && read.is_empty() {
+            return Err(ErrorCode::InvalidArgument);
+        }
+        // The target address can only change while the controller is disabled
+        self.disable();
+        // Safety: the address is a 7-bit one, and the enable bit is valid
+        unsafe {
+            self.i2c.ic_tar.write(|w| w.bits(u32::from(address)));
+            self.i2c.ic_enable.write(|w| w.bits(ENABLE));
+        }
+        // Drop a stop or abort left over from an earlier transfer
+        self.clear_stop_and_abort();
+
+        let deadline = self.timer.get_counter_low().wrapping_add(self.timeout_us);
+        let last = write.len() + read.len() - 1;
+        for (index, &byte) in write.iter().enumerate() {
+            let mut command = u32::from(byte);
+            if index == last {
+                command |= DATA_CMD_STOP;
+            }
+            self.wait(deadline, |i2c| i2c.ic_txflr.read().bits() < TX_FIFO_DEPTH)?;
+            self.push(command);
+        }
+        for (index, byte) in read.iter_mut().enumerate() {
+            let mut command = DATA_CMD_READ;
+            if index == 0 && !write.is_empty() {
+                command |= DATA_CMD_RESTART;
+            }
+            if write.len() + index == last {
+                command |= DATA_CMD_STOP;
+            }
+            // Read one byte at a time, so the receive FIFO can't overflow
+            self.push(command);
+            self.wait(deadline, |i2c| i2c.ic_rxflr.read().bits() > 0)?;
+            *byte = self.i2c.ic_data_cmd.read().bits() as u8;
+        }
+        self.wait(deadline, |i2c| {
+            i2c.ic_raw_intr_stat.read().bits() & INTR_STOP_DET != 0
+        })?;
+        self.clear_stop_and_abort();
+        Ok(())
+    }
+
+    fn push(&self, command: u32) {
+        // Safety: the command only has bits of IC_DATA_CMD
+        self.i2c.ic_data_cmd.write(|w| unsafe { w.bits(command) });
+    }
+
+    /// Wait until `done`, failing if the transfer is aborted, or if it goes
+    /// on past `deadline`.
+    fn wait(&self, deadline: u32, done: impl Fn(&pac::I2C0) -> bool) -> Result<(), ErrorCode> {
+        loop {
+            if self.i2c.ic_raw_intr_stat.read().bits() & INTR_TX_ABRT != 0 {
+                return Err(self.finish_abort(deadline));
+            }
+            if done(&self.i2c) {
+                return Ok(());
+            }
+            if self.is_past(deadline) {
+                // Have the controller give up, and let go of the bus if it
+                // can
+                // Safety: both bits are valid
+                self.i2c
+                    .ic_enable
+                    .write(|w| unsafe { w.bits(ENABLE | ENABLE_ABORT) });
+                let grace = self.timer.get_counter_low().wrapping_add(ABORT_GRACE_US);
+                while !self.is_past(grace)
+                    && self.i2c.ic_raw_intr_stat.read().bits() & INTR_STOP_DET == 0
+                {
+                }
+                self.clear_stop_and_abort();
+                return Err(ErrorCode::Timeout);
+            }
+        }
+    }
+
+    /// The error of an aborted transfer, once the controller has stopped.
+    fn finish_abort(&self, deadline: u32) -> ErrorCode {
+        let source = self.i2c.ic_tx_abrt_source.read().bits();
+        let deadline = deadline.wrapping_add(ABORT_GRACE_US);
+        while !self.is_past(deadline)
+            && self.i2c.ic_raw_intr_stat.read().bits() & INTR_STOP_DET == 0
+        {}
+        self.clear_stop_and_abort();
+        if source & ABRT_7B_ADDR_NOACK != 0 {
+            ErrorCode::AddressNack
+        } else if source & ABRT_TXDATA_NOACK != 0 {
+            ErrorCode::DataNack
+        } else if source & ABRT_ARB_LOST != 0 {
+            ErrorCode::ArbitrationLost
+        } else {
+            ErrorCode::BusError
+        }
+    }
+
+    fn clear_stop_and_abort(&self) {
+        // Reading these registers clears the interrupts
+        let _ = self.i2c.ic_clr_stop_det.read();
+        let _ = self.i2c.ic_clr_tx_abrt.read();
+    }
+
+    fn is_past(&self, deadline: u32) -> bool {
+        // Compare through a wrapping difference so the counter may roll over
+        (self.timer.get_counter_low().wrapping_sub(deadline) as i32) >= 0
+    }
+}
+
+impl Bus for BridgeBus {
+    fn probe(&mut self, address: u8) -> Result<bool, ErrorCode> {
+        // Reading a byte is safe for most devices, unlike writing one
+        match self.transfer(address, &[], &mut [0]) {
+            Ok(()) => Ok(true),
+            Err(ErrorCode::AddressNack) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn write(&mut self, address: u8, data: &[u8]) -> Result<(), ErrorCode> {
+        self.transfer(address, data, &mut [])
+    }
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), ErrorCode> {
+        self.transfer(address, &[], buffer)
+    }
+
+    fn write_read(&mut self, address: u8, data: &[u8], buffer: &mut [u8]) -> Result<(), ErrorCode> {
+        self.transfer(address, data, buffer)
+    }
+
+    fn set_speed(&mut self, frequency_hz: u32) -> Result<(), ErrorCode> {
+        self.configure(frequency_hz)
+    }
+
+    fn set_timeout(&mut self, timeout_ms: u16) -> Result<(), ErrorCode> {
+        self.timeout_us = u32::from(timeout_ms) * 1000;
+        Ok(())
+    }
+}
@@ -17,6 +17,10 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
 pub use hal::pac;
 
+pub mod bridge;
+pub use bridge::BridgeBus;
+pub use i2c_bridge;
+
 hal::bsp_pins!(
     Gpio12 { name: button },
     Gpio16 {
@@ -0,0 +1,135 @@
+//! # Badger2040 Battery Example
+//!
+//! Measures the batteries of a badger2040 board, shows their voltage and the
+//! charge left on the e-ink display, then switches the board off.
+//!
+//! The charge is estimated for three alkaline AAA cells. Use
+//! `DischargeCurve::LIPO` instead for a LiPo battery.
+//!
+//! See the `Cargo.toml` file for Copyright and licence details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use pimoroni_badger2040::entry;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+// A shorter alias for the Peripheral Access Crate, which provides low-level
+// register access
+use pimoroni_badger2040::hal::pac;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use pimoroni_badger2040::hal;
+
+// Some traits we need
+use arrayvec::ArrayString;
+use core::fmt::Write;
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    text::{Alignment, Text},
+};
+use fugit::RateExtU32;
+use hal::clocks::Clock;
+
+use pimoroni_badger2040::{
+    Badger2040Display, BatteryMonitor, Buttons, DischargeCurve, PowerLatch, RefreshMode,
+};
+
+#[entry]
+fn main() -> ! {
+    // Grab our singleton objects
+    let mut pac = pac::Peripherals::take().unwrap();
+    let core = pac::CorePeripherals::take().unwrap();
+
+    // Set up the watchdog driver - needed by the clock setup code
+    let mut watchdog = hal::Watchdog::new(pac.WATCHDOG);
+
+    // Configure the clocks
+    //
+    // The default is to generate a 125 MHz system clock
+    let clocks = hal::clocks::init_clocks_and_plls(
+        pimoroni_badger2040::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    // The single-cycle I/O block controls our GPIO pins
+    let sio = hal::Sio::new(pac.SIO);
+
+    // Set the pins up according to their function on this particular board
+    let pins = pimoroni_badger2040::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // Keep the board powered when running from batteries
+    let buttons = Buttons::new(
+        pins.sw_a,
+        pins.sw_b,
+        pins.sw_c,
+        pins.sw_up,
+        pins.sw_down,
+        pins.user_sw,
+    );
+    let mut latch = PowerLatch::new(pins.p3v3_en, &buttons);
+
+    let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+
+    // Measure the batteries
+    let mut adc = hal::Adc::new(pac.ADC, &mut pac.RESETS);
+    let mut battery = BatteryMonitor::new(
+        pins.vref_power,
+        pins.vref_1v24,
+        pins.vbat_sense,
+        DischargeCurve::ALKALINE_AAA_X3,
+    );
+    let reading = battery.read(&mut adc);
+
+    // The display is on SPI0
+    let _ = pins.sclk.into_mode::<hal::gpio::FunctionSpi>();
+    let _ = pins.mosi.into_mode::<hal::gpio::FunctionSpi>();
+    let spi = hal::spi::Spi::<_, _, 8>::new(pac.SPI0).init(
+        &mut pac.RESETS,
+        clocks.peripheral_clock.freq(),
+        12.MHz(),
+        &embedded_hal::spi::MODE_0,
+    );
+
+    let mut display = Badger2040Display::new(
+        spi,
+        pins.inky_cs_gpio,
+        pins.inky_dc,
+        pins.inky_res,
+        pins.inky_busy,
+    );
+    display.init(RefreshMode::Full, &mut delay).unwrap();
+
+    let mut text = ArrayString::<32>::new();
+    write!(text, "{:.2}V - {:.0}%", reading.volts, reading.percent).unwrap();
+    let style = MonoTextStyle::new(&FONT_10X20, BinaryColor::On);
+    display.clear(BinaryColor::Off).unwrap();
+    Text::with_alignment(&text, Point::new(148, 70), style, Alignment::Center)
+        .draw(&mut display)
+        .unwrap();
+    display.update().unwrap();
+
+    latch.power_off();
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
@@ -0,0 +1,152 @@
+//! # Badger2040 Display Example
+//!
+//! Draws a badge on the e-ink display of a badger2040 board, then fills a
+//! progress bar along its bottom edge using fast, partial updates.
+//!
+//! See the `Cargo.toml` file for Copyright and licence details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use pimoroni_badger2040::entry;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+// A shorter alias for the Peripheral Access Crate, which provides low-level
+// register access
+use pimoroni_badger2040::hal::pac;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use pimoroni_badger2040::hal;
+
+// Some traits we need
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Alignment, Text},
+};
+use fugit::RateExtU32;
+use hal::clocks::Clock;
+
+use pimoroni_badger2040::{Badger2040Display, Buttons, PowerLatch, RefreshMode};
+
+/// Number of steps of the progress bar.
+const STEPS: u32 = 10;
+
+#[entry]
+fn main() -> ! {
+    // Grab our singleton objects
+    let mut pac = pac::Peripherals::take().unwrap();
+    let core = pac::CorePeripherals::take().unwrap();
+
+    // Set up the watchdog driver - needed by the clock setup code
+    let mut watchdog = hal::Watchdog::new(pac.WATCHDOG);
+
+    // Configure the clocks
+    //
+    // The default is to generate a 125 MHz system clock
+    let clocks = hal::clocks::init_clocks_and_plls(
+        pimoroni_badger2040::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    // The single-cycle I/O block controls our GPIO pins
+    let sio = hal::Sio::new(pac.SIO);
+
+    // Set the pins up according to their function on this particular board
+    let pins = pimoroni_badger2040::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // Keep the board powered when running from batteries
+    let buttons = Buttons::new(
+        pins.sw_a,
+        pins.sw_b,
+        pins.sw_c,
+        pins.sw_up,
+        pins.sw_down,
+        pins.user_sw,
+    );
+    let mut latch = PowerLatch::new(pins.p3v3_en, &buttons);
+
+    let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+
+    // The display is on SPI0
+    let _ = pins.sclk.into_mode::<hal::gpio::FunctionSpi>();
+    let _ = pins.mosi.into_mode::<hal::gpio::FunctionSpi>();
+    let spi = hal::spi::Spi::<_, _, 8>::new(pac.SPI0).init(
+        &mut pac.RESETS,
+        clocks.peripheral_clock.freq(),
+        12.MHz(),
+        &embedded_hal::spi::MODE_0,
+    );
+
+    let mut display = Badger2040Display::new(
+        spi,
+        pins.inky_cs_gpio,
+        pins.inky_dc,
+        pins.inky_res,
+        pins.inky_busy,
+    );
+    display.init(RefreshMode::Full, &mut delay).unwrap();
+
+    // Draw the badge, and show it with a full refresh
+    let style = MonoTextStyle::new(&FONT_10X20, BinaryColor::On);
+    let border = PrimitiveStyle::with_stroke(BinaryColor::On, 2);
+    display.clear(BinaryColor::Off).unwrap();
+    display
+        .bounding_box()
+        .into_styled(border)
+        .draw(&mut display)
+        .unwrap();
+    Text::with_alignment(
+        "Hello, rp-rs!",
+        Point::new(148, 56),
+        style,
+        Alignment::Center,
+    )
+    .draw(&mut display)
+    .unwrap();
+    display.update().unwrap();
+
+    // Fill the progress bar one step at a time, only refreshing the bar
+    display.set_mode(RefreshMode::Fast).unwrap();
+    let bar = Rectangle::new(Point::new(16, 96), Size::new(264, 16));
+    bar.into_styled(border).draw(&mut display).unwrap();
+    display.update_window(bar).unwrap();
+    for step in 1..=STEPS {
+        delay.delay_ms(1000);
+        let filled = Size::new(bar.size.width * step / STEPS, bar.size.height);
+        Rectangle::new(bar.top_left, filled)
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(&mut display)
+            .unwrap();
+        display.update_window(bar).unwrap();
+    }
+
+    // Clean up the ghosting left by the fast updates
+    display.set_mode(RefreshMode::Full).unwrap();
+    display.update().unwrap();
+
+    // The e-ink display keeps showing the badge without power
+    latch.power_off();
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
@@ -0,0 +1,147 @@
+//! # Badger2040 Wake Example
+//!
+//! Shows which button woke a badger2040 board up on its e-ink display, then
+//! switches the board off until the next button press.
+//!
+//! When powered over USB the board can't switch off, so it waits for the next
+//! press instead.
+//!
+//! See the `Cargo.toml` file for Copyright and licence details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use pimoroni_badger2040::entry;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+// A shorter alias for the Peripheral Access Crate, which provides low-level
+// register access
+use pimoroni_badger2040::hal::pac;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use pimoroni_badger2040::hal;
+
+// Some traits we need
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    text::{Alignment, Text},
+};
+use fugit::RateExtU32;
+use hal::clocks::Clock;
+
+use pimoroni_badger2040::{
+    Badger2040Display, Button, ButtonSet, Buttons, PowerLatch, RefreshMode, WakeReason,
+};
+
+#[entry]
+fn main() -> ! {
+    // Grab our singleton objects
+    let mut pac = pac::Peripherals::take().unwrap();
+    let core = pac::CorePeripherals::take().unwrap();
+
+    // The single-cycle I/O block controls our GPIO pins
+    let sio = hal::Sio::new(pac.SIO);
+
+    // Set the pins up according to their function on this particular board
+    let pins = pimoroni_badger2040::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // Hold the power on before anything else, while the button that powered
+    // the board up is still held down
+    let buttons = Buttons::new(
+        pins.sw_a,
+        pins.sw_b,
+        pins.sw_c,
+        pins.sw_up,
+        pins.sw_down,
+        pins.user_sw,
+    );
+    let mut latch = PowerLatch::new(pins.p3v3_en, &buttons);
+    let mut pressed = match latch.wake_reason() {
+        WakeReason::Buttons(pressed) => pressed,
+        WakeReason::PowerOn => ButtonSet::empty(),
+    };
+
+    // Set up the watchdog driver - needed by the clock setup code
+    let mut watchdog = hal::Watchdog::new(pac.WATCHDOG);
+
+    // Configure the clocks
+    //
+    // The default is to generate a 125 MHz system clock
+    let clocks = hal::clocks::init_clocks_and_plls(
+        pimoroni_badger2040::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+
+    // The display is on SPI0
+    let _ = pins.sclk.into_mode::<hal::gpio::FunctionSpi>();
+    let _ = pins.mosi.into_mode::<hal::gpio::FunctionSpi>();
+    let spi = hal::spi::Spi::<_, _, 8>::new(pac.SPI0).init(
+        &mut pac.RESETS,
+        clocks.peripheral_clock.freq(),
+        12.MHz(),
+        &embedded_hal::spi::MODE_0,
+    );
+
+    let mut display = Badger2040Display::new(
+        spi,
+        pins.inky_cs_gpio,
+        pins.inky_dc,
+        pins.inky_res,
+        pins.inky_busy,
+    );
+    display.init(RefreshMode::Fast, &mut delay).unwrap();
+
+    let style = MonoTextStyle::new(&FONT_10X20, BinaryColor::On);
+    loop {
+        let message = match pressed.iter().next() {
+            Some(Button::A) => "Woken up by A",
+            Some(Button::B) => "Woken up by B",
+            Some(Button::C) => "Woken up by C",
+            Some(Button::Up) => "Woken up by up",
+            Some(Button::Down) => "Woken up by down",
+            Some(Button::User) => "Woken up by user",
+            None => "Press a button",
+        };
+        display.clear(BinaryColor::Off).unwrap();
+        Text::with_alignment(message, Point::new(148, 70), style, Alignment::Center)
+            .draw(&mut display)
+            .unwrap();
+        display.update().unwrap();
+
+        // Switches the board off when running from batteries
+        latch.power_off();
+
+        // Still running: wait for the buttons to be released, then for the
+        // next press
+        while !buttons.pressed().is_empty() {}
+        delay.delay_ms(20);
+        loop {
+            pressed = buttons.pressed();
+            if !pressed.is_empty() {
+                break;
+            }
+        }
+        latch.hold();
+    }
+}
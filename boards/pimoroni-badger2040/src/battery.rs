@@ -0,0 +1,222 @@
+//! Battery voltage math, independent of the hardware.
+//!
+//! The `vbat_sense` pin sees a third of the battery voltage, and the ADC
+//! measures it relative to the 3.3V supply, which sags as the batteries run
+//! down. Measuring the 1.24V reference on `vref_1v24` too gives the supply
+//! voltage, so [`battery_volts`] only depends on the ratio of both readings.
+//!
+//! A [`DischargeCurve`] then turns the voltage into an estimate of the charge
+//! left.
+
+/// Voltage of the reference on `vref_1v24`.
+pub const VREF_VOLTS: f32 = 1.24;
+/// Ratio of the divider between the battery and `vbat_sense`.
+const VBAT_DIVIDER: f32 = 3.0;
+
+/// Battery voltage from raw readings of `vbat_sense` and `vref_1v24`, taken
+/// with the same ADC.
+///
+/// Returns 0.0 if `vref_raw` is 0, which only happens when the reference is
+/// off.
+pub fn battery_volts(vbat_raw: u16, vref_raw: u16) -> f32 {
+    if vref_raw == 0 {
+        return 0.0;
+    }
+    VBAT_DIVIDER * VREF_VOLTS * f32::from(vbat_raw) / f32::from(vref_raw)
+}
+
+/// A point of a [`DischargeCurve`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CurvePoint {
+    pub volts: f32,
+    /// Charge left at `volts`, from 0.0 to 100.0.
+    pub percent: f32,
+}
+
+impl CurvePoint {
+    pub const fn new(volts: f32, percent: f32) -> Self {
+        CurvePoint { volts, percent }
+    }
+}
+
+/// Why a set of curve points was rejected.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CurveError {
+    /// No points were given.
+    Empty,
+    /// The voltages are not strictly decreasing.
+    VoltageOrder,
+}
+
+/// Charge left in a battery by voltage, linearly interpolated between points.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DischargeCurve<'a> {
+    points: &'a [CurvePoint],
+}
+
+impl<'a> DischargeCurve<'a> {
+    /// Three alkaline AAA cells in series, as in the battery holder sold with
+    /// the board, under the light load of the board.
+    pub const ALKALINE_AAA_X3: DischargeCurve<'static> = DischargeCurve {
+        points: &[
+            CurvePoint::new(4.65, 100.0),
+            CurvePoint::new(4.35, 90.0),
+            CurvePoint::new(4.14, 80.0),
+            CurvePoint::new(3.96, 70.0),
+            CurvePoint::new(3.81, 60.0),
+            CurvePoint::new(3.69, 50.0),
+            CurvePoint::new(3.57, 40.0),
+            CurvePoint::new(3.45, 30.0),
+            CurvePoint::new(3.30, 20.0),
+            CurvePoint::new(3.09, 10.0),
+            CurvePoint::new(2.70, 0.0),
+        ],
+    };
+
+    /// A single-cell LiPo or Li-ion battery.
+    pub const LIPO: DischargeCurve<'static> = DischargeCurve {
+        points: &[
+            CurvePoint::new(4.20, 100.0),
+            CurvePoint::new(4.10, 90.0),
+            CurvePoint::new(4.00, 80.0),
+            CurvePoint::new(3.92, 70.0),
+            CurvePoint::new(3.85, 60.0),
+            CurvePoint::new(3.80, 50.0),
+            CurvePoint::new(3.76, 40.0),
+            CurvePoint::new(3.72, 30.0),
+            CurvePoint::new(3.68, 20.0),
+            CurvePoint::new(3.60, 10.0),
+            CurvePoint::new(3.30, 0.0),
+        ],
+    };
+
+    /// A curve through `points`, in order of decreasing voltage.
+    pub fn new(points: &'a [CurvePoint]) -> Result<Self, CurveError> {
+        if points.is_empty() {
+            return Err(CurveError::Empty);
+        }
+        // Written so that NaNs are rejected too
+        if !points.windows(2).all(|w| w[0].volts > w[1].volts) {
+            return Err(CurveError::VoltageOrder);
+        }
+        Ok(DischargeCurve { points })
+    }
+
+    pub fn points(&self) -> &'a [CurvePoint] {
+        self.points
+    }
+
+    /// Charge left at `volts`, from 0.0 to 100.0.
+    ///
+    /// Voltages above the first point give its percentage, and voltages below
+    /// the last point give the percentage of that one.
+    pub fn percent(&self, volts: f32) -> f32 {
+        let first = self.points[0];
+        let last = self.points[self.points.len() - 1];
+        if volts >= first.volts {
+            return first.percent.clamp(0.0, 100.0);
+        }
+        for pair in self.points.windows(2) {
+            let (high, low) = (pair[0], pair[1]);
+            if volts >= low.volts {
+                let fraction = (volts - low.volts) / (high.volts - low.volts);
+                let percent = low.percent + (high.percent - low.percent) * fraction;
+                return percent.clamp(0.0, 100.0);
+            }
+        }
+        // Below the last point, or NaN
+        last.percent.clamp(0.0, 100.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "{} isn't {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn volts_from_readings() {
+        // The battery at three times the reference
+        assert_near(battery_volts(1000, 1000), 3.72);
+        assert_near(battery_volts(1500, 1240), 4.5);
+        assert_near(battery_volts(1000, 0), 0.0);
+    }
+
+    #[test]
+    fn percent_at_the_points() {
+        for curve in [DischargeCurve::ALKALINE_AAA_X3, DischargeCurve::LIPO] {
+            for point in curve.points() {
+                assert_near(curve.percent(point.volts), point.percent);
+            }
+        }
+    }
+
+    #[test]
+    fn percent_between_the_points() {
+        let curve = DischargeCurve::LIPO;
+        assert_near(curve.percent(4.15), 95.0);
+        assert_near(curve.percent(3.45), 5.0);
+        assert_near(DischargeCurve::ALKALINE_AAA_X3.percent(3.63), 45.0);
+    }
+
+    #[test]
+    fn percent_is_clamped_to_the_ends() {
+        let curve = DischargeCurve::LIPO;
+        assert_near(curve.percent(5.0), 100.0);
+        assert_near(curve.percent(3.0), 0.0);
+        assert_near(curve.percent(0.0), 0.0);
+        assert_near(curve.percent(f32::NAN), 0.0);
+
+        let points = [CurvePoint::new(4.0, 120.0), CurvePoint::new(3.0, -10.0)];
+        let curve = DischargeCurve::new(&points).unwrap();
+        assert_near(curve.percent(4.0), 100.0);
+        assert_near(curve.percent(3.0), 0.0);
+        assert_near(curve.percent(3.5), 55.0);
+    }
+
+    #[test]
+    fn percent_goes_down_with_the_voltage() {
+        for curve in [DischargeCurve::ALKALINE_AAA_X3, DischargeCurve::LIPO] {
+            let mut previous = 100.0;
+            for millivolts in (2500..5000).rev() {
+                let percent = curve.percent(millivolts as f32 / 1000.0);
+                assert!(percent <= previous, "{}mV", millivolts);
+                previous = percent;
+            }
+        }
+    }
+
+    #[test]
+    fn single_point_curve() {
+        let points = [CurvePoint::new(3.0, 50.0)];
+        let curve = DischargeCurve::new(&points).unwrap();
+        assert_near(curve.percent(4.0), 50.0);
+        assert_near(curve.percent(2.0), 50.0);
+    }
+
+    #[test]
+    fn invalid_points_are_rejected() {
+        assert_eq!(DischargeCurve::new(&[]), Err(CurveError::Empty));
+        let rising = [CurvePoint::new(3.0, 0.0), CurvePoint::new(4.0, 100.0)];
+        assert_eq!(DischargeCurve::new(&rising), Err(CurveError::VoltageOrder));
+        let repeated = [
+            CurvePoint::new(4.0, 100.0),
+            CurvePoint::new(3.5, 50.0),
+            CurvePoint::new(3.5, 40.0),
+        ];
+        assert_eq!(
+            DischargeCurve::new(&repeated),
+            Err(CurveError::VoltageOrder)
+        );
+        let nan = [CurvePoint::new(4.0, 100.0), CurvePoint::new(f32::NAN, 0.0)];
+        assert_eq!(DischargeCurve::new(&nan), Err(CurveError::VoltageOrder));
+    }
+}
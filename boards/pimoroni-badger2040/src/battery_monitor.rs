@@ -0,0 +1,86 @@
+//! Measure the battery voltage and charge.
+//!
+//! [`BatteryMonitor`] switches the 1.24V reference on with `vref_power` just
+//! for the time of a measurement, reads it along with `vbat_sense`, and works
+//! out the battery voltage from the ratio of both readings.
+
+use embedded_hal::adc::OneShot;
+use embedded_hal::digital::v2::OutputPin;
+use hal::adc::Adc;
+use hal::gpio::bank0::{Gpio27, Gpio28, Gpio29};
+use hal::gpio::{FloatingInput, Pin, PinId, PinState, PushPullOutput};
+
+use crate::battery::{battery_volts, DischargeCurve};
+
+/// A measurement of the battery.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BatteryReading {
+    pub volts: f32,
+    /// Estimated charge left, from 0.0 to 100.0.
+    pub percent: f32,
+}
+
+/// Measures the battery with the ADC.
+pub struct BatteryMonitor {
+    vref_power: Pin<Gpio27, PushPullOutput>,
+    vref: Pin<Gpio28, FloatingInput>,
+    vbat: Pin<Gpio29, FloatingInput>,
+    curve: DischargeCurve<'static>,
+}
+
+impl BatteryMonitor {
+    /// Set up the pins, with the reference off until a measurement, and
+    /// estimate the charge left with `curve`.
+    pub fn new(
+        vref_power: Pin<Gpio27, <Gpio27 as PinId>::Reset>,
+        vref_1v24: Pin<Gpio28, <Gpio28 as PinId>::Reset>,
+        vbat_sense: Pin<Gpio29, <Gpio29 as PinId>::Reset>,
+        curve: DischargeCurve<'static>,
+    ) -> Self {
+        BatteryMonitor {
+            vref_power: vref_power.into_push_pull_output_in_state(PinState::Low),
+            vref: vref_1v24.into_floating_input(),
+            vbat: vbat_sense.into_floating_input(),
+            curve,
+        }
+    }
+
+    pub fn curve(&self) -> DischargeCurve<'static> {
+        self.curve
+    }
+
+    pub fn set_curve(&mut self, curve: DischargeCurve<'static>) {
+        self.curve = curve;
+    }
+
+    /// Measure the battery voltage.
+    pub fn read_volts(&mut self, adc: &mut Adc) -> f32 {
+        // Setting GPIO outputs can't fail
+        let _ = self.vref_power.set_high();
+        // The RP2040 ADC blocks until the conversion is done and never fails
+        let vref_raw: u16 = nb::block!(adc.read(&mut self.vref)).unwrap();
+        let vbat_raw: u16 = nb::block!(adc.read(&mut self.vbat)).unwrap();
+        let _ = self.vref_power.set_low();
+        battery_volts(vbat_raw, vref_raw)
+    }
+
+    /// Measure the battery voltage, and estimate the charge left from it.
+    pub fn read(&mut self, adc: &mut Adc) -> BatteryReading {
+        let volts = self.read_volts(adc);
+        BatteryReading {
+            volts,
+            percent: self.curve.percent(volts),
+        }
+    }
+
+    /// Release the pins.
+    pub fn free(
+        self,
+    ) -> (
+        Pin<Gpio27, PushPullOutput>,
+        Pin<Gpio28, FloatingInput>,
+        Pin<Gpio29, FloatingInput>,
+    ) {
+        (self.vref_power, self.vref, self.vbat)
+    }
+}
@@ -0,0 +1,127 @@
+//! The buttons on the front and back of the board.
+//!
+//! The `sw_a`, `sw_b`, `sw_c`, `sw_up` and `sw_down` buttons on the front
+//! connect their pin to 3.3V, and also power the board up when it runs from
+//! batteries. The `user_sw` button on the back connects its pin to ground.
+
+use embedded_hal::digital::v2::InputPin;
+use hal::gpio::bank0::{Gpio11, Gpio12, Gpio13, Gpio14, Gpio15, Gpio23};
+use hal::gpio::{Pin, PinId, PullDownInput, PullUpInput};
+
+/// One of the buttons.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Button {
+    A,
+    B,
+    C,
+    Up,
+    Down,
+    /// The button on the back of the board.
+    User,
+}
+
+impl Button {
+    /// All the buttons, from left to right on the front, then the one on the
+    /// back.
+    pub const ALL: [Button; 6] = [
+        Button::A,
+        Button::B,
+        Button::C,
+        Button::Up,
+        Button::Down,
+        Button::User,
+    ];
+
+    fn mask(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// A set of buttons, such as the ones held down at a given time.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct ButtonSet(u8);
+
+impl ButtonSet {
+    pub const fn empty() -> Self {
+        ButtonSet(0)
+    }
+
+    pub fn insert(&mut self, button: Button) {
+        self.0 |= button.mask();
+    }
+
+    pub fn contains(&self, button: Button) -> bool {
+        self.0 & button.mask() != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// The buttons in the set, in the order of [`Button::ALL`].
+    pub fn iter(&self) -> impl Iterator<Item = Button> {
+        let set = *self;
+        Button::ALL
+            .iter()
+            .copied()
+            .filter(move |&button| set.contains(button))
+    }
+}
+
+/// Reads the buttons.
+pub struct Buttons {
+    a: Pin<Gpio12, PullDownInput>,
+    b: Pin<Gpio13, PullDownInput>,
+    c: Pin<Gpio14, PullDownInput>,
+    up: Pin<Gpio15, PullDownInput>,
+    down: Pin<Gpio11, PullDownInput>,
+    user: Pin<Gpio23, PullUpInput>,
+}
+
+impl Buttons {
+    pub fn new(
+        sw_a: Pin<Gpio12, <Gpio12 as PinId>::Reset>,
+        sw_b: Pin<Gpio13, <Gpio13 as PinId>::Reset>,
+        sw_c: Pin<Gpio14, <Gpio14 as PinId>::Reset>,
+        sw_up: Pin<Gpio15, <Gpio15 as PinId>::Reset>,
+        sw_down: Pin<Gpio11, <Gpio11 as PinId>::Reset>,
+        user_sw: Pin<Gpio23, <Gpio23 as PinId>::Reset>,
+    ) -> Self {
+        let buttons = Buttons {
+            a: sw_a.into_pull_down_input(),
+            b: sw_b.into_pull_down_input(),
+            c: sw_c.into_pull_down_input(),
+            up: sw_up.into_pull_down_input(),
+            down: sw_down.into_pull_down_input(),
+            user: user_sw.into_pull_up_input(),
+        };
+        // `user_sw` comes out of reset pulled down, give the pull-up a moment
+        // to bring it high before it is read
+        cortex_m::asm::delay(100);
+        buttons
+    }
+
+    /// Whether `button` is held down.
+    pub fn is_pressed(&self, button: Button) -> bool {
+        // Reading GPIO inputs can't fail
+        match button {
+            Button::A => self.a.is_high().unwrap_or(false),
+            Button::B => self.b.is_high().unwrap_or(false),
+            Button::C => self.c.is_high().unwrap_or(false),
+            Button::Up => self.up.is_high().unwrap_or(false),
+            Button::Down => self.down.is_high().unwrap_or(false),
+            Button::User => self.user.is_low().unwrap_or(false),
+        }
+    }
+
+    /// The buttons held down.
+    pub fn pressed(&self) -> ButtonSet {
+        let mut set = ButtonSet::empty();
+        for &button in Button::ALL.iter() {
+            if self.is_pressed(button) {
+                set.insert(button);
+            }
+        }
+        set
+    }
+}
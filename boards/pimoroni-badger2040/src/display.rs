@@ -0,0 +1,363 @@
+//! The 2.9" e-ink panel, driven by a UC8151 controller.
+//!
+//! [`Badger2040Display`] draws into a [`Framebuffer`] in RAM, and sends it to
+//! the panel on [`Badger2040Display::update`] or
+//! [`Badger2040Display::update_window`]. Refreshing an e-ink panel takes from
+//! a fraction of a second to a few seconds, during which the controller holds
+//! the `inky_busy` pin low.
+
+use core::convert::Infallible;
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Dimensions, OriginDimensions, Size},
+    pixelcolor::BinaryColor,
+    primitives::Rectangle,
+    Pixel,
+};
+use embedded_hal::{
+    blocking::{delay::DelayUs, spi::Write},
+    digital::v2::{InputPin, OutputPin},
+};
+use hal::gpio::bank0::{Gpio17, Gpio20, Gpio21, Gpio26};
+use hal::gpio::{Pin, PinId, PullUpInput, PushPullOutput};
+
+use crate::framebuffer::{Framebuffer, HEIGHT, WIDTH};
+
+/// UC8151 commands.
+mod command {
+    pub const PSR: u8 = 0x00;
+    pub const PWR: u8 = 0x01;
+    pub const POF: u8 = 0x02;
+    pub const PFS: u8 = 0x03;
+    pub const PON: u8 = 0x04;
+    pub const BTST: u8 = 0x06;
+    pub const DSP: u8 = 0x11;
+    pub const DRF: u8 = 0x12;
+    pub const DTM2: u8 = 0x13;
+    pub const LUT_VCOM: u8 = 0x20;
+    pub const LUT_WW: u8 = 0x21;
+    pub const LUT_BW: u8 = 0x22;
+    pub const LUT_WB: u8 = 0x23;
+    pub const LUT_BB: u8 = 0x24;
+    pub const PLL: u8 = 0x30;
+    pub const TSE: u8 = 0x41;
+    pub const CDI: u8 = 0x50;
+    pub const TCON: u8 = 0x60;
+    pub const PTL: u8 = 0x90;
+    pub const PTIN: u8 = 0x91;
+    pub const PTOU: u8 = 0x92;
+}
+
+/// Panel settings: 128×296, black and white, booster on, no soft reset,
+/// scanning in the orientation of [`Framebuffer`].
+const PSR_BASE: u8 = 0b1001_0111;
+/// Panel settings bit selecting the waveforms sent with the LUT commands
+/// instead of the ones in the controller's OTP memory.
+const PSR_LUT_REG: u8 = 0b0010_0000;
+
+/// Internal supplies, with gate voltages of ±16V and source voltages of
+/// ±11V.
+const POWER_SETTINGS: [u8; 5] = [0x03, 0x00, 0x2B, 0x2B, 0x2B];
+
+/// Frame rate of 100Hz, used by the OTP waveforms.
+const PLL_100HZ: u8 = 0x3A;
+/// Frame rate of 200Hz, used by the fast waveforms.
+const PLL_200HZ: u8 = 0x39;
+
+/// Fast waveforms: three short phases instead of the long flashing of a full
+/// refresh.
+const FAST_LUT_VCOM: [u8; 44] = [
+    0x00, 0x04, 0x04, 0x07, 0x00, 0x01, //
+    0x00, 0x0C, 0x0C, 0x00, 0x00, 0x02, //
+    0x00, 0x04, 0x04, 0x07, 0x00, 0x02, //
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    0x00, 0x00,
+];
+/// Fast waveform for pixels ending up white.
+const FAST_LUT_TO_WHITE: [u8; 42] = [
+    0x54, 0x04, 0x04, 0x07, 0x00, 0x01, //
+    0x60, 0x0C, 0x0C, 0x00, 0x00, 0x02, //
+    0xA8, 0x04, 0x04, 0x07, 0x00, 0x02, //
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+/// Fast waveform for pixels ending up black.
+const FAST_LUT_TO_BLACK: [u8; 42] = [
+    0xA8, 0x04, 0x04, 0x07, 0x00, 0x01, //
+    0x60, 0x0C, 0x0C, 0x00, 0x00, 0x02, //
+    0x54, 0x04, 0x04, 0x07, 0x00, 0x02, //
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// How the panel is refreshed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RefreshMode {
+    /// The waveforms built into the controller. The panel flashes for about
+    /// four seconds, and ends up without any ghosting.
+    Full,
+    /// Short waveforms taking well under a second, which leave some ghosting
+    /// of the previous image. Do a [`RefreshMode::Full`] refresh every now
+    /// and then to clean it up.
+    Fast,
+}
+
+/// Drives the e-ink panel over SPI.
+///
+/// `SPI` is the SPI0 bus on the `sclk` and `mosi` pins, in mode 0 at up to
+/// 12MHz. Everything drawn through [`DrawTarget`] goes to the framebuffer
+/// only, and shows up on the next update.
+pub struct Badger2040Display<SPI> {
+    bus: Interface<SPI>,
+    reset: Pin<Gpio21, PushPullOutput>,
+    busy: Pin<Gpio26, PullUpInput>,
+    framebuffer: Framebuffer,
+    mode: RefreshMode,
+}
+
+impl<SPI> Badger2040Display<SPI>
+where
+    SPI: Write<u8>,
+{
+    /// Take control of the panel. It must be set up with
+    /// [`Badger2040Display::init`] before it can be updated.
+    pub fn new(
+        spi: SPI,
+        inky_cs_gpio: Pin<Gpio17, <Gpio17 as PinId>::Reset>,
+        inky_dc: Pin<Gpio20, <Gpio20 as PinId>::Reset>,
+        inky_res: Pin<Gpio21, <Gpio21 as PinId>::Reset>,
+        inky_busy: Pin<Gpio26, <Gpio26 as PinId>::Reset>,
+    ) -> Self {
+        let mut cs = inky_cs_gpio.into_push_pull_output();
+        // Setting GPIO outputs can't fail
+        let _ = cs.set_high();
+        Badger2040Display {
+            bus: Interface {
+                spi,
+                cs,
+                dc: inky_dc.into_push_pull_output(),
+            },
+            reset: inky_res.into_push_pull_output(),
+            busy: inky_busy.into_pull_up_input(),
+            framebuffer: Framebuffer::new(),
+            mode: RefreshMode::Full,
+        }
+    }
+
+    /// Reset the controller and set it up for `mode` refreshes.
+    pub fn init(
+        &mut self,
+        mode: RefreshMode,
+        delay: &mut impl DelayUs<u32>,
+    ) -> Result<(), SPI::Error> {
+        let _ = self.reset.set_low();
+        delay.delay_us(10_000);
+        let _ = self.reset.set_high();
+        delay.delay_us(10_000);
+        self.wait_until_idle();
+
+        self.set_mode(mode)?;
+        self.bus.command(command::PWR, &POWER_SETTINGS)?;
+        self.bus.command(command::PON, &[])?;
+        self.wait_until_idle();
+        // Booster soft start, as set by Pimoroni's UC8151 driver
+        self.bus.command(command::BTST, &[0x17, 0x17, 0x17])?;
+        self.bus.command(command::PFS, &[0x00])?;
+        self.bus.command(command::TSE, &[0x00])?;
+        self.bus.command(command::TCON, &[0x22])?;
+        // White border, with the data interval the waveforms are tuned for
+        self.bus.command(command::CDI, &[0x5C])?;
+        self.bus.command(command::POF, &[])?;
+        self.wait_until_idle();
+        Ok(())
+    }
+
+    pub fn mode(&self) -> RefreshMode {
+        self.mode
+    }
+
+    /// Use `mode` for the next updates.
+    pub fn set_mode(&mut self, mode: RefreshMode) -> Result<(), SPI::Error> {
+        match mode {
+            RefreshMode::Full => {
+                self.bus.command(command::PSR, &[PSR_BASE])?;
+                self.bus.command(command::PLL, &[PLL_100HZ])?;
+            }
+            RefreshMode::Fast => {
+                let bus = &mut self.bus;
+                bus.command(command::PSR, &[PSR_BASE | PSR_LUT_REG])?;
+                bus.command(command::LUT_VCOM, &FAST_LUT_VCOM)?;
+                bus.command(command::LUT_WW, &FAST_LUT_TO_WHITE)?;
+                bus.command(command::LUT_BW, &FAST_LUT_TO_WHITE)?;
+                bus.command(command::LUT_WB, &FAST_LUT_TO_BLACK)?;
+                bus.command(command::LUT_BB, &FAST_LUT_TO_BLACK)?;
+                bus.command(command::PLL, &[PLL_200HZ])?;
+            }
+        }
+        self.mode = mode;
+        Ok(())
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    pub fn framebuffer_mut(&mut self) -> &mut Framebuffer {
+        &mut self.framebuffer
+    }
+
+    /// Whether the panel is still refreshing.
+    pub fn is_busy(&self) -> bool {
+        // Reading GPIO inputs can't fail
+        self.busy.is_low().unwrap_or(false)
+    }
+
+    /// Wait for the panel to finish refreshing.
+    pub fn wait_until_idle(&self) {
+        while self.is_busy() {}
+    }
+
+    /// Show the whole framebuffer, and wait for the refresh to end.
+    pub fn update(&mut self) -> Result<(), SPI::Error> {
+        self.wait_until_idle();
+        self.bus.command(command::PON, &[])?;
+        self.bus.command(command::PTOU, &[])?;
+        self.bus
+            .command(command::DTM2, self.framebuffer.as_bytes())?;
+        self.finish_update()
+    }
+
+    /// Show the part of the framebuffer in `area`, leaving the rest of the
+    /// panel untouched, and wait for the refresh to end.
+    ///
+    /// The panel updates whole bytes of each column, so `area` is extended to
+    /// the nearest multiples of 8 pixels vertically. Partial updates are
+    /// meant for [`RefreshMode::Fast`]; with [`RefreshMode::Full`] they take
+    /// as long as a full update.
+    pub fn update_window(&mut self, area: Rectangle) -> Result<(), SPI::Error> {
+        let area = area.intersection(&self.framebuffer.bounding_box());
+        let bottom_right = match area.bottom_right() {
+            Some(point) => point,
+            None => return Ok(()),
+        };
+        let (left, right) = (area.top_left.x as u16, bottom_right.x as u16);
+        let top = area.top_left.y as u8 & !0x07;
+        let bottom = bottom_right.y as u8 | 0x07;
+
+        self.wait_until_idle();
+        self.bus.command(command::PON, &[])?;
+        self.bus.command(command::PTIN, &[])?;
+        // Columns of the framebuffer are the gate lines of the panel, and rows
+        // its source lines
+        let [left_high, left_low] = left.to_be_bytes();
+        let [right_high, right_low] = right.to_be_bytes();
+        self.bus.command(
+            command::PTL,
+            &[
+                top, bottom, left_high, left_low, right_high, right_low, 0x01,
+            ],
+        )?;
+
+        self.bus.command(command::DTM2, &[])?;
+        let rows = usize::from(top / 8)..=usize::from(bottom / 8);
+        for x in left..=right {
+            let column = &self.framebuffer.column(u32::from(x))[rows.clone()];
+            self.bus.data(column)?;
+        }
+        let result = self.finish_update();
+        self.bus.command(command::PTOU, &[])?;
+        result
+    }
+
+    /// Release the bus and the pins.
+    #[allow(clippy::type_complexity)]
+    pub fn free(
+        self,
+    ) -> (
+        SPI,
+        Pin<Gpio17, PushPullOutput>,
+        Pin<Gpio20, PushPullOutput>,
+        Pin<Gpio21, PushPullOutput>,
+        Pin<Gpio26, PullUpInput>,
+    ) {
+        let Interface { spi, cs, dc } = self.bus;
+        (spi, cs, dc, self.reset, self.busy)
+    }
+
+    /// Refresh the panel with the data sent, then power it off.
+    fn finish_update(&mut self) -> Result<(), SPI::Error> {
+        self.bus.command(command::DSP, &[])?;
+        self.bus.command(command::DRF, &[])?;
+        self.wait_until_idle();
+        self.bus.command(command::POF, &[])
+    }
+}
+
+/// The SPI bus and the pins framing its transfers.
+struct Interface<SPI> {
+    spi: SPI,
+    cs: Pin<Gpio17, PushPullOutput>,
+    dc: Pin<Gpio20, PushPullOutput>,
+}
+
+impl<SPI> Interface<SPI>
+where
+    SPI: Write<u8>,
+{
+    /// Send `command`, followed by its `data`.
+    fn command(&mut self, command: u8, data: &[u8]) -> Result<(), SPI::Error> {
+        let _ = self.dc.set_low();
+        self.transfer(&[command])?;
+        if !data.is_empty() {
+            self.data(data)?;
+        }
+        Ok(())
+    }
+
+    /// Send data for the last command.
+    fn data(&mut self, data: &[u8]) -> Result<(), SPI::Error> {
+        let _ = self.dc.set_high();
+        self.transfer(data)
+    }
+
+    fn transfer(&mut self, bytes: &[u8]) -> Result<(), SPI::Error> {
+        let _ = self.cs.set_low();
+        let result = self.spi.write(bytes);
+        let _ = self.cs.set_high();
+        result
+    }
+}
+
+impl<SPI> OriginDimensions for Badger2040Display<SPI> {
+    fn size(&self) -> Size {
+        Size::new(WIDTH, HEIGHT)
+    }
+}
+
+impl<SPI> DrawTarget for Badger2040Display<SPI> {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        self.framebuffer.draw_iter(pixels)
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.framebuffer.fill_solid(area, color)
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.framebuffer.clear(color)
+    }
+}
@@ -0,0 +1,267 @@
+//! The contents of the e-ink panel, kept in RAM.
+//!
+//! [`Framebuffer`] holds one bit per pixel, laid out the way the UC8151
+//! controller expects it, so it can be sent to the panel as it is. It
+//! doesn't touch any hardware, and implements
+//! [`DrawTarget`](embedded_graphics::draw_target::DrawTarget) so anything from
+//! `embedded-graphics` can be drawn into it.
+
+use core::convert::Infallible;
+
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Dimensions, OriginDimensions, Point, Size},
+    pixelcolor::BinaryColor,
+    primitives::Rectangle,
+    Pixel,
+};
+
+/// Width of the panel in pixels, with the buttons at the bottom.
+pub const WIDTH: u32 = 296;
+/// Height of the panel in pixels.
+pub const HEIGHT: u32 = 128;
+
+/// Bytes taken by each column of pixels.
+const COLUMN_BYTES: usize = HEIGHT as usize / 8;
+/// Bytes taken by the whole panel.
+pub const BUFFER_SIZE: usize = WIDTH as usize * COLUMN_BYTES;
+
+/// A 296×128 black and white image.
+///
+/// [`BinaryColor::On`] is black ink and [`BinaryColor::Off`] is white paper.
+/// Pixels are stored column by column from the left, each column from the top
+/// with the most significant bit first. A set bit is white, as the panel
+/// expects.
+#[derive(Clone)]
+pub struct Framebuffer {
+    data: [u8; BUFFER_SIZE],
+}
+
+impl Framebuffer {
+    /// A blank, white image.
+    pub const fn new() -> Self {
+        Framebuffer {
+            data: [0xFF; BUFFER_SIZE],
+        }
+    }
+
+    /// The color of the pixel at (`x`, `y`), or `None` outside the panel.
+    pub fn pixel(&self, x: u32, y: u32) -> Option<BinaryColor> {
+        let (index, mask) = Self::locate(x, y)?;
+        Some(if self.data[index] & mask == 0 {
+            BinaryColor::On
+        } else {
+            BinaryColor::Off
+        })
+    }
+
+    /// Set the pixel at (`x`, `y`) to `color`. Pixels outside the panel are
+    /// ignored.
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: BinaryColor) {
+        if let Some((index, mask)) = Self::locate(x, y) {
+            match color {
+                BinaryColor::On => self.data[index] &= !mask,
+                BinaryColor::Off => self.data[index] |= mask,
+            }
+        }
+    }
+
+    /// Flip the color of every pixel.
+    pub fn invert(&mut self) {
+        for byte in self.data.iter_mut() {
+            *byte = !*byte;
+        }
+    }
+
+    /// The raw contents of the panel, as sent to the UC8151.
+    pub fn as_bytes(&self) -> &[u8; BUFFER_SIZE] {
+        &self.data
+    }
+
+    /// The raw contents of column `x`, from top to bottom.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `x` is not below [`WIDTH`].
+    pub fn column(&self, x: u32) -> &[u8] {
+        let start = x as usize * COLUMN_BYTES;
+        &self.data[start..start + COLUMN_BYTES]
+    }
+
+    /// Byte index and bit mask of the pixel at (`x`, `y`).
+    fn locate(x: u32, y: u32) -> Option<(usize, u8)> {
+        if x >= WIDTH || y >= HEIGHT {
+            return None;
+        }
+        let index = x as usize * COLUMN_BYTES + y as usize / 8;
+        Some((index, 0x80 >> (y % 8)))
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        Size::new(WIDTH, HEIGHT)
+    }
+}
+
+impl DrawTarget for Framebuffer {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(Point { x, y }, color) in pixels {
+            // Negative coordinates wrap to large ones, which are ignored
+            self.set_pixel(x as u32, y as u32, color);
+        }
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let area = area.intersection(&self.bounding_box());
+        let bottom_right = match area.bottom_right() {
+            Some(point) => point,
+            None => return Ok(()),
+        };
+        let (top, bottom) = (area.top_left.y as u32, bottom_right.y as u32);
+        for x in area.top_left.x as u32..=bottom_right.x as u32 {
+            // Whole bytes in the middle of the column are set at once
+            let mut y = top;
+            while y <= bottom {
+                if y.is_multiple_of(8) && y + 7 <= bottom {
+                    let index = x as usize * COLUMN_BYTES + y as usize / 8;
+                    self.data[index] = match color {
+                        BinaryColor::On => 0x00,
+                        BinaryColor::Off => 0xFF,
+                    };
+                    y += 8;
+                } else {
+                    self.set_pixel(x, y, color);
+                    y += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.data = match color {
+            BinaryColor::On => [0x00; BUFFER_SIZE],
+            BinaryColor::Off => [0xFF; BUFFER_SIZE],
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::primitives::{PointsIter, Primitive, PrimitiveStyle};
+    use embedded_graphics::Drawable;
+
+    #[test]
+    fn starts_white() {
+        let framebuffer = Framebuffer::new();
+        assert!(framebuffer.as_bytes().iter().all(|byte| *byte == 0xFF));
+        assert_eq!(framebuffer.pixel(0, 0), Some(BinaryColor::Off));
+    }
+
+    #[test]
+    fn pixels_are_packed_by_column() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.set_pixel(0, 0, BinaryColor::On);
+        framebuffer.set_pixel(0, 7, BinaryColor::On);
+        framebuffer.set_pixel(0, 9, BinaryColor::On);
+        framebuffer.set_pixel(1, 0, BinaryColor::On);
+        framebuffer.set_pixel(WIDTH - 1, HEIGHT - 1, BinaryColor::On);
+        let bytes = framebuffer.as_bytes();
+        assert_eq!(bytes[0], 0b0111_1110);
+        assert_eq!(bytes[1], 0b1011_1111);
+        assert_eq!(bytes[COLUMN_BYTES], 0b0111_1111);
+        assert_eq!(bytes[BUFFER_SIZE - 1], 0b1111_1110);
+        let set = bytes.iter().map(|byte| byte.count_zeros()).sum::<u32>();
+        assert_eq!(set, 5);
+
+        assert_eq!(framebuffer.pixel(0, 9), Some(BinaryColor::On));
+        assert_eq!(framebuffer.pixel(0, 8), Some(BinaryColor::Off));
+        assert_eq!(framebuffer.column(1)[0], 0b0111_1111);
+        assert_eq!(framebuffer.column(1).len(), COLUMN_BYTES);
+
+        framebuffer.set_pixel(0, 0, BinaryColor::Off);
+        assert_eq!(framebuffer.as_bytes()[0], 0b1111_1110);
+    }
+
+    #[test]
+    fn pixels_outside_are_ignored() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.set_pixel(WIDTH, 0, BinaryColor::On);
+        framebuffer.set_pixel(0, HEIGHT, BinaryColor::On);
+        assert!(framebuffer.as_bytes().iter().all(|byte| *byte == 0xFF));
+        assert_eq!(framebuffer.pixel(WIDTH, 0), None);
+        assert_eq!(framebuffer.pixel(0, HEIGHT), None);
+
+        // Negative coordinates too, when drawing
+        Pixel(Point::new(-1, 5), BinaryColor::On)
+            .draw(&mut framebuffer)
+            .unwrap();
+        assert!(framebuffer.as_bytes().iter().all(|byte| *byte == 0xFF));
+    }
+
+    #[test]
+    fn invert_and_clear() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.set_pixel(3, 3, BinaryColor::On);
+        framebuffer.invert();
+        assert_eq!(framebuffer.pixel(3, 3), Some(BinaryColor::Off));
+        assert_eq!(framebuffer.pixel(3, 4), Some(BinaryColor::On));
+        framebuffer.clear(BinaryColor::Off).unwrap();
+        assert!(framebuffer.as_bytes().iter().all(|byte| *byte == 0xFF));
+        framebuffer.clear(BinaryColor::On).unwrap();
+        assert!(framebuffer.as_bytes().iter().all(|byte| *byte == 0x00));
+    }
+
+    #[test]
+    fn fill_solid_matches_drawing_pixels() {
+        let areas = [
+            Rectangle::new(Point::new(0, 0), Size::new(WIDTH, HEIGHT)),
+            Rectangle::new(Point::new(10, 8), Size::new(3, 16)),
+            Rectangle::new(Point::new(5, 3), Size::new(7, 30)),
+            Rectangle::new(Point::new(5, 3), Size::new(2, 4)),
+            Rectangle::new(Point::new(290, 120), Size::new(20, 20)),
+            Rectangle::new(Point::new(-4, -6), Size::new(10, 20)),
+            Rectangle::new(Point::new(20, 20), Size::new(0, 10)),
+            Rectangle::new(Point::new(400, 20), Size::new(10, 10)),
+        ];
+        for area in areas {
+            for (background, color) in [
+                (BinaryColor::Off, BinaryColor::On),
+                (BinaryColor::On, BinaryColor::Off),
+            ] {
+                let mut filled = Framebuffer::new();
+                filled.clear(background).unwrap();
+                let mut drawn = filled.clone();
+                filled.fill_solid(&area, color).unwrap();
+                area.into_styled(PrimitiveStyle::with_fill(color))
+                    .draw(&mut drawn)
+                    .unwrap();
+                // Drawing a styled rectangle could go through fill_solid too,
+                // so compare with pixels set one at a time as well
+                let mut pixels = Framebuffer::new();
+                pixels.clear(background).unwrap();
+                pixels
+                    .draw_iter(area.points().map(|point| Pixel(point, color)))
+                    .unwrap();
+                assert!(filled.as_bytes() == pixels.as_bytes(), "{:?}", area);
+                assert!(drawn.as_bytes() == pixels.as_bytes(), "{:?}", area);
+            }
+        }
+    }
+}
@@ -4,6 +4,19 @@ pub extern crate rp2040_hal as hal;
 
 pub use hal::pac;
 
+pub mod battery;
+pub use battery::{CurveError, DischargeCurve};
+pub mod battery_monitor;
+pub use battery_monitor::{BatteryMonitor, BatteryReading};
+pub mod buttons;
+pub use buttons::{Button, ButtonSet, Buttons};
+pub mod display;
+pub use display::{Badger2040Display, RefreshMode};
+pub mod framebuffer;
+pub use framebuffer::Framebuffer;
+pub mod power_latch;
+pub use power_latch::{PowerLatch, WakeReason};
+
 #[cfg(feature = "rt")]
 pub use rp2040_hal::entry;
 
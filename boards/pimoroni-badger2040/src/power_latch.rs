@@ -0,0 +1,79 @@
+//! Keep the board powered, or let it switch off.
+//!
+//! When running from batteries, pressing one of the front buttons powers the
+//! board up, but only for as long as the button is held. The firmware keeps
+//! the power on by driving `p3v3_en` high, and switches the board off by
+//! driving it low again; the next button press starts it from scratch.
+//! Which buttons were held down at boot tells why the board started, so a
+//! badge app can handle that press, then power off until the next one.
+
+use embedded_hal::digital::v2::OutputPin;
+use hal::gpio::bank0::Gpio10;
+use hal::gpio::{Pin, PinId, PinState, PushPullOutput};
+
+use crate::buttons::{ButtonSet, Buttons};
+
+/// Why the board started.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WakeReason {
+    /// These buttons were held down, which powers the board up when it runs
+    /// from batteries.
+    Buttons(ButtonSet),
+    /// No button was held down: the board was plugged in over USB, or reset.
+    PowerOn,
+}
+
+/// Holds the board power on through `p3v3_en`.
+pub struct PowerLatch {
+    pin: Pin<Gpio10, PushPullOutput>,
+    held: bool,
+    wake_reason: WakeReason,
+}
+
+impl PowerLatch {
+    /// Hold the power on at once, and work out why the board started from
+    /// the buttons held down.
+    ///
+    /// This should be called as early as possible, before the button that
+    /// powered the board up is released.
+    pub fn new(p3v3_en: Pin<Gpio10, <Gpio10 as PinId>::Reset>, buttons: &Buttons) -> Self {
+        let pin = p3v3_en.into_push_pull_output_in_state(PinState::High);
+        let pressed = buttons.pressed();
+        let wake_reason = if pressed.is_empty() {
+            WakeReason::PowerOn
+        } else {
+            WakeReason::Buttons(pressed)
+        };
+        PowerLatch {
+            pin,
+            held: true,
+            wake_reason,
+        }
+    }
+
+    pub fn wake_reason(&self) -> WakeReason {
+        self.wake_reason
+    }
+
+    /// Keep the board powered.
+    pub fn hold(&mut self) {
+        // Setting GPIO outputs can't fail
+        let _ = self.pin.set_high();
+        self.held = true;
+    }
+
+    /// Release the power, switching the board off once all buttons are
+    /// released.
+    ///
+    /// This does return: a board powered over USB keeps running, and should
+    /// carry on, for instance by waiting for the next button press.
+    pub fn power_off(&mut self) {
+        let _ = self.pin.set_low();
+        self.held = false;
+    }
+
+    /// Whether the power is held on.
+    pub fn is_held(&self) -> bool {
+        self.held
+    }
+}
@@ -0,0 +1,87 @@
+//! # Pimoroni Pico Explorer Motor Example
+//!
+//! Ramps both motors on the Pico Explorer up and down, reversing direction
+//! every cycle. Hold the A button to brake the motors instead of letting
+//! them coast.
+#![no_std]
+#![no_main]
+
+use hal::{adc::Adc, clocks::*, watchdog::Watchdog, Sio};
+use panic_halt as _;
+use pimoroni_pico_explorer::entry;
+use pimoroni_pico_explorer::{
+    hal, pac, Button, DecayMode, Motor, MotorAction, PicoExplorer, MOTOR_PWM_FREQ,
+    XOSC_CRYSTAL_FREQ,
+};
+
+#[entry]
+fn main() -> ! {
+    let mut p = pac::Peripherals::take().unwrap();
+    let cp = pac::CorePeripherals::take().unwrap();
+
+    // Enable watchdog and clocks
+    let mut watchdog = Watchdog::new(p.WATCHDOG);
+    let clocks = init_clocks_and_plls(
+        XOSC_CRYSTAL_FREQ,
+        p.XOSC,
+        p.CLOCKS,
+        p.PLL_SYS,
+        p.PLL_USB,
+        &mut p.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    let mut delay = cortex_m::delay::Delay::new(cp.SYST, clocks.system_clock.freq().to_Hz());
+
+    let adc = Adc::new(p.ADC, &mut p.RESETS);
+
+    let sio = Sio::new(p.SIO);
+
+    let (mut explorer, _pins) = PicoExplorer::new(
+        p.IO_BANK0,
+        p.PADS_BANK0,
+        sio.gpio_bank0,
+        p.SPI0,
+        p.PWM,
+        adc,
+        &mut p.RESETS,
+        &mut delay,
+    );
+
+    // Use the real system clock rather than the default assumption
+    explorer.set_motor_frequency(clocks.system_clock.freq(), MOTOR_PWM_FREQ);
+
+    let mut forward = true;
+    loop {
+        let mode = if explorer.is_pressed(Button::A) {
+            DecayMode::Brake
+        } else {
+            DecayMode::Coast
+        };
+        explorer.set_decay_mode(Motor::_1, mode);
+        explorer.set_decay_mode(Motor::_2, mode);
+
+        // Ramp up, then back down
+        for step in (0..=100).chain((0..100).rev()) {
+            let speed = step as f32 / 100.0;
+            let (action1, action2) = if forward {
+                (MotorAction::Forward(speed), MotorAction::Reverse(speed))
+            } else {
+                (MotorAction::Reverse(speed), MotorAction::Forward(speed))
+            };
+            explorer.set_motor(Motor::_1, action1);
+            explorer.set_motor(Motor::_2, action2);
+            delay.delay_ms(20);
+        }
+
+        explorer.set_motor(Motor::_1, MotorAction::Stop);
+        explorer.set_motor(Motor::_2, MotorAction::Stop);
+        delay.delay_ms(1000);
+
+        forward = !forward;
+    }
+}
+
+// End of file
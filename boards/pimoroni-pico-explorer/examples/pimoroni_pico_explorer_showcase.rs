@@ -53,6 +53,7 @@ fn main() -> ! {
         p.PADS_BANK0,
         sio.gpio_bank0,
         p.SPI0,
+        p.PWM,
         adc,
         &mut p.RESETS,
         &mut delay,
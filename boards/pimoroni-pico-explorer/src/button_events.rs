@@ -0,0 +1,315 @@
+//! Debounced button events for the A, B, X and Y buttons.
+//!
+//! [`ButtonEvents`] turns raw button levels into [`ButtonEvent`]s. It does not
+//! touch any hardware itself: feed it the current time and button levels,
+//! either by hand through [`ButtonEvents::update`] or straight from the board
+//! through [`ButtonEvents::poll`].
+
+use crate::{Button, PicoExplorer, PicoExplorerError};
+
+const BUTTONS: [Button; 4] = [Button::A, Button::B, Button::X, Button::Y];
+
+/// Something that happened to a button.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ButtonEvent {
+    /// The button was pressed down.
+    Pressed(Button),
+    /// The button was let go.
+    Released(Button),
+    /// The button has been held down for [`ButtonTimings::long_press_ms`].
+    LongPress(Button),
+    /// The button is still held down after a long press, sent every
+    /// [`ButtonTimings::repeat_interval_ms`].
+    Repeat(Button),
+}
+
+/// Timings used to generate [`ButtonEvent`]s, all in milliseconds.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ButtonTimings {
+    /// How long a button level must be stable before it is accepted.
+    pub debounce_ms: u32,
+    /// How long a button must be held before a [`ButtonEvent::LongPress`].
+    pub long_press_ms: u32,
+    /// Time between [`ButtonEvent::Repeat`]s once a button is long pressed,
+    /// or `None` to disable repeats.
+    pub repeat_interval_ms: Option<u32>,
+}
+
+impl Default for ButtonTimings {
+    fn default() -> Self {
+        ButtonTimings {
+            debounce_ms: 20,
+            long_press_ms: 800,
+            repeat_interval_ms: Some(200),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ButtonState {
+    /// Last raw level seen and when it was first seen
+    raw: bool,
+    raw_since: u32,
+    /// Debounced level and when it last changed
+    pressed: bool,
+    pressed_since: u32,
+    long_pressed: bool,
+    next_repeat: u32,
+}
+
+impl ButtonState {
+    const fn new() -> Self {
+        ButtonState {
+            raw: false,
+            raw_since: 0,
+            pressed: false,
+            pressed_since: 0,
+            long_pressed: false,
+            next_repeat: 0,
+        }
+    }
+
+    fn update(
+        &mut self,
+        button: Button,
+        raw: bool,
+        now: u32,
+        timings: &ButtonTimings,
+    ) -> Option<ButtonEvent> {
+        if raw != self.raw {
+            self.raw = raw;
+            self.raw_since = now;
+        }
+
+        if self.raw != self.pressed {
+            if now.wrapping_sub(self.raw_since) < timings.debounce_ms {
+                return None;
+            }
+            self.pressed = self.raw;
+            self.pressed_since = now;
+            self.long_pressed = false;
+            return Some(if self.pressed {
+                ButtonEvent::Pressed(button)
+            } else {
+                ButtonEvent::Released(button)
+            });
+        }
+
+        if !self.pressed {
+            return None;
+        }
+
+        if !self.long_pressed {
+            if now.wrapping_sub(self.pressed_since) < timings.long_press_ms {
+                return None;
+            }
+            self.long_pressed = true;
+            self.next_repeat = now.wrapping_add(timings.repeat_interval_ms.unwrap_or(0));
+            return Some(ButtonEvent::LongPress(button));
+        }
+
+        match timings.repeat_interval_ms {
+            // Compare through a wrapping difference so timestamps may roll over
+            Some(interval) if (now.wrapping_sub(self.next_repeat) as i32) >= 0 => {
+                self.next_repeat = self.next_repeat.wrapping_add(interval.max(1));
+                Some(ButtonEvent::Repeat(button))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Generates debounced press, release, long press and repeat events for the
+/// four buttons.
+///
+/// Timestamps are in milliseconds from any monotonic source, and may wrap
+/// around. Each button generates at most one event per update, so updates
+/// should happen at least as often as the shortest timing.
+pub struct ButtonEvents {
+    timings: ButtonTimings,
+    states: [ButtonState; 4],
+}
+
+impl ButtonEvents {
+    pub const fn new(timings: ButtonTimings) -> Self {
+        ButtonEvents {
+            timings,
+            states: [ButtonState::new(); 4],
+        }
+    }
+
+    pub fn timings(&self) -> &ButtonTimings {
+        &self.timings
+    }
+
+    pub fn set_timings(&mut self, timings: ButtonTimings) {
+        self.timings = timings;
+    }
+
+    /// Whether `button` is currently pressed, after debouncing.
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.states[button as usize].pressed
+    }
+
+    /// Feed the raw levels of the buttons, in `[A, B, X, Y]` order with `true`
+    /// meaning pressed, and get the resulting events.
+    pub fn update(&mut self, now_ms: u32, pressed: [bool; 4]) -> impl Iterator<Item = ButtonEvent> {
+        let mut events = [None; 4];
+        for (i, button) in BUTTONS.iter().enumerate() {
+            events[i] = self.states[i].update(*button, pressed[i], now_ms, &self.timings);
+        }
+        IntoIterator::into_iter(events).flatten()
+    }
+
+    /// Sample the buttons of `explorer` and get the resulting events.
+    pub fn poll(
+        &mut self,
+        explorer: &PicoExplorer,
+        now_ms: u32,
+    ) -> Result<impl Iterator<Item = ButtonEvent>, PicoExplorerError> {
+        let mut pressed = [false; 4];
+        for (i, button) in BUTTONS.iter().enumerate() {
+            pressed[i] = explorer.try_is_pressed(*button)?;
+        }
+        Ok(self.update(now_ms, pressed))
+    }
+}
+
+impl Default for ButtonEvents {
+    fn default() -> Self {
+        Self::new(ButtonTimings::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed the level of button A, with the others released, and get its
+    /// event.
+    fn update_a(events: &mut ButtonEvents, now_ms: u32, pressed: bool) -> Option<ButtonEvent> {
+        let mut events = events.update(now_ms, [pressed, false, false, false]);
+        let event = events.next();
+        assert_eq!(events.next(), None);
+        event
+    }
+
+    /// Press button A at `now_ms`, and get past the debounce time.
+    fn press_a(events: &mut ButtonEvents, now_ms: u32) -> u32 {
+        assert_eq!(update_a(events, now_ms, true), None);
+        let now_ms = now_ms.wrapping_add(20);
+        assert_eq!(
+            update_a(events, now_ms, true),
+            Some(ButtonEvent::Pressed(Button::A))
+        );
+        now_ms
+    }
+
+    #[test]
+    fn press_and_release_after_debounce() {
+        let mut events = ButtonEvents::default();
+        assert_eq!(update_a(&mut events, 0, true), None);
+        assert_eq!(update_a(&mut events, 19, true), None);
+        assert!(!events.is_pressed(Button::A));
+        assert_eq!(
+            update_a(&mut events, 20, true),
+            Some(ButtonEvent::Pressed(Button::A))
+        );
+        assert!(events.is_pressed(Button::A));
+        assert_eq!(update_a(&mut events, 100, false), None);
+        assert_eq!(update_a(&mut events, 119, false), None);
+        assert_eq!(
+            update_a(&mut events, 120, false),
+            Some(ButtonEvent::Released(Button::A))
+        );
+        assert!(!events.is_pressed(Button::A));
+    }
+
+    #[test]
+    fn bounces_are_ignored() {
+        let mut events = ButtonEvents::default();
+        assert_eq!(update_a(&mut events, 0, true), None);
+        assert_eq!(update_a(&mut events, 5, false), None);
+        assert_eq!(update_a(&mut events, 10, true), None);
+        assert_eq!(update_a(&mut events, 25, true), None);
+        assert_eq!(
+            update_a(&mut events, 30, true),
+            Some(ButtonEvent::Pressed(Button::A))
+        );
+        // A glitch shorter than the debounce time doesn't release the button
+        assert_eq!(update_a(&mut events, 40, false), None);
+        assert_eq!(update_a(&mut events, 50, true), None);
+        assert_eq!(update_a(&mut events, 80, true), None);
+        assert!(events.is_pressed(Button::A));
+    }
+
+    #[test]
+    fn long_press_then_repeats() {
+        let mut events = ButtonEvents::default();
+        let pressed = press_a(&mut events, 0);
+        assert_eq!(update_a(&mut events, pressed + 799, true), None);
+        assert_eq!(
+            update_a(&mut events, pressed + 800, true),
+            Some(ButtonEvent::LongPress(Button::A))
+        );
+        assert_eq!(update_a(&mut events, pressed + 999, true), None);
+        assert_eq!(
+            update_a(&mut events, pressed + 1000, true),
+            Some(ButtonEvent::Repeat(Button::A))
+        );
+        assert_eq!(update_a(&mut events, pressed + 1100, true), None);
+        // Repeats keep to the interval even when updates are late
+        assert_eq!(
+            update_a(&mut events, pressed + 1250, true),
+            Some(ButtonEvent::Repeat(Button::A))
+        );
+        assert_eq!(
+            update_a(&mut events, pressed + 1400, true),
+            Some(ButtonEvent::Repeat(Button::A))
+        );
+        assert_eq!(update_a(&mut events, pressed + 1500, false), None);
+        assert_eq!(
+            update_a(&mut events, pressed + 1520, false),
+            Some(ButtonEvent::Released(Button::A))
+        );
+    }
+
+    #[test]
+    fn repeats_can_be_disabled() {
+        let mut events = ButtonEvents::new(ButtonTimings {
+            repeat_interval_ms: None,
+            ..ButtonTimings::default()
+        });
+        let pressed = press_a(&mut events, 0);
+        assert_eq!(
+            update_a(&mut events, pressed + 800, true),
+            Some(ButtonEvent::LongPress(Button::A))
+        );
+        for now in (pressed + 801..pressed + 5000).step_by(50) {
+            assert_eq!(update_a(&mut events, now, true), None);
+        }
+    }
+
+    #[test]
+    fn timestamps_may_wrap() {
+        let mut events = ButtonEvents::default();
+        let pressed = press_a(&mut events, u32::MAX - 10);
+        assert_eq!(pressed, 9);
+        assert_eq!(
+            update_a(&mut events, pressed + 800, true),
+            Some(ButtonEvent::LongPress(Button::A))
+        );
+    }
+
+    #[test]
+    fn buttons_are_independent() {
+        let mut events = ButtonEvents::default();
+        assert_eq!(events.update(0, [true, false, false, true]).next(), None);
+        let mut pressed = events.update(20, [true, false, false, true]);
+        assert_eq!(pressed.next(), Some(ButtonEvent::Pressed(Button::A)));
+        assert_eq!(pressed.next(), Some(ButtonEvent::Pressed(Button::Y)));
+        assert_eq!(pressed.next(), None);
+        assert!(!events.is_pressed(Button::B));
+        assert!(events.is_pressed(Button::Y));
+    }
+}
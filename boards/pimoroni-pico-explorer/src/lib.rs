@@ -19,6 +19,8 @@ pub use hal::entry;
 #[used]
 pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
+use core::convert::Infallible;
+use display_interface::DisplayError;
 use display_interface_spi::SPIInterface;
 use embedded_graphics::{
     draw_target::DrawTarget,
@@ -29,8 +31,9 @@ use embedded_hal::{
     blocking::delay::DelayUs,
     digital::v2::{InputPin, OutputPin},
     spi::MODE_0,
+    PwmPin,
 };
-use fugit::RateExtU32;
+use fugit::{HertzU32, RateExtU32};
 pub use hal::pac;
 use hal::{
     adc::Adc,
@@ -40,14 +43,18 @@ use hal::{
             Gpio20, Gpio21, Gpio22, Gpio23, Gpio24, Gpio25, Gpio26, Gpio27, Gpio28, Gpio29, Gpio3,
             Gpio4, Gpio5, Gpio6, Gpio7,
         },
-        FunctionI2C, FunctionPwm, FunctionSpi, Pin, PinId, PullUpInput, PushPullOutput,
+        FunctionI2C, FunctionSpi, Pin, PinId, PullUpInput, PushPullOutput,
     },
-    pac::{RESETS, SPI0},
+    pac::{PWM, RESETS, SPI0},
+    pwm::{FreeRunning, Pwm4, Pwm5, Slice, Slices},
     sio::SioGpioBank0,
     spi::{Enabled, Spi},
 };
 use st7789::ST7789;
 
+pub mod button_events;
+pub use button_events::{ButtonEvent, ButtonEvents, ButtonTimings};
+
 pub mod all_pins {
     hal::bsp_pins!(
         Gpio0 { name: gpio0 },
@@ -140,6 +147,7 @@ pub struct Pins {
 }
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Button {
     A,
     B,
@@ -152,12 +160,71 @@ pub enum Motor {
     _2,
 }
 
+/// Speeds are given as a fraction of full speed and are clamped to `0.0..=1.0`.
 pub enum MotorAction {
     Forward(f32),
     Reverse(f32),
     Stop,
 }
 
+/// How the DRV8833 H-bridge treats the motor while the PWM signal is off.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DecayMode {
+    /// Fast decay: the motor freewheels between pulses and when stopped.
+    Coast,
+    /// Slow decay: the motor windings are shorted between pulses and when
+    /// stopped, which holds the motor in place.
+    Brake,
+}
+
+/// Default PWM frequency of the motor outputs, above the audible range.
+pub const MOTOR_PWM_FREQ: HertzU32 = HertzU32::from_raw(25_000);
+
+/// System clock assumed by [`PicoExplorer::new`] until
+/// [`PicoExplorer::set_motor_frequency`] is called.
+const DEFAULT_SYSTEM_CLOCK: HertzU32 = HertzU32::from_raw(125_000_000);
+
+impl MotorAction {
+    /// Compute the `(positive, negative)` duty cycles for the two H-bridge
+    /// inputs of a motor, for a PWM slice counting up to `top`.
+    ///
+    /// Full speed is a compare value of `top + 1`, so `top` must be below
+    /// `u16::MAX` for it to keep the output high for the whole period.
+    pub fn duty_cycles(&self, mode: DecayMode, top: u16) -> (u16, u16) {
+        let full = top.saturating_add(1);
+        let to_duty = |speed: f32| -> u16 {
+            // `f32::clamp` passes NaN through, and casting NaN to an integer yields 0
+            let speed = speed.clamp(0.0, 1.0);
+            ((speed * f32::from(full)) as u16).min(full)
+        };
+        let on = to_duty(1.0);
+
+        match (self, mode) {
+            (MotorAction::Forward(speed), DecayMode::Coast) => (to_duty(*speed), 0),
+            (MotorAction::Reverse(speed), DecayMode::Coast) => (0, to_duty(*speed)),
+            (MotorAction::Forward(speed), DecayMode::Brake) => (on, on - to_duty(*speed)),
+            (MotorAction::Reverse(speed), DecayMode::Brake) => (on - to_duty(*speed), on),
+            (MotorAction::Stop, DecayMode::Coast) => (0, 0),
+            (MotorAction::Stop, DecayMode::Brake) => (on, on),
+        }
+    }
+}
+
+/// Compute the integer divider, fractional divider (in 16ths) and top value
+/// needed to run a PWM slice at `frequency` from `system_clock`.
+///
+/// The smallest possible divider is used to keep the duty cycle resolution as
+/// high as possible. The period is kept to 65535 counts at most, so the
+/// compare value of full speed, `top + 1`, still fits in 16 bits.
+fn pwm_config(system_clock: HertzU32, frequency: HertzU32) -> (u8, u8, u16) {
+    const MAX_PERIOD: u64 = u16::MAX as u64;
+    let period_cycles = u64::from(system_clock.to_Hz()) / u64::from(frequency.to_Hz().max(1));
+    // Divider in 16ths, so it can be written to the integer and fractional registers directly
+    let div16 = (period_cycles * 16).div_ceil(MAX_PERIOD).clamp(16, 0xFFF);
+    let top = (period_cycles * 16 / div16).clamp(2, MAX_PERIOD) - 1;
+    ((div16 >> 4) as u8, (div16 & 0xF) as u8, top as u16)
+}
+
 pub type Screen = ST7789<
     SPIInterface<Spi<Enabled, SPI0, 8>, Pin<Gpio16, PushPullOutput>, Pin<Gpio17, PushPullOutput>>,
     DummyPin,
@@ -170,6 +237,47 @@ pub struct PicoExplorer {
     pub y: Pin<Gpio15, PullUpInput>,
     adc: Adc,
     pub screen: Screen,
+    motor1: Slice<Pwm4, FreeRunning>,
+    motor2: Slice<Pwm5, FreeRunning>,
+    motor_top: u16,
+    decay_modes: [DecayMode; 2],
+}
+
+/// Errors reported by the fallible [`PicoExplorer`] methods.
+#[derive(Debug)]
+pub enum PicoExplorerError {
+    /// The ST7789 driver failed to control the screen. Its
+    /// [`DisplayError`](st7789::Error::DisplayError) variant is for the SPI
+    /// bus failing, as the driver doesn't keep the error of the interface.
+    Display(st7789::Error<()>),
+    /// Writing to the screen through its display interface failed.
+    Spi(DisplayError),
+    /// Reading from the ADC failed.
+    Adc(nb::Error<()>),
+}
+
+impl From<st7789::Error<()>> for PicoExplorerError {
+    fn from(error: st7789::Error<()>) -> Self {
+        PicoExplorerError::Display(error)
+    }
+}
+
+impl From<DisplayError> for PicoExplorerError {
+    fn from(error: DisplayError) -> Self {
+        PicoExplorerError::Spi(error)
+    }
+}
+
+impl From<nb::Error<()>> for PicoExplorerError {
+    fn from(error: nb::Error<()>) -> Self {
+        PicoExplorerError::Adc(error)
+    }
+}
+
+impl From<Infallible> for PicoExplorerError {
+    fn from(error: Infallible) -> Self {
+        match error {}
+    }
 }
 
 pub struct DummyPin;
@@ -185,15 +293,45 @@ impl OutputPin for DummyPin {
 }
 
 impl PicoExplorer {
+    /// Set up the board and initialise the screen.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the screen fails to initialise, see [`PicoExplorer::try_new`]
+    /// for a fallible version.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         io: pac::IO_BANK0,
         pads: pac::PADS_BANK0,
         sio: SioGpioBank0,
         spi0: SPI0,
+        pwm: PWM,
         adc: Adc,
         resets: &mut RESETS,
         delay: &mut impl DelayUs<u32>,
     ) -> (Self, Pins) {
+        Self::try_new(io, pads, sio, spi0, pwm, adc, resets, delay)
+            .map_err(|(error, _, _)| error)
+            .unwrap()
+    }
+
+    /// Set up the board and initialise the screen, without panicking.
+    ///
+    /// If the screen fails to initialise, the error is returned together with
+    /// the board and its pins so the application can carry on without a
+    /// screen. Everything but `screen` is fully usable in that case, and
+    /// [`PicoExplorer::init_screen`] can be used to retry.
+    #[allow(clippy::too_many_arguments, clippy::type_complexity)]
+    pub fn try_new(
+        io: pac::IO_BANK0,
+        pads: pac::PADS_BANK0,
+        sio: SioGpioBank0,
+        spi0: SPI0,
+        pwm: PWM,
+        adc: Adc,
+        resets: &mut RESETS,
+        delay: &mut impl DelayUs<u32>,
+    ) -> Result<(Self, Pins), (PicoExplorerError, Self, Pins)> {
         let internal_pins = all_pins::Pins::new(io, pads, sio, resets);
 
         let a = internal_pins.switch_a.into_pull_up_input();
@@ -201,10 +339,17 @@ impl PicoExplorer {
         let x = internal_pins.switch_x.into_pull_up_input();
         let y = internal_pins.switch_y.into_pull_up_input();
 
-        internal_pins.motor1_pos.into_mode::<FunctionPwm>();
-        internal_pins.motor1_neg.into_mode::<FunctionPwm>();
-        internal_pins.motor2_pos.into_mode::<FunctionPwm>();
-        internal_pins.motor2_neg.into_mode::<FunctionPwm>();
+        let pwm_slices = Slices::new(pwm, resets);
+
+        // Motor 1 is driven by PWM4 (A: negative, B: positive) and motor 2 by
+        // PWM5 (A: negative, B: positive)
+        let mut motor1 = pwm_slices.pwm4;
+        motor1.channel_a.output_to(internal_pins.motor1_neg);
+        motor1.channel_b.output_to(internal_pins.motor1_pos);
+
+        let mut motor2 = pwm_slices.pwm5;
+        motor2.channel_a.output_to(internal_pins.motor2_neg);
+        motor2.channel_b.output_to(internal_pins.motor2_pos);
 
         let dc = internal_pins.spi_miso.into_push_pull_output();
         let cs = internal_pins.lcd_cs.into_push_pull_output();
@@ -215,62 +360,200 @@ impl PicoExplorer {
 
         let spii_screen = SPIInterface::new(spi_screen, dc, cs);
 
-        let mut screen = ST7789::new(spii_screen, DummyPin, 240, 240);
-
-        screen.init(delay).unwrap();
-        screen
-            .set_orientation(st7789::Orientation::Portrait)
-            .unwrap();
-        screen.clear(Rgb565::BLACK).unwrap();
-
-        (
-            PicoExplorer {
-                a,
-                b,
-                x,
-                y,
-                adc,
-                screen,
-            },
-            Pins {
-                gpio0: internal_pins.gpio0,
-                gpio1: internal_pins.gpio1,
-                gpio2: internal_pins.gpio2,
-                gpio3: internal_pins.gpio3,
-                gpio4: internal_pins.gpio4,
-                gpio5: internal_pins.gpio5,
-                gpio6: internal_pins.gpio6,
-                gpio7: internal_pins.gpio7,
-                spi_sclk,
-                spi_mosi,
-                i2c_sda: internal_pins.i2c_sda.into_mode(),
-                i2c_scl: internal_pins.i2c_scl.into_mode(),
-                i2c_int: internal_pins.i2c_int.into_mode(),
-                b_power_save: internal_pins.b_power_save,
-                vbus_detect: internal_pins.vbus_detect,
-                led: internal_pins.led,
-                adc0: internal_pins.adc0,
-                adc1: internal_pins.adc1,
-                adc2: internal_pins.adc2,
-                voltage_monitor: internal_pins.voltage_monitor,
-            },
-        )
+        let screen = ST7789::new(spii_screen, DummyPin, 240, 240);
+
+        let mut explorer = PicoExplorer {
+            a,
+            b,
+            x,
+            y,
+            adc,
+            screen,
+            motor1,
+            motor2,
+            motor_top: 0,
+            decay_modes: [DecayMode::Coast; 2],
+        };
+        explorer.set_motor_frequency(DEFAULT_SYSTEM_CLOCK, MOTOR_PWM_FREQ);
+        explorer.set_motor(Motor::_1, MotorAction::Stop);
+        explorer.set_motor(Motor::_2, MotorAction::Stop);
+        explorer.motor1.enable();
+        explorer.motor2.enable();
+
+        let pins = Pins {
+            gpio0: internal_pins.gpio0,
+            gpio1: internal_pins.gpio1,
+            gpio2: internal_pins.gpio2,
+            gpio3: internal_pins.gpio3,
+            gpio4: internal_pins.gpio4,
+            gpio5: internal_pins.gpio5,
+            gpio6: internal_pins.gpio6,
+            gpio7: internal_pins.gpio7,
+            spi_sclk,
+            spi_mosi,
+            i2c_sda: internal_pins.i2c_sda.into_mode(),
+            i2c_scl: internal_pins.i2c_scl.into_mode(),
+            i2c_int: internal_pins.i2c_int.into_mode(),
+            b_power_save: internal_pins.b_power_save,
+            vbus_detect: internal_pins.vbus_detect,
+            led: internal_pins.led,
+            adc0: internal_pins.adc0,
+            adc1: internal_pins.adc1,
+            adc2: internal_pins.adc2,
+            voltage_monitor: internal_pins.voltage_monitor,
+        };
+
+        match explorer.init_screen(delay) {
+            Ok(()) => Ok((explorer, pins)),
+            Err(error) => Err((error, explorer, pins)),
+        }
+    }
+
+    /// (Re-)initialise the screen and clear it to black.
+    pub fn init_screen(&mut self, delay: &mut impl DelayUs<u32>) -> Result<(), PicoExplorerError> {
+        self.screen.init(delay)?;
+        self.screen.set_orientation(st7789::Orientation::Portrait)?;
+        self.screen.clear(Rgb565::BLACK)?;
+        Ok(())
     }
 
     pub fn is_pressed(&self, button: Button) -> bool {
+        self.try_is_pressed(button).unwrap()
+    }
+
+    pub fn try_is_pressed(&self, button: Button) -> Result<bool, PicoExplorerError> {
         use Button::*;
-        match button {
-            A => self.a.is_low().unwrap(),
-            B => self.b.is_low().unwrap(),
-            X => self.x.is_low().unwrap(),
-            Y => self.y.is_low().unwrap(),
+        let pressed = match button {
+            A => self.a.is_low()?,
+            B => self.b.is_low()?,
+            X => self.x.is_low()?,
+            Y => self.y.is_low()?,
+        };
+        Ok(pressed)
+    }
+
+    /// Drive one of the motor outputs.
+    pub fn set_motor(&mut self, motor: Motor, action: MotorAction) {
+        match motor {
+            Motor::_1 => {
+                let (pos, neg) = action.duty_cycles(self.decay_modes[0], self.motor_top);
+                self.motor1.channel_b.set_duty(pos);
+                self.motor1.channel_a.set_duty(neg);
+            }
+            Motor::_2 => {
+                let (pos, neg) = action.duty_cycles(self.decay_modes[1], self.motor_top);
+                self.motor2.channel_b.set_duty(pos);
+                self.motor2.channel_a.set_duty(neg);
+            }
         }
     }
 
+    /// Select whether a motor coasts or brakes when not driven.
+    ///
+    /// Takes effect on the next call to [`PicoExplorer::set_motor`].
+    pub fn set_decay_mode(&mut self, motor: Motor, mode: DecayMode) {
+        match motor {
+            Motor::_1 => self.decay_modes[0] = mode,
+            Motor::_2 => self.decay_modes[1] = mode,
+        }
+    }
+
+    /// Change the PWM frequency of both motor outputs.
+    ///
+    /// `system_clock` is the frequency the PWM peripheral is clocked from.
+    /// Both motors are stopped, so call [`PicoExplorer::set_motor`] again
+    /// afterwards.
+    pub fn set_motor_frequency(&mut self, system_clock: HertzU32, frequency: HertzU32) {
+        let (div_int, div_frac, top) = pwm_config(system_clock, frequency);
+        self.motor_top = top;
+
+        self.motor1.set_div_int(div_int);
+        self.motor1.set_div_frac(div_frac);
+        self.motor1.set_top(top);
+        self.motor2.set_div_int(div_int);
+        self.motor2.set_div_frac(div_frac);
+        self.motor2.set_top(top);
+
+        self.set_motor(Motor::_1, MotorAction::Stop);
+        self.set_motor(Motor::_2, MotorAction::Stop);
+    }
+
     pub fn get_adc<Pin: Channel<Adc, ID = u8>>(&mut self, channel: &mut Pin) -> f32 {
+        self.try_get_adc(channel).unwrap()
+    }
+
+    pub fn try_get_adc<Pin: Channel<Adc, ID = u8>>(
+        &mut self,
+        channel: &mut Pin,
+    ) -> Result<f32, PicoExplorerError> {
         // scale raw 12-bit adc value to 0 .. 1 float
-        let adc_value: u16 = self.adc.read(channel).unwrap();
+        let adc_value: u16 = self.adc.read(channel)?;
         let result: f32 = f32::from(adc_value) / f32::from(1u16 << 12);
-        result.clamp(0.0, 1.0)
+        Ok(result.clamp(0.0, 1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOP: u16 = 4999;
+
+    #[test]
+    fn coast_drives_one_input() {
+        let forward = MotorAction::Forward(0.5).duty_cycles(DecayMode::Coast, TOP);
+        assert_eq!(forward, (2500, 0));
+        let reverse = MotorAction::Reverse(0.5).duty_cycles(DecayMode::Coast, TOP);
+        assert_eq!(reverse, (0, 2500));
+        let stop = MotorAction::Stop.duty_cycles(DecayMode::Coast, TOP);
+        assert_eq!(stop, (0, 0));
+    }
+
+    #[test]
+    fn brake_holds_one_input_high() {
+        let forward = MotorAction::Forward(0.25).duty_cycles(DecayMode::Brake, TOP);
+        assert_eq!(forward, (5000, 3750));
+        let reverse = MotorAction::Reverse(0.25).duty_cycles(DecayMode::Brake, TOP);
+        assert_eq!(reverse, (3750, 5000));
+        let stop = MotorAction::Stop.duty_cycles(DecayMode::Brake, TOP);
+        assert_eq!(stop, (5000, 5000));
+    }
+
+    #[test]
+    fn full_speed_is_a_whole_period() {
+        for mode in [DecayMode::Coast, DecayMode::Brake] {
+            let (pos, neg) = MotorAction::Forward(1.0).duty_cycles(mode, TOP);
+            assert_eq!(pos, TOP + 1);
+            assert_eq!(neg, 0);
+        }
+    }
+
+    #[test]
+    fn speeds_are_clamped() {
+        let action = |speed| MotorAction::Forward(speed).duty_cycles(DecayMode::Coast, TOP);
+        assert_eq!(action(2.0), (TOP + 1, 0));
+        assert_eq!(action(-1.0), (0, 0));
+        assert_eq!(action(f32::NAN), (0, 0));
+    }
+
+    #[test]
+    fn pwm_config_uses_the_smallest_divider() {
+        let clock = HertzU32::from_raw(125_000_000);
+        assert_eq!(pwm_config(clock, MOTOR_PWM_FREQ), (1, 0, 4999));
+        // 125MHz / 1kHz is 125000 cycles, so the clock is divided by 31/16
+        assert_eq!(pwm_config(clock, HertzU32::from_raw(1_000)), (1, 15, 64515));
+    }
+
+    #[test]
+    fn pwm_config_leaves_room_for_full_speed() {
+        let clock = HertzU32::from_raw(125_000_000);
+        for frequency in [
+            7, 100, 1_000, 1_907, 1_908, 1_909, 20_000, 25_000, 1_000_000,
+        ] {
+            let (_, _, top) = pwm_config(clock, HertzU32::from_raw(frequency));
+            assert!(top < u16::MAX, "{} Hz", frequency);
+            let (pos, _) = MotorAction::Forward(1.0).duty_cycles(DecayMode::Coast, top);
+            assert_eq!(u32::from(pos), u32::from(top) + 1);
+        }
     }
 }
@@ -0,0 +1,176 @@
+//! # Pimoroni Plasma 2040 LED Effects Example
+//!
+//! Plays the effects of the `led-effects` crate one after the other on an LED
+//! strip connected to the Pimoroni Plasma 2040 via its terminal block,
+//! cross-fading between them. Set `CHIPSET` to match the LEDs on your strip.
+
+#![no_std]
+#![no_main]
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+// Pull in any important traits
+use pimoroni_plasma_2040::hal::prelude::*;
+
+// A shorter alias for the Peripheral Access Crate, which provides low-level
+// register access
+use pimoroni_plasma_2040::hal::pac;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use pimoroni_plasma_2040::hal;
+
+// PIOExt for the split() method that is needed to bring
+// PIO0 into useable form for the strip:
+use pimoroni_plasma_2040::hal::pio::PIOExt;
+
+use pimoroni_plasma_2040::{Chipset, PlasmaStrip};
+
+// The effects, and the scheduler that writes them to the strip:
+use led_effects::animation::{
+    Animation, Breathe, Chase, Comet, Fire, FrameScheduler, Rainbow, Sparkle, Transition,
+};
+use smart_leds::RGB8;
+
+/// The LEDs on the strip.
+const CHIPSET: Chipset = Chipset::Ws2812;
+
+/// Number of LEDs on the strip.
+const STRIP_LEN: usize = 30;
+
+/// Time between two frames.
+const FRAME_MS: u32 = 20;
+
+/// How long each effect plays, including the fade in.
+const EFFECT_MS: u32 = 8000;
+
+/// How long the fade from one effect to the next takes.
+const FADE_MS: u32 = 1000;
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[pimoroni_plasma_2040::entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables and the spinlock are initialised.
+#[pimoroni_plasma_2040::entry]
+fn main() -> ! {
+    // Grab our singleton objects
+    let mut pac = pac::Peripherals::take().unwrap();
+    let core = pac::CorePeripherals::take().unwrap();
+
+    // Set up the watchdog driver - needed by the clock setup code
+    let mut watchdog = hal::Watchdog::new(pac.WATCHDOG);
+
+    // Configure the clocks
+    //
+    // The default is to generate a 125 MHz system clock
+    let clocks = hal::clocks::init_clocks_and_plls(
+        pimoroni_plasma_2040::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    // The single-cycle I/O block controls our GPIO pins
+    let sio = hal::Sio::new(pac.SIO);
+
+    // Set the pins up according to their function on this particular board
+    let pins = pimoroni_plasma_2040::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // Setup a delay to pace the main loop:
+    let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+
+    // Split the PIO state machine 0 into individual objects, so that
+    // the strip can use it:
+    let (mut pio, sm0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
+
+    let mut strip = PlasmaStrip::new(
+        CHIPSET,
+        pins.clk.into_mode(),
+        pins.data.into_mode(),
+        &mut pio,
+        sm0,
+        clocks.system_clock.freq(),
+    )
+    .unwrap();
+
+    let mut rainbow = Rainbow::new(5000, 255);
+    let mut chase = Chase::new(RGB8::new(255, 128, 0), 3, 100);
+    let mut breathe = Breathe::new(RGB8::new(0, 128, 255), 4000);
+    let mut sparkle: Sparkle<STRIP_LEN> = Sparkle::new(RGB8::new(255, 255, 255), 8, 400, 1);
+    let mut fire: Fire<STRIP_LEN> = Fire::new(2);
+    let mut comet = Comet::new(RGB8::new(255, 0, 255), 6, 1500);
+    comet.bounce = true;
+    let mut effects: [&mut dyn Animation; 6] = [
+        &mut rainbow,
+        &mut chase,
+        &mut breathe,
+        &mut sparkle,
+        &mut fire,
+        &mut comet,
+    ];
+
+    let mut scheduler: FrameScheduler<STRIP_LEN> = FrameScheduler::new(FRAME_MS);
+    // Keep the brightness low, to not blow the USB power supply
+    scheduler.set_brightness(32);
+
+    // Count the time from the delays of the main loop, which is close enough
+    // for animations
+    let mut now_ms: u32 = 0;
+    let mut current = 0;
+    let mut started_ms = now_ms;
+    loop {
+        let next = (current + 1) % effects.len();
+        let elapsed = now_ms.wrapping_sub(started_ms);
+        if elapsed >= EFFECT_MS {
+            current = next;
+            started_ms = now_ms;
+            continue;
+        }
+
+        // Fade to the next effect at the end of this one
+        let fade_start = started_ms.wrapping_add(EFFECT_MS - FADE_MS);
+        if elapsed >= EFFECT_MS - FADE_MS {
+            let (from, to) = pick_two(&mut effects, current, next);
+            let mut transition: Transition<_, _, STRIP_LEN> =
+                Transition::new(from, to, fade_start, FADE_MS);
+            scheduler.poll(now_ms, &mut transition, &mut strip).unwrap();
+        } else {
+            scheduler
+                .poll(now_ms, &mut effects[current], &mut strip)
+                .unwrap();
+        }
+
+        delay.delay_ms(FRAME_MS);
+        now_ms = now_ms.wrapping_add(FRAME_MS);
+    }
+}
+
+/// Borrow two different effects at once.
+fn pick_two<'a>(
+    effects: &'a mut [&mut dyn Animation],
+    a: usize,
+    b: usize,
+) -> (&'a mut dyn Animation, &'a mut dyn Animation) {
+    assert_ne!(a, b);
+    let (low, high) = effects.split_at_mut(a.max(b));
+    let (first, second) = (&mut *low[a.min(b)], &mut *high[0]);
+    if a < b {
+        (first, second)
+    } else {
+        (second, first)
+    }
+}
+
+// End of file
@@ -0,0 +1,110 @@
+//! # Pimoroni Plasma 2040 LED Strip Example
+//!
+//! Runs a rainbow along an LED strip connected to the Pimoroni Plasma 2040 via
+//! its terminal block. Set `CHIPSET` to match the LEDs on your strip.
+
+#![no_std]
+#![no_main]
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+// Pull in any important traits
+use pimoroni_plasma_2040::hal::prelude::*;
+
+// A shorter alias for the Peripheral Access Crate, which provides low-level
+// register access
+use pimoroni_plasma_2040::hal::pac;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use pimoroni_plasma_2040::hal;
+
+// PIOExt for the split() method that is needed to bring
+// PIO0 into useable form for the strip:
+use pimoroni_plasma_2040::hal::pio::PIOExt;
+
+use pimoroni_plasma_2040::{Chipset, PlasmaStrip};
+
+// Import useful traits to handle the LEDs:
+use led_effects::color::wheel;
+use smart_leds::SmartLedsWrite;
+
+/// The LEDs on the strip.
+const CHIPSET: Chipset = Chipset::Ws2812;
+
+/// Number of LEDs on the strip.
+const STRIP_LEN: usize = 30;
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[pimoroni_plasma_2040::entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables and the spinlock are initialised.
+#[pimoroni_plasma_2040::entry]
+fn main() -> ! {
+    // Grab our singleton objects
+    let mut pac = pac::Peripherals::take().unwrap();
+    let core = pac::CorePeripherals::take().unwrap();
+
+    // Set up the watchdog driver - needed by the clock setup code
+    let mut watchdog = hal::Watchdog::new(pac.WATCHDOG);
+
+    // Configure the clocks
+    //
+    // The default is to generate a 125 MHz system clock
+    let clocks = hal::clocks::init_clocks_and_plls(
+        pimoroni_plasma_2040::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    // The single-cycle I/O block controls our GPIO pins
+    let sio = hal::Sio::new(pac.SIO);
+
+    // Set the pins up according to their function on this particular board
+    let pins = pimoroni_plasma_2040::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // Setup a delay between frames:
+    let mut frame_delay =
+        cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+
+    // Split the PIO state machine 0 into individual objects, so that
+    // the strip can use it:
+    let (mut pio, sm0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
+
+    let mut strip = PlasmaStrip::new(
+        CHIPSET,
+        pins.clk.into_mode(),
+        pins.data.into_mode(),
+        &mut pio,
+        sm0,
+        clocks.system_clock.freq(),
+    )
+    .unwrap();
+
+    // Keep the brightness low, to not blow the USB power supply
+    strip.set_brightness(4);
+
+    let mut offset: u8 = 0;
+    loop {
+        let leds = (0..STRIP_LEN).map(|i| wheel(offset.wrapping_add((i * 256 / STRIP_LEN) as u8)));
+        strip.write(leds).unwrap();
+        offset = offset.wrapping_add(1);
+
+        frame_delay.delay_ms(16); // ~60 FPS
+    }
+}
+
+// End of file
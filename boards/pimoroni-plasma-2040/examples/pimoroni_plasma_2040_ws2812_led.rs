@@ -29,7 +29,13 @@ use pimoroni_plasma_2040::hal;
 use pimoroni_plasma_2040::hal::pio::PIOExt;
 
 // Import useful traits to handle the ws2812 LEDs:
-use smart_leds::{brightness, SmartLedsWrite, RGB8};
+use smart_leds::{SmartLedsWrite, RGB8};
+
+// Import the color conversion from the shared color math crate:
+use led_effects::color::{hsv_to_rgb, Hsv};
+
+// Import the current limiter to keep the strip within the USB power budget:
+use pimoroni_plasma_2040::{CurrentLimiter, CurrentLimiterConfig, CurrentSense};
 
 // Import the actual crate to handle the Ws2812 protocol:
 use ws2812_pio::Ws2812;
@@ -107,8 +113,15 @@ fn main() -> ! {
     // Bring down the overall brightness of the strip to not blow
     // the USB power supply: every LED draws ~60mA, RGB means 3 LEDs per
     // ws2812 LED, for 3 LEDs that would be: 3 * 3 * 60mA, which is
-    // already 540mA for just 3 white LEDs!
-    let strip_brightness = 64u8; // Limit brightness to 64/256
+    // already 540mA for just 3 white LEDs! Rather than guessing a safe
+    // brightness, measure the strip current and dim the strip when it
+    // goes over the budget:
+    let mut adc = hal::adc::Adc::new(pac.ADC, &mut pac.RESETS);
+    let mut current_sense = CurrentSense::new(pins.current_sense);
+    let mut limiter = CurrentLimiter::new(CurrentLimiterConfig {
+        budget_amps: 0.3,
+        release: 0.1,
+    });
 
     // Slow down timer by this factor (0.1 will result in 10 seconds):
     let animation_speed = 0.1;
@@ -126,22 +139,24 @@ fn main() -> ! {
             // Bring -1..1 sine range to 0..1 range:
             let sin_01 = (sin_11 + 1.0) * 0.5;
 
-            let hue = 360.0 * sin_01;
-            let sat = 1.0;
-            let val = 1.0;
+            let hue = (255.0 * sin_01) as u8;
+            let sat = 255;
+            let val = 255;
 
-            let rgb = hsv2rgb_u8(hue, sat, val);
-            *led = rgb.into();
+            *led = hsv_to_rgb(Hsv::new(hue, sat, val));
         }
 
         // Here the magic happens and the `leds` buffer is written to the
         // ws2812 LEDs:
-        ws.write(brightness(leds.iter().copied(), strip_brightness))
-            .unwrap();
+        ws.write(limiter.limit(leds.iter().copied())).unwrap();
 
         // Wait a bit until calculating the next frame:
         frame_delay.delay_ms(16); // ~60 FPS
 
+        // Measure the current drawn by the frame just sent, to adjust the
+        // brightness of the next one:
+        limiter.measure(&mut current_sense, &mut adc);
+
         // Increase the time counter variable and make sure it
         // stays inbetween 0.0 to 1.0 range:
         t += (16.0 / 1000.0) * animation_speed;
@@ -150,35 +165,3 @@ fn main() -> ! {
         }
     }
 }
-
-pub fn hsv2rgb(hue: f32, sat: f32, val: f32) -> (f32, f32, f32) {
-    let c = val * sat;
-    let v = (hue / 60.0) % 2.0 - 1.0;
-    let v = if v < 0.0 { -v } else { v };
-    let x = c * (1.0 - v);
-    let m = val - c;
-    let (r, g, b) = if hue < 60.0 {
-        (c, x, 0.0)
-    } else if hue < 120.0 {
-        (x, c, 0.0)
-    } else if hue < 180.0 {
-        (0.0, c, x)
-    } else if hue < 240.0 {
-        (0.0, x, c)
-    } else if hue < 300.0 {
-        (x, 0.0, c)
-    } else {
-        (c, 0.0, x)
-    };
-    (r + m, g + m, b + m)
-}
-
-pub fn hsv2rgb_u8(h: f32, s: f32, v: f32) -> (u8, u8, u8) {
-    let r = hsv2rgb(h, s, v);
-
-    (
-        (r.0 * 255.0) as u8,
-        (r.1 * 255.0) as u8,
-        (r.2 * 255.0) as u8,
-    )
-}
@@ -0,0 +1,260 @@
+//! Keep the LED strip current under a budget.
+//!
+//! The strip power goes through a shunt resistor, whose amplified voltage is
+//! on the `current_sense` pin. [`CurrentSense`] reads it in amps, and a
+//! [`CurrentLimiter`] uses those readings to scale down the brightness of the
+//! frames sent to the strip whenever they would draw too much.
+
+use embedded_hal::adc::OneShot;
+use hal::adc::Adc;
+use hal::gpio::bank0::Gpio29;
+use hal::gpio::{FloatingInput, Pin, PinId};
+use smart_leds::RGB8;
+
+use crate::{ADC_GAIN, SHUNT_RESISTOR};
+
+/// ADC reference voltage.
+const ADC_VREF: f32 = 3.3;
+/// Full scale of the 12-bit ADC.
+const ADC_FULL_SCALE: f32 = (1 << 12) as f32;
+
+/// Reads the strip current from the `current_sense` pin.
+pub struct CurrentSense {
+    pin: Pin<Gpio29, FloatingInput>,
+}
+
+impl CurrentSense {
+    pub fn new(current_sense: Pin<Gpio29, <Gpio29 as PinId>::Reset>) -> Self {
+        CurrentSense {
+            pin: current_sense.into_floating_input(),
+        }
+    }
+
+    /// Read the current drawn by the strip, in amps.
+    pub fn read_amps(&mut self, adc: &mut Adc) -> f32 {
+        // The RP2040 ADC blocks until the conversion is done and never fails
+        let raw: u16 = nb::block!(adc.read(&mut self.pin)).unwrap();
+        raw_to_amps(raw)
+    }
+
+    /// Release the pin.
+    pub fn free(self) -> Pin<Gpio29, FloatingInput> {
+        self.pin
+    }
+}
+
+/// Convert a raw 12-bit reading of the `current_sense` pin to amps.
+pub fn raw_to_amps(raw: u16) -> f32 {
+    let volts = f32::from(raw) * ADC_VREF / ADC_FULL_SCALE;
+    volts / ADC_GAIN as f32 / SHUNT_RESISTOR
+}
+
+/// Lowest scale a [`CurrentLimiter`] goes down to, so the LEDs still draw
+/// some current to measure and the scale can recover.
+const MIN_SCALE: f32 = 1.0 / 255.0;
+
+/// How a [`CurrentLimiter`] reacts to the measured current.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CurrentLimiterConfig {
+    /// The highest current in amps the strip may draw.
+    pub budget_amps: f32,
+    /// How much of the way back up to the allowed brightness, or to full
+    /// brightness at most, is taken on each update, from 0.0 (never) to 1.0
+    /// (at once). Lower values avoid flicker when the strip hovers around the
+    /// budget.
+    pub release: f32,
+}
+
+impl Default for CurrentLimiterConfig {
+    /// A budget fit for a USB port.
+    fn default() -> Self {
+        CurrentLimiterConfig {
+            budget_amps: 0.5,
+            release: 0.1,
+        }
+    }
+}
+
+/// Scales frame brightness so the measured strip current stays under a
+/// budget.
+///
+/// The current drawn by the LEDs is roughly proportional to their brightness.
+/// From each measurement, the limiter works out the scale that would bring
+/// the current to the budget. It drops to that scale at once when the
+/// current is too high, and rises back towards it gradually otherwise.
+pub struct CurrentLimiter {
+    config: CurrentLimiterConfig,
+    scale: f32,
+}
+
+impl CurrentLimiter {
+    pub const fn new(config: CurrentLimiterConfig) -> Self {
+        CurrentLimiter { config, scale: 1.0 }
+    }
+
+    pub fn config(&self) -> &CurrentLimiterConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: CurrentLimiterConfig) {
+        self.config = config;
+    }
+
+    /// The scale applied to frames, up to 1.0.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// The scale as a brightness from 0 to 255, as used by
+    /// [`smart_leds::brightness`].
+    pub fn brightness(&self) -> u8 {
+        (self.scale * 255.0 + 0.5) as u8
+    }
+
+    /// Feed a measurement of the strip current in amps, taken while showing
+    /// frames at [`CurrentLimiter::scale`], and get the new scale.
+    pub fn update(&mut self, measured_amps: f32) -> f32 {
+        let target = if measured_amps <= 0.0 {
+            1.0
+        } else {
+            // Capped at full brightness, so that after a frame drawing next
+            // to nothing, like an all-dark one, the scale still rises at the
+            // release rate rather than at once
+            let target = self.scale * self.config.budget_amps / measured_amps;
+            if target > 1.0 {
+                1.0
+            } else {
+                target
+            }
+        };
+        // A NaN target fails both comparisons, which keeps the scale as it is
+        if target < self.scale {
+            self.scale = target;
+        } else if target > self.scale {
+            self.scale += (target - self.scale) * self.config.release.clamp(0.0, 1.0);
+        }
+        self.scale = self.scale.clamp(MIN_SCALE, 1.0);
+        self.scale
+    }
+
+    /// Measure the strip current with `sense` and update the scale.
+    pub fn measure(&mut self, sense: &mut CurrentSense, adc: &mut Adc) -> f32 {
+        let amps = sense.read_amps(adc);
+        self.update(amps)
+    }
+
+    /// Scale the colors of a frame by [`CurrentLimiter::scale`].
+    pub fn limit<I>(&self, colors: I) -> impl Iterator<Item = RGB8>
+    where
+        I: Iterator<Item = RGB8>,
+    {
+        smart_leds::brightness(colors, self.brightness())
+    }
+}
+
+impl Default for CurrentLimiter {
+    fn default() -> Self {
+        Self::new(CurrentLimiterConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{} isn't {}",
+            actual,
+            expected
+        );
+    }
+
+    fn limiter() -> CurrentLimiter {
+        CurrentLimiter::new(CurrentLimiterConfig {
+            budget_amps: 1.0,
+            release: 0.5,
+        })
+    }
+
+    #[test]
+    fn raw_readings_to_amps() {
+        assert_near(raw_to_amps(0), 0.0);
+        // Half of 3.3V, through a gain of 50 and a 15mΩ shunt
+        assert_near(raw_to_amps(2048), 2.2);
+    }
+
+    #[test]
+    fn under_budget_keeps_full_brightness() {
+        let mut limiter = limiter();
+        assert_near(limiter.update(0.8), 1.0);
+        assert_eq!(limiter.brightness(), 255);
+    }
+
+    #[test]
+    fn over_budget_drops_at_once() {
+        let mut limiter = limiter();
+        assert_near(limiter.update(4.0), 0.25);
+        // At a quarter of the brightness the strip draws the budget
+        assert_near(limiter.update(1.0), 0.25);
+        // A brighter frame drops the scale further
+        assert_near(limiter.update(2.0), 0.125);
+        assert_eq!(limiter.brightness(), 32);
+    }
+
+    #[test]
+    fn under_budget_rises_at_the_release_rate() {
+        let mut limiter = limiter();
+        limiter.update(4.0);
+        // The allowed scale is 0.5, half of the way there is taken
+        assert_near(limiter.update(0.5), 0.375);
+        // The allowed scale is 0.75, half of the way there is taken
+        assert_near(limiter.update(0.5), 0.5625);
+    }
+
+    #[test]
+    fn dark_frames_ramp_back_up() {
+        for measured_amps in [0.0, 0.001] {
+            let mut limiter = limiter();
+            limiter.update(10.0);
+            let mut previous = limiter.scale();
+            assert_near(previous, 0.1);
+            for _ in 0..5 {
+                let scale = limiter.update(measured_amps);
+                // Half of the way up to full brightness, at most
+                assert_near(scale, previous + (1.0 - previous) / 2.0);
+                previous = scale;
+            }
+        }
+    }
+
+    #[test]
+    fn scale_stays_in_range() {
+        let mut limiter = limiter();
+        assert_near(limiter.update(1e9), MIN_SCALE);
+        assert_eq!(limiter.brightness(), 1);
+        for _ in 0..100 {
+            limiter.update(0.0);
+        }
+        assert_near(limiter.scale(), 1.0);
+    }
+
+    #[test]
+    fn nan_readings_are_ignored() {
+        let mut limiter = limiter();
+        limiter.update(2.0);
+        assert_near(limiter.update(f32::NAN), 0.5);
+    }
+
+    #[test]
+    fn frames_are_scaled() {
+        let mut limiter = limiter();
+        limiter.update(2.0);
+        let frame = [RGB8::new(255, 100, 0), RGB8::new(2, 4, 8)];
+        let mut limited = limiter.limit(frame.iter().copied());
+        assert_eq!(limited.next(), Some(RGB8::new(128, 50, 0)));
+        assert_eq!(limited.next(), Some(RGB8::new(1, 2, 4)));
+        assert_eq!(limited.next(), None);
+    }
+}
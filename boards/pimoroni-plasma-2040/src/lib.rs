@@ -15,6 +15,12 @@ pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
 pub use hal::pac;
 
+pub mod current_limiter;
+pub use current_limiter::{CurrentLimiter, CurrentLimiterConfig, CurrentSense};
+
+pub mod plasma_strip;
+pub use plasma_strip::{Chipset, PlasmaStrip};
+
 hal::bsp_pins!(
     /// GPIO 0 is connected to I2C0_SDA
     Gpio0 { name: i2c0_sda },
@@ -0,0 +1,254 @@
+//! Drive an LED strip on the `clk` and `data` terminals, with either WS2812
+//! or APA102 LEDs.
+//!
+//! Both chipsets are driven by a single PIO state machine, so switching
+//! between them only takes a different [`Chipset`] when creating the
+//! [`PlasmaStrip`]. Frames are written through the
+//! [`SmartLedsWrite`](smart_leds::SmartLedsWrite) trait, or through
+//! [`PlasmaStrip::write_with_brightness`] to also set the brightness of each
+//! LED.
+
+use fugit::HertzU32;
+use hal::gpio::bank0::{Gpio14, Gpio15};
+use hal::gpio::{Function, FunctionConfig, Pin, ValidPinMode};
+use hal::pio::{
+    Buffers, InstallError, PIOBuilder, PIOExt, PinDir, Running, ShiftDirection, StateMachine,
+    StateMachineIndex, Tx, UninitStateMachine, PIO,
+};
+use smart_leds::{SmartLedsWrite, RGB8};
+
+/// Highest per-LED brightness, as used by APA102 LEDs.
+pub const MAX_BRIGHTNESS: u8 = 31;
+
+/// Frequency the PIO program runs at. WS2812 LEDs take 10 cycles per bit
+/// (800kHz) and APA102 LEDs take 2 cycles per bit (4MHz).
+const PIO_FREQ_HZ: u32 = 8_000_000;
+
+/// The kind of LEDs on the strip.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Chipset {
+    /// WS2812 (NeoPixel) LEDs, with a single data line on `data`.
+    Ws2812,
+    /// APA102 (DotStar) LEDs, with the clock on `clk` and data on `data`.
+    Apa102,
+}
+
+/// An LED strip connected to the terminal block.
+///
+/// WS2812 LEDs have no brightness of their own, so the brightness is applied
+/// by scaling their colors. They also need about 300µs without data to latch
+/// a frame, so frames should not be written back to back.
+pub struct PlasmaStrip<P, SM>
+where
+    P: PIOExt + FunctionConfig,
+    SM: StateMachineIndex,
+{
+    chipset: Chipset,
+    _sm: StateMachine<(P, SM), Running>,
+    tx: Tx<(P, SM)>,
+    brightness: u8,
+}
+
+impl<P, SM> PlasmaStrip<P, SM>
+where
+    P: PIOExt + FunctionConfig,
+    SM: StateMachineIndex,
+{
+    /// Set up a strip of `chipset` LEDs.
+    ///
+    /// The `clk` pin is only used by APA102 LEDs, but is taken in both cases
+    /// so the chipset can be changed without touching anything else.
+    /// `system_clock` is the frequency the PIO is clocked from.
+    pub fn new(
+        chipset: Chipset,
+        clk: Pin<Gpio14, Function<P>>,
+        data: Pin<Gpio15, Function<P>>,
+        pio: &mut PIO<P>,
+        sm: UninitStateMachine<(P, SM)>,
+        system_clock: HertzU32,
+    ) -> Result<Self, InstallError>
+    where
+        Function<P>: ValidPinMode<Gpio14> + ValidPinMode<Gpio15>,
+    {
+        let (program, bits_per_led) = match chipset {
+            // Each bit starts high, and stays high for longer for a 1
+            Chipset::Ws2812 => (
+                pio_proc::pio_asm!(
+                    ".side_set 1",
+                    ".wrap_target",
+                    "bitloop:",
+                    "    out x, 1        side 0 [2]",
+                    "    jmp !x do_zero  side 1 [1]",
+                    "    jmp bitloop     side 1 [4]",
+                    "do_zero:",
+                    "    nop             side 0 [4]",
+                    ".wrap",
+                )
+                .program,
+                24,
+            ),
+            // Data changes on the falling edge of the clock, and is sampled on
+            // the rising edge
+            Chipset::Apa102 => (
+                pio_proc::pio_asm!(
+                    ".side_set 1",
+                    ".wrap_target",
+                    "    out pins, 1  side 0",
+                    "    nop          side 1",
+                    ".wrap",
+                )
+                .program,
+                32,
+            ),
+        };
+        let installed = pio.install(&program)?;
+
+        let divisor = system_clock.to_Hz() as f32 / PIO_FREQ_HZ as f32;
+        let builder = PIOBuilder::from_program(installed)
+            .buffers(Buffers::OnlyTx)
+            .out_shift_direction(ShiftDirection::Left)
+            .autopull(true)
+            .pull_threshold(bits_per_led)
+            .clock_divisor(divisor);
+        let (sm, tx) = match chipset {
+            Chipset::Ws2812 => {
+                let (mut sm, _, tx) = builder.side_set_pin_base(15).build(sm);
+                sm.set_pindirs([(15, PinDir::Output)]);
+                (sm, tx)
+            }
+            Chipset::Apa102 => {
+                let (mut sm, _, tx) = builder.out_pins(15, 1).side_set_pin_base(14).build(sm);
+                sm.set_pindirs([(14, PinDir::Output), (15, PinDir::Output)]);
+                (sm, tx)
+            }
+        };
+        // The pins are owned by the state machine from now on
+        let _ = (clk, data);
+
+        Ok(PlasmaStrip {
+            chipset,
+            _sm: sm.start(),
+            tx,
+            brightness: MAX_BRIGHTNESS,
+        })
+    }
+
+    pub fn chipset(&self) -> Chipset {
+        self.chipset
+    }
+
+    /// Set the brightness used by [`SmartLedsWrite::write`], from 0 to
+    /// [`MAX_BRIGHTNESS`]. Higher values are clamped.
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness.min(MAX_BRIGHTNESS);
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Write a frame of colors, each with its own brightness from 0 to
+    /// [`MAX_BRIGHTNESS`].
+    pub fn write_with_brightness<I>(&mut self, leds: I)
+    where
+        I: IntoIterator<Item = (RGB8, u8)>,
+    {
+        match self.chipset {
+            Chipset::Ws2812 => {
+                for (color, brightness) in leds {
+                    let color = scale(color, brightness);
+                    self.send(
+                        u32::from(color.g) << 24
+                            | u32::from(color.r) << 16
+                            | u32::from(color.b) << 8,
+                    );
+                }
+            }
+            Chipset::Apa102 => {
+                // Start frame
+                self.send(0);
+                let mut count: u32 = 0;
+                for (color, brightness) in leds {
+                    self.send(
+                        u32::from(0xE0 | brightness.min(MAX_BRIGHTNESS)) << 24
+                            | u32::from(color.b) << 16
+                            | u32::from(color.g) << 8
+                            | u32::from(color.r),
+                    );
+                    count += 1;
+                }
+                // Each LED delays the data by half a clock, so push enough
+                // extra clock pulses for the data to reach the last LED
+                for _ in 0..=count / 64 {
+                    self.send(0);
+                }
+            }
+        }
+    }
+
+    /// Queue one word for the state machine, waiting for room in the FIFO.
+    fn send(&mut self, word: u32) {
+        while !self.tx.write(word) {}
+    }
+}
+
+impl<P, SM> SmartLedsWrite for PlasmaStrip<P, SM>
+where
+    P: PIOExt + FunctionConfig,
+    SM: StateMachineIndex,
+{
+    type Color = RGB8;
+    type Error = ();
+
+    /// Write a frame of colors, at the brightness set with
+    /// [`PlasmaStrip::set_brightness`].
+    fn write<T, I>(&mut self, iterator: T) -> Result<(), ()>
+    where
+        T: Iterator<Item = I>,
+        I: Into<Self::Color>,
+    {
+        let brightness = self.brightness;
+        self.write_with_brightness(iterator.map(|color| (color.into(), brightness)));
+        Ok(())
+    }
+}
+
+/// Scale `color` by `brightness` / [`MAX_BRIGHTNESS`].
+fn scale(color: RGB8, brightness: u8) -> RGB8 {
+    let brightness = u16::from(brightness.min(MAX_BRIGHTNESS));
+    let channel = |c: u8| {
+        ((u16::from(c) * brightness + MAX_BRIGHTNESS as u16 / 2) / MAX_BRIGHTNESS as u16) as u8
+    };
+    RGB8::new(channel(color.r), channel(color.g), channel(color.b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLOR: RGB8 = RGB8 {
+        r: 255,
+        g: 100,
+        b: 1,
+    };
+
+    #[test]
+    fn scale_ends() {
+        assert_eq!(scale(COLOR, 0), RGB8::new(0, 0, 0));
+        assert_eq!(scale(COLOR, MAX_BRIGHTNESS), COLOR);
+    }
+
+    #[test]
+    fn scale_clamps_the_brightness() {
+        assert_eq!(scale(COLOR, MAX_BRIGHTNESS + 1), COLOR);
+        assert_eq!(scale(COLOR, u8::MAX), COLOR);
+    }
+
+    #[test]
+    fn scale_rounds_to_nearest() {
+        // 255 * 16 / 31 = 131.6, 100 * 16 / 31 = 51.6, 16 / 31 = 0.52
+        assert_eq!(scale(COLOR, 16), RGB8::new(132, 52, 1));
+        // 15 / 31 = 0.48
+        assert_eq!(scale(COLOR, 15).b, 0);
+    }
+}
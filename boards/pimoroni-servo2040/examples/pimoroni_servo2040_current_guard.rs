@@ -0,0 +1,155 @@
+//! # Pimoroni Servo2040 Over-Current Protection Example
+//!
+//! Sweeps all servos while watching the servo current. If the current stays
+//! above the limit for too long, all servos are disabled and the on-board
+//! user button has to be pressed to start again.
+#![no_std]
+#![no_main]
+
+use bsp::entry;
+use bsp::hal::{
+    adc::Adc,
+    clocks::{init_clocks_and_plls, Clock},
+    pac,
+    pio::PIOExt,
+    pwm::Slices,
+    sio::Sio,
+    watchdog::Watchdog,
+    Timer,
+};
+use bsp::{
+    AnalogMux, OverCurrentConfig, OverCurrentGuard, ProtectionState, ServoCluster, ServoPins,
+};
+use defmt::*;
+use defmt_rtt as _;
+use embedded_hal::digital::v2::InputPin;
+use embedded_hal::timer::CountDown;
+use fugit::ExtU32;
+use panic_halt as _;
+use pimoroni_servo2040 as bsp;
+
+/// Time between two iterations of the main loop.
+const TICK_MS: u32 = 10;
+
+#[entry]
+fn main() -> ! {
+    let mut pac = pac::Peripherals::take().unwrap();
+    let mut watchdog = Watchdog::new(pac.WATCHDOG);
+
+    let sio = Sio::new(pac.SIO);
+
+    let clocks = init_clocks_and_plls(
+        bsp::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    let pins = bsp::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    let timer = Timer::new(pac.TIMER, &mut pac.RESETS);
+    let mut delay = timer.count_down();
+
+    let user_sw = pins.user_sw.into_pull_up_input();
+
+    let adc = Adc::new(pac.ADC, &mut pac.RESETS);
+    let mut mux = AnalogMux::new(
+        pins.adc_addr_0,
+        pins.adc_addr_1,
+        pins.adc_addr_2,
+        pins.shared_adc,
+        adc,
+        clocks.system_clock.freq(),
+    );
+    mux.set_samples(8);
+
+    let pwm_slices = Slices::new(pac.PWM, &mut pac.RESETS);
+    let (mut pio, sm0, sm1, _, _) = pac.PIO0.split(&mut pac.RESETS);
+    let servo_pins = ServoPins {
+        servo1: pins.servo1.into_mode(),
+        servo2: pins.servo2.into_mode(),
+        servo3: pins.servo3.into_mode(),
+        servo4: pins.servo4.into_mode(),
+        servo5: pins.servo5.into_mode(),
+        servo6: pins.servo6.into_mode(),
+        servo7: pins.servo7.into_mode(),
+        servo8: pins.servo8.into_mode(),
+        servo9: pins.servo9.into_mode(),
+        servo10: pins.servo10.into_mode(),
+        servo11: pins.servo11.into_mode(),
+        servo12: pins.servo12.into_mode(),
+        servo13: pins.servo13.into_mode(),
+        servo14: pins.servo14.into_mode(),
+        servo15: pins.servo15.into_mode(),
+        servo16: pins.servo16.into_mode(),
+        servo17: pins.servo17.into_mode(),
+        servo18: pins.servo18.into_mode(),
+    };
+    let mut servos = ServoCluster::new(
+        servo_pins,
+        pwm_slices,
+        &mut pio,
+        sm0,
+        sm1,
+        clocks.system_clock.freq(),
+    )
+    .unwrap();
+
+    let mut guard = OverCurrentGuard::new(OverCurrentConfig {
+        limit_amps: 3.0,
+        trip_time_ms: 200,
+    });
+
+    let calibration = bsp::calibration::Calibration::angular();
+    let mut now_ms: u32 = 0;
+    let mut running = false;
+    loop {
+        if !running {
+            for servo in 0..bsp::NUM_SERVOS as usize {
+                servos.enable(servo);
+            }
+            running = true;
+        }
+
+        // Sweep from -45° to 45° and back every 4 seconds
+        let phase = (now_ms % 4000) as f32 / 4000.0;
+        let angle = if phase < 0.5 {
+            -45.0 + phase * 180.0
+        } else {
+            135.0 - phase * 180.0
+        };
+        for servo in 0..bsp::NUM_SERVOS as usize {
+            servos.set_value(servo, &calibration, angle);
+        }
+        let _ = nb::block!(servos.apply());
+
+        if guard.check(now_ms, &mut mux, &mut servos) == ProtectionState::Tripped {
+            warn!(
+                "Over-current: {} A, press the user button to restart",
+                guard.last_current()
+            );
+            while user_sw.is_high().unwrap() {
+                delay.start(TICK_MS.millis());
+                let _ = nb::block!(delay.wait());
+            }
+            guard.reset();
+            running = false;
+        }
+
+        delay.start(TICK_MS.millis());
+        let _ = nb::block!(delay.wait());
+        now_ms = now_ms.wrapping_add(TICK_MS);
+    }
+}
+
+// End of file
@@ -0,0 +1,88 @@
+//! # Pimoroni Servo2040 Current Meter Example
+//!
+//! Shows the current drawn by the servos as a bar graph on the 6 color LEDs
+//! of a Servo2040 board.
+#![no_std]
+#![no_main]
+
+use bsp::entry;
+use bsp::hal::{
+    adc::Adc,
+    clocks::{init_clocks_and_plls, Clock},
+    pac,
+    pio::PIOExt,
+    sio::Sio,
+    watchdog::Watchdog,
+    Timer,
+};
+use bsp::{AnalogMux, LedBar};
+use defmt::*;
+use defmt_rtt as _;
+use embedded_hal::timer::CountDown;
+use fugit::ExtU32;
+use panic_halt as _;
+use pimoroni_servo2040 as bsp;
+
+/// The current in amps that lights up the whole bar.
+const FULL_SCALE_AMPS: f32 = 3.0;
+
+#[entry]
+fn main() -> ! {
+    info!("Program start");
+    let mut pac = pac::Peripherals::take().unwrap();
+    let mut watchdog = Watchdog::new(pac.WATCHDOG);
+
+    let sio = Sio::new(pac.SIO);
+
+    let clocks = init_clocks_and_plls(
+        bsp::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    let pins = bsp::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    let timer = Timer::new(pac.TIMER, &mut pac.RESETS);
+    let mut delay = timer.count_down();
+
+    let (mut pio, sm0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
+    let mut leds = LedBar::new(
+        pins.led_data.into_mode(),
+        &mut pio,
+        sm0,
+        clocks.system_clock.freq(),
+    );
+    leds.set_brightness(32);
+
+    let adc = Adc::new(pac.ADC, &mut pac.RESETS);
+    let mut mux = AnalogMux::new(
+        pins.adc_addr_0,
+        pins.adc_addr_1,
+        pins.adc_addr_2,
+        pins.shared_adc,
+        adc,
+        clocks.system_clock.freq(),
+    );
+    mux.set_samples(16);
+
+    loop {
+        let current = mux.read_current();
+        leds.show_level(current / FULL_SCALE_AMPS);
+
+        delay.start(50.millis());
+        let _ = nb::block!(delay.wait());
+    }
+}
+
+// End of file
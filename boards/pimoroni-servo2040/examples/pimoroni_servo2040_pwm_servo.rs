@@ -25,8 +25,11 @@ use pimoroni_servo2040::hal::pac;
 // higher-level drivers.
 use pimoroni_servo2040::hal;
 
+// Maps servo angles to pulse widths and duty cycles
+use pimoroni_servo2040::calibration::Calibration;
+
 /// Number of microseconds for the pwm signal period.
-const PERIOD_US: u32 = 20_000;
+const PERIOD_US: f32 = 20_000.0;
 /// Max resolution for the pwm signal.
 const TOP: u16 = u16::MAX;
 
@@ -62,9 +65,10 @@ fn main() -> ! {
 
     let pwm_slices = hal::pwm::Slices::new(pac.PWM, &mut pac.RESETS);
 
-    const MIN_PULSE: u16 = 1000;
-    const MID_PULSE: u16 = 1500;
-    const MAX_PULSE: u16 = 2000;
+    // Pulse widths in microseconds for -90°, 0° and 90°.
+    // You may need to adjust the pulse widths since several servos from
+    // different manufacturers respond differently.
+    let calibration = Calibration::new(1000.0, 1500.0, 2000.0, -90.0, 90.0).unwrap();
 
     let mut pwm: hal::pwm::Slice<_, _> = pwm_slices.pwm0;
 
@@ -101,36 +105,25 @@ fn main() -> ! {
     let movement_delay = 400.millis();
 
     // Infinite loop, moving micro servo from one position to another.
-    // You may need to adjust the pulse width since several servos from
-    // different manufacturers respond differently.
     loop {
         // move to 0°
-        channel_a.set_duty(us_to_duty(MID_PULSE));
+        channel_a.set_duty(calibration.value_to_duty(0.0, PERIOD_US, TOP));
         count_down.start(movement_delay);
         let _ = nb::block!(count_down.wait());
 
         // 0° to 90°
-        channel_a.set_duty(us_to_duty(MAX_PULSE));
+        channel_a.set_duty(calibration.value_to_duty(90.0, PERIOD_US, TOP));
         count_down.start(movement_delay);
         let _ = nb::block!(count_down.wait());
 
         // 90° to 0°
-        channel_a.set_duty(us_to_duty(MID_PULSE));
+        channel_a.set_duty(calibration.value_to_duty(0.0, PERIOD_US, TOP));
         count_down.start(movement_delay);
         let _ = nb::block!(count_down.wait());
 
         // 0° to -90°
-        channel_a.set_duty(us_to_duty(MIN_PULSE));
+        channel_a.set_duty(calibration.value_to_duty(-90.0, PERIOD_US, TOP));
         count_down.start(movement_delay);
         let _ = nb::block!(count_down.wait());
     }
 }
-
-/// Convert microseconds to duty value.
-///
-/// This function uses the constants TOP and PERIOD_US defined at the top of the file.
-fn us_to_duty(us: u16) -> u16 {
-    // Do math in u32 so we maintain higher precision. If we do math in u16, we need to divide first
-    // and lose some precision when truncating the remainder.
-    (TOP as u32 * us as u32 / PERIOD_US) as u16
-}
@@ -13,11 +13,12 @@ use defmt::*;
 use defmt_rtt as _;
 use embedded_hal::timer::CountDown;
 use fugit::ExtU32;
+use led_effects::color::wheel;
 use panic_halt as _;
 use pimoroni_servo2040 as bsp;
 use rp2040_hal::pio::PIOExt;
 use rp2040_hal::Timer;
-use smart_leds::{brightness, SmartLedsWrite, RGB8};
+use smart_leds::{brightness, SmartLedsWrite};
 use ws2812_pio::Ws2812;
 
 #[entry]
@@ -85,23 +86,4 @@ fn main() -> ! {
     }
 }
 
-/// Convert a number from `0..=255` to an RGB color triplet.
-///
-/// The colours are a transition from red, to green, to blue and back to red.
-fn wheel(mut wheel_pos: u8) -> RGB8 {
-    wheel_pos = 255 - wheel_pos;
-    if wheel_pos < 85 {
-        // No green in this sector - red and blue only
-        (255 - (wheel_pos * 3), 0, wheel_pos * 3).into()
-    } else if wheel_pos < 170 {
-        // No red in this sector - green and blue only
-        wheel_pos -= 85;
-        (0, wheel_pos * 3, 255 - (wheel_pos * 3)).into()
-    } else {
-        // No blue in this sector - red and green only
-        wheel_pos -= 170;
-        (wheel_pos * 3, 255 - (wheel_pos * 3), 0).into()
-    }
-}
-
 // End of file
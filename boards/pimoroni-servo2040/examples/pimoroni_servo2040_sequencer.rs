@@ -0,0 +1,148 @@
+//! # Pimoroni Servo2040 Servo Sequencer Example
+//!
+//! Moves all 18 servos of a Servo2040 board through a looping sequence of
+//! keyframes: the odd servos lead, the even servos follow, and every move
+//! eases in and out.
+#![no_std]
+#![no_main]
+
+use bsp::calibration::Calibration;
+use bsp::entry;
+use bsp::hal::{
+    clocks::{init_clocks_and_plls, Clock},
+    pac,
+    pio::PIOExt,
+    pwm::Slices,
+    sio::Sio,
+    watchdog::Watchdog,
+    Timer,
+};
+use bsp::{Easing, Keyframe, ServoCluster, ServoPins, ServoSequencer};
+use embedded_hal::timer::CountDown;
+use fugit::ExtU32;
+use panic_halt as _;
+use pimoroni_servo2040 as bsp;
+
+const NUM_SERVOS: usize = bsp::NUM_SERVOS as usize;
+
+/// Time between two updates of the servo positions.
+const TICK_MS: u32 = 20;
+
+/// Targets moving the odd servos (servo1, servo3, ...) to `odd` and the even
+/// ones to `even`.
+fn odd_even(odd: f32, even: f32) -> [Option<f32>; NUM_SERVOS] {
+    let mut targets = [Some(even); NUM_SERVOS];
+    for target in targets.iter_mut().step_by(2) {
+        *target = Some(odd);
+    }
+    targets
+}
+
+#[entry]
+fn main() -> ! {
+    let mut pac = pac::Peripherals::take().unwrap();
+    let mut watchdog = Watchdog::new(pac.WATCHDOG);
+
+    let sio = Sio::new(pac.SIO);
+
+    let clocks = init_clocks_and_plls(
+        bsp::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    let pins = bsp::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    let timer = Timer::new(pac.TIMER, &mut pac.RESETS);
+    let mut delay = timer.count_down();
+
+    let pwm_slices = Slices::new(pac.PWM, &mut pac.RESETS);
+    let (mut pio, sm0, sm1, _, _) = pac.PIO0.split(&mut pac.RESETS);
+
+    let servo_pins = ServoPins {
+        servo1: pins.servo1.into_mode(),
+        servo2: pins.servo2.into_mode(),
+        servo3: pins.servo3.into_mode(),
+        servo4: pins.servo4.into_mode(),
+        servo5: pins.servo5.into_mode(),
+        servo6: pins.servo6.into_mode(),
+        servo7: pins.servo7.into_mode(),
+        servo8: pins.servo8.into_mode(),
+        servo9: pins.servo9.into_mode(),
+        servo10: pins.servo10.into_mode(),
+        servo11: pins.servo11.into_mode(),
+        servo12: pins.servo12.into_mode(),
+        servo13: pins.servo13.into_mode(),
+        servo14: pins.servo14.into_mode(),
+        servo15: pins.servo15.into_mode(),
+        servo16: pins.servo16.into_mode(),
+        servo17: pins.servo17.into_mode(),
+        servo18: pins.servo18.into_mode(),
+    };
+
+    let mut servos = ServoCluster::new(
+        servo_pins,
+        pwm_slices,
+        &mut pio,
+        sm0,
+        sm1,
+        clocks.system_clock.freq(),
+    )
+    .unwrap();
+
+    let sequence = [
+        Keyframe::all(0, 0.0, Easing::Linear),
+        Keyframe {
+            time_ms: 1000,
+            targets: odd_even(60.0, 0.0),
+            easing: Easing::EaseInOut,
+        },
+        Keyframe {
+            time_ms: 2000,
+            targets: odd_even(60.0, 60.0),
+            easing: Easing::EaseInOut,
+        },
+        Keyframe {
+            time_ms: 3000,
+            targets: odd_even(-60.0, 60.0),
+            easing: Easing::Cubic,
+        },
+        Keyframe {
+            time_ms: 4000,
+            targets: odd_even(-60.0, -60.0),
+            easing: Easing::Cubic,
+        },
+        Keyframe::all(5000, 0.0, Easing::EaseOut),
+    ];
+
+    let mut now_ms: u32 = 0;
+    let mut sequencer = ServoSequencer::new(&sequence, now_ms).unwrap();
+    sequencer.set_looping(true);
+
+    let calibration = Calibration::angular();
+    let _ = nb::block!(sequencer.update(now_ms, &mut servos, &calibration));
+    for servo in 0..NUM_SERVOS {
+        servos.enable(servo);
+    }
+
+    loop {
+        let _ = nb::block!(sequencer.update(now_ms, &mut servos, &calibration));
+
+        delay.start(TICK_MS.millis());
+        let _ = nb::block!(delay.wait());
+        now_ms = now_ms.wrapping_add(TICK_MS);
+    }
+}
+
+// End of file
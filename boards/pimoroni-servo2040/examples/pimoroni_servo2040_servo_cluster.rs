@@ -0,0 +1,112 @@
+//! # Pimoroni Servo2040 Servo Cluster Example
+//!
+//! Sweeps all 18 servos of a Servo2040 board back and forth, each one a bit
+//! behind the previous one.
+#![no_std]
+#![no_main]
+
+use bsp::entry;
+use bsp::hal::{
+    clocks::{init_clocks_and_plls, Clock},
+    pac,
+    pio::PIOExt,
+    pwm::Slices,
+    sio::Sio,
+    watchdog::Watchdog,
+    Timer,
+};
+use bsp::servo_cluster::{ServoCluster, ServoPins};
+use embedded_hal::timer::CountDown;
+use fugit::ExtU32;
+use panic_halt as _;
+use pimoroni_servo2040 as bsp;
+
+const MIN_PULSE: u16 = 1000;
+const MAX_PULSE: u16 = 2000;
+
+#[entry]
+fn main() -> ! {
+    let mut pac = pac::Peripherals::take().unwrap();
+    let mut watchdog = Watchdog::new(pac.WATCHDOG);
+
+    let sio = Sio::new(pac.SIO);
+
+    let clocks = init_clocks_and_plls(
+        bsp::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    let pins = bsp::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    let timer = Timer::new(pac.TIMER, &mut pac.RESETS);
+    let mut delay = timer.count_down();
+
+    let pwm_slices = Slices::new(pac.PWM, &mut pac.RESETS);
+    let (mut pio, sm0, sm1, _, _) = pac.PIO0.split(&mut pac.RESETS);
+
+    let servo_pins = ServoPins {
+        servo1: pins.servo1.into_mode(),
+        servo2: pins.servo2.into_mode(),
+        servo3: pins.servo3.into_mode(),
+        servo4: pins.servo4.into_mode(),
+        servo5: pins.servo5.into_mode(),
+        servo6: pins.servo6.into_mode(),
+        servo7: pins.servo7.into_mode(),
+        servo8: pins.servo8.into_mode(),
+        servo9: pins.servo9.into_mode(),
+        servo10: pins.servo10.into_mode(),
+        servo11: pins.servo11.into_mode(),
+        servo12: pins.servo12.into_mode(),
+        servo13: pins.servo13.into_mode(),
+        servo14: pins.servo14.into_mode(),
+        servo15: pins.servo15.into_mode(),
+        servo16: pins.servo16.into_mode(),
+        servo17: pins.servo17.into_mode(),
+        servo18: pins.servo18.into_mode(),
+    };
+
+    let mut servos = ServoCluster::new(
+        servo_pins,
+        pwm_slices,
+        &mut pio,
+        sm0,
+        sm1,
+        clocks.system_clock.freq(),
+    )
+    .unwrap();
+
+    for servo in 0..bsp::NUM_SERVOS as usize {
+        servos.set_pulse(servo, (MIN_PULSE + MAX_PULSE) / 2);
+        servos.enable(servo);
+    }
+    let _ = nb::block!(servos.apply());
+
+    // Triangle wave from 0 to 200 and back
+    let mut step: u16 = 0;
+    loop {
+        for servo in 0..bsp::NUM_SERVOS as usize {
+            let phase = (step + servo as u16 * 10) % 400;
+            let position = if phase < 200 { phase } else { 400 - phase };
+            servos.set_pulse(servo, MIN_PULSE + position * (MAX_PULSE - MIN_PULSE) / 200);
+        }
+        let _ = nb::block!(servos.apply());
+        step = (step + 1) % 400;
+
+        delay.start(20.millis());
+        let _ = nb::block!(delay.wait());
+    }
+}
+
+// End of file
@@ -0,0 +1,176 @@
+//! Read the sensor headers, servo rail voltage and servo current through the
+//! Servo2040's analog multiplexer.
+//!
+//! The six sensor headers and the voltage and current sense circuits all go
+//! through one analog multiplexer, whose output is connected to the
+//! `shared_adc` pin. The `adc_addr_0..2` pins select which input is routed to
+//! it.
+
+use embedded_hal::adc::OneShot;
+use embedded_hal::digital::v2::OutputPin;
+use fugit::HertzU32;
+use hal::adc::Adc;
+use hal::gpio::bank0::{Gpio22, Gpio24, Gpio25, Gpio29};
+use hal::gpio::{FloatingInput, Pin, PinId, PushPullOutput};
+
+use crate::{
+    CURRENT_GAIN, CURRENT_OFFSET, CURRENT_SENSE_ADDR, NUM_SENSORS, SENSOR_1_ADDR, SHUNT_RESISTOR,
+    VOLTAGE_GAIN, VOLTAGE_SENSE_ADDR,
+};
+
+/// ADC reference voltage.
+const ADC_VREF: f32 = 3.3;
+/// Full scale of the 12-bit ADC.
+const ADC_FULL_SCALE: f32 = (1 << 12) as f32;
+
+/// Default time given to the multiplexer output to settle after switching.
+pub const DEFAULT_SETTLE_TIME_US: u32 = 10;
+
+/// Reads the inputs of the analog multiplexer.
+pub struct AnalogMux {
+    addr_0: Pin<Gpio22, PushPullOutput>,
+    addr_1: Pin<Gpio24, PushPullOutput>,
+    addr_2: Pin<Gpio25, PushPullOutput>,
+    shared_adc: Pin<Gpio29, FloatingInput>,
+    adc: Adc,
+    /// The address currently selected, if any
+    selected: Option<u8>,
+    settle_cycles: u32,
+    system_clock: HertzU32,
+    samples: u16,
+}
+
+impl AnalogMux {
+    /// Take control of the multiplexer.
+    ///
+    /// `system_clock` is needed to time the settling delay after switching
+    /// inputs.
+    pub fn new(
+        adc_addr_0: Pin<Gpio22, <Gpio22 as PinId>::Reset>,
+        adc_addr_1: Pin<Gpio24, <Gpio24 as PinId>::Reset>,
+        adc_addr_2: Pin<Gpio25, <Gpio25 as PinId>::Reset>,
+        shared_adc: Pin<Gpio29, <Gpio29 as PinId>::Reset>,
+        adc: Adc,
+        system_clock: HertzU32,
+    ) -> Self {
+        let mut mux = AnalogMux {
+            addr_0: adc_addr_0.into_push_pull_output(),
+            addr_1: adc_addr_1.into_push_pull_output(),
+            addr_2: adc_addr_2.into_push_pull_output(),
+            shared_adc: shared_adc.into_floating_input(),
+            adc,
+            selected: None,
+            settle_cycles: 0,
+            system_clock,
+            samples: 1,
+        };
+        mux.set_settle_time_us(DEFAULT_SETTLE_TIME_US);
+        mux
+    }
+
+    /// Change how long to wait for the multiplexer output to settle after
+    /// switching inputs.
+    pub fn set_settle_time_us(&mut self, settle_time_us: u32) {
+        self.settle_cycles = (self.system_clock.to_MHz()).saturating_mul(settle_time_us);
+    }
+
+    /// Change how many samples are averaged for every reading.
+    ///
+    /// A count of 0 is treated as 1.
+    pub fn set_samples(&mut self, samples: u16) {
+        self.samples = samples.max(1);
+    }
+
+    /// Access the ADC, to read other channels like the on-board temperature
+    /// sensor or `adc0..2`.
+    pub fn adc(&mut self) -> &mut Adc {
+        &mut self.adc
+    }
+
+    /// Route multiplexer input `address` to `shared_adc`, and wait for it to
+    /// settle if it wasn't already selected.
+    pub fn select(&mut self, address: u8) {
+        if self.selected == Some(address) {
+            return;
+        }
+        // Setting GPIO outputs can't fail
+        let _ = self.addr_0.set_state((address & 0b001 != 0).into());
+        let _ = self.addr_1.set_state((address & 0b010 != 0).into());
+        let _ = self.addr_2.set_state((address & 0b100 != 0).into());
+        self.selected = Some(address);
+        cortex_m::asm::delay(self.settle_cycles);
+    }
+
+    /// Read the raw 12-bit ADC value of multiplexer input `address`, averaged
+    /// over the configured number of samples.
+    pub fn read_raw(&mut self, address: u8) -> f32 {
+        self.select(address);
+        let mut total: u32 = 0;
+        for _ in 0..self.samples {
+            // The RP2040 ADC blocks until the conversion is done and never fails
+            let sample: u16 = nb::block!(self.adc.read(&mut self.shared_adc)).unwrap();
+            total += u32::from(sample);
+        }
+        total as f32 / f32::from(self.samples)
+    }
+
+    /// Read the voltage on multiplexer input `address`.
+    pub fn read_volts(&mut self, address: u8) -> f32 {
+        self.read_raw(address) * ADC_VREF / ADC_FULL_SCALE
+    }
+
+    /// Read the voltage on sensor header `sensor`, numbered from 0 (sensor 1)
+    /// to 5 (sensor 6).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sensor` is not below [`NUM_SENSORS`].
+    pub fn read_sensor(&mut self, sensor: u8) -> f32 {
+        assert!(sensor < NUM_SENSORS, "sensor {} out of range", sensor);
+        self.read_volts(SENSOR_1_ADDR + sensor)
+    }
+
+    /// Read the voltage of the servo power rail.
+    pub fn read_voltage(&mut self) -> f32 {
+        let volts = self.read_volts(VOLTAGE_SENSE_ADDR);
+        volts_to_rail_voltage(volts)
+    }
+
+    /// Read the total current drawn by the servos, in amps.
+    pub fn read_current(&mut self) -> f32 {
+        let volts = self.read_volts(CURRENT_SENSE_ADDR);
+        volts_to_current(volts)
+    }
+
+    /// Release the pins and the ADC.
+    #[allow(clippy::type_complexity)]
+    pub fn free(
+        self,
+    ) -> (
+        Pin<Gpio22, PushPullOutput>,
+        Pin<Gpio24, PushPullOutput>,
+        Pin<Gpio25, PushPullOutput>,
+        Pin<Gpio29, FloatingInput>,
+        Adc,
+    ) {
+        (
+            self.addr_0,
+            self.addr_1,
+            self.addr_2,
+            self.shared_adc,
+            self.adc,
+        )
+    }
+}
+
+/// Convert the voltage measured on the voltage sense input to the servo rail
+/// voltage.
+pub fn volts_to_rail_voltage(volts: f32) -> f32 {
+    volts / VOLTAGE_GAIN
+}
+
+/// Convert the voltage measured on the current sense input to the servo
+/// current in amps.
+pub fn volts_to_current(volts: f32) -> f32 {
+    (volts + CURRENT_OFFSET) / (f32::from(CURRENT_GAIN) * SHUNT_RESISTOR)
+}
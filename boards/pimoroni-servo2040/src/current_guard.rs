@@ -0,0 +1,221 @@
+//! Over-current protection for the servo power rail.
+//!
+//! Stalled servos can draw enough current to brown out the supply. An
+//! [`OverCurrentGuard`] watches the servo current and, once it has stayed
+//! above a limit for long enough, disables all servo outputs until it is
+//! explicitly reset.
+
+use hal::gpio::FunctionConfig;
+use hal::pio::{PIOExt, StateMachineIndex};
+
+use crate::{AnalogMux, ServoCluster};
+
+/// When the guard trips.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct OverCurrentConfig {
+    /// Current in amps above which the servos are overloaded.
+    pub limit_amps: f32,
+    /// How long in milliseconds the current must stay above the limit before
+    /// the guard trips.
+    pub trip_time_ms: u32,
+}
+
+/// The state of an [`OverCurrentGuard`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProtectionState {
+    /// The current is below the limit.
+    Normal,
+    /// The current is above the limit, but not for long enough to trip.
+    OverCurrent,
+    /// The current was above the limit for too long and the servo outputs
+    /// were disabled. Stays in this state until [`OverCurrentGuard::reset`].
+    Tripped,
+}
+
+/// Disables all servos when the servo current stays above a limit.
+///
+/// The guard does nothing on its own: call [`OverCurrentGuard::check`]
+/// periodically, for example from the main loop or a timer interrupt, with a
+/// millisecond timestamp from any monotonic source. Timestamps may wrap
+/// around.
+pub struct OverCurrentGuard {
+    config: OverCurrentConfig,
+    state: ProtectionState,
+    over_since: u32,
+    last_current: f32,
+    /// Whether the servos still have to be disabled after tripping.
+    disable_pending: bool,
+}
+
+impl OverCurrentGuard {
+    pub const fn new(config: OverCurrentConfig) -> Self {
+        OverCurrentGuard {
+            config,
+            state: ProtectionState::Normal,
+            over_since: 0,
+            last_current: 0.0,
+            disable_pending: false,
+        }
+    }
+
+    pub fn config(&self) -> &OverCurrentConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: OverCurrentConfig) {
+        self.config = config;
+    }
+
+    pub fn state(&self) -> ProtectionState {
+        self.state
+    }
+
+    pub fn is_tripped(&self) -> bool {
+        self.state == ProtectionState::Tripped
+    }
+
+    /// The current in amps seen by the last update.
+    pub fn last_current(&self) -> f32 {
+        self.last_current
+    }
+
+    /// Feed a current measurement in amps taken at `now_ms`, and get the new
+    /// state.
+    ///
+    /// This only tracks the state; use [`OverCurrentGuard::check`] to also
+    /// measure the current and disable the servos.
+    pub fn update(&mut self, now_ms: u32, current_amps: f32) -> ProtectionState {
+        self.last_current = current_amps;
+        self.state = match self.state {
+            ProtectionState::Tripped => ProtectionState::Tripped,
+            _ if current_amps <= self.config.limit_amps => ProtectionState::Normal,
+            ProtectionState::Normal => {
+                self.over_since = now_ms;
+                if self.config.trip_time_ms == 0 {
+                    ProtectionState::Tripped
+                } else {
+                    ProtectionState::OverCurrent
+                }
+            }
+            ProtectionState::OverCurrent => {
+                if now_ms.wrapping_sub(self.over_since) >= self.config.trip_time_ms {
+                    ProtectionState::Tripped
+                } else {
+                    ProtectionState::OverCurrent
+                }
+            }
+        };
+        self.state
+    }
+
+    /// Measure the servo current and disable all servos if the guard trips.
+    ///
+    /// The disabled outputs are applied when the guard trips, or on the next
+    /// checks if [`ServoCluster::apply`] can't take them yet, so a check
+    /// never waits on the servos. While tripped, the servos are staged as
+    /// disabled again on every check, so the next apply doesn't turn on
+    /// servos enabled in the meantime.
+    pub fn check<P, SM17, SM18>(
+        &mut self,
+        now_ms: u32,
+        mux: &mut AnalogMux,
+        servos: &mut ServoCluster<P, SM17, SM18>,
+    ) -> ProtectionState
+    where
+        P: PIOExt + FunctionConfig,
+        SM17: StateMachineIndex,
+        SM18: StateMachineIndex,
+    {
+        let current = mux.read_current();
+        let was_tripped = self.is_tripped();
+        let state = self.update(now_ms, current);
+        if state == ProtectionState::Tripped {
+            if !was_tripped {
+                self.disable_pending = true;
+            }
+            servos.disable_all();
+            if self.disable_pending && servos.apply().is_ok() {
+                self.disable_pending = false;
+            }
+        }
+        state
+    }
+
+    /// Re-arm a tripped guard.
+    ///
+    /// The servos stay disabled; enable the ones needed again afterwards.
+    pub fn reset(&mut self) {
+        self.state = ProtectionState::Normal;
+        self.disable_pending = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard(trip_time_ms: u32) -> OverCurrentGuard {
+        OverCurrentGuard::new(OverCurrentConfig {
+            limit_amps: 3.0,
+            trip_time_ms,
+        })
+    }
+
+    #[test]
+    fn trips_after_the_trip_time() {
+        let mut guard = guard(200);
+        assert_eq!(guard.update(0, 1.0), ProtectionState::Normal);
+        assert_eq!(guard.update(100, 3.5), ProtectionState::OverCurrent);
+        assert_eq!(guard.update(299, 3.5), ProtectionState::OverCurrent);
+        assert!(!guard.is_tripped());
+        assert_eq!(guard.update(300, 3.5), ProtectionState::Tripped);
+        assert!(guard.is_tripped());
+        assert_eq!(guard.last_current(), 3.5);
+    }
+
+    #[test]
+    fn the_limit_itself_is_fine() {
+        let mut guard = guard(0);
+        assert_eq!(guard.update(0, 3.0), ProtectionState::Normal);
+    }
+
+    #[test]
+    fn dips_below_the_limit_restart_the_timer() {
+        let mut guard = guard(200);
+        guard.update(0, 3.5);
+        guard.update(150, 3.5);
+        assert_eq!(guard.update(160, 2.0), ProtectionState::Normal);
+        assert_eq!(guard.update(170, 3.5), ProtectionState::OverCurrent);
+        assert_eq!(guard.update(369, 3.5), ProtectionState::OverCurrent);
+        assert_eq!(guard.update(370, 3.5), ProtectionState::Tripped);
+    }
+
+    #[test]
+    fn no_trip_time_trips_at_once() {
+        let mut guard = guard(0);
+        assert_eq!(guard.update(0, 3.5), ProtectionState::Tripped);
+    }
+
+    #[test]
+    fn timestamps_may_wrap_around() {
+        let mut guard = guard(200);
+        assert_eq!(
+            guard.update(u32::MAX - 99, 3.5),
+            ProtectionState::OverCurrent
+        );
+        assert_eq!(guard.update(99, 3.5), ProtectionState::OverCurrent);
+        assert_eq!(guard.update(100, 3.5), ProtectionState::Tripped);
+    }
+
+    #[test]
+    fn stays_tripped_until_reset() {
+        let mut guard = guard(0);
+        guard.update(0, 3.5);
+        assert_eq!(guard.update(10, 0.0), ProtectionState::Tripped);
+        guard.reset();
+        assert_eq!(guard.state(), ProtectionState::Normal);
+        assert_eq!(guard.update(20, 0.0), ProtectionState::Normal);
+        // And trips again afterwards
+        assert_eq!(guard.update(30, 3.5), ProtectionState::Tripped);
+    }
+}
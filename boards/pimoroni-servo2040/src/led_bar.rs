@@ -0,0 +1,132 @@
+//! The six addressable LEDs on the `led_data` pin.
+//!
+//! [`LedBar`] keeps the colors of the LEDs in a buffer, and sends them to
+//! the LEDs through a PIO state machine on [`LedBar::show`]. The bar graph
+//! mode of [`LedBar::show_level`] makes it easy to display a reading such as
+//! the servo current.
+
+use fugit::HertzU32;
+use hal::gpio::bank0::Gpio18;
+use hal::gpio::{Function, FunctionConfig, Pin, ValidPinMode};
+use hal::pio::{PIOExt, StateMachineIndex, UninitStateMachine, PIO};
+use smart_leds::{brightness, SmartLedsWrite, RGB8};
+use ws2812_pio::Ws2812Direct;
+
+use crate::NUM_LEDS;
+
+const OFF: RGB8 = RGB8 { r: 0, g: 0, b: 0 };
+
+/// Drives the LEDs on the `led_data` pin.
+///
+/// LEDs are numbered from 0 to [`NUM_LEDS`] - 1, in the order of the data
+/// chain. The LEDs need about 300µs between two calls to
+/// [`LedBar::show`] to latch their colors; a show every few milliseconds is
+/// plenty for animations.
+pub struct LedBar<P, SM>
+where
+    P: PIOExt + FunctionConfig,
+    SM: StateMachineIndex,
+    Function<P>: ValidPinMode<Gpio18>,
+{
+    ws: Ws2812Direct<P, SM, Gpio18>,
+    colors: [RGB8; NUM_LEDS as usize],
+    brightness: u8,
+}
+
+impl<P, SM> LedBar<P, SM>
+where
+    P: PIOExt + FunctionConfig,
+    SM: StateMachineIndex,
+    Function<P>: ValidPinMode<Gpio18>,
+{
+    /// Take control of the LEDs, initially off at full brightness.
+    ///
+    /// `clock_freq` is the frequency the PIO is clocked from, which is the
+    /// system clock.
+    pub fn new(
+        led_data: Pin<Gpio18, Function<P>>,
+        pio: &mut PIO<P>,
+        sm: UninitStateMachine<(P, SM)>,
+        clock_freq: HertzU32,
+    ) -> Self {
+        LedBar {
+            ws: Ws2812Direct::new(led_data, pio, sm, clock_freq),
+            colors: [OFF; NUM_LEDS as usize],
+            brightness: 255,
+        }
+    }
+
+    /// Set the color of LED `index`, shown on the next [`LedBar::show`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not below [`NUM_LEDS`].
+    pub fn set(&mut self, index: usize, color: RGB8) {
+        self.colors[index] = color;
+    }
+
+    /// Set the color of all LEDs, shown on the next [`LedBar::show`].
+    pub fn set_all(&mut self, color: RGB8) {
+        self.colors = [color; NUM_LEDS as usize];
+    }
+
+    /// Turn all LEDs off on the next [`LedBar::show`].
+    pub fn clear(&mut self) {
+        self.set_all(OFF);
+    }
+
+    /// The color of LED `index`, before brightness is applied.
+    pub fn get(&self, index: usize) -> RGB8 {
+        self.colors[index]
+    }
+
+    /// Scale all colors by `brightness` / 255 when they are shown.
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Send the colors to the LEDs.
+    pub fn show(&mut self) {
+        // Writing to the PIO FIFO can't fail
+        let _ = self
+            .ws
+            .write(brightness(self.colors.iter().copied(), self.brightness));
+    }
+
+    /// Light the LEDs as a bar graph of `level`, from 0.0 (all off) to 1.0
+    /// (all on), fading from green to red along the bar, and show it.
+    ///
+    /// The LED at the tip of the bar is dimmed according to how far the level
+    /// reaches into it.
+    pub fn show_level(&mut self, level: f32) {
+        for (index, color) in self.colors.iter_mut().enumerate() {
+            let red = (255 * index / (NUM_LEDS as usize - 1)) as u8;
+            *color = RGB8::new(red, 255 - red, 0);
+        }
+        self.apply_level(level);
+        self.show();
+    }
+
+    /// Light the LEDs as a bar graph of `level`, from 0.0 (all off) to 1.0
+    /// (all on), in a single `color`, and show it.
+    pub fn show_level_with(&mut self, level: f32, color: RGB8) {
+        self.set_all(color);
+        self.apply_level(level);
+        self.show();
+    }
+
+    /// Turn off or dim the LEDs beyond `level`.
+    fn apply_level(&mut self, level: f32) {
+        // A NaN level stays NaN here, and turns every LED off below
+        let lit = level.clamp(0.0, 1.0) * f32::from(NUM_LEDS);
+        for (index, color) in self.colors.iter_mut().enumerate() {
+            let fill = (lit - index as f32).clamp(0.0, 1.0);
+            let scale = |c: u8| (f32::from(c) * fill + 0.5) as u8;
+            *color = RGB8::new(scale(color.r), scale(color.g), scale(color.b));
+        }
+    }
+}
@@ -35,6 +35,23 @@ pub const CURRENT_OFFSET: f32 = -0.02;
 
 pub use hal::pac;
 
+pub use servo_calibration as calibration;
+
+pub mod analog_mux;
+pub use analog_mux::AnalogMux;
+
+pub mod current_guard;
+pub use current_guard::{OverCurrentConfig, OverCurrentGuard, ProtectionState};
+
+pub mod led_bar;
+pub use led_bar::LedBar;
+
+pub mod servo_cluster;
+pub use servo_cluster::{ServoCluster, ServoPins};
+
+pub mod servo_sequencer;
+pub use servo_sequencer::{Easing, Keyframe, SequenceError, ServoSequencer};
+
 hal::bsp_pins!(
     /// GPIO 0 supports following functions:
     ///
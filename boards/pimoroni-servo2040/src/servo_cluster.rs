@@ -0,0 +1,413 @@
+//! Drive all 18 servo outputs of the Servo2040 at once.
+//!
+//! Servos 1 to 16 use the eight hardware PWM slices. Servos 17 and 18 sit on
+//! GPIO 16 and 17, which share PWM slice 0 with servos 1 and 2, so they are
+//! driven by two PIO state machines instead.
+//!
+//! Both backends count in microseconds, and every output starts its pulse at a
+//! different point of the 20 ms servo period so the servos don't all draw
+//! their current spike at the same time.
+
+use core::convert::Infallible;
+
+use embedded_hal::PwmPin;
+use fugit::HertzU32;
+use hal::gpio::bank0::{Gpio16, Gpio17};
+use hal::gpio::{Function, FunctionConfig, Pin, ValidPinMode};
+use hal::pio::{
+    InstallError, PIOBuilder, PIOExt, PinDir, Running, ShiftDirection, StateMachine,
+    StateMachineIndex, Tx, UninitStateMachine, PIO,
+};
+use hal::pwm::{FreeRunning, Pwm0, Pwm1, Pwm2, Pwm3, Pwm4, Pwm5, Pwm6, Pwm7, Slice, Slices};
+
+use crate::calibration::Calibration;
+use crate::{
+    Servo10Pwm4B, Servo11Pwm5A, Servo12Pwm5B, Servo13Pwm6A, Servo14Pwm6B, Servo15Pwm7A,
+    Servo16Pwm7B, Servo1Pwm0A, Servo2Pwm0B, Servo3Pwm1A, Servo4Pwm1B, Servo5Pwm2A, Servo6Pwm2B,
+    Servo7Pwm3A, Servo8Pwm3B, Servo9Pwm4A, NUM_SERVOS,
+};
+
+/// Length of one servo period in microseconds (50Hz).
+pub const SERVO_PERIOD_US: u16 = 20_000;
+
+/// Both backends tick once per microsecond.
+const TICK_HZ: u32 = 1_000_000;
+
+/// Number of outputs with their own phase: the eight PWM slices and the two
+/// PIO state machines, which start together.
+const PHASE_GROUPS: u16 = 9;
+
+/// Cycles of the PIO program spent in the high and low halves of the period
+/// on top of the two loop counters.
+const PIO_HIGH_OVERHEAD: u16 = 3;
+const PIO_LOW_OVERHEAD: u16 = 5;
+
+/// The servo pins, switched to the function driving them.
+///
+/// All fields can be filled in with `pins.servoN.into_mode()`.
+pub struct ServoPins<P: PIOExt + FunctionConfig> {
+    pub servo1: Servo1Pwm0A,
+    pub servo2: Servo2Pwm0B,
+    pub servo3: Servo3Pwm1A,
+    pub servo4: Servo4Pwm1B,
+    pub servo5: Servo5Pwm2A,
+    pub servo6: Servo6Pwm2B,
+    pub servo7: Servo7Pwm3A,
+    pub servo8: Servo8Pwm3B,
+    pub servo9: Servo9Pwm4A,
+    pub servo10: Servo10Pwm4B,
+    pub servo11: Servo11Pwm5A,
+    pub servo12: Servo12Pwm5B,
+    pub servo13: Servo13Pwm6A,
+    pub servo14: Servo14Pwm6B,
+    pub servo15: Servo15Pwm7A,
+    pub servo16: Servo16Pwm7B,
+    pub servo17: Pin<Gpio16, Function<P>>,
+    pub servo18: Pin<Gpio17, Function<P>>,
+}
+
+/// Drives all 18 servo outputs.
+///
+/// Pulse widths are staged with [`ServoCluster::set_pulse`],
+/// [`ServoCluster::enable`] and [`ServoCluster::disable`], and only reach
+/// the outputs on [`ServoCluster::apply`]. Each output picks up its new value
+/// at the start of its next period, so no pulse is ever cut short.
+///
+/// Servos are numbered from 0 (servo 1) to 17 (servo 18).
+pub struct ServoCluster<
+    P: PIOExt + FunctionConfig,
+    SM17: StateMachineIndex,
+    SM18: StateMachineIndex,
+> {
+    pwm0: Slice<Pwm0, FreeRunning>,
+    pwm1: Slice<Pwm1, FreeRunning>,
+    pwm2: Slice<Pwm2, FreeRunning>,
+    pwm3: Slice<Pwm3, FreeRunning>,
+    pwm4: Slice<Pwm4, FreeRunning>,
+    pwm5: Slice<Pwm5, FreeRunning>,
+    pwm6: Slice<Pwm6, FreeRunning>,
+    pwm7: Slice<Pwm7, FreeRunning>,
+    _sm17: StateMachine<(P, SM17), Running>,
+    _sm18: StateMachine<(P, SM18), Running>,
+    tx17: Tx<(P, SM17)>,
+    tx18: Tx<(P, SM18)>,
+    pulses: [u16; NUM_SERVOS as usize],
+    enabled: [bool; NUM_SERVOS as usize],
+}
+
+impl<P, SM17, SM18> ServoCluster<P, SM17, SM18>
+where
+    P: PIOExt + FunctionConfig,
+    SM17: StateMachineIndex,
+    SM18: StateMachineIndex,
+{
+    /// Set up all servo outputs, initially disabled.
+    ///
+    /// `system_clock` is the frequency both the PWM and PIO peripherals are
+    /// clocked from.
+    pub fn new(
+        pins: ServoPins<P>,
+        pwm_slices: Slices,
+        pio: &mut PIO<P>,
+        sm17: UninitStateMachine<(P, SM17)>,
+        sm18: UninitStateMachine<(P, SM18)>,
+        system_clock: HertzU32,
+    ) -> Result<Self, InstallError>
+    where
+        Function<P>: ValidPinMode<Gpio16> + ValidPinMode<Gpio17>,
+    {
+        // Each period, pull the newest pulse word, or reuse the last one kept
+        // in X if none was queued. A word holds the enable bit in bit 0, the
+        // high time in bits 1..16 and the low time in bits 16..32.
+        let program = pio_proc::pio_asm!(
+            ".side_set 1 opt",
+            ".wrap_target",
+            "drain:",
+            "    pull noblock",
+            "    mov y, osr",
+            "    jmp x!=y update",
+            "    out pins, 1",
+            "    out y, 15",
+            "high:",
+            "    jmp y-- high",
+            "    out y, 16 side 0",
+            "low:",
+            "    jmp y-- low",
+            ".wrap",
+            "update:",
+            "    mov x, y",
+            "    jmp drain",
+        );
+        let installed = pio.install(&program.program)?;
+        // SAFETY: both state machines run the same program, which is never
+        // uninstalled.
+        let installed_18 = unsafe { installed.share() };
+
+        let divisor = system_clock.to_Hz() as f32 / TICK_HZ as f32;
+        let (mut sm17, _, tx17) = PIOBuilder::from_program(installed)
+            .out_pins(16, 1)
+            .side_set_pin_base(16)
+            .out_shift_direction(ShiftDirection::Right)
+            .clock_divisor(divisor)
+            .build(sm17);
+        sm17.set_pindirs([(16, PinDir::Output)]);
+        let (mut sm18, _, tx18) = PIOBuilder::from_program(installed_18)
+            .out_pins(17, 1)
+            .side_set_pin_base(17)
+            .out_shift_direction(ShiftDirection::Right)
+            .clock_divisor(divisor)
+            .build(sm18);
+        sm18.set_pindirs([(17, PinDir::Output)]);
+        // The pins are owned by the state machines from now on
+        let _ = (pins.servo17, pins.servo18);
+
+        let (div_int, div_frac) = pwm_divider(system_clock);
+        let mut pwm0 = pwm_slices.pwm0;
+        let mut pwm1 = pwm_slices.pwm1;
+        let mut pwm2 = pwm_slices.pwm2;
+        let mut pwm3 = pwm_slices.pwm3;
+        let mut pwm4 = pwm_slices.pwm4;
+        let mut pwm5 = pwm_slices.pwm5;
+        let mut pwm6 = pwm_slices.pwm6;
+        let mut pwm7 = pwm_slices.pwm7;
+
+        macro_rules! setup_slice {
+            ($slice:ident, $group:expr, $pin_a:expr, $pin_b:expr) => {
+                $slice.set_div_int(div_int);
+                $slice.set_div_frac(div_frac);
+                $slice.set_top(SERVO_PERIOD_US - 1);
+                $slice.set_counter(phase_offset($group));
+                $slice.channel_a.set_duty(0);
+                $slice.channel_b.set_duty(0);
+                $slice.channel_a.output_to($pin_a);
+                $slice.channel_b.output_to($pin_b);
+            };
+        }
+        setup_slice!(pwm0, 0, pins.servo1, pins.servo2);
+        setup_slice!(pwm1, 1, pins.servo3, pins.servo4);
+        setup_slice!(pwm2, 2, pins.servo5, pins.servo6);
+        setup_slice!(pwm3, 3, pins.servo7, pins.servo8);
+        setup_slice!(pwm4, 4, pins.servo9, pins.servo10);
+        setup_slice!(pwm5, 5, pins.servo11, pins.servo12);
+        setup_slice!(pwm6, 6, pins.servo13, pins.servo14);
+        setup_slice!(pwm7, 7, pins.servo15, pins.servo16);
+
+        pwm0.enable();
+        pwm1.enable();
+        pwm2.enable();
+        pwm3.enable();
+        pwm4.enable();
+        pwm5.enable();
+        pwm6.enable();
+        pwm7.enable();
+
+        Ok(ServoCluster {
+            pwm0,
+            pwm1,
+            pwm2,
+            pwm3,
+            pwm4,
+            pwm5,
+            pwm6,
+            pwm7,
+            _sm17: sm17.start(),
+            _sm18: sm18.start(),
+            tx17,
+            tx18,
+            pulses: [0; NUM_SERVOS as usize],
+            enabled: [false; NUM_SERVOS as usize],
+        })
+    }
+
+    /// Stage the pulse width of `servo` in microseconds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `servo` is not below [`NUM_SERVOS`].
+    pub fn set_pulse(&mut self, servo: usize, pulse_us: u16) {
+        self.pulses[servo] = pulse_us;
+    }
+
+    /// Stage moving `servo` to `value`, using `calibration` to find the
+    /// pulse width.
+    pub fn set_value(&mut self, servo: usize, calibration: &Calibration, value: f32) {
+        self.set_pulse(servo, calibration.value_to_pulse(value) as u16);
+    }
+
+    /// The staged pulse width of `servo` in microseconds.
+    pub fn pulse(&self, servo: usize) -> u16 {
+        self.pulses[servo]
+    }
+
+    /// Stage sending pulses to `servo`.
+    pub fn enable(&mut self, servo: usize) {
+        self.enabled[servo] = true;
+    }
+
+    /// Stage stopping the pulses to `servo`, letting it go limp.
+    pub fn disable(&mut self, servo: usize) {
+        self.enabled[servo] = false;
+    }
+
+    /// Stage stopping the pulses to all servos.
+    pub fn disable_all(&mut self) {
+        self.enabled = [false; NUM_SERVOS as usize];
+    }
+
+    pub fn is_enabled(&self, servo: usize) -> bool {
+        self.enabled[servo]
+    }
+
+    /// Send all staged pulse widths and enable states to the outputs.
+    ///
+    /// The PIO programs of servos 17 and 18 drain their FIFOs at the start of
+    /// each period, so they only fill up when applying more than four times
+    /// in one period. This then returns [`nb::Error::WouldBlock`] without
+    /// sending anything, and can be tried again, within a period at most.
+    pub fn apply(&mut self) -> nb::Result<(), Infallible> {
+        if self.tx17.is_full() || self.tx18.is_full() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let mut duties = [0; NUM_SERVOS as usize];
+        for (duty, (pulse, enabled)) in duties
+            .iter_mut()
+            .zip(self.pulses.iter().zip(self.enabled.iter()))
+        {
+            *duty = pwm_duty(*pulse, *enabled);
+        }
+
+        // The compare registers are double buffered and only latched when the
+        // counter wraps
+        macro_rules! apply_slice {
+            ($slice:expr, $a:expr) => {
+                $slice.channel_a.set_duty(duties[$a]);
+                $slice.channel_b.set_duty(duties[$a + 1]);
+            };
+        }
+        apply_slice!(self.pwm0, 0);
+        apply_slice!(self.pwm1, 2);
+        apply_slice!(self.pwm2, 4);
+        apply_slice!(self.pwm3, 6);
+        apply_slice!(self.pwm4, 8);
+        apply_slice!(self.pwm5, 10);
+        apply_slice!(self.pwm6, 12);
+        apply_slice!(self.pwm7, 14);
+
+        // Both FIFOs have room, and only this cluster writes to them
+        self.tx17.write(pio_word(self.pulses[16], self.enabled[16]));
+        self.tx18.write(pio_word(self.pulses[17], self.enabled[17]));
+        Ok(())
+    }
+}
+
+/// The PWM divider, as integer and 16ths, giving one count per microsecond.
+fn pwm_divider(system_clock: HertzU32) -> (u8, u8) {
+    let div16 = (system_clock.to_Hz() / (TICK_HZ / 16)).clamp(16, 0xFFF);
+    ((div16 >> 4) as u8, (div16 & 0xF) as u8)
+}
+
+/// The counter value a PWM slice starts at, so that each phase group starts
+/// its pulse at a different point of the period.
+fn phase_offset(group: u16) -> u16 {
+    (SERVO_PERIOD_US - group * (SERVO_PERIOD_US / PHASE_GROUPS)) % SERVO_PERIOD_US
+}
+
+/// The compare value for a hardware PWM channel.
+fn pwm_duty(pulse_us: u16, enabled: bool) -> u16 {
+    if enabled {
+        pulse_us.min(SERVO_PERIOD_US)
+    } else {
+        0
+    }
+}
+
+/// The word to push to the PIO program for one period.
+fn pio_word(pulse_us: u16, enabled: bool) -> u32 {
+    let high = pulse_us.clamp(PIO_HIGH_OVERHEAD, SERVO_PERIOD_US - PIO_LOW_OVERHEAD - 1);
+    let high_count = u32::from(high - PIO_HIGH_OVERHEAD);
+    let low_count = u32::from(SERVO_PERIOD_US - high - PIO_LOW_OVERHEAD);
+    low_count << 16 | high_count << 1 | u32::from(enabled && pulse_us > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fugit::RateExtU32;
+
+    #[test]
+    fn divider_counts_microseconds() {
+        assert_eq!(pwm_divider(125.MHz()), (125, 0));
+        assert_eq!(pwm_divider(133.MHz()), (133, 0));
+        assert_eq!(pwm_divider(12.MHz()), (12, 0));
+        // In 16ths when the clock isn't a whole number of MHz
+        assert_eq!(pwm_divider(12_500.kHz()), (12, 8));
+        // Clamped to what the divider can hold
+        assert_eq!(pwm_divider(500.kHz()), (1, 0));
+        assert_eq!(pwm_divider(300.MHz()), (255, 15));
+    }
+
+    #[test]
+    fn phase_offsets_spread_over_the_period() {
+        assert_eq!(phase_offset(0), 0);
+        assert_eq!(phase_offset(1), 17_778);
+        assert_eq!(phase_offset(PHASE_GROUPS - 1), 2224);
+        // Each group starts its pulse 2222us after the one before
+        for group in 1..PHASE_GROUPS {
+            let offset = phase_offset(group);
+            assert!(offset < SERVO_PERIOD_US, "{}", group);
+            let gap = (phase_offset(group - 1) + SERVO_PERIOD_US - offset) % SERVO_PERIOD_US;
+            assert_eq!(gap, SERVO_PERIOD_US / PHASE_GROUPS, "{}", group);
+        }
+    }
+
+    #[test]
+    fn pwm_duty_is_the_pulse() {
+        assert_eq!(pwm_duty(1500, true), 1500);
+        assert_eq!(pwm_duty(1500, false), 0);
+        assert_eq!(pwm_duty(u16::MAX, true), SERVO_PERIOD_US);
+    }
+
+    /// The enable bit, and the lengths of the high and low halves of the
+    /// period in microseconds, of a PIO word.
+    fn pio_timing(word: u32) -> (bool, u32, u32) {
+        let high = (word >> 1 & 0x7FFF) + u32::from(PIO_HIGH_OVERHEAD);
+        let low = (word >> 16) + u32::from(PIO_LOW_OVERHEAD);
+        (word & 1 != 0, high, low)
+    }
+
+    #[test]
+    fn pio_word_at_the_pulse_limits() {
+        assert_eq!(pio_word(500, true), 19_495 << 16 | 497 << 1 | 1);
+        assert_eq!(pio_timing(pio_word(500, true)), (true, 500, 19_500));
+        assert_eq!(pio_word(2500, true), 17_495 << 16 | 2497 << 1 | 1);
+        assert_eq!(pio_timing(pio_word(2500, true)), (true, 2500, 17_500));
+    }
+
+    #[test]
+    fn pio_word_enable_bit() {
+        assert_eq!(pio_word(1500, false) & 1, 0);
+        // A pulse of 0 sends nothing either
+        assert_eq!(pio_word(0, true) & 1, 0);
+    }
+
+    #[test]
+    fn pio_word_fills_the_period() {
+        for pulse in [
+            0,
+            1,
+            3,
+            500,
+            1500,
+            2500,
+            19_994,
+            19_995,
+            SERVO_PERIOD_US,
+            u16::MAX,
+        ] {
+            let (_, high, low) = pio_timing(pio_word(pulse, true));
+            assert_eq!(high + low, u32::from(SERVO_PERIOD_US), "{}", pulse);
+            assert!(low > u32::from(PIO_LOW_OVERHEAD), "{}", pulse);
+        }
+    }
+}
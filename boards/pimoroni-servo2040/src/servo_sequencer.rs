@@ -0,0 +1,395 @@
+//! Scripted servo motions from keyframes and easing curves.
+//!
+//! A [`ServoSequencer`] plays a list of [`Keyframe`]s, each giving target
+//! values for some of the servos at a point in time. Between two keyframes a
+//! servo moves from one target to the next along an [`Easing`] curve. The
+//! sequencer only computes targets from timestamps, so a sequence always
+//! produces the same trajectory for the same timestamps.
+
+use core::convert::Infallible;
+
+use hal::gpio::FunctionConfig;
+use hal::pio::{PIOExt, StateMachineIndex};
+
+use crate::calibration::Calibration;
+use crate::ServoCluster;
+
+/// How a servo moves from one target to the next.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Easing {
+    /// Constant speed.
+    #[default]
+    Linear,
+    /// Start slowly and speed up (quadratic).
+    EaseIn,
+    /// Start fast and slow down (quadratic).
+    EaseOut,
+    /// Speed up, then slow down (quadratic).
+    EaseInOut,
+    /// Speed up, then slow down, more gently than [`Easing::EaseInOut`] at
+    /// both ends (cubic).
+    Cubic,
+}
+
+impl Easing {
+    /// Map the progress `t` through a move, from 0.0 to 1.0, to the fraction
+    /// of the distance covered. `t` is clamped to `0.0..=1.0`.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    let u = 1.0 - t;
+                    1.0 - 2.0 * u * u
+                }
+            }
+            Easing::Cubic => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    let u = 1.0 - t;
+                    1.0 - 4.0 * u * u * u
+                }
+            }
+        }
+    }
+}
+
+/// Targets for `N` servos at a point in a sequence.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Keyframe<const N: usize> {
+    /// Time in milliseconds from the start of the sequence.
+    pub time_ms: u32,
+    /// The value each servo should reach at `time_ms`, or `None` for servos
+    /// that keep moving towards their next target.
+    pub targets: [Option<f32>; N],
+    /// The curve followed to reach the targets from the previous ones.
+    pub easing: Easing,
+}
+
+impl<const N: usize> Keyframe<N> {
+    /// A keyframe moving all servos to the same `value`.
+    pub const fn all(time_ms: u32, value: f32, easing: Easing) -> Self {
+        Keyframe {
+            time_ms,
+            targets: [Some(value); N],
+            easing,
+        }
+    }
+}
+
+/// Why a list of keyframes was rejected.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SequenceError {
+    /// No keyframes were given.
+    Empty,
+    /// The keyframe times are not increasing.
+    TimeOrder,
+}
+
+/// Plays a sequence of [`Keyframe`]s for `N` servos.
+///
+/// Timestamps are in milliseconds from any monotonic source, and may wrap
+/// around. Before its first target, a servo holds that target; after its last
+/// target, it holds the last one, unless the sequence loops.
+pub struct ServoSequencer<'a, const N: usize> {
+    keyframes: &'a [Keyframe<N>],
+    start_ms: u32,
+    looping: bool,
+}
+
+impl<'a, const N: usize> ServoSequencer<'a, N> {
+    /// A sequencer for `keyframes`, ordered by time, starting at `start_ms`.
+    pub fn new(keyframes: &'a [Keyframe<N>], start_ms: u32) -> Result<Self, SequenceError> {
+        if keyframes.is_empty() {
+            return Err(SequenceError::Empty);
+        }
+        if keyframes.windows(2).any(|w| w[0].time_ms > w[1].time_ms) {
+            return Err(SequenceError::TimeOrder);
+        }
+        Ok(ServoSequencer {
+            keyframes,
+            start_ms,
+            looping: false,
+        })
+    }
+
+    /// Restart the sequence at `start_ms`.
+    pub fn restart(&mut self, start_ms: u32) {
+        self.start_ms = start_ms;
+    }
+
+    /// Whether the sequence starts over once it reaches the last keyframe.
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// The length of the sequence in milliseconds, which is the time of the
+    /// last keyframe.
+    pub fn duration_ms(&self) -> u32 {
+        self.keyframes[self.keyframes.len() - 1].time_ms
+    }
+
+    /// Whether a sequence that doesn't loop has played to the end at `now_ms`.
+    pub fn is_finished(&self, now_ms: u32) -> bool {
+        !self.looping && now_ms.wrapping_sub(self.start_ms) >= self.duration_ms()
+    }
+
+    /// The time in the sequence at `now_ms`.
+    fn position(&self, now_ms: u32) -> u32 {
+        let elapsed = now_ms.wrapping_sub(self.start_ms);
+        let duration = self.duration_ms();
+        if self.looping && duration > 0 {
+            elapsed % duration
+        } else {
+            elapsed.min(duration)
+        }
+    }
+
+    /// The target of `servo` at `now_ms`, or `None` if no keyframe has a
+    /// target for it.
+    pub fn target(&self, servo: usize, now_ms: u32) -> Option<f32> {
+        let position = self.position(now_ms);
+        let mut previous: Option<(u32, f32)> = None;
+        for keyframe in self.keyframes {
+            let value = match keyframe.targets[servo] {
+                Some(value) => value,
+                None => continue,
+            };
+            if keyframe.time_ms <= position {
+                previous = Some((keyframe.time_ms, value));
+                continue;
+            }
+            return Some(match previous {
+                // Hold the first target until it is reached
+                None => value,
+                Some((from_ms, from)) => {
+                    let t = (position - from_ms) as f32 / (keyframe.time_ms - from_ms) as f32;
+                    from + (value - from) * keyframe.easing.apply(t)
+                }
+            });
+        }
+        previous.map(|(_, value)| value)
+    }
+
+    /// The targets of all servos at `now_ms`.
+    pub fn targets(&self, now_ms: u32) -> [Option<f32>; N] {
+        let mut targets = [None; N];
+        for (servo, target) in targets.iter_mut().enumerate() {
+            *target = self.target(servo, now_ms);
+        }
+        targets
+    }
+
+    /// Move the first `N` servos of `servos` to their targets at `now_ms`,
+    /// through `calibration`, and apply the new pulses.
+    ///
+    /// Servos without a target are left alone. Returns
+    /// [`nb::Error::WouldBlock`] when [`ServoCluster::apply`] does; the new
+    /// pulses are staged either way.
+    pub fn update<P, SM17, SM18>(
+        &self,
+        now_ms: u32,
+        servos: &mut ServoCluster<P, SM17, SM18>,
+        calibration: &Calibration,
+    ) -> nb::Result<(), Infallible>
+    where
+        P: PIOExt + FunctionConfig,
+        SM17: StateMachineIndex,
+        SM18: StateMachineIndex,
+    {
+        for (servo, target) in self.targets(now_ms).iter().enumerate() {
+            if let Some(value) = target {
+                servos.set_value(servo, calibration, *value);
+            }
+        }
+        servos.apply()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EASINGS: [Easing; 5] = [
+        Easing::Linear,
+        Easing::EaseIn,
+        Easing::EaseOut,
+        Easing::EaseInOut,
+        Easing::Cubic,
+    ];
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{} isn't {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn easings_start_and_end_in_place() {
+        for easing in EASINGS {
+            assert_near(easing.apply(0.0), 0.0);
+            assert_near(easing.apply(1.0), 1.0);
+            assert_near(easing.apply(-1.0), 0.0);
+            assert_near(easing.apply(2.0), 1.0);
+        }
+    }
+
+    #[test]
+    fn easing_curves() {
+        assert_near(Easing::Linear.apply(0.25), 0.25);
+        assert_near(Easing::EaseIn.apply(0.25), 0.0625);
+        assert_near(Easing::EaseOut.apply(0.25), 0.4375);
+        assert_near(Easing::EaseInOut.apply(0.25), 0.125);
+        assert_near(Easing::EaseInOut.apply(0.75), 0.875);
+        assert_near(Easing::Cubic.apply(0.25), 0.0625);
+        assert_near(Easing::Cubic.apply(0.75), 0.9375);
+        for easing in [Easing::Linear, Easing::EaseInOut, Easing::Cubic] {
+            assert_near(easing.apply(0.5), 0.5);
+        }
+    }
+
+    #[test]
+    fn easings_never_go_back() {
+        for easing in EASINGS {
+            let mut previous = 0.0;
+            for step in 1..=100 {
+                let eased = easing.apply(step as f32 / 100.0);
+                assert!(eased >= previous, "{:?} at step {}", easing, step);
+                previous = eased;
+            }
+        }
+    }
+
+    #[test]
+    fn linear_trajectory() {
+        let keyframes = [
+            Keyframe::all(0, 0.0, Easing::Linear),
+            Keyframe::all(1000, 90.0, Easing::Linear),
+            Keyframe::all(1500, -90.0, Easing::Linear),
+        ];
+        let sequencer = ServoSequencer::<2>::new(&keyframes, 100).unwrap();
+        let expected = [
+            (100, 0.0),
+            (350, 22.5),
+            (600, 45.0),
+            (1100, 90.0),
+            (1225, 45.0),
+            (1350, 0.0),
+            (1600, -90.0),
+            (5000, -90.0),
+        ];
+        for (now_ms, value) in expected {
+            assert_near(sequencer.target(0, now_ms).unwrap(), value);
+            assert_near(sequencer.target(1, now_ms).unwrap(), value);
+        }
+        assert!(!sequencer.is_finished(1599));
+        assert!(sequencer.is_finished(1600));
+    }
+
+    #[test]
+    fn eased_trajectory() {
+        let keyframes = [
+            Keyframe::all(0, 0.0, Easing::Linear),
+            Keyframe::all(1000, 100.0, Easing::EaseIn),
+            Keyframe::all(2000, 0.0, Easing::EaseOut),
+        ];
+        let sequencer = ServoSequencer::<1>::new(&keyframes, 0).unwrap();
+        assert_near(sequencer.target(0, 500).unwrap(), 25.0);
+        assert_near(sequencer.target(0, 1000).unwrap(), 100.0);
+        assert_near(sequencer.target(0, 1500).unwrap(), 25.0);
+    }
+
+    #[test]
+    fn servos_without_a_target_keep_moving() {
+        let keyframes = [
+            Keyframe {
+                time_ms: 0,
+                targets: [Some(0.0), None],
+                easing: Easing::Linear,
+            },
+            Keyframe {
+                time_ms: 1000,
+                targets: [None, Some(10.0)],
+                easing: Easing::Linear,
+            },
+            Keyframe {
+                time_ms: 2000,
+                targets: [Some(20.0), Some(30.0)],
+                easing: Easing::Linear,
+            },
+        ];
+        let sequencer = ServoSequencer::new(&keyframes, 0).unwrap();
+        // Servo 0 moves from its first target to its next, over 2s
+        assert_near(sequencer.target(0, 1000).unwrap(), 10.0);
+        // Servo 1 holds its first target until it is reached
+        assert_eq!(sequencer.targets(500)[1], Some(10.0));
+        assert_near(sequencer.target(1, 1500).unwrap(), 20.0);
+    }
+
+    #[test]
+    fn servos_without_any_target() {
+        let keyframes = [Keyframe {
+            time_ms: 0,
+            targets: [Some(1.0), None],
+            easing: Easing::Linear,
+        }];
+        let sequencer = ServoSequencer::new(&keyframes, 0).unwrap();
+        assert_eq!(sequencer.targets(10), [Some(1.0), None]);
+    }
+
+    #[test]
+    fn looping_starts_over() {
+        let keyframes = [
+            Keyframe::all(0, 0.0, Easing::Linear),
+            Keyframe::all(1000, 100.0, Easing::Linear),
+        ];
+        let mut sequencer = ServoSequencer::<1>::new(&keyframes, 0).unwrap();
+        sequencer.set_looping(true);
+        assert_near(sequencer.target(0, 999).unwrap(), 99.9);
+        assert_near(sequencer.target(0, 1000).unwrap(), 0.0);
+        assert_near(sequencer.target(0, 2250).unwrap(), 25.0);
+        assert!(!sequencer.is_finished(5000));
+    }
+
+    #[test]
+    fn timestamps_may_wrap() {
+        let keyframes = [
+            Keyframe::all(0, 0.0, Easing::Linear),
+            Keyframe::all(1000, 100.0, Easing::Linear),
+        ];
+        let mut sequencer = ServoSequencer::<1>::new(&keyframes, 0).unwrap();
+        sequencer.restart(u32::MAX - 499);
+        assert_near(sequencer.target(0, u32::MAX).unwrap(), 49.9);
+        assert_near(sequencer.target(0, 250).unwrap(), 75.0);
+    }
+
+    #[test]
+    fn invalid_keyframes_are_rejected() {
+        let empty: [Keyframe<1>; 0] = [];
+        assert_eq!(
+            ServoSequencer::new(&empty, 0).err(),
+            Some(SequenceError::Empty)
+        );
+        let unordered = [
+            Keyframe::<1>::all(1000, 0.0, Easing::Linear),
+            Keyframe::all(500, 0.0, Easing::Linear),
+        ];
+        assert_eq!(
+            ServoSequencer::new(&unordered, 0).err(),
+            Some(SequenceError::TimeOrder)
+        );
+    }
+}
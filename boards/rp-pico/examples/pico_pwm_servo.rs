@@ -29,6 +29,17 @@ use rp_pico::hal::pac;
 // higher-level drivers.
 use rp_pico::hal;
 
+// Maps servo angles to pulse widths and duty cycles
+use servo_calibration::Calibration;
+
+/// Length of the PWM period in microseconds.
+///
+/// With phase correction, a divider of 20 and a top of 65535, PWM0 runs at
+/// 125MHz / (2 * 20 * 65536) = 47.68Hz.
+const PERIOD_US: f32 = 20_971.52;
+/// PWM counter top value.
+const TOP: u16 = u16::MAX;
+
 /// Entry point to our bare-metal application.
 ///
 /// The `#[rp2040_hal::entry]` macro ensures the Cortex-M start-up code calls this function
@@ -87,27 +98,30 @@ fn main() -> ! {
     let channel = &mut pwm.channel_b;
     channel.output_to(pins.gpio1);
 
-    // Infinite loop, moving micro servo from one position to another.
-    // You may need to adjust the pulse width since several servos from
+    // Pulse widths in microseconds for 0°, 90° and 180°.
+    // You may need to adjust the pulse widths since several servos from
     // different manufacturers respond differently.
+    let calibration = Calibration::new(800.0, 1258.0, 2515.0, 0.0, 180.0).unwrap();
+
+    // Infinite loop, moving micro servo from one position to another.
     loop {
         // move to 0°
-        channel.set_duty(2500);
+        channel.set_duty(calibration.value_to_duty(0.0, PERIOD_US, TOP));
         count_down.start(400.millis());
         let _ = nb::block!(count_down.wait());
 
         // 0° to 90°
-        channel.set_duty(3930);
+        channel.set_duty(calibration.value_to_duty(90.0, PERIOD_US, TOP));
         count_down.start(400.millis());
         let _ = nb::block!(count_down.wait());
 
         // 90° to 180°
-        channel.set_duty(7860);
+        channel.set_duty(calibration.value_to_duty(180.0, PERIOD_US, TOP));
         count_down.start(400.millis());
         let _ = nb::block!(count_down.wait());
 
         // 180° to 90°
-        channel.set_duty(3930);
+        channel.set_duty(calibration.value_to_duty(90.0, PERIOD_US, TOP));
         count_down.start(400.millis());
         let _ = nb::block!(count_down.wait());
     }
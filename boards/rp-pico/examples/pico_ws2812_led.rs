@@ -7,7 +7,7 @@
 //! For a more large scale and longer strips you should use an extra power
 //! supply for the LED strip (or know what you are doing ;-) ).
 //!
-//! The example also comes with an utility function to calculate the colors
+//! The example uses the `led-effects` crate to calculate the colors
 //! from HSV color space. It also limits the brightness a bit to save a
 //! few milliamperes - be careful if you increase the strip length you will
 //! quickly get into power consumption of multiple amperes.
@@ -68,6 +68,9 @@ use rp_pico::hal::pio::PIOExt;
 // Import useful traits to handle the ws2812 LEDs:
 use smart_leds::{brightness, SmartLedsWrite, RGB8};
 
+// Import the color conversion from the shared color math crate:
+use led_effects::color::{hsv_to_rgb, Hsv};
+
 // Import the actual crate to handle the Ws2812 protocol:
 use ws2812_pio::Ws2812;
 
@@ -161,12 +164,11 @@ fn main() -> ! {
             // Bring -1..1 sine range to 0..1 range:
             let sin_01 = (sin_11 + 1.0) * 0.5;
 
-            let hue = 360.0 * sin_01;
-            let sat = 1.0;
-            let val = 1.0;
+            let hue = (255.0 * sin_01) as u8;
+            let sat = 255;
+            let val = 255;
 
-            let rgb = hsv2rgb_u8(hue, sat, val);
-            *led = rgb.into();
+            *led = hsv_to_rgb(Hsv::new(hue, sat, val));
         }
 
         // Here the magic happens and the `leds` buffer is written to the
@@ -185,35 +187,3 @@ fn main() -> ! {
         }
     }
 }
-
-pub fn hsv2rgb(hue: f32, sat: f32, val: f32) -> (f32, f32, f32) {
-    let c = val * sat;
-    let v = (hue / 60.0) % 2.0 - 1.0;
-    let v = if v < 0.0 { -v } else { v };
-    let x = c * (1.0 - v);
-    let m = val - c;
-    let (r, g, b) = if hue < 60.0 {
-        (c, x, 0.0)
-    } else if hue < 120.0 {
-        (x, c, 0.0)
-    } else if hue < 180.0 {
-        (0.0, c, x)
-    } else if hue < 240.0 {
-        (0.0, x, c)
-    } else if hue < 300.0 {
-        (x, 0.0, c)
-    } else {
-        (c, 0.0, x)
-    };
-    (r + m, g + m, b + m)
-}
-
-pub fn hsv2rgb_u8(h: f32, s: f32, v: f32) -> (u8, u8, u8) {
-    let r = hsv2rgb(h, s, v);
-
-    (
-        (r.0 * 255.0) as u8,
-        (r.1 * 255.0) as u8,
-        (r.2 * 255.0) as u8,
-    )
-}
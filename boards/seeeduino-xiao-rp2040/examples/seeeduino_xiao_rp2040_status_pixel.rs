@@ -0,0 +1,102 @@
+//! # Seeeduino XIAO RP2040 Status Pixel Example
+//!
+//! Cycles the NeoPixel on a Seeeduino XIAO RP2040 board through the colors of
+//! the rainbow, cutting its power for a second between two rounds.
+//!
+//! See the `Cargo.toml` file for Copyright and license details.
+
+#![no_std]
+#![no_main]
+
+// The macro for our start-up function
+use seeeduino_xiao_rp2040::entry;
+
+// Ensure we halt the program on panic (if we don't mention this crate it won't
+// be linked)
+use panic_halt as _;
+
+// Pull in any important traits
+use seeeduino_xiao_rp2040::hal::prelude::*;
+
+// A shorter alias for the Peripheral Access Crate, which provides low-level
+// register access
+use seeeduino_xiao_rp2040::hal::pac;
+
+// A shorter alias for the Hardware Abstraction Layer, which provides
+// higher-level drivers.
+use seeeduino_xiao_rp2040::hal;
+
+use hal::pio::PIOExt;
+use led_effects::color::{scale, wheel};
+use seeeduino_xiao_rp2040::StatusPixel;
+
+/// Entry point to our bare-metal application.
+///
+/// The `#[entry]` macro ensures the Cortex-M start-up code calls this function
+/// as soon as all global variables are initialised.
+///
+/// The function configures the RP2040 peripherals, then cycles the NeoPixel
+/// colors in an infinite loop.
+#[entry]
+fn main() -> ! {
+    // Grab our singleton objects
+    let mut pac = pac::Peripherals::take().unwrap();
+    let core = pac::CorePeripherals::take().unwrap();
+
+    // Set up the watchdog driver - needed by the clock setup code
+    let mut watchdog = hal::Watchdog::new(pac.WATCHDOG);
+
+    // Configure the clocks
+    //
+    // The default is to generate a 125 MHz system clock
+    let clocks = hal::clocks::init_clocks_and_plls(
+        seeeduino_xiao_rp2040::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .ok()
+    .unwrap();
+
+    // The single-cycle I/O block controls our GPIO pins
+    let sio = hal::Sio::new(pac.SIO);
+
+    // Set the pins up according to their function on this particular board
+    let pins = seeeduino_xiao_rp2040::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    // The delay object lets us wait for specified amounts of time (in
+    // milliseconds)
+    let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+
+    // Power up the NeoPixel and drive it from a PIO state machine
+    let (mut pio, sm0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
+    let mut pixel = StatusPixel::new(
+        pins.neopixel_power,
+        pins.neopixel_data.into_mode(),
+        &mut pio,
+        sm0,
+        clocks.system_clock.freq(),
+    );
+
+    loop {
+        // One round of the color wheel, dimmed to an eighth
+        for n in 0..=255 {
+            pixel.set(scale(wheel(n), 32));
+            delay.delay_ms(10);
+        }
+
+        // Cut the power until the next round
+        pixel.shutdown();
+        delay.delay_ms(1000);
+    }
+}
+
+// End of file
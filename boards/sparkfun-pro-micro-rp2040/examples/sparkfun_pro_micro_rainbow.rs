@@ -14,7 +14,8 @@ use fugit::ExtU32;
 use panic_halt as _;
 use sparkfun_pro_micro_rp2040::entry;
 
-use smart_leds::{brightness, SmartLedsWrite, RGB8};
+use led_effects::color::wheel;
+use smart_leds::{brightness, SmartLedsWrite};
 use sparkfun_pro_micro_rp2040::{
     hal::{
         clocks::{init_clocks_and_plls, Clock},
@@ -87,22 +88,3 @@ fn main() -> ! {
         let _ = nb::block!(delay.wait());
     }
 }
-
-/// Convert a number from `0..=255` to an RGB color triplet.
-///
-/// The colours are a transition from red, to green, to blue and back to red.
-fn wheel(mut wheel_pos: u8) -> RGB8 {
-    wheel_pos = 255 - wheel_pos;
-    if wheel_pos < 85 {
-        // No green in this sector - red and blue only
-        (255 - (wheel_pos * 3), 0, wheel_pos * 3).into()
-    } else if wheel_pos < 170 {
-        // No red in this sector - green and blue only
-        wheel_pos -= 85;
-        (0, wheel_pos * 3, 255 - (wheel_pos * 3)).into()
-    } else {
-        // No blue in this sector - red and green only
-        wheel_pos -= 170;
-        (wheel_pos * 3, 255 - (wheel_pos * 3), 0).into()
-    }
-}
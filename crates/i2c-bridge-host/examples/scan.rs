@@ -0,0 +1,46 @@
+//! Lists the devices on the I2C bus of a bridge
+//!
+//! Run with the serial port of the bridge, which is `/dev/ttyACM0` if left
+//! out:
+//!
+//! ```console
+//! $ cargo run --target x86_64-unknown-linux-gnu --example scan -- /dev/ttyACM0
+//! ```
+//!
+//! The addresses devices answer to are shown in a table, as `i2cdetect`
+//! does.
+
+use i2c_bridge_host::{Client, Error, VERSION};
+
+fn main() -> Result<(), Error> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "/dev/ttyACM0".into());
+    let mut client = Client::open(&path)?;
+
+    let version = client.version()?;
+    if version != VERSION {
+        eprintln!(
+            "warning: the bridge speaks version {} of the protocol, not {}",
+            version, VERSION
+        );
+    }
+    client.set_speed(100_000)?;
+    let found = client.scan()?;
+
+    println!("     0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f");
+    for row in (0..0x80).step_by(16) {
+        print!("{:02x}:", row);
+        for address in row..row + 16 {
+            if !(0x08..=0x77).contains(&address) {
+                print!("   ");
+            } else if found.contains(&address) {
+                print!(" {:02x}", address);
+            } else {
+                print!(" --");
+            }
+        }
+        println!();
+    }
+    Ok(())
+}
@@ -0,0 +1,525 @@
+//! Drive an I2C bus through a board running the `i2c-bridge` protocol.
+//!
+//! A [`Client`] sends requests over any byte stream, such as the serial port
+//! a board shows up as on the host, and waits for the answer to each. On
+//! Linux, [`Client::open`] sets up the serial port and opens it.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+use std::process::Command;
+
+use i2c_bridge::message::SCAN_SIZE;
+use i2c_bridge::{Decoder, ErrorCode, FrameError, Request, Response, MAX_FRAME_SIZE};
+
+pub use i2c_bridge::{message::VERSION, MAX_DATA};
+
+/// Tenths of a second [`Client::open`] has reads of the serial port wait for
+/// data before giving up.
+const READ_TIMEOUT_DS: u8 = 50;
+
+/// Why a request failed.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing the stream failed.
+    Io(io::Error),
+    /// The arguments of the request aren't valid, so it wasn't sent.
+    InvalidRequest,
+    /// The bridge answered with an error.
+    Bridge(ErrorCode),
+    /// The stream ended, or timed out, before the bridge answered.
+    NoResponse,
+    /// The answer of the bridge was damaged.
+    Frame(FrameError),
+    /// The answer of the bridge doesn't fit the request.
+    UnexpectedResponse,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "I/O error: {}", error),
+            Error::InvalidRequest => f.write_str("invalid request"),
+            Error::Bridge(code) => write!(f, "bridge error: {:?}", code),
+            Error::NoResponse => f.write_str("no response from the bridge"),
+            Error::Frame(error) => write!(f, "damaged response: {:?}", error),
+            Error::UnexpectedResponse => f.write_str("unexpected response"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+/// Sends requests to a bridge over `P`, one at a time.
+pub struct Client<P: Read + Write> {
+    port: BufReader<P>,
+    seq: u8,
+    decoder: Decoder<MAX_FRAME_SIZE>,
+}
+
+impl Client<File> {
+    /// Open the serial port at `path`, such as `/dev/ttyACM0`, in raw mode,
+    /// with reads giving up after 5s without data.
+    ///
+    /// This calls `stty` to set up the port, as on Linux.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let status = Command::new("stty")
+            .arg("-F")
+            .arg(path)
+            .args(["raw", "-echo", "min", "0", "time"])
+            .arg(READ_TIMEOUT_DS.to_string())
+            .status()?;
+        if !status.success() {
+            return Err(Error::Io(io::Error::other(format!(
+                "stty failed to set up {}",
+                path.display()
+            ))));
+        }
+        let port = OpenOptions::new().read(true).write(true).open(path)?;
+        Ok(Client::new(port))
+    }
+}
+
+impl<P: Read + Write> Client<P> {
+    pub fn new(port: P) -> Self {
+        Client {
+            port: BufReader::new(port),
+            seq: 0,
+            decoder: Decoder::new(),
+        }
+    }
+
+    /// Release the stream.
+    pub fn into_inner(self) -> P {
+        self.port.into_inner()
+    }
+
+    /// The version of the protocol the bridge speaks.
+    pub fn version(&mut self) -> Result<u8, Error> {
+        match self.request(Request::Version)?.as_slice() {
+            [version] => Ok(*version),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// The addresses from 0x08 to 0x77 a device answers to, in order.
+    pub fn scan(&mut self) -> Result<Vec<u8>, Error> {
+        let found = self.request(Request::Scan)?;
+        if found.len() != SCAN_SIZE {
+            return Err(Error::UnexpectedResponse);
+        }
+        Ok((0..0x80)
+            .filter(|address| found[usize::from(address / 8)] & 1 << (address % 8) != 0)
+            .collect())
+    }
+
+    /// Write `data`, from 1 to [`MAX_DATA`] bytes, to the device at
+    /// `address`.
+    pub fn write(&mut self, address: u8, data: &[u8]) -> Result<(), Error> {
+        self.request_data(Request::Write { address, data }, &mut [])
+    }
+
+    /// Fill `buffer`, of 1 to [`MAX_DATA`] bytes, with bytes read from the
+    /// device at `address`.
+    pub fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        let size = data_size(buffer)?;
+        self.request_data(Request::Read { address, size }, buffer)
+    }
+
+    /// Write `data` to the device at `address`, then fill `buffer` with bytes
+    /// read from it after a repeated start. Both are from 1 to [`MAX_DATA`]
+    /// bytes.
+    pub fn write_read(&mut self, address: u8, data: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+        let size = data_size(buffer)?;
+        let request = Request::WriteRead {
+            address,
+            data,
+            size,
+        };
+        self.request_data(request, buffer)
+    }
+
+    /// Clock the bus at `frequency_hz`, such as 100_000 or 400_000.
+    pub fn set_speed(&mut self, frequency_hz: u32) -> Result<(), Error> {
+        self.request_data(Request::SetSpeed { frequency_hz }, &mut [])
+    }
+
+    /// Have the bridge give up on transfers taking longer than
+    /// `timeout_ms`.
+    pub fn set_timeout(&mut self, timeout_ms: u16) -> Result<(), Error> {
+        self.request_data(Request::SetTimeout { timeout_ms }, &mut [])
+    }
+
+    /// Send `request`, and fill `buffer` with the data of the response,
+    /// which must be as long.
+    fn request_data(&mut self, request: Request<'_>, buffer: &mut [u8]) -> Result<(), Error> {
+        let data = self.request(request)?;
+        if data.len() != buffer.len() {
+            return Err(Error::UnexpectedResponse);
+        }
+        buffer.copy_from_slice(&data);
+        Ok(())
+    }
+
+    /// Send `request`, and return the data of the response.
+    fn request(&mut self, request: Request<'_>) -> Result<Vec<u8>, Error> {
+        request.check().map_err(|_| Error::InvalidRequest)?;
+        // Sequence number 0 is for answers to damaged frames
+        self.seq = self.seq.checked_add(1).unwrap_or(1);
+        let mut frame = [0; MAX_FRAME_SIZE + 1];
+        // Start with an empty frame, so the bridge drops anything left over
+        // from an earlier request
+        let size = request
+            .encode(self.seq, &mut frame[1..])
+            .map_err(|_| Error::InvalidRequest)?;
+        let port = self.port.get_mut();
+        port.write_all(&frame[..size + 1])?;
+        port.flush()?;
+
+        // Whether the bridge answered a damaged frame, which the leftover
+        // bytes are, but which could be the request too if nothing else comes
+        let mut damaged = false;
+        loop {
+            let mut byte = [0];
+            if self.port.read(&mut byte)? == 0 {
+                return Err(if damaged {
+                    Error::Bridge(ErrorCode::BadFrame)
+                } else {
+                    Error::NoResponse
+                });
+            }
+            let payload = match self.decoder.push(byte[0]) {
+                Some(payload) => payload.map_err(Error::Frame)?,
+                None => continue,
+            };
+            let response = Response::decode(payload).map_err(Error::Frame)?;
+            match response.result {
+                // Answers to earlier requests, which were given up on
+                _ if response.seq != self.seq && response.seq != 0 => continue,
+                Err(ErrorCode::BadFrame) if response.seq == 0 => damaged = true,
+                Ok(data) if response.seq == self.seq => return Ok(data.to_vec()),
+                Ok(_) => return Err(Error::UnexpectedResponse),
+                Err(code) => return Err(Error::Bridge(code)),
+            }
+        }
+    }
+}
+
+/// The size of `buffer`, as a request has it.
+fn data_size(buffer: &[u8]) -> Result<u16, Error> {
+    u16::try_from(buffer.len()).map_err(|_| Error::InvalidRequest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use i2c_bridge::{Bus, Device};
+    use std::collections::VecDeque;
+
+    /// A bus with a device at 0x20 reading back what was last written to
+    /// it, and one at 0x50 holding the clock low if `stuck`.
+    #[derive(Default)]
+    struct MockBus {
+        stuck: bool,
+        written: Vec<u8>,
+        frequency_hz: u32,
+        timeout_ms: u16,
+    }
+
+    impl MockBus {
+        fn check(&self, address: u8) -> Result<(), ErrorCode> {
+            match address {
+                0x20 => Ok(()),
+                0x50 if self.stuck => Err(ErrorCode::Timeout),
+                _ => Err(ErrorCode::AddressNack),
+            }
+        }
+
+        fn fill(&self, buffer: &mut [u8]) {
+            for (index, byte) in buffer.iter_mut().enumerate() {
+                *byte = self.written.get(index).copied().unwrap_or(0xFF);
+            }
+        }
+    }
+
+    impl Bus for MockBus {
+        fn probe(&mut self, address: u8) -> Result<bool, ErrorCode> {
+            match self.check(address) {
+                Err(ErrorCode::AddressNack) => Ok(false),
+                result => result.map(|()| true),
+            }
+        }
+
+        fn write(&mut self, address: u8, data: &[u8]) -> Result<(), ErrorCode> {
+            self.check(address)?;
+            self.written = data.to_vec();
+            Ok(())
+        }
+
+        fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), ErrorCode> {
+            self.check(address)?;
+            self.fill(buffer);
+            Ok(())
+        }
+
+        fn write_read(
+            &mut self,
+            address: u8,
+            data: &[u8],
+            buffer: &mut [u8],
+        ) -> Result<(), ErrorCode> {
+            self.write(address, data)?;
+            self.fill(buffer);
+            Ok(())
+        }
+
+        fn set_speed(&mut self, frequency_hz: u32) -> Result<(), ErrorCode> {
+            if frequency_hz > 1_000_000 {
+                return Err(ErrorCode::UnsupportedSpeed);
+            }
+            self.frequency_hz = frequency_hz;
+            Ok(())
+        }
+
+        fn set_timeout(&mut self, timeout_ms: u16) -> Result<(), ErrorCode> {
+            self.timeout_ms = timeout_ms;
+            Ok(())
+        }
+    }
+
+    /// Hands the bytes written to a [`Device`], and reads back its
+    /// responses.
+    struct Loopback {
+        device: Device<MockBus>,
+        responses: VecDeque<u8>,
+        requests: usize,
+    }
+
+    impl Loopback {
+        fn new() -> Self {
+            Loopback {
+                device: Device::new(MockBus::default()),
+                responses: VecDeque::new(),
+                requests: 0,
+            }
+        }
+    }
+
+    impl Write for Loopback {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            let mut response = [0; MAX_FRAME_SIZE];
+            for &byte in bytes {
+                if let Some(size) = self.device.receive(byte, &mut response) {
+                    self.responses.extend(&response[..size]);
+                    self.requests += 1;
+                }
+            }
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for Loopback {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            let size = buffer.len().min(self.responses.len());
+            for (slot, byte) in buffer.iter_mut().zip(self.responses.drain(..size)) {
+                *slot = byte;
+            }
+            Ok(size)
+        }
+    }
+
+    fn client() -> Client<Loopback> {
+        Client::new(Loopback::new())
+    }
+
+    fn bus(client: &Client<Loopback>) -> &MockBus {
+        client.port.get_ref().device.bus()
+    }
+
+    #[test]
+    fn version() {
+        assert_eq!(client().version().unwrap(), VERSION);
+    }
+
+    #[test]
+    fn scan() {
+        let mut client = client();
+        assert_eq!(client.scan().unwrap(), [0x20]);
+        // Probing a device holding the clock low fails
+        client.port.get_mut().device.bus_mut().stuck = true;
+        assert!(matches!(
+            client.scan(),
+            Err(Error::Bridge(ErrorCode::Timeout))
+        ));
+    }
+
+    #[test]
+    fn write() {
+        let mut client = client();
+        client.write(0x20, &[1, 2, 3]).unwrap();
+        assert_eq!(bus(&client).written, [1, 2, 3]);
+        let data = [0x42; MAX_DATA];
+        client.write(0x20, &data).unwrap();
+        assert_eq!(bus(&client).written, data);
+    }
+
+    #[test]
+    fn read() {
+        let mut client = client();
+        client.write(0x20, &[7, 8]).unwrap();
+        let mut buffer = [0; 3];
+        client.read(0x20, &mut buffer).unwrap();
+        assert_eq!(buffer, [7, 8, 0xFF]);
+        let mut buffer = [0; MAX_DATA];
+        client.read(0x20, &mut buffer).unwrap();
+        assert_eq!(buffer[..3], [7, 8, 0xFF]);
+    }
+
+    #[test]
+    fn write_read() {
+        let mut client = client();
+        let mut buffer = [0; 2];
+        client.write_read(0x20, &[5, 6], &mut buffer).unwrap();
+        assert_eq!(buffer, [5, 6]);
+        assert_eq!(bus(&client).written, [5, 6]);
+    }
+
+    #[test]
+    fn set_speed() {
+        let mut client = client();
+        client.set_speed(400_000).unwrap();
+        assert_eq!(bus(&client).frequency_hz, 400_000);
+        assert!(matches!(
+            client.set_speed(2_000_000),
+            Err(Error::Bridge(ErrorCode::UnsupportedSpeed))
+        ));
+        assert_eq!(bus(&client).frequency_hz, 400_000);
+    }
+
+    #[test]
+    fn set_timeout() {
+        let mut client = client();
+        client.set_timeout(250).unwrap();
+        assert_eq!(bus(&client).timeout_ms, 250);
+    }
+
+    #[test]
+    fn bridge_errors() {
+        let mut client = client();
+        client.port.get_mut().device.bus_mut().stuck = true;
+        assert!(matches!(
+            client.write(0x21, &[1]),
+            Err(Error::Bridge(ErrorCode::AddressNack))
+        ));
+        assert!(matches!(
+            client.read(0x50, &mut [0]),
+            Err(Error::Bridge(ErrorCode::Timeout))
+        ));
+        // Still in step afterwards
+        assert_eq!(client.version().unwrap(), VERSION);
+    }
+
+    #[test]
+    fn invalid_requests_are_not_sent() {
+        let mut client = client();
+        assert!(matches!(
+            client.write(0x80, &[1]),
+            Err(Error::InvalidRequest)
+        ));
+        assert!(matches!(
+            client.write(0x20, &[]),
+            Err(Error::InvalidRequest)
+        ));
+        assert!(matches!(
+            client.read(0x20, &mut [0; MAX_DATA + 1]),
+            Err(Error::InvalidRequest)
+        ));
+        assert!(matches!(client.set_timeout(0), Err(Error::InvalidRequest)));
+        assert_eq!(client.into_inner().requests, 0);
+    }
+
+    #[test]
+    fn leftover_bytes_are_dropped() {
+        let mut client = client();
+        client.port.get_mut().write_all(&[0x05, 0x01]).unwrap();
+        assert_eq!(client.version().unwrap(), VERSION);
+        // The bridge answered the leftover bytes too
+        assert_eq!(client.into_inner().requests, 2);
+    }
+
+    #[test]
+    fn damaged_requests() {
+        /// Loses what is written to it, and answers with what a bridge
+        /// answers to leftover bytes.
+        struct Lossy(VecDeque<u8>);
+
+        impl Read for Lossy {
+            fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+                self.0.read(buffer)
+            }
+        }
+
+        impl Write for Lossy {
+            fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+                Ok(bytes.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut loopback = Loopback::new();
+        loopback.write_all(&[0x05, 0x01, 0]).unwrap();
+        assert!(matches!(
+            Client::new(Lossy(loopback.responses)).version(),
+            Err(Error::Bridge(ErrorCode::BadFrame))
+        ));
+    }
+
+    #[test]
+    fn no_response() {
+        struct Silent;
+
+        impl Read for Silent {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Ok(0)
+            }
+        }
+
+        impl Write for Silent {
+            fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+                Ok(bytes.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        assert!(matches!(
+            Client::new(Silent).version(),
+            Err(Error::NoResponse)
+        ));
+    }
+}
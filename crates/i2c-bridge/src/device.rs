@@ -0,0 +1,438 @@
+//! The bridge side of the protocol.
+//!
+//! [`Device`] takes the bytes received from the host, carries out the
+//! requests on a [`Bus`], and encodes the responses to send back. It doesn't
+//! care how the bytes travel, or how the bus is driven.
+
+use crate::frame::Decoder;
+use crate::message::{ErrorCode, Request, Response, MAX_DATA, MAX_FRAME_SIZE, SCAN_SIZE, VERSION};
+
+/// First address probed by [`Request::Scan`]; the ones below are reserved.
+const FIRST_SCAN_ADDRESS: u8 = 0x08;
+/// Last address probed by [`Request::Scan`]; the ones above are reserved.
+const LAST_SCAN_ADDRESS: u8 = 0x77;
+
+/// An I2C bus driven by the bridge.
+///
+/// Sizes of data are from 1 to [`MAX_DATA`] bytes, and addresses are 7-bit
+/// ones.
+pub trait Bus {
+    /// Whether a device acknowledges `address`.
+    fn probe(&mut self, address: u8) -> Result<bool, ErrorCode>;
+
+    /// Write `data` to the device at `address`.
+    fn write(&mut self, address: u8, data: &[u8]) -> Result<(), ErrorCode>;
+
+    /// Fill `buffer` with bytes read from the device at `address`.
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), ErrorCode>;
+
+    /// Write `data` to the device at `address`, then fill `buffer` with
+    /// bytes read from it after a repeated start.
+    fn write_read(&mut self, address: u8, data: &[u8], buffer: &mut [u8]) -> Result<(), ErrorCode>;
+
+    /// Clock the bus at `frequency_hz`.
+    fn set_speed(&mut self, frequency_hz: u32) -> Result<(), ErrorCode>;
+
+    /// Give up on transfers taking longer than `timeout_ms`.
+    fn set_timeout(&mut self, timeout_ms: u16) -> Result<(), ErrorCode>;
+}
+
+/// Answers the requests of the host with a [`Bus`].
+pub struct Device<B> {
+    bus: B,
+    decoder: Decoder<MAX_FRAME_SIZE>,
+    data: [u8; MAX_DATA],
+}
+
+impl<B: Bus> Device<B> {
+    pub fn new(bus: B) -> Self {
+        Device {
+            bus,
+            decoder: Decoder::new(),
+            data: [0; MAX_DATA],
+        }
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    /// Release the bus.
+    pub fn free(self) -> B {
+        self.bus
+    }
+
+    /// Take in the next byte received from the host. When it ends a request,
+    /// carry out the request and encode the response into `response`.
+    /// Returns the size of the response then, which must all be sent back
+    /// before the next request.
+    pub fn receive(&mut self, byte: u8, response: &mut [u8; MAX_FRAME_SIZE]) -> Option<usize> {
+        let (seq, result) = match self.decoder.push(byte)? {
+            Ok(payload) => match Request::decode(payload) {
+                (seq, Ok(request)) => (seq, handle(&mut self.bus, &mut self.data, request)),
+                (seq, Err(error)) => (seq, Err(error)),
+            },
+            Err(_) => (0, Err(ErrorCode::BadFrame)),
+        };
+        // Responses are never larger than the largest frame
+        Response { seq, result }.encode(response).ok()
+    }
+}
+
+/// Carry out `request` on `bus`, and return the data of the response, in
+/// `data`.
+fn handle<'a, B: Bus>(
+    bus: &mut B,
+    data: &'a mut [u8; MAX_DATA],
+    request: Request<'_>,
+) -> Result<&'a [u8], ErrorCode> {
+    match request {
+        Request::Version => {
+            data[0] = VERSION;
+            Ok(&data[..1])
+        }
+        Request::Scan => {
+            let found = &mut data[..SCAN_SIZE];
+            found.fill(0);
+            for address in FIRST_SCAN_ADDRESS..=LAST_SCAN_ADDRESS {
+                if bus.probe(address)? {
+                    found[usize::from(address / 8)] |= 1 << (address % 8);
+                }
+            }
+            Ok(found)
+        }
+        Request::Write {
+            address,
+            data: written,
+        } => {
+            bus.write(address, written)?;
+            Ok(&[])
+        }
+        Request::Read { address, size } => {
+            let buffer = &mut data[..usize::from(size)];
+            bus.read(address, buffer)?;
+            Ok(buffer)
+        }
+        Request::WriteRead {
+            address,
+            data: written,
+            size,
+        } => {
+            let buffer = &mut data[..usize::from(size)];
+            bus.write_read(address, written, buffer)?;
+            Ok(buffer)
+        }
+        Request::SetSpeed { frequency_hz } => {
+            bus.set_speed(frequency_hz)?;
+            Ok(&[])
+        }
+        Request::SetTimeout { timeout_ms } => {
+            bus.set_timeout(timeout_ms)?;
+            Ok(&[])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame;
+
+    /// A bus with devices at 0x08, 0x20 and 0x77, which read back what was
+    /// last written to any of them, and at the reserved 0x00 and 0x78. The
+    /// one at 0x50 holds the clock low if `stuck`.
+    struct MockBus {
+        stuck: bool,
+        written: [u8; MAX_DATA],
+        written_size: usize,
+        frequency_hz: u32,
+        timeout_ms: u16,
+    }
+
+    impl MockBus {
+        fn new() -> Self {
+            MockBus {
+                stuck: false,
+                written: [0; MAX_DATA],
+                written_size: 0,
+                frequency_hz: 0,
+                timeout_ms: 0,
+            }
+        }
+
+        fn check(&self, address: u8) -> Result<(), ErrorCode> {
+            match address {
+                0x00 | 0x08 | 0x20 | 0x77 | 0x78 => Ok(()),
+                0x50 if self.stuck => Err(ErrorCode::Timeout),
+                _ => Err(ErrorCode::AddressNack),
+            }
+        }
+
+        fn fill(&self, buffer: &mut [u8]) {
+            for (index, byte) in buffer.iter_mut().enumerate() {
+                *byte = if index < self.written_size {
+                    self.written[index]
+                } else {
+                    0xFF
+                };
+            }
+        }
+    }
+
+    impl Bus for MockBus {
+        fn probe(&mut self, address: u8) -> Result<bool, ErrorCode> {
+            match self.check(address) {
+                Err(ErrorCode::AddressNack) => Ok(false),
+                result => result.map(|()| true),
+            }
+        }
+
+        fn write(&mut self, address: u8, data: &[u8]) -> Result<(), ErrorCode> {
+            self.check(address)?;
+            self.written[..data.len()].copy_from_slice(data);
+            self.written_size = data.len();
+            Ok(())
+        }
+
+        fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), ErrorCode> {
+            self.check(address)?;
+            self.fill(buffer);
+            Ok(())
+        }
+
+        fn write_read(
+            &mut self,
+            address: u8,
+            data: &[u8],
+            buffer: &mut [u8],
+        ) -> Result<(), ErrorCode> {
+            self.write(address, data)?;
+            self.fill(buffer);
+            Ok(())
+        }
+
+        fn set_speed(&mut self, frequency_hz: u32) -> Result<(), ErrorCode> {
+            if frequency_hz > 1_000_000 {
+                return Err(ErrorCode::UnsupportedSpeed);
+            }
+            self.frequency_hz = frequency_hz;
+            Ok(())
+        }
+
+        fn set_timeout(&mut self, timeout_ms: u16) -> Result<(), ErrorCode> {
+            self.timeout_ms = timeout_ms;
+            Ok(())
+        }
+    }
+
+    /// Hand the bytes of `frame` to `device`, which must answer after the
+    /// last one only, and decode the response into `response`.
+    fn exchange<'r>(
+        device: &mut Device<MockBus>,
+        frame: &[u8],
+        response: &'r mut [u8; MAX_FRAME_SIZE],
+    ) -> Response<'r> {
+        let (last, bytes) = frame.split_last().unwrap();
+        for &byte in bytes {
+            assert_eq!(device.receive(byte, response), None);
+        }
+        let size = device.receive(*last, response).unwrap();
+        let payload_size = frame::decode(&mut response[..size - 1]).unwrap();
+        Response::decode(&response[..payload_size]).unwrap()
+    }
+
+    /// Send `request` to `device` with sequence number `seq`, and decode the
+    /// response into `response`.
+    fn request<'r>(
+        device: &mut Device<MockBus>,
+        seq: u8,
+        request: Request<'_>,
+        response: &'r mut [u8; MAX_FRAME_SIZE],
+    ) -> Response<'r> {
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let size = request.encode(seq, &mut frame).unwrap();
+        exchange(device, &frame[..size], response)
+    }
+
+    #[test]
+    fn version() {
+        let mut device = Device::new(MockBus::new());
+        let mut response = [0; MAX_FRAME_SIZE];
+        assert_eq!(
+            request(&mut device, 7, Request::Version, &mut response),
+            Response {
+                seq: 7,
+                result: Ok(&[VERSION]),
+            }
+        );
+    }
+
+    #[test]
+    fn scan_skips_reserved_addresses() {
+        let mut device = Device::new(MockBus::new());
+        let mut response = [0; MAX_FRAME_SIZE];
+        let mut found = [0; SCAN_SIZE];
+        found[1] = 1 << 0; // 0x08
+        found[4] = 1 << 0; // 0x20
+        found[14] = 1 << 7; // 0x77
+        assert_eq!(
+            request(&mut device, 1, Request::Scan, &mut response).result,
+            Ok(&found[..])
+        );
+
+        // Probing a device holding the clock low fails
+        device.bus_mut().stuck = true;
+        assert_eq!(
+            request(&mut device, 2, Request::Scan, &mut response).result,
+            Err(ErrorCode::Timeout)
+        );
+    }
+
+    #[test]
+    fn largest_transfers() {
+        let mut device = Device::new(MockBus::new());
+        let mut response = [0; MAX_FRAME_SIZE];
+        let mut data = [0; MAX_DATA];
+        for (index, byte) in data.iter_mut().enumerate() {
+            // Zeroes too, which the frames have to stuff
+            *byte = index as u8;
+        }
+        let write = Request::Write {
+            address: 0x20,
+            data: &data,
+        };
+        assert_eq!(
+            request(&mut device, 1, write, &mut response).result,
+            Ok(&[][..])
+        );
+        assert_eq!(device.bus().written, data);
+
+        let read = Request::Read {
+            address: 0x20,
+            size: MAX_DATA as u16,
+        };
+        assert_eq!(
+            request(&mut device, 2, read, &mut response).result,
+            Ok(&data[..])
+        );
+
+        let write_read = Request::WriteRead {
+            address: 0x08,
+            data: &data,
+            size: MAX_DATA as u16,
+        };
+        assert_eq!(
+            request(&mut device, 3, write_read, &mut response).result,
+            Ok(&data[..])
+        );
+    }
+
+    #[test]
+    fn short_reads() {
+        let mut device = Device::new(MockBus::new());
+        let mut response = [0; MAX_FRAME_SIZE];
+        let write_read = Request::WriteRead {
+            address: 0x20,
+            data: &[5, 6],
+            size: 3,
+        };
+        assert_eq!(
+            request(&mut device, 1, write_read, &mut response).result,
+            Ok(&[5, 6, 0xFF][..])
+        );
+        assert_eq!(device.bus().written[..device.bus().written_size], [5, 6]);
+    }
+
+    #[test]
+    fn bus_errors() {
+        let mut device = Device::new(MockBus::new());
+        device.bus_mut().stuck = true;
+        let mut response = [0; MAX_FRAME_SIZE];
+        let write = Request::Write {
+            address: 0x21,
+            data: &[1],
+        };
+        assert_eq!(
+            request(&mut device, 1, write, &mut response),
+            Response {
+                seq: 1,
+                result: Err(ErrorCode::AddressNack),
+            }
+        );
+        let read = Request::Read {
+            address: 0x50,
+            size: 1,
+        };
+        assert_eq!(
+            request(&mut device, 2, read, &mut response).result,
+            Err(ErrorCode::Timeout)
+        );
+    }
+
+    #[test]
+    fn settings() {
+        let mut device = Device::new(MockBus::new());
+        let mut response = [0; MAX_FRAME_SIZE];
+        let set_speed = |frequency_hz| Request::SetSpeed { frequency_hz };
+        assert_eq!(
+            request(&mut device, 1, set_speed(400_000), &mut response).result,
+            Ok(&[][..])
+        );
+        assert_eq!(
+            request(&mut device, 2, set_speed(2_000_000), &mut response).result,
+            Err(ErrorCode::UnsupportedSpeed)
+        );
+        assert_eq!(device.bus().frequency_hz, 400_000);
+
+        let set_timeout = Request::SetTimeout { timeout_ms: 250 };
+        assert_eq!(
+            request(&mut device, 3, set_timeout, &mut response).result,
+            Ok(&[][..])
+        );
+        assert_eq!(device.bus().timeout_ms, 250);
+    }
+
+    #[test]
+    fn unknown_commands_keep_their_seq() {
+        let mut device = Device::new(MockBus::new());
+        let mut response = [0; MAX_FRAME_SIZE];
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let size = frame::encode(&[9, 0xEE], &mut frame).unwrap();
+        assert_eq!(
+            exchange(&mut device, &frame[..size], &mut response),
+            Response {
+                seq: 9,
+                result: Err(ErrorCode::UnknownCommand),
+            }
+        );
+    }
+
+    #[test]
+    fn damaged_frames_get_seq_0() {
+        let mut device = Device::new(MockBus::new());
+        let mut response = [0; MAX_FRAME_SIZE];
+        // Leftover bytes, ended by the empty frame starting a request
+        assert_eq!(
+            exchange(&mut device, &[0x05, 0x01, 0], &mut response),
+            Response {
+                seq: 0,
+                result: Err(ErrorCode::BadFrame),
+            }
+        );
+        // Empty frames aren't answered
+        assert_eq!(device.receive(0, &mut response), None);
+
+        // Back in step with the next request
+        assert_eq!(
+            request(&mut device, 3, Request::Version, &mut response),
+            Response {
+                seq: 3,
+                result: Ok(&[VERSION]),
+            }
+        );
+    }
+}
@@ -0,0 +1,350 @@
+//! Frames carrying the messages over a byte stream.
+//!
+//! Each payload is followed by its CRC-16/CCITT-FALSE, little endian, and
+//! the whole is encoded with Consistent Overhead Byte Stuffing so it doesn't
+//! contain any zero byte. A zero byte then ends the frame. A receiver that
+//! starts in the middle of a frame, or gets a damaged one, is back in step
+//! after the next zero byte; empty frames are ignored, so a sender can start
+//! with a zero byte to be sure.
+
+/// Why a frame can't be encoded or decoded.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FrameError {
+    /// The buffer is too small for the encoded frame.
+    BufferTooSmall,
+    /// The frame is longer than the receiver can take, or the message is
+    /// longer than the protocol allows.
+    TooLong,
+    /// The frame isn't valid Consistent Overhead Byte Stuffing, or is too
+    /// short to hold a checksum.
+    Malformed,
+    /// The checksum doesn't match the payload.
+    Checksum,
+}
+
+/// Size of the checksum after the payload.
+const CHECKSUM_SIZE: usize = 2;
+
+/// The largest size of the frame of a `payload_size` bytes payload,
+/// including the zero byte ending it.
+pub const fn max_encoded_size(payload_size: usize) -> usize {
+    let size = payload_size + CHECKSUM_SIZE;
+    // A code byte starts the frame, then every 254 bytes, and a zero ends it
+    size + 1 + size / 254 + 1
+}
+
+/// Encode `payload` into a frame in `buffer`, and return the size of the
+/// frame, including the zero byte ending it.
+pub fn encode(payload: &[u8], buffer: &mut [u8]) -> Result<usize, FrameError> {
+    let checksum = crc16(payload).to_le_bytes();
+    let mut put = |index: usize, byte: u8| match buffer.get_mut(index) {
+        Some(slot) => {
+            *slot = byte;
+            Ok(())
+        }
+        None => Err(FrameError::BufferTooSmall),
+    };
+
+    // Each block starts with a code byte, one more than the number of
+    // non-zero bytes following it. Blocks shorter than 254 bytes stand for a
+    // zero byte after them, except the last one.
+    let mut code_index = 0;
+    let mut code = 1;
+    let mut size = 1;
+    for &byte in payload.iter().chain(checksum.iter()) {
+        if byte != 0 {
+            put(size, byte)?;
+            size += 1;
+            code += 1;
+        }
+        if byte == 0 || code == 0xFF {
+            put(code_index, code)?;
+            code_index = size;
+            code = 1;
+            size += 1;
+        }
+    }
+    put(code_index, code)?;
+    put(size, 0)?;
+    Ok(size + 1)
+}
+
+/// Decode the frame in `frame`, without the zero byte ending it, in place.
+/// Returns the size of the payload, which is then at the start of `frame`.
+pub fn decode(frame: &mut [u8]) -> Result<usize, FrameError> {
+    let mut read = 0;
+    let mut write = 0;
+    while read < frame.len() {
+        let code = usize::from(frame[read]);
+        if code == 0 {
+            return Err(FrameError::Malformed);
+        }
+        let start = read + 1;
+        let end = start + code - 1;
+        if end > frame.len() {
+            return Err(FrameError::Malformed);
+        }
+        // The payload is never longer than the frame, so it can be written
+        // over the part already read
+        frame.copy_within(start..end, write);
+        write += end - start;
+        read = end;
+        if code != 0xFF && read < frame.len() {
+            frame[write] = 0;
+            write += 1;
+        }
+    }
+
+    let size = write
+        .checked_sub(CHECKSUM_SIZE)
+        .ok_or(FrameError::Malformed)?;
+    let checksum = u16::from_le_bytes([frame[size], frame[size + 1]]);
+    if checksum != crc16(&frame[..size]) {
+        return Err(FrameError::Checksum);
+    }
+    Ok(size)
+}
+
+/// Splits a byte stream into frames, and decodes them.
+///
+/// Frames longer than `N` bytes, without the zero byte ending them, are
+/// dropped.
+pub struct Decoder<const N: usize> {
+    buffer: [u8; N],
+    size: usize,
+    overflow: bool,
+}
+
+impl<const N: usize> Decoder<N> {
+    pub const fn new() -> Self {
+        Decoder {
+            buffer: [0; N],
+            size: 0,
+            overflow: false,
+        }
+    }
+
+    /// Take in the next byte of the stream. When it ends a frame, return the
+    /// payload of the frame, or why it can't be decoded.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&[u8], FrameError>> {
+        if byte != 0 {
+            match self.buffer.get_mut(self.size) {
+                Some(slot) => {
+                    *slot = byte;
+                    self.size += 1;
+                }
+                None => self.overflow = true,
+            }
+            return None;
+        }
+
+        let size = core::mem::replace(&mut self.size, 0);
+        if core::mem::replace(&mut self.overflow, false) {
+            return Some(Err(FrameError::TooLong));
+        }
+        if size == 0 {
+            return None;
+        }
+        match decode(&mut self.buffer[..size]) {
+            Ok(size) => Some(Ok(&self.buffer[..size])),
+            Err(error) => Some(Err(error)),
+        }
+    }
+
+    /// Drop the part of a frame received so far.
+    pub fn reset(&mut self) {
+        self.size = 0;
+        self.overflow = false;
+    }
+}
+
+impl<const N: usize> Default for Decoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The CRC-16/CCITT-FALSE of `data`.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFF_u16;
+    for &byte in data {
+        crc ^= u16::from(byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_PAYLOAD: usize = 600;
+    const MAX_FRAME: usize = max_encoded_size(MAX_PAYLOAD);
+
+    /// Encode `payload`, check the frame, and decode it back.
+    fn round_trip(payload: &[u8]) {
+        let mut frame = [0xAA; MAX_FRAME];
+        let size = encode(payload, &mut frame).unwrap();
+        assert!(size <= max_encoded_size(payload.len()), "{}", payload.len());
+        assert_eq!(frame[size - 1], 0);
+        assert!(!frame[..size - 1].contains(&0));
+
+        let decoded = decode(&mut frame[..size - 1]).unwrap();
+        assert_eq!(&frame[..decoded], payload);
+    }
+
+    /// Feed `bytes` to `decoder`, and return what the last one gives.
+    fn push_all<'a, const N: usize>(
+        decoder: &'a mut Decoder<N>,
+        bytes: &[u8],
+    ) -> Option<Result<&'a [u8], FrameError>> {
+        let (last, rest) = bytes.split_last().unwrap();
+        for &byte in rest {
+            assert_eq!(decoder.push(byte), None);
+        }
+        decoder.push(*last)
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut payload = [0; MAX_PAYLOAD];
+        for (index, byte) in payload.iter_mut().enumerate() {
+            *byte = (index % 255) as u8 + 1;
+        }
+        // Around the 254 bytes blocks, counting the checksum
+        for size in [0, 1, 2, 251, 252, 253, 254, 255, 256, 506, 507, MAX_PAYLOAD] {
+            round_trip(&payload[..size]);
+        }
+    }
+
+    #[test]
+    fn round_trips_with_zeros() {
+        let zeros = [0; MAX_PAYLOAD];
+        for size in [1, 2, 253, 254, 255, MAX_PAYLOAD] {
+            round_trip(&zeros[..size]);
+        }
+        let mut mixed = [0; 300];
+        for (index, byte) in mixed.iter_mut().enumerate() {
+            *byte = if index % 7 == 0 { 0 } else { index as u8 };
+        }
+        round_trip(&mixed);
+    }
+
+    #[test]
+    fn encoding() {
+        let mut frame = [0; 8];
+        assert_eq!(encode(&[0x11, 0x00, 0x22], &mut frame), Ok(7));
+        let checksum = crc16(&[0x11, 0x00, 0x22]).to_le_bytes();
+        assert_eq!(
+            frame[..7],
+            [0x02, 0x11, 0x04, 0x22, checksum[0], checksum[1], 0x00]
+        );
+    }
+
+    #[test]
+    fn encoding_needs_room() {
+        let mut frame = [0; 8];
+        let size = encode(&[1, 2, 3], &mut frame).unwrap();
+        assert_eq!(
+            encode(&[1, 2, 3], &mut frame[..size - 1]),
+            Err(FrameError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut frame = [0; 16];
+        let size = encode(b"hello", &mut frame).unwrap();
+        frame[3] ^= 0x01;
+        assert_eq!(decode(&mut frame[..size - 1]), Err(FrameError::Checksum));
+    }
+
+    #[test]
+    fn malformed_frames() {
+        // A code byte going past the end
+        assert_eq!(decode(&mut [0x05, 0x01, 0x02]), Err(FrameError::Malformed));
+        // Too short for a checksum
+        assert_eq!(decode(&mut [0x02, 0x01]), Err(FrameError::Malformed));
+    }
+
+    #[test]
+    fn decoder_gives_each_payload() {
+        let mut frame = [0; 16];
+        let size = encode(b"hello", &mut frame).unwrap();
+        let mut decoder = Decoder::<16>::new();
+        for _ in 0..2 {
+            assert_eq!(
+                push_all(&mut decoder, &frame[..size]),
+                Some(Ok(&b"hello"[..]))
+            );
+        }
+    }
+
+    #[test]
+    fn decoder_skips_empty_frames() {
+        let mut decoder = Decoder::<16>::new();
+        assert_eq!(decoder.push(0), None);
+        assert_eq!(decoder.push(0), None);
+    }
+
+    #[test]
+    fn decoder_resyncs_after_a_partial_frame() {
+        let mut frame = [0; 16];
+        let size = encode(b"hello", &mut frame).unwrap();
+        let mut decoder = Decoder::<16>::new();
+
+        // Starting in the middle of a frame
+        assert_eq!(
+            push_all(&mut decoder, &frame[3..size]),
+            Some(Err(FrameError::Malformed))
+        );
+        assert_eq!(
+            push_all(&mut decoder, &frame[..size]),
+            Some(Ok(&b"hello"[..]))
+        );
+
+        // A frame cut short, then a zero byte to start over
+        assert!(push_all(&mut decoder, &frame[..4]).is_none());
+        assert_eq!(decoder.push(0), Some(Err(FrameError::Malformed)));
+        assert_eq!(
+            push_all(&mut decoder, &frame[..size]),
+            Some(Ok(&b"hello"[..]))
+        );
+
+        // Or dropped with a reset
+        assert!(push_all(&mut decoder, &frame[..4]).is_none());
+        decoder.reset();
+        assert_eq!(
+            push_all(&mut decoder, &frame[..size]),
+            Some(Ok(&b"hello"[..]))
+        );
+    }
+
+    #[test]
+    fn decoder_overflow() {
+        let mut frame = [0; 16];
+        let size = encode(b"hello!", &mut frame).unwrap();
+        // One byte short for the frame, without its zero byte
+        let mut decoder = Decoder::<8>::new();
+        assert_eq!(size - 1, 9);
+        assert_eq!(
+            push_all(&mut decoder, &frame[..size]),
+            Some(Err(FrameError::TooLong))
+        );
+
+        // The next frame that fits goes through
+        let size = encode(b"hi", &mut frame).unwrap();
+        assert_eq!(push_all(&mut decoder, &frame[..size]), Some(Ok(&b"hi"[..])));
+    }
+}
@@ -0,0 +1,20 @@
+//! A framed binary protocol to drive an I2C bus from a USB host.
+//!
+//! A bridge, such as a board with a USB serial port and an I2C bus, takes
+//! [`Request`]s from the host and answers each with a [`Response`], one
+//! request at a time. Messages travel in [`frame`]s with a checksum, so a
+//! damaged one is noticed, and the receiver gets back in step with the next
+//! frame.
+//!
+//! Both sides are in this crate: the host encodes requests and decodes
+//! responses with the [`message`] types, and the bridge hands the received
+//! bytes to a [`Device`], which carries out the requests on its [`Bus`]. It
+//! has no dependencies and doesn't touch any hardware.
+#![no_std]
+
+pub mod device;
+pub use device::{Bus, Device};
+pub mod frame;
+pub use frame::{Decoder, FrameError};
+pub mod message;
+pub use message::{ErrorCode, Request, Response, MAX_DATA, MAX_FRAME_SIZE};
@@ -0,0 +1,495 @@
+//! The requests of the host, and the responses of the bridge.
+//!
+//! Requests start with a sequence number chosen by the host and a command.
+//! Responses start with the sequence number of the request they answer and a
+//! status, 0 for success or an [`ErrorCode`]. Numbers of more than one byte
+//! are little endian.
+//!
+//! | Command | Request                  | Arguments                             | Response data            |
+//! |---------|--------------------------|---------------------------------------|--------------------------|
+//! | 1       | [`Request::Version`]     |                                       | Protocol version         |
+//! | 2       | [`Request::Scan`]        |                                       | 16 bytes, one bit per address, LSB first |
+//! | 3       | [`Request::Write`]       | Address, data                         |                          |
+//! | 4       | [`Request::Read`]        | Address, size on 2 bytes              | Data read                |
+//! | 5       | [`Request::WriteRead`]   | Address, read size on 2 bytes, data   | Data read                |
+//! | 6       | [`Request::SetSpeed`]    | Clock frequency in Hz, on 4 bytes     |                          |
+//! | 7       | [`Request::SetTimeout`]  | Timeout in ms, on 2 bytes             |                          |
+//!
+//! A bridge answers damaged frames with [`ErrorCode::BadFrame`] and a
+//! sequence number of 0, so hosts number their requests from 1.
+
+use crate::frame::{self, FrameError};
+
+/// Version of the protocol, as answered to [`Request::Version`].
+pub const VERSION: u8 = 1;
+
+/// Most bytes written or read on the bus by a single request.
+pub const MAX_DATA: usize = 256;
+
+/// Largest payload of a request or response.
+pub const MAX_PAYLOAD: usize = 5 + MAX_DATA;
+
+/// Largest frame of a request or response, including the zero byte ending
+/// it.
+pub const MAX_FRAME_SIZE: usize = frame::max_encoded_size(MAX_PAYLOAD);
+
+/// Size of the response to [`Request::Scan`].
+pub const SCAN_SIZE: usize = 16;
+
+const VERSION_COMMAND: u8 = 1;
+const SCAN_COMMAND: u8 = 2;
+const WRITE_COMMAND: u8 = 3;
+const READ_COMMAND: u8 = 4;
+const WRITE_READ_COMMAND: u8 = 5;
+const SET_SPEED_COMMAND: u8 = 6;
+const SET_TIMEOUT_COMMAND: u8 = 7;
+
+/// Status of a response to a request that succeeded.
+const OK: u8 = 0;
+
+/// Why a request failed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum ErrorCode {
+    /// The frame of the request was damaged.
+    BadFrame = 1,
+    /// The command isn't known to the bridge.
+    UnknownCommand = 2,
+    /// The arguments don't fit the command, the address isn't a 7-bit one,
+    /// or a size is 0 or larger than [`MAX_DATA`].
+    InvalidArgument = 3,
+    /// No device acknowledged the address.
+    AddressNack = 4,
+    /// The device didn't acknowledge some of the data written.
+    DataNack = 5,
+    /// Another controller took over the bus.
+    ArbitrationLost = 6,
+    /// The transfer didn't complete in time, as when a device holds the
+    /// clock low.
+    Timeout = 7,
+    /// The bus failed in some other way.
+    BusError = 8,
+    /// The bus can't be clocked at that frequency.
+    UnsupportedSpeed = 9,
+}
+
+impl ErrorCode {
+    /// The error with the status `code`, or `None` if it isn't an error.
+    pub fn from_u8(code: u8) -> Option<Self> {
+        Some(match code {
+            1 => ErrorCode::BadFrame,
+            2 => ErrorCode::UnknownCommand,
+            3 => ErrorCode::InvalidArgument,
+            4 => ErrorCode::AddressNack,
+            5 => ErrorCode::DataNack,
+            6 => ErrorCode::ArbitrationLost,
+            7 => ErrorCode::Timeout,
+            8 => ErrorCode::BusError,
+            9 => ErrorCode::UnsupportedSpeed,
+            _ => return None,
+        })
+    }
+}
+
+/// A request from the host.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Request<'a> {
+    /// Ask for the version of the protocol.
+    Version,
+    /// Find the addresses from 0x08 to 0x77 a device answers to.
+    Scan,
+    /// Write `data` to the device at `address`.
+    Write { address: u8, data: &'a [u8] },
+    /// Read `size` bytes from the device at `address`.
+    Read { address: u8, size: u16 },
+    /// Write `data` to the device at `address`, then read `size` bytes from
+    /// it after a repeated start.
+    WriteRead {
+        address: u8,
+        data: &'a [u8],
+        size: u16,
+    },
+    /// Clock the bus at `frequency_hz`, up to 1MHz.
+    SetSpeed { frequency_hz: u32 },
+    /// Give up on transfers taking longer than `timeout_ms`.
+    SetTimeout { timeout_ms: u16 },
+}
+
+impl<'a> Request<'a> {
+    /// Check the arguments, as the bridge does.
+    pub fn check(&self) -> Result<(), ErrorCode> {
+        let valid = match *self {
+            Request::Version | Request::Scan => true,
+            Request::Write { address, data } => is_address(address) && is_data_size(data.len()),
+            Request::Read { address, size } => is_address(address) && is_data_size(size.into()),
+            Request::WriteRead {
+                address,
+                data,
+                size,
+            } => is_address(address) && is_data_size(data.len()) && is_data_size(size.into()),
+            Request::SetSpeed { frequency_hz } => frequency_hz > 0,
+            Request::SetTimeout { timeout_ms } => timeout_ms > 0,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(ErrorCode::InvalidArgument)
+        }
+    }
+
+    /// Encode the request with sequence number `seq` into a frame in
+    /// `buffer`, and return the size of the frame.
+    pub fn encode(&self, seq: u8, buffer: &mut [u8]) -> Result<usize, FrameError> {
+        let mut payload = Payload::new();
+        payload.push(&[seq])?;
+        match *self {
+            Request::Version => payload.push(&[VERSION_COMMAND])?,
+            Request::Scan => payload.push(&[SCAN_COMMAND])?,
+            Request::Write { address, data } => {
+                payload.push(&[WRITE_COMMAND, address])?;
+                payload.push(data)?;
+            }
+            Request::Read { address, size } => {
+                payload.push(&[READ_COMMAND, address])?;
+                payload.push(&size.to_le_bytes())?;
+            }
+            Request::WriteRead {
+                address,
+                data,
+                size,
+            } => {
+                payload.push(&[WRITE_READ_COMMAND, address])?;
+                payload.push(&size.to_le_bytes())?;
+                payload.push(data)?;
+            }
+            Request::SetSpeed { frequency_hz } => {
+                payload.push(&[SET_SPEED_COMMAND])?;
+                payload.push(&frequency_hz.to_le_bytes())?;
+            }
+            Request::SetTimeout { timeout_ms } => {
+                payload.push(&[SET_TIMEOUT_COMMAND])?;
+                payload.push(&timeout_ms.to_le_bytes())?;
+            }
+        }
+        frame::encode(payload.as_slice(), buffer)
+    }
+
+    /// Decode the request in the payload of a frame. Returns its sequence
+    /// number, 0 if the payload is empty, and the request or why it isn't
+    /// valid.
+    pub fn decode(payload: &'a [u8]) -> (u8, Result<Self, ErrorCode>) {
+        match payload.split_first() {
+            Some((&seq, body)) => (seq, Self::decode_body(body)),
+            None => (0, Err(ErrorCode::BadFrame)),
+        }
+    }
+
+    fn decode_body(body: &'a [u8]) -> Result<Self, ErrorCode> {
+        let (&command, arguments) = body.split_first().ok_or(ErrorCode::BadFrame)?;
+        let request = match (command, arguments) {
+            (VERSION_COMMAND, []) => Request::Version,
+            (SCAN_COMMAND, []) => Request::Scan,
+            (WRITE_COMMAND, [address, data @ ..]) => Request::Write {
+                address: *address,
+                data,
+            },
+            (READ_COMMAND, [address, size_low, size_high]) => Request::Read {
+                address: *address,
+                size: u16::from_le_bytes([*size_low, *size_high]),
+            },
+            (WRITE_READ_COMMAND, [address, size_low, size_high, data @ ..]) => Request::WriteRead {
+                address: *address,
+                data,
+                size: u16::from_le_bytes([*size_low, *size_high]),
+            },
+            (SET_SPEED_COMMAND, [a, b, c, d]) => Request::SetSpeed {
+                frequency_hz: u32::from_le_bytes([*a, *b, *c, *d]),
+            },
+            (SET_TIMEOUT_COMMAND, [low, high]) => Request::SetTimeout {
+                timeout_ms: u16::from_le_bytes([*low, *high]),
+            },
+            (VERSION_COMMAND..=SET_TIMEOUT_COMMAND, _) => return Err(ErrorCode::InvalidArgument),
+            _ => return Err(ErrorCode::UnknownCommand),
+        };
+        request.check()?;
+        Ok(request)
+    }
+}
+
+/// A response from the bridge.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Response<'a> {
+    /// Sequence number of the request answered.
+    pub seq: u8,
+    /// The data of the response, or why the request failed.
+    pub result: Result<&'a [u8], ErrorCode>,
+}
+
+impl<'a> Response<'a> {
+    /// Encode the response into a frame in `buffer`, and return the size of
+    /// the frame.
+    pub fn encode(&self, buffer: &mut [u8]) -> Result<usize, FrameError> {
+        let mut payload = Payload::new();
+        match self.result {
+            Ok(data) => {
+                payload.push(&[self.seq, OK])?;
+                payload.push(data)?;
+            }
+            Err(error) => payload.push(&[self.seq, error as u8])?,
+        }
+        frame::encode(payload.as_slice(), buffer)
+    }
+
+    /// Decode the response in the payload of a frame.
+    pub fn decode(payload: &'a [u8]) -> Result<Self, FrameError> {
+        let (seq, status, data) = match payload {
+            [seq, status, data @ ..] => (*seq, *status, data),
+            _ => return Err(FrameError::Malformed),
+        };
+        let result = match (status, data) {
+            (OK, data) => Ok(data),
+            (status, []) => Err(ErrorCode::from_u8(status).ok_or(FrameError::Malformed)?),
+            _ => return Err(FrameError::Malformed),
+        };
+        Ok(Response { seq, result })
+    }
+}
+
+/// Whether `address` is a 7-bit address.
+fn is_address(address: u8) -> bool {
+    address <= 0x7F
+}
+
+/// Whether `size` bytes can be written or read in one go.
+fn is_data_size(size: usize) -> bool {
+    size > 0 && size <= MAX_DATA
+}
+
+/// The payload of a frame, as it is put together.
+struct Payload {
+    bytes: [u8; MAX_PAYLOAD],
+    size: usize,
+}
+
+impl Payload {
+    fn new() -> Self {
+        Payload {
+            bytes: [0; MAX_PAYLOAD],
+            size: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) -> Result<(), FrameError> {
+        let end = self.size + bytes.len();
+        self.bytes
+            .get_mut(self.size..end)
+            .ok_or(FrameError::TooLong)?
+            .copy_from_slice(bytes);
+        self.size = end;
+        Ok(())
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.size]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encode `request` with sequence number `seq`, and decode it back.
+    fn round_trip(seq: u8, request: Request<'_>) {
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let size = request.encode(seq, &mut frame).unwrap();
+        let payload_size = frame::decode(&mut frame[..size - 1]).unwrap();
+        assert_eq!(Request::decode(&frame[..payload_size]), (seq, Ok(request)));
+    }
+
+    fn response_round_trip(response: Response<'_>) {
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let size = response.encode(&mut frame).unwrap();
+        let payload_size = frame::decode(&mut frame[..size - 1]).unwrap();
+        assert_eq!(Response::decode(&frame[..payload_size]), Ok(response));
+    }
+
+    #[test]
+    fn requests_round_trip() {
+        let data = [0xA5; MAX_DATA];
+        for request in [
+            Request::Version,
+            Request::Scan,
+            Request::Write {
+                address: 0x3C,
+                data: &[0x00, 0xAF],
+            },
+            Request::Write {
+                address: 0x7F,
+                data: &data,
+            },
+            Request::Read {
+                address: 0x08,
+                size: 1,
+            },
+            Request::Read {
+                address: 0x50,
+                size: MAX_DATA as u16,
+            },
+            Request::WriteRead {
+                address: 0x50,
+                data: &[0x00, 0x10],
+                size: 32,
+            },
+            Request::WriteRead {
+                address: 0x50,
+                data: &data,
+                size: MAX_DATA as u16,
+            },
+            Request::SetSpeed {
+                frequency_hz: 400_000,
+            },
+            Request::SetTimeout { timeout_ms: 1000 },
+        ] {
+            round_trip(1, request);
+            round_trip(u8::MAX, request);
+        }
+    }
+
+    #[test]
+    fn request_layout() {
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let size = Request::WriteRead {
+            address: 0x50,
+            data: &[0xAB],
+            size: 0x0102,
+        }
+        .encode(9, &mut frame)
+        .unwrap();
+        let payload_size = frame::decode(&mut frame[..size - 1]).unwrap();
+        assert_eq!(
+            frame[..payload_size],
+            [9, WRITE_READ_COMMAND, 0x50, 0x02, 0x01, 0xAB]
+        );
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let data = [0; MAX_DATA + 1];
+        for request in [
+            Request::Write {
+                address: 0x80,
+                data: &[1],
+            },
+            Request::Write {
+                address: 0x10,
+                data: &[],
+            },
+            Request::Write {
+                address: 0x10,
+                data: &data,
+            },
+            Request::Read {
+                address: 0x10,
+                size: 0,
+            },
+            Request::Read {
+                address: 0x10,
+                size: MAX_DATA as u16 + 1,
+            },
+            Request::WriteRead {
+                address: 0x10,
+                data: &[1],
+                size: 0,
+            },
+            Request::SetSpeed { frequency_hz: 0 },
+            Request::SetTimeout { timeout_ms: 0 },
+        ] {
+            assert_eq!(request.check(), Err(ErrorCode::InvalidArgument));
+        }
+    }
+
+    #[test]
+    fn bad_requests() {
+        assert_eq!(Request::decode(&[]), (0, Err(ErrorCode::BadFrame)));
+        assert_eq!(Request::decode(&[3]), (3, Err(ErrorCode::BadFrame)));
+        assert_eq!(
+            Request::decode(&[3, 0]),
+            (3, Err(ErrorCode::UnknownCommand))
+        );
+        assert_eq!(
+            Request::decode(&[3, SET_TIMEOUT_COMMAND + 1]),
+            (3, Err(ErrorCode::UnknownCommand))
+        );
+        // Arguments missing or left over
+        assert_eq!(
+            Request::decode(&[3, VERSION_COMMAND, 0]),
+            (3, Err(ErrorCode::InvalidArgument))
+        );
+        assert_eq!(
+            Request::decode(&[3, READ_COMMAND, 0x10, 1]),
+            (3, Err(ErrorCode::InvalidArgument))
+        );
+        assert_eq!(
+            Request::decode(&[3, SET_SPEED_COMMAND, 1, 2, 3, 4, 5]),
+            (3, Err(ErrorCode::InvalidArgument))
+        );
+        // Arguments that don't pass the checks
+        assert_eq!(
+            Request::decode(&[3, WRITE_COMMAND, 0x10]),
+            (3, Err(ErrorCode::InvalidArgument))
+        );
+    }
+
+    #[test]
+    fn requests_too_long_to_encode() {
+        let data = [0; MAX_PAYLOAD];
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let request = Request::Write {
+            address: 0x10,
+            data: &data,
+        };
+        assert_eq!(request.encode(1, &mut frame), Err(FrameError::TooLong));
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        let data = [0x5A; MAX_DATA];
+        response_round_trip(Response {
+            seq: 1,
+            result: Ok(&[]),
+        });
+        response_round_trip(Response {
+            seq: 2,
+            result: Ok(&data),
+        });
+        for code in 1..=9 {
+            response_round_trip(Response {
+                seq: 3,
+                result: Err(ErrorCode::from_u8(code).unwrap()),
+            });
+        }
+    }
+
+    #[test]
+    fn error_codes() {
+        assert_eq!(ErrorCode::from_u8(OK), None);
+        assert_eq!(ErrorCode::from_u8(10), None);
+        for code in 1..=9 {
+            assert_eq!(
+                ErrorCode::from_u8(code).map(|error| error as u8),
+                Some(code)
+            );
+        }
+    }
+
+    #[test]
+    fn bad_responses() {
+        assert_eq!(Response::decode(&[1]), Err(FrameError::Malformed));
+        // An error with data, and an unknown status
+        assert_eq!(
+            Response::decode(&[1, ErrorCode::Timeout as u8, 0]),
+            Err(FrameError::Malformed)
+        );
+        assert_eq!(Response::decode(&[1, 10]), Err(FrameError::Malformed));
+    }
+}
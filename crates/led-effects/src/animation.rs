@@ -0,0 +1,793 @@
+//! Animations for LED strips, and a scheduler to play them.
+//!
+//! An [`Animation`] renders the frame for a timestamp into a buffer of
+//! colors, which a [`FrameScheduler`] then sends to any
+//! [`SmartLedsWrite`] target at a fixed frame rate. [`Transition`] fades from
+//! one animation to another.
+//!
+//! Timestamps are in milliseconds from any monotonic source, and may wrap
+//! around. Rendering is deterministic: the same timestamps, and the same seed
+//! for the effects with randomness, always produce the same frames.
+
+use smart_leds::{SmartLedsWrite, RGB8};
+
+use crate::color::{blend, hsv_to_rgb, palette, scale, Hsv};
+
+const BLACK: RGB8 = RGB8::new(0, 0, 0);
+
+/// Something that can draw frames on an LED strip.
+pub trait Animation {
+    /// Draw the frame shown at `now_ms` into `frame`, one color per LED.
+    fn render(&mut self, now_ms: u32, frame: &mut [RGB8]);
+}
+
+impl<A: Animation + ?Sized> Animation for &mut A {
+    fn render(&mut self, now_ms: u32, frame: &mut [RGB8]) {
+        (**self).render(now_ms, frame)
+    }
+}
+
+/// How far through a cycle of `period_ms` the time `now_ms` is, from 0 to
+/// 255.
+fn phase(now_ms: u32, period_ms: u32) -> u8 {
+    let period_ms = period_ms.max(1);
+    (u64::from(now_ms % period_ms) * 256 / u64::from(period_ms)) as u8
+}
+
+/// A small xorshift pseudo random number generator, so the random effects
+/// are reproducible from their seed.
+#[derive(Clone, Copy, Debug)]
+struct Rng(u32);
+
+impl Rng {
+    const fn new(seed: u32) -> Self {
+        // Xorshift never leaves 0
+        Rng(if seed == 0 { 0x9E37_79B9 } else { seed })
+    }
+
+    fn next(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+
+    fn next_u8(&mut self) -> u8 {
+        (self.next() >> 24) as u8
+    }
+
+    /// A random number in `0..n`, for `n` above 0.
+    fn below(&mut self, n: usize) -> usize {
+        self.next() as usize % n
+    }
+}
+
+/// The color wheel scrolling along the strip.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rainbow {
+    /// Time for a full turn of the color wheel.
+    pub period_ms: u32,
+    /// How much of the color wheel is spread along the strip, from 0 (all
+    /// LEDs the same color) to 255 (almost the whole wheel).
+    pub spread: u8,
+    pub brightness: u8,
+}
+
+impl Rainbow {
+    pub const fn new(period_ms: u32, spread: u8) -> Self {
+        Rainbow {
+            period_ms,
+            spread,
+            brightness: 255,
+        }
+    }
+}
+
+impl Animation for Rainbow {
+    fn render(&mut self, now_ms: u32, frame: &mut [RGB8]) {
+        let start = phase(now_ms, self.period_ms);
+        let len = frame.len().max(1);
+        for (i, led) in frame.iter_mut().enumerate() {
+            let offset = (i * usize::from(self.spread) / len) as u8;
+            *led = hsv_to_rgb(Hsv::new(start.wrapping_add(offset), 255, self.brightness));
+        }
+    }
+}
+
+/// Evenly spaced lit LEDs marching along the strip, like theater marquee
+/// lights.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Chase {
+    pub color: RGB8,
+    pub background: RGB8,
+    /// One LED in every `spacing` is lit.
+    pub spacing: usize,
+    /// Time for the lights to move by one LED.
+    pub step_ms: u32,
+}
+
+impl Chase {
+    pub const fn new(color: RGB8, spacing: usize, step_ms: u32) -> Self {
+        Chase {
+            color,
+            background: BLACK,
+            spacing,
+            step_ms,
+        }
+    }
+}
+
+impl Animation for Chase {
+    fn render(&mut self, now_ms: u32, frame: &mut [RGB8]) {
+        let spacing = self.spacing.max(1);
+        let offset = (now_ms / self.step_ms.max(1)) as usize % spacing;
+        for (i, led) in frame.iter_mut().enumerate() {
+            // Move the lights towards the end of the strip
+            *led = if (i + spacing - offset).is_multiple_of(spacing) {
+                self.color
+            } else {
+                self.background
+            };
+        }
+    }
+}
+
+/// All LEDs slowly fading in and out together.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Breathe {
+    pub color: RGB8,
+    /// Time for a full breath, from off to full and back.
+    pub period_ms: u32,
+}
+
+impl Breathe {
+    pub const fn new(color: RGB8, period_ms: u32) -> Self {
+        Breathe { color, period_ms }
+    }
+}
+
+impl Animation for Breathe {
+    fn render(&mut self, now_ms: u32, frame: &mut [RGB8]) {
+        let phase = phase(now_ms, self.period_ms);
+        // Triangle wave, squared so it lingers near off like a real breath
+        let triangle = if phase < 128 {
+            phase * 2
+        } else {
+            (255 - phase) * 2
+        };
+        let level = ((u16::from(triangle) * u16::from(triangle) + 127) / 255) as u8;
+        let color = scale(self.color, level);
+        for led in frame.iter_mut() {
+            *led = color;
+        }
+    }
+}
+
+/// Random LEDs flashing up and fading out, on a strip of `N` LEDs.
+///
+/// LEDs past the first `N` of a frame are left dark.
+#[derive(Clone, Debug)]
+pub struct Sparkle<const N: usize> {
+    pub color: RGB8,
+    /// Chance out of 255 for each dark LED to flash on each frame.
+    pub density: u8,
+    /// Time for a flash to fade out.
+    pub fade_ms: u32,
+    levels: [u8; N],
+    /// What was left over of the fading after the last frame, in 255ths of
+    /// a millisecond, so that slow fades still make progress at high frame
+    /// rates.
+    fade_carry: u32,
+    rng: Rng,
+    last_ms: Option<u32>,
+}
+
+impl<const N: usize> Sparkle<N> {
+    /// Sparkles of `color`, with the random flashes generated from `seed`.
+    pub const fn new(color: RGB8, density: u8, fade_ms: u32, seed: u32) -> Self {
+        Sparkle {
+            color,
+            density,
+            fade_ms,
+            levels: [0; N],
+            fade_carry: 0,
+            rng: Rng::new(seed),
+            last_ms: None,
+        }
+    }
+}
+
+impl<const N: usize> Animation for Sparkle<N> {
+    fn render(&mut self, now_ms: u32, frame: &mut [RGB8]) {
+        let elapsed = self.last_ms.map_or(0, |last| now_ms.wrapping_sub(last));
+        self.last_ms = Some(now_ms);
+        let fade_ms = u64::from(self.fade_ms.max(1));
+        let fading = u64::from(self.fade_carry) + u64::from(elapsed) * 255;
+        let fade = (fading / fade_ms).min(255) as u8;
+        self.fade_carry = (fading % fade_ms) as u32;
+
+        for level in self.levels.iter_mut() {
+            *level = level.saturating_sub(fade);
+            if *level == 0 && self.rng.next_u8() < self.density {
+                *level = 255;
+            }
+        }
+        for (i, led) in frame.iter_mut().enumerate() {
+            *led = match self.levels.get(i) {
+                Some(level) => scale(self.color, *level),
+                None => BLACK,
+            };
+        }
+    }
+}
+
+/// Flickering flames rising from the start of the strip, on a strip of `N`
+/// LEDs.
+///
+/// The flames move on every rendered frame, so their speed depends on the
+/// frame rate; 30 to 60 frames per second look natural. LEDs past the first
+/// `N` of a frame are left dark.
+#[derive(Clone, Debug)]
+pub struct Fire<const N: usize> {
+    /// How fast the flames cool down as they rise. Higher values give
+    /// shorter flames.
+    pub cooling: u8,
+    /// Chance out of 255 of a new spark at the base on each frame. Higher
+    /// values give a more roaring fire.
+    pub sparking: u8,
+    heat: [u8; N],
+    rng: Rng,
+}
+
+impl<const N: usize> Fire<N> {
+    /// A fire with typical settings, with the flicker generated from `seed`.
+    pub const fn new(seed: u32) -> Self {
+        Fire {
+            cooling: 55,
+            sparking: 120,
+            heat: [0; N],
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// Move the flames on by a frame, for `N` above 0.
+    fn step(&mut self) {
+        // Cool down every cell a little
+        let max_cooling = (usize::from(self.cooling) * 10 / N + 2).min(255) as u8;
+        for heat in self.heat.iter_mut() {
+            let cooling = self.rng.next_u8() % max_cooling;
+            *heat = heat.saturating_sub(cooling);
+        }
+        // Heat drifts up and diffuses
+        for i in (2..N).rev() {
+            self.heat[i] =
+                ((u16::from(self.heat[i - 1]) + 2 * u16::from(self.heat[i - 2])) / 3) as u8;
+        }
+        // Randomly ignite new sparks near the base
+        if self.rng.next_u8() < self.sparking {
+            let i = self.rng.below(N.min(7));
+            let spark = 160 + self.rng.next_u8() % 96;
+            self.heat[i] = self.heat[i].saturating_add(spark);
+        }
+    }
+}
+
+/// Colors of the flames from cold to hot.
+const FIRE_PALETTE: [RGB8; 4] = [
+    RGB8::new(0, 0, 0),
+    RGB8::new(255, 0, 0),
+    RGB8::new(255, 160, 0),
+    RGB8::new(255, 255, 160),
+];
+
+impl<const N: usize> Animation for Fire<N> {
+    fn render(&mut self, _now_ms: u32, frame: &mut [RGB8]) {
+        if N > 0 {
+            self.step();
+        }
+        for (i, led) in frame.iter_mut().enumerate() {
+            *led = match self.heat.get(i) {
+                Some(heat) => palette(&FIRE_PALETTE, *heat),
+                None => BLACK,
+            };
+        }
+    }
+}
+
+/// A bright head with a fading tail, sweeping along the strip.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Comet {
+    pub color: RGB8,
+    pub background: RGB8,
+    /// Number of LEDs in the tail, behind the head.
+    pub tail: usize,
+    /// Time for the head to travel the whole strip.
+    pub period_ms: u32,
+    /// Whether to sweep back and forth rather than always forwards.
+    pub bounce: bool,
+}
+
+impl Comet {
+    pub const fn new(color: RGB8, tail: usize, period_ms: u32) -> Self {
+        Comet {
+            color,
+            background: BLACK,
+            tail,
+            period_ms,
+            bounce: false,
+        }
+    }
+}
+
+impl Animation for Comet {
+    fn render(&mut self, now_ms: u32, frame: &mut [RGB8]) {
+        let len = frame.len();
+        if len == 0 {
+            return;
+        }
+        let period_ms = u64::from(self.period_ms.max(1));
+        let elapsed = u64::from(now_ms) % (period_ms * if self.bounce { 2 } else { 1 });
+        let travelled = (elapsed * len as u64 / period_ms) as usize;
+        // Going backwards after a bounce, the tail trails on the other side
+        let (head, backwards) = if travelled < len {
+            (travelled, false)
+        } else {
+            (2 * len - 1 - travelled, true)
+        };
+
+        for (i, led) in frame.iter_mut().enumerate() {
+            let behind = if backwards {
+                i.checked_sub(head)
+            } else {
+                head.checked_sub(i)
+            };
+            *led = match behind {
+                Some(distance) if distance <= self.tail => {
+                    let level = 255 - distance * 255 / (self.tail + 1);
+                    blend(self.background, self.color, level as u8)
+                }
+                _ => self.background,
+            };
+        }
+    }
+}
+
+/// Cross-fades from one animation to another, for a strip of `N` LEDs.
+///
+/// Both animations keep running during the fade. Once it is over, only the
+/// new animation is rendered. LEDs past the first `N` of a frame are left
+/// dark during the fade.
+pub struct Transition<A, B, const N: usize> {
+    from: A,
+    to: B,
+    start_ms: u32,
+    duration_ms: u32,
+    buffer: [RGB8; N],
+}
+
+impl<A: Animation, B: Animation, const N: usize> Transition<A, B, N> {
+    /// Fade from `from` to `to` over `duration_ms`, starting at `start_ms`.
+    pub fn new(from: A, to: B, start_ms: u32, duration_ms: u32) -> Self {
+        Transition {
+            from,
+            to,
+            start_ms,
+            duration_ms,
+            buffer: [BLACK; N],
+        }
+    }
+
+    /// Whether the fade is over at `now_ms`.
+    pub fn is_finished(&self, now_ms: u32) -> bool {
+        now_ms.wrapping_sub(self.start_ms) >= self.duration_ms
+    }
+
+    /// Get back both animations.
+    pub fn into_inner(self) -> (A, B) {
+        (self.from, self.to)
+    }
+}
+
+impl<A: Animation, B: Animation, const N: usize> Animation for Transition<A, B, N> {
+    fn render(&mut self, now_ms: u32, frame: &mut [RGB8]) {
+        if self.is_finished(now_ms) {
+            self.to.render(now_ms, frame);
+            return;
+        }
+        let elapsed = now_ms.wrapping_sub(self.start_ms);
+        let amount = (u64::from(elapsed) * 255 / u64::from(self.duration_ms)) as u8;
+
+        let (frame, rest) = frame.split_at_mut(frame.len().min(N));
+        self.from.render(now_ms, frame);
+        self.to.render(now_ms, &mut self.buffer[..frame.len()]);
+        for (led, to) in frame.iter_mut().zip(self.buffer.iter()) {
+            *led = blend(*led, *to, amount);
+        }
+        for led in rest {
+            *led = BLACK;
+        }
+    }
+}
+
+/// Renders an animation into a frame of `N` LEDs and writes it out at a
+/// fixed frame rate.
+pub struct FrameScheduler<const N: usize> {
+    frame: [RGB8; N],
+    interval_ms: u32,
+    next_ms: Option<u32>,
+    brightness: u8,
+}
+
+impl<const N: usize> FrameScheduler<N> {
+    /// A scheduler writing a frame every `interval_ms`.
+    pub const fn new(interval_ms: u32) -> Self {
+        FrameScheduler {
+            frame: [BLACK; N],
+            interval_ms,
+            next_ms: None,
+            brightness: 255,
+        }
+    }
+
+    /// The last rendered frame, before brightness is applied.
+    pub fn frame(&self) -> &[RGB8; N] {
+        &self.frame
+    }
+
+    /// Scale every frame by `brightness` / 255 when it is written.
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Render and write the next frame right away at the next poll.
+    pub fn reset(&mut self) {
+        self.next_ms = None;
+    }
+
+    /// Whether a new frame is due at `now_ms`.
+    pub fn is_due(&self, now_ms: u32) -> bool {
+        match self.next_ms {
+            None => true,
+            // Compare through a wrapping difference so timestamps may roll over
+            Some(next_ms) => (now_ms.wrapping_sub(next_ms) as i32) >= 0,
+        }
+    }
+
+    /// If a frame is due at `now_ms`, render `animation` and write the frame
+    /// to `writer`. Returns whether a frame was written.
+    ///
+    /// When polled too late, frames are skipped rather than written in a
+    /// burst to catch up.
+    pub fn poll<A, W>(
+        &mut self,
+        now_ms: u32,
+        animation: &mut A,
+        writer: &mut W,
+    ) -> Result<bool, W::Error>
+    where
+        A: Animation + ?Sized,
+        W: SmartLedsWrite,
+        W::Color: From<RGB8>,
+    {
+        if !self.is_due(now_ms) {
+            return Ok(false);
+        }
+        let next_ms = match self.next_ms {
+            Some(next_ms) if now_ms.wrapping_sub(next_ms) < self.interval_ms => next_ms,
+            _ => now_ms,
+        };
+        self.next_ms = Some(next_ms.wrapping_add(self.interval_ms));
+
+        animation.render(now_ms, &mut self.frame);
+        let brightness = self.brightness;
+        writer.write(self.frame.iter().map(|color| scale(*color, brightness)))?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: RGB8 = RGB8::new(255, 0, 0);
+    const BLUE: RGB8 = RGB8::new(0, 0, 255);
+    /// What frames are filled with before rendering, to catch LEDs left
+    /// untouched.
+    const UNTOUCHED: RGB8 = RGB8::new(1, 2, 3);
+
+    fn render<A: Animation, const LEN: usize>(animation: &mut A, now_ms: u32) -> [RGB8; LEN] {
+        let mut frame = [UNTOUCHED; LEN];
+        animation.render(now_ms, &mut frame);
+        frame
+    }
+
+    /// Fills the frame with one color.
+    struct Solid(RGB8);
+
+    impl Animation for Solid {
+        fn render(&mut self, _now_ms: u32, frame: &mut [RGB8]) {
+            for led in frame.iter_mut() {
+                *led = self.0;
+            }
+        }
+    }
+
+    /// Keeps the last frame written, and counts the frames.
+    struct Recorder {
+        frame: [RGB8; 4],
+        count: usize,
+    }
+
+    impl SmartLedsWrite for Recorder {
+        type Error = ();
+        type Color = RGB8;
+
+        fn write<T, I>(&mut self, iterator: T) -> Result<(), ()>
+        where
+            T: IntoIterator<Item = I>,
+            I: Into<RGB8>,
+        {
+            for (led, color) in self.frame.iter_mut().zip(iterator) {
+                *led = color.into();
+            }
+            self.count += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn phases() {
+        assert_eq!(phase(0, 1000), 0);
+        assert_eq!(phase(250, 1000), 64);
+        assert_eq!(phase(500, 1000), 128);
+        assert_eq!(phase(999, 1000), 255);
+        assert_eq!(phase(1000, 1000), 0);
+        assert_eq!(phase(1234, 0), 0);
+    }
+
+    #[test]
+    fn rainbow() {
+        let mut rainbow = Rainbow::new(1000, 0);
+        assert_eq!(render(&mut rainbow, 0), [RED; 4]);
+        assert_eq!(render(&mut rainbow, 500), [RGB8::new(0, 255, 255); 4]);
+
+        let mut rainbow = Rainbow::new(1000, 128);
+        let frame: [RGB8; 4] = render(&mut rainbow, 0);
+        for (i, led) in frame.iter().enumerate() {
+            assert_eq!(*led, hsv_to_rgb(Hsv::new(i as u8 * 32, 255, 255)));
+        }
+
+        rainbow.brightness = 0;
+        assert_eq!(render(&mut rainbow, 0), [BLACK; 4]);
+    }
+
+    #[test]
+    fn chase() {
+        let mut chase = Chase::new(RED, 3, 100);
+        let lit = |frame: [RGB8; 7]| frame.map(|led| led == RED);
+        let first = [true, false, false, true, false, false, true];
+        assert_eq!(lit(render(&mut chase, 0)), first);
+        assert_eq!(lit(render(&mut chase, 99)), first);
+        assert_eq!(
+            lit(render(&mut chase, 100)),
+            [false, true, false, false, true, false, false]
+        );
+        assert_eq!(lit(render(&mut chase, 300)), first);
+
+        chase.background = BLUE;
+        let frame: [RGB8; 3] = render(&mut chase, 200);
+        assert_eq!(frame, [BLUE, BLUE, RED]);
+
+        chase.spacing = 0;
+        assert_eq!(render(&mut chase, 100), [RED; 3]);
+    }
+
+    #[test]
+    fn breathe() {
+        let mut breathe = Breathe::new(RED, 1000);
+        assert_eq!(render(&mut breathe, 0), [BLACK; 3]);
+        // Halfway up the triangle, a quarter of the way up the breath
+        assert_eq!(render(&mut breathe, 250), [scale(RED, 64); 3]);
+        assert_eq!(render(&mut breathe, 500), [RGB8::new(253, 0, 0); 3]);
+        assert_eq!(render(&mut breathe, 1000), [BLACK; 3]);
+    }
+
+    #[test]
+    fn sparkle_is_reproducible() {
+        let mut a = Sparkle::<16>::new(RED, 40, 300, 1234);
+        let mut b = Sparkle::<16>::new(RED, 40, 300, 1234);
+        let mut lit = 0;
+        for now_ms in (0..2000).step_by(20) {
+            let frame: [RGB8; 16] = render(&mut a, now_ms);
+            assert_eq!(frame, render(&mut b, now_ms));
+            lit += frame.iter().filter(|led| **led != BLACK).count();
+        }
+        assert!(lit > 0);
+    }
+
+    #[test]
+    fn sparkle_without_density_stays_dark() {
+        let mut sparkle = Sparkle::<8>::new(RED, 0, 300, 1);
+        for now_ms in (0..1000).step_by(20) {
+            assert_eq!(render(&mut sparkle, now_ms), [BLACK; 8]);
+        }
+    }
+
+    #[test]
+    fn slow_sparkles_fade_at_high_frame_rates() {
+        // Fading 255 levels over 5s takes 0.8 of a level per 16ms frame
+        let mut sparkle = Sparkle::<1>::new(RED, 255, 5000, 7);
+        let frame: [RGB8; 1] = render(&mut sparkle, 0);
+        assert_eq!(frame, [RED]);
+        sparkle.density = 0;
+        for step in 1..313 {
+            let frame: [RGB8; 1] = render(&mut sparkle, step * 16);
+            assert_ne!(frame, [BLACK], "dark after {} frames", step);
+            if step == 156 {
+                // About half way
+                assert_eq!(frame, [scale(RED, 128)]);
+            }
+        }
+        assert_eq!(render(&mut sparkle, 313 * 16), [BLACK]);
+    }
+
+    #[test]
+    fn slow_sparkles_make_way_for_new_ones() {
+        let mut sparkle = Sparkle::<4>::new(RED, 64, 5000, 99);
+        let mut flashes = 0;
+        let mut previous = [BLACK; 4];
+        for now_ms in (0..30_000).step_by(16) {
+            let frame: [RGB8; 4] = render(&mut sparkle, now_ms);
+            flashes += frame
+                .iter()
+                .zip(previous.iter())
+                .filter(|(led, previous)| **led == RED && **previous != RED)
+                .count();
+            previous = frame;
+        }
+        // Each LED flashes again once its last flash has faded out
+        assert!(flashes >= 4 * 5, "{} flashes", flashes);
+    }
+
+    #[test]
+    fn sparkle_leaves_extra_leds_dark() {
+        let mut sparkle = Sparkle::<2>::new(RED, 255, 300, 3);
+        let frame: [RGB8; 4] = render(&mut sparkle, 0);
+        assert_eq!(frame[2..], [BLACK; 2]);
+    }
+
+    #[test]
+    fn fire_is_reproducible() {
+        let mut a = Fire::<30>::new(42);
+        let mut b = Fire::<30>::new(42);
+        let mut hot = 0;
+        for now_ms in (0..3000).step_by(20) {
+            let frame: [RGB8; 30] = render(&mut a, now_ms);
+            assert_eq!(frame, render(&mut b, now_ms));
+            for led in frame.iter() {
+                // Every color is on the palette, from black through red and
+                // yellow to white
+                assert!(led.r >= led.g && led.g >= led.b, "{:?}", led);
+            }
+            hot += frame.iter().filter(|led| **led != BLACK).count();
+        }
+        assert!(hot > 0);
+    }
+
+    #[test]
+    fn fire_leaves_extra_leds_dark() {
+        let mut fire = Fire::<3>::new(5);
+        for now_ms in 0..20 {
+            let frame: [RGB8; 5] = render(&mut fire, now_ms);
+            assert_eq!(frame[3..], [BLACK; 2]);
+        }
+        let mut fire = Fire::<0>::new(5);
+        assert_eq!(render(&mut fire, 0), [BLACK; 2]);
+    }
+
+    #[test]
+    fn comet() {
+        let mut comet = Comet::new(RED, 2, 1000);
+        let frame: [RGB8; 10] = render(&mut comet, 0);
+        assert_eq!(frame[0], RED);
+        assert_eq!(frame[1..], [BLACK; 9]);
+
+        let frame: [RGB8; 10] = render(&mut comet, 500);
+        assert_eq!(frame[..3], [BLACK; 3]);
+        assert_eq!(
+            frame[3..6],
+            [RGB8::new(85, 0, 0), RGB8::new(170, 0, 0), RED]
+        );
+        assert_eq!(frame[6..], [BLACK; 4]);
+
+        // On the way back, the tail is on the other side
+        comet.bounce = true;
+        let frame: [RGB8; 10] = render(&mut comet, 1500);
+        assert_eq!(frame[..4], [BLACK; 4]);
+        assert_eq!(
+            frame[4..7],
+            [RED, RGB8::new(170, 0, 0), RGB8::new(85, 0, 0)]
+        );
+        assert_eq!(frame[7..], [BLACK; 3]);
+
+        // Nothing to draw on an empty strip
+        comet.render(0, &mut []);
+    }
+
+    #[test]
+    fn transition() {
+        let mut transition = Transition::<_, _, 4>::new(Solid(RED), Solid(BLUE), 100, 1000);
+        assert_eq!(render(&mut transition, 100), [RED; 4]);
+        assert_eq!(render(&mut transition, 600), [RGB8::new(128, 0, 127); 4]);
+        assert!(!transition.is_finished(1099));
+        assert!(transition.is_finished(1100));
+        assert_eq!(render(&mut transition, 1100), [BLUE; 4]);
+    }
+
+    #[test]
+    fn transition_leaves_extra_leds_dark() {
+        let mut transition = Transition::<_, _, 2>::new(Solid(RED), Solid(BLUE), 0, 1000);
+        assert_eq!(render(&mut transition, 0), [RED, RED, BLACK, BLACK]);
+        // Once over, only the new animation is drawn, on all LEDs
+        assert_eq!(render(&mut transition, 1000), [BLUE; 4]);
+    }
+
+    #[test]
+    fn scheduler_keeps_to_the_frame_rate() {
+        let mut scheduler = FrameScheduler::<4>::new(20);
+        let mut animation = Solid(RED);
+        let mut recorder = Recorder {
+            frame: [BLACK; 4],
+            count: 0,
+        };
+        let mut poll = |now_ms| {
+            scheduler
+                .poll(now_ms, &mut animation, &mut recorder)
+                .unwrap()
+        };
+        assert!(poll(0));
+        assert!(!poll(10));
+        assert!(!poll(19));
+        assert!(poll(20));
+        // A bit late, the next frame is still due on schedule
+        assert!(poll(45));
+        assert!(!poll(59));
+        assert!(poll(60));
+        // Too late, frames are skipped
+        assert!(poll(100));
+        assert!(!poll(119));
+        assert!(poll(120));
+        assert_eq!(recorder.count, 6);
+        assert_eq!(recorder.frame, [RED; 4]);
+    }
+
+    #[test]
+    fn scheduler_applies_brightness() {
+        let mut scheduler = FrameScheduler::<4>::new(20);
+        let mut recorder = Recorder {
+            frame: [BLACK; 4],
+            count: 0,
+        };
+        scheduler.set_brightness(128);
+        assert_eq!(scheduler.poll(0, &mut Solid(RED), &mut recorder), Ok(true));
+        assert_eq!(recorder.frame, [RGB8::new(128, 0, 0); 4]);
+        assert_eq!(scheduler.frame(), &[RED; 4]);
+
+        // Timestamps may wrap, and a reset renders right away
+        assert!(scheduler.is_due(20));
+        scheduler.reset();
+        assert_eq!(
+            scheduler.poll(u32::MAX - 5, &mut Solid(BLUE), &mut recorder),
+            Ok(true)
+        );
+        assert!(!scheduler.is_due(13));
+        assert!(scheduler.is_due(14));
+        assert_eq!(recorder.count, 2);
+    }
+}
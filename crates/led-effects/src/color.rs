@@ -0,0 +1,391 @@
+//! Color conversions, gamma correction, blending and palettes.
+//!
+//! Hues, saturations, lightnesses and blend amounts are all `u8`s, where 255
+//! stands for a full turn of the color wheel or for 100%.
+
+use smart_leds::RGB8;
+
+const BLACK: RGB8 = RGB8::new(0, 0, 0);
+
+/// A color as hue, saturation and value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Hsv {
+    /// Position on the color wheel, from red (0) through yellow, green, cyan,
+    /// blue and magenta back towards red (255).
+    pub hue: u8,
+    pub sat: u8,
+    pub val: u8,
+}
+
+impl Hsv {
+    pub const fn new(hue: u8, sat: u8, val: u8) -> Self {
+        Hsv { hue, sat, val }
+    }
+}
+
+impl From<Hsv> for RGB8 {
+    fn from(hsv: Hsv) -> Self {
+        hsv_to_rgb(hsv)
+    }
+}
+
+/// A color as hue, saturation and lightness.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Hsl {
+    /// Position on the color wheel, as for [`Hsv::hue`].
+    pub hue: u8,
+    pub sat: u8,
+    /// From black (0) through the pure color (128) to white (255).
+    pub light: u8,
+}
+
+impl Hsl {
+    pub const fn new(hue: u8, sat: u8, light: u8) -> Self {
+        Hsl { hue, sat, light }
+    }
+}
+
+impl From<Hsl> for RGB8 {
+    fn from(hsl: Hsl) -> Self {
+        hsl_to_rgb(hsl)
+    }
+}
+
+/// Multiply `a` by `b` / 255, rounding to the nearest integer.
+fn mul8(a: u8, b: u8) -> u8 {
+    ((u16::from(a) * u16::from(b) + 127) / 255) as u8
+}
+
+/// Convert a color from HSV to RGB.
+pub fn hsv_to_rgb(hsv: Hsv) -> RGB8 {
+    let Hsv { hue, sat, val } = hsv;
+    // Split the wheel into 6 sectors, and find how far into its sector the hue is
+    let scaled = u16::from(hue) * 6;
+    let sector = scaled >> 8;
+    let frac = (scaled & 0xFF) as u8;
+
+    let p = mul8(val, 255 - sat);
+    let q = mul8(val, 255 - mul8(sat, frac));
+    let t = mul8(val, 255 - mul8(sat, 255 - frac));
+    let (r, g, b) = match sector {
+        0 => (val, t, p),
+        1 => (q, val, p),
+        2 => (p, val, t),
+        3 => (p, q, val),
+        4 => (t, p, val),
+        _ => (val, p, q),
+    };
+    RGB8 { r, g, b }
+}
+
+/// Convert a color from HSL to RGB.
+pub fn hsl_to_rgb(hsl: Hsl) -> RGB8 {
+    let Hsl { hue, sat, light } = hsl;
+    let val = light.saturating_add(mul8(sat, light.min(255 - light)));
+    let sat = if val == 0 {
+        0
+    } else {
+        (2 * u16::from(val - light) * 255 / u16::from(val)).min(255) as u8
+    };
+    hsv_to_rgb(Hsv { hue, sat, val })
+}
+
+/// The color at `position` on a color wheel going from red through green and
+/// blue back to red, as used by the rainbow examples.
+pub fn wheel(position: u8) -> RGB8 {
+    let position = 255 - position;
+    if position < 85 {
+        // No green in this sector - red and blue only
+        RGB8::new(255 - position * 3, 0, position * 3)
+    } else if position < 170 {
+        // No red in this sector - green and blue only
+        let position = position - 85;
+        RGB8::new(0, position * 3, 255 - position * 3)
+    } else {
+        // No blue in this sector - red and green only
+        let position = position - 170;
+        RGB8::new(position * 3, 255 - position * 3, 0)
+    }
+}
+
+/// Gamma correction table for a gamma of 2.8, mapping linear brightness
+/// values to the LED duty cycles that look evenly spaced to the eye.
+pub const GAMMA8: [u8; 256] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1,
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 5, 5, 5,
+    5, 6, 6, 6, 6, 7, 7, 7, 7, 8, 8, 8, 9, 9, 9, 10, 10, 10, 11, 11, 11, 12, 12, 13, 13, 13, 14,
+    14, 15, 15, 16, 16, 17, 17, 18, 18, 19, 19, 20, 20, 21, 21, 22, 22, 23, 24, 24, 25, 25, 26, 27,
+    27, 28, 29, 29, 30, 31, 32, 32, 33, 34, 35, 35, 36, 37, 38, 39, 39, 40, 41, 42, 43, 44, 45, 46,
+    47, 48, 49, 50, 50, 51, 52, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 66, 67, 68, 69, 70, 72,
+    73, 74, 75, 77, 78, 79, 81, 82, 83, 85, 86, 87, 89, 90, 92, 93, 95, 96, 98, 99, 101, 102, 104,
+    105, 107, 109, 110, 112, 114, 115, 117, 119, 120, 122, 124, 126, 127, 129, 131, 133, 135, 137,
+    138, 140, 142, 144, 146, 148, 150, 152, 154, 156, 158, 160, 162, 164, 167, 169, 171, 173, 175,
+    177, 180, 182, 184, 186, 189, 191, 193, 196, 198, 200, 203, 205, 208, 210, 213, 215, 218, 220,
+    223, 225, 228, 231, 233, 236, 239, 241, 244, 247, 249, 252, 255,
+];
+
+/// Apply [`GAMMA8`] to every channel of `color`.
+pub fn gamma(color: RGB8) -> RGB8 {
+    RGB8 {
+        r: GAMMA8[usize::from(color.r)],
+        g: GAMMA8[usize::from(color.g)],
+        b: GAMMA8[usize::from(color.b)],
+    }
+}
+
+/// Apply [`GAMMA8`] to every color of a frame.
+pub fn gamma_iter<I>(colors: I) -> impl Iterator<Item = RGB8>
+where
+    I: Iterator<Item = RGB8>,
+{
+    colors.map(gamma)
+}
+
+/// Lowest color temperature in [`KELVIN`].
+const KELVIN_MIN: u16 = 1000;
+/// Temperature difference between two entries of [`KELVIN`].
+const KELVIN_STEP: u16 = 500;
+
+/// The color of black body light, from 1000K to 12000K.
+const KELVIN: [RGB8; 23] = [
+    RGB8::new(255, 68, 0),    // 1000K
+    RGB8::new(255, 108, 0),   // 1500K
+    RGB8::new(255, 137, 14),  // 2000K
+    RGB8::new(255, 159, 70),  // 2500K
+    RGB8::new(255, 177, 110), // 3000K
+    RGB8::new(255, 193, 141), // 3500K
+    RGB8::new(255, 206, 166), // 4000K
+    RGB8::new(255, 218, 187), // 4500K
+    RGB8::new(255, 228, 206), // 5000K
+    RGB8::new(255, 237, 222), // 5500K
+    RGB8::new(255, 246, 237), // 6000K
+    RGB8::new(255, 254, 250), // 6500K
+    RGB8::new(243, 242, 255), // 7000K
+    RGB8::new(230, 235, 255), // 7500K
+    RGB8::new(221, 230, 255), // 8000K
+    RGB8::new(215, 226, 255), // 8500K
+    RGB8::new(210, 223, 255), // 9000K
+    RGB8::new(205, 220, 255), // 9500K
+    RGB8::new(202, 218, 255), // 10000K
+    RGB8::new(199, 216, 255), // 10500K
+    RGB8::new(196, 214, 255), // 11000K
+    RGB8::new(193, 213, 255), // 11500K
+    RGB8::new(191, 211, 255), // 12000K
+];
+
+/// The color of light at a color temperature of `kelvin`, clamped to
+/// 1000K..=12000K.
+///
+/// Candle light is around 1900K, incandescent bulbs around 2700K and daylight
+/// around 6500K.
+pub fn color_temperature(kelvin: u16) -> RGB8 {
+    let offset = kelvin.saturating_sub(KELVIN_MIN);
+    let index = usize::from(offset / KELVIN_STEP);
+    if index >= KELVIN.len() - 1 {
+        return KELVIN[KELVIN.len() - 1];
+    }
+    let amount = (u32::from(offset % KELVIN_STEP) * 255 / u32::from(KELVIN_STEP)) as u8;
+    blend(KELVIN[index], KELVIN[index + 1], amount)
+}
+
+/// Scale every channel of `color` by `amount` / 255.
+pub fn scale(color: RGB8, amount: u8) -> RGB8 {
+    RGB8 {
+        r: mul8(color.r, amount),
+        g: mul8(color.g, amount),
+        b: mul8(color.b, amount),
+    }
+}
+
+/// Mix `from` and `to`, from all `from` at an `amount` of 0 to all `to` at
+/// 255.
+pub fn blend(from: RGB8, to: RGB8, amount: u8) -> RGB8 {
+    let channel = |a: u8, b: u8| {
+        let delta = (i32::from(b) - i32::from(a)) * i32::from(amount);
+        // Round to the nearest integer
+        let step = (delta + delta.signum() * 127) / 255;
+        (i32::from(a) + step) as u8
+    };
+    RGB8 {
+        r: channel(from.r, to.r),
+        g: channel(from.g, to.g),
+        b: channel(from.b, to.b),
+    }
+}
+
+/// The color at `position` along a palette of evenly spaced `colors`, from
+/// the first color at 0 to the last at 255, blending between neighbours.
+///
+/// An empty palette is black.
+pub fn palette(colors: &[RGB8], position: u8) -> RGB8 {
+    match colors.len() {
+        0 => BLACK,
+        1 => colors[0],
+        len => {
+            let segments = (len - 1) as u32;
+            let scaled = u32::from(position) * segments;
+            let index = (scaled / 255) as usize;
+            if index >= len - 1 {
+                return colors[len - 1];
+            }
+            blend(colors[index], colors[index + 1], (scaled % 255) as u8)
+        }
+    }
+}
+
+/// Like [`palette`], but wrapping around from the last color back to the
+/// first, for palettes that are cycled through.
+pub fn palette_wrapping(colors: &[RGB8], position: u8) -> RGB8 {
+    match colors.len() {
+        0 => BLACK,
+        len => {
+            let scaled = u32::from(position) * len as u32;
+            let index = (scaled >> 8) as usize;
+            let amount = (scaled & 0xFF) as u8;
+            blend(colors[index], colors[(index + 1) % len], amount)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: RGB8 = RGB8::new(255, 0, 0);
+    const GREEN: RGB8 = RGB8::new(0, 255, 0);
+    const BLUE: RGB8 = RGB8::new(0, 0, 255);
+    const WHITE: RGB8 = RGB8::new(255, 255, 255);
+
+    /// Whether no channel of `a` and `b` differs by more than `tolerance`.
+    fn is_near(a: RGB8, b: RGB8, tolerance: u8) -> bool {
+        a.r.abs_diff(b.r) <= tolerance
+            && a.g.abs_diff(b.g) <= tolerance
+            && a.b.abs_diff(b.b) <= tolerance
+    }
+
+    #[test]
+    fn hsv_primaries() {
+        assert_eq!(hsv_to_rgb(Hsv::new(0, 255, 255)), RED);
+        assert!(is_near(hsv_to_rgb(Hsv::new(85, 255, 255)), GREEN, 2));
+        assert!(is_near(hsv_to_rgb(Hsv::new(171, 255, 255)), BLUE, 2));
+        assert_eq!(hsv_to_rgb(Hsv::new(128, 255, 255)), RGB8::new(0, 255, 255));
+        assert_eq!(RGB8::from(Hsv::new(0, 255, 255)), RED);
+    }
+
+    #[test]
+    fn hsv_saturation_and_value() {
+        for hue in [0, 42, 100, 200, 255] {
+            assert_eq!(hsv_to_rgb(Hsv::new(hue, 0, 255)), WHITE);
+            assert_eq!(hsv_to_rgb(Hsv::new(hue, 0, 100)), RGB8::new(100, 100, 100));
+            assert_eq!(hsv_to_rgb(Hsv::new(hue, 255, 0)), BLACK);
+        }
+        assert_eq!(hsv_to_rgb(Hsv::new(0, 255, 128)), RGB8::new(128, 0, 0));
+    }
+
+    #[test]
+    fn hue_wraps_around() {
+        // The end of the wheel leads back into red
+        assert!(is_near(hsv_to_rgb(Hsv::new(255, 255, 255)), RED, 6));
+        // Neighbouring hues give neighbouring colors all the way round
+        for hue in 0..=255u8 {
+            let a = hsv_to_rgb(Hsv::new(hue, 255, 255));
+            let b = hsv_to_rgb(Hsv::new(hue.wrapping_add(1), 255, 255));
+            assert!(is_near(a, b, 6), "hue {}: {:?} to {:?}", hue, a, b);
+        }
+    }
+
+    #[test]
+    fn hsl_lightness() {
+        assert_eq!(hsl_to_rgb(Hsl::new(0, 255, 0)), BLACK);
+        assert_eq!(hsl_to_rgb(Hsl::new(0, 255, 255)), WHITE);
+        assert!(is_near(hsl_to_rgb(Hsl::new(0, 255, 128)), RED, 1));
+        assert_eq!(hsl_to_rgb(Hsl::new(0, 0, 128)), RGB8::new(128, 128, 128));
+        assert!(is_near(
+            RGB8::from(Hsl::new(0, 255, 191)),
+            RGB8::new(255, 127, 127),
+            1
+        ));
+    }
+
+    #[test]
+    fn wheel_primaries() {
+        assert_eq!(wheel(0), RED);
+        assert_eq!(wheel(85), GREEN);
+        assert_eq!(wheel(170), BLUE);
+        assert!(is_near(wheel(255), RED, 3));
+    }
+
+    #[test]
+    fn gamma_end_points() {
+        assert_eq!(GAMMA8[0], 0);
+        assert_eq!(GAMMA8[255], 255);
+        assert!(GAMMA8.windows(2).all(|w| w[0] <= w[1]));
+        // Halfway on the eye is far below halfway on the duty cycle
+        assert!(GAMMA8[128] < 64);
+        assert_eq!(gamma(BLACK), BLACK);
+        assert_eq!(gamma(WHITE), WHITE);
+        assert_eq!(
+            gamma(RGB8::new(0, 128, 255)),
+            RGB8::new(0, GAMMA8[128], 255)
+        );
+        let colors = [RED, RGB8::new(1, 2, 3)];
+        let mut frame = gamma_iter(colors.iter().copied());
+        assert_eq!(frame.next(), Some(RED));
+        assert_eq!(frame.next(), Some(BLACK));
+        assert_eq!(frame.next(), None);
+    }
+
+    #[test]
+    fn blend_end_points() {
+        let from = RGB8::new(10, 200, 0);
+        let to = RGB8::new(250, 0, 255);
+        assert_eq!(blend(from, to, 0), from);
+        assert_eq!(blend(from, to, 255), to);
+        assert_eq!(blend(BLACK, WHITE, 128), RGB8::new(128, 128, 128));
+        // Rounding is the same both ways
+        assert_eq!(blend(WHITE, BLACK, 128), RGB8::new(127, 127, 127));
+        assert_eq!(blend(from, to, 128), RGB8::new(130, 100, 128));
+    }
+
+    #[test]
+    fn scale_end_points() {
+        let color = RGB8::new(10, 200, 255);
+        assert_eq!(scale(color, 255), color);
+        assert_eq!(scale(color, 0), BLACK);
+        assert_eq!(scale(color, 128), RGB8::new(5, 100, 128));
+    }
+
+    #[test]
+    fn color_temperature_end_points() {
+        assert_eq!(color_temperature(0), KELVIN[0]);
+        assert_eq!(color_temperature(1000), KELVIN[0]);
+        assert_eq!(color_temperature(6500), RGB8::new(255, 254, 250));
+        assert_eq!(color_temperature(12000), KELVIN[KELVIN.len() - 1]);
+        assert_eq!(color_temperature(u16::MAX), KELVIN[KELVIN.len() - 1]);
+        // Halfway between 1000K and 1500K
+        assert_eq!(color_temperature(1250), RGB8::new(255, 88, 0));
+    }
+
+    #[test]
+    fn palettes() {
+        let colors = [RED, GREEN, BLUE];
+        assert_eq!(palette(&[], 100), BLACK);
+        assert_eq!(palette(&[GREEN], 100), GREEN);
+        assert_eq!(palette(&colors, 0), RED);
+        assert_eq!(palette(&colors, 255), BLUE);
+        assert_eq!(palette(&colors, 64), RGB8::new(127, 128, 0));
+        assert_eq!(palette(&colors, 128), RGB8::new(0, 254, 1));
+    }
+
+    #[test]
+    fn wrapping_palettes() {
+        let colors = [RED, GREEN, BLUE, WHITE];
+        assert_eq!(palette_wrapping(&[], 100), BLACK);
+        assert_eq!(palette_wrapping(&colors, 0), RED);
+        assert_eq!(palette_wrapping(&colors, 64), GREEN);
+        assert_eq!(palette_wrapping(&colors, 128), BLUE);
+        assert_eq!(palette_wrapping(&colors, 192), WHITE);
+        // On the way back from the last color to the first
+        assert!(is_near(palette_wrapping(&colors, 255), RED, 16));
+    }
+}
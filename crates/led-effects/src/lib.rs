@@ -0,0 +1,9 @@
+//! Color math and animations for addressable LEDs, shared by the rp-hal
+//! board support packages.
+//!
+//! Everything works on [`RGB8`](smart_leds::RGB8) colors with integer math
+//! only, so it is just as fast on boards without an FPU.
+#![no_std]
+
+pub mod animation;
+pub mod color;
@@ -0,0 +1,388 @@
+//! Servo calibration shared by the rp-hal board support packages.
+//!
+//! A [`Calibration`] maps a servo value, like an angle in degrees or a speed,
+//! to the width of the pulse the servo expects, by linearly interpolating
+//! between calibration points. The pulse width can then be turned into a
+//! duty cycle for a PWM peripheral with [`pulse_to_duty`].
+#![no_std]
+
+/// Maximum number of points in a [`Calibration`].
+pub const MAX_POINTS: usize = 8;
+
+/// The servo value reached with a pulse of `pulse_us` microseconds.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Point {
+    pub pulse_us: f32,
+    pub value: f32,
+}
+
+impl Point {
+    pub const fn new(pulse_us: f32, value: f32) -> Self {
+        Point { pulse_us, value }
+    }
+}
+
+/// Filler for the unused entries of [`Calibration::points`].
+const UNUSED: Point = Point::new(0.0, 0.0);
+
+/// Why a set of calibration points was rejected.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CalibrationError {
+    /// Less than two or more than [`MAX_POINTS`] points were given.
+    PointCount,
+    /// The pulse widths are not strictly increasing.
+    PulseOrder,
+    /// The values are neither strictly increasing nor strictly decreasing.
+    ValueOrder,
+}
+
+/// A piecewise linear mapping between servo values and pulse widths.
+///
+/// Values and pulse widths outside of the calibrated range are clamped to the
+/// first and last points.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Calibration {
+    points: [Point; MAX_POINTS],
+    len: usize,
+}
+
+impl Calibration {
+    /// A servo that turns to an angle, from -90° at 500µs to +90° at 2500µs.
+    pub const fn angular() -> Self {
+        Self::three_points(500.0, 1500.0, 2500.0, -90.0, 0.0, 90.0)
+    }
+
+    /// A servo that moves along a line, from 0.0 at 500µs to 1.0 at 2500µs.
+    pub const fn linear() -> Self {
+        Calibration {
+            points: [
+                Point::new(500.0, 0.0),
+                Point::new(2500.0, 1.0),
+                UNUSED,
+                UNUSED,
+                UNUSED,
+                UNUSED,
+                UNUSED,
+                UNUSED,
+            ],
+            len: 2,
+        }
+    }
+
+    /// A continuous rotation servo, from full speed backwards (-1.0) at 500µs,
+    /// through stopped at 1500µs, to full speed forwards (1.0) at 2500µs.
+    pub const fn continuous() -> Self {
+        Self::three_points(500.0, 1500.0, 2500.0, -1.0, 0.0, 1.0)
+    }
+
+    /// A calibration from the pulse widths of the two ends and the middle of
+    /// the range of a servo, and the values at both ends.
+    ///
+    /// The value at `mid_pulse_us` is halfway between `min_value` and
+    /// `max_value`. The pulse widths must be increasing, and the values
+    /// different, as for [`Calibration::from_points`].
+    pub fn new(
+        min_pulse_us: f32,
+        mid_pulse_us: f32,
+        max_pulse_us: f32,
+        min_value: f32,
+        max_value: f32,
+    ) -> Result<Self, CalibrationError> {
+        Self::from_points(&[
+            Point::new(min_pulse_us, min_value),
+            Point::new(mid_pulse_us, (min_value + max_value) / 2.0),
+            Point::new(max_pulse_us, max_value),
+        ])
+    }
+
+    const fn three_points(
+        min_pulse_us: f32,
+        mid_pulse_us: f32,
+        max_pulse_us: f32,
+        min_value: f32,
+        mid_value: f32,
+        max_value: f32,
+    ) -> Self {
+        Calibration {
+            points: [
+                Point::new(min_pulse_us, min_value),
+                Point::new(mid_pulse_us, mid_value),
+                Point::new(max_pulse_us, max_value),
+                UNUSED,
+                UNUSED,
+                UNUSED,
+                UNUSED,
+                UNUSED,
+            ],
+            len: 3,
+        }
+    }
+
+    /// A calibration from measured points, ordered by pulse width.
+    ///
+    /// Values may go up or down with the pulse width, but must do so
+    /// consistently.
+    pub fn from_points(points: &[Point]) -> Result<Self, CalibrationError> {
+        if points.len() < 2 || points.len() > MAX_POINTS {
+            return Err(CalibrationError::PointCount);
+        }
+        // Written so that NaNs are rejected too
+        if !points.windows(2).all(|w| w[0].pulse_us < w[1].pulse_us) {
+            return Err(CalibrationError::PulseOrder);
+        }
+        let increasing = points.windows(2).all(|w| w[0].value < w[1].value);
+        let decreasing = points.windows(2).all(|w| w[0].value > w[1].value);
+        if !increasing && !decreasing {
+            return Err(CalibrationError::ValueOrder);
+        }
+
+        let mut calibration = Calibration {
+            points: [UNUSED; MAX_POINTS],
+            len: points.len(),
+        };
+        calibration.points[..points.len()].copy_from_slice(points);
+        Ok(calibration)
+    }
+
+    /// The calibration points, ordered by pulse width.
+    pub fn points(&self) -> &[Point] {
+        &self.points[..self.len]
+    }
+
+    fn first(&self) -> Point {
+        self.points[0]
+    }
+
+    fn last(&self) -> Point {
+        self.points[self.len - 1]
+    }
+
+    pub fn min_pulse(&self) -> f32 {
+        self.first().pulse_us
+    }
+
+    /// The pulse width halfway between the minimum and maximum values.
+    pub fn mid_pulse(&self) -> f32 {
+        self.value_to_pulse(self.mid_value())
+    }
+
+    pub fn max_pulse(&self) -> f32 {
+        self.last().pulse_us
+    }
+
+    /// The value at the minimum pulse width.
+    pub fn min_value(&self) -> f32 {
+        self.first().value
+    }
+
+    pub fn mid_value(&self) -> f32 {
+        (self.min_value() + self.max_value()) / 2.0
+    }
+
+    /// The value at the maximum pulse width.
+    pub fn max_value(&self) -> f32 {
+        self.last().value
+    }
+
+    /// The pulse width in microseconds that moves the servo to `value`.
+    ///
+    /// A NaN `value` gives the middle pulse width, rather than sending the
+    /// servo to either end of its travel.
+    pub fn value_to_pulse(&self, value: f32) -> f32 {
+        if value.is_nan() {
+            return self.mid_pulse();
+        }
+        let points = self.points();
+        let increasing = self.max_value() > self.min_value();
+        for w in points.windows(2) {
+            let (a, b) = (w[0], w[1]);
+            let below_b = if increasing {
+                value <= b.value
+            } else {
+                value >= b.value
+            };
+            if below_b {
+                return interpolate(value, a.value, b.value, a.pulse_us, b.pulse_us)
+                    .clamp(self.min_pulse(), self.max_pulse());
+            }
+        }
+        self.max_pulse()
+    }
+
+    /// The value a servo moves to when sent pulses of `pulse_us`
+    /// microseconds.
+    pub fn pulse_to_value(&self, pulse_us: f32) -> f32 {
+        let pulse_us = pulse_us.clamp(self.min_pulse(), self.max_pulse());
+        for w in self.points().windows(2) {
+            let (a, b) = (w[0], w[1]);
+            if pulse_us <= b.pulse_us {
+                return interpolate(pulse_us, a.pulse_us, b.pulse_us, a.value, b.value);
+            }
+        }
+        self.max_value()
+    }
+
+    /// The duty cycle that moves the servo to `value`, for a PWM with a period
+    /// of `period_us` microseconds counting up to `top`.
+    pub fn value_to_duty(&self, value: f32, period_us: f32, top: u16) -> u16 {
+        pulse_to_duty(self.value_to_pulse(value), period_us, top)
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::angular()
+    }
+}
+
+/// Map `x` from the range `x0..=x1` onto `y0..=y1`.
+fn interpolate(x: f32, x0: f32, x1: f32, y0: f32, y1: f32) -> f32 {
+    y0 + (x - x0) * (y1 - y0) / (x1 - x0)
+}
+
+/// The duty cycle giving a pulse of `pulse_us` microseconds, for a PWM with
+/// a period of `period_us` microseconds counting up to `top`.
+///
+/// The result is rounded to the nearest count and clamped to `0..=top + 1`,
+/// where `top + 1` keeps the output high for the whole period.
+pub fn pulse_to_duty(pulse_us: f32, period_us: f32, top: u16) -> u16 {
+    let wrap = u32::from(top) + 1;
+    let duty = pulse_us / period_us * wrap as f32 + 0.5;
+    // Casting saturates, and turns NaN into 0
+    (duty as u32).min(wrap).min(u32::from(u16::MAX)) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "{} isn't {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn angular_maps_angles_to_pulses() {
+        let calibration = Calibration::angular();
+        assert_near(calibration.value_to_pulse(-90.0), 500.0);
+        assert_near(calibration.value_to_pulse(0.0), 1500.0);
+        assert_near(calibration.value_to_pulse(45.0), 2000.0);
+        assert_near(calibration.value_to_pulse(90.0), 2500.0);
+        assert_near(calibration.pulse_to_value(1000.0), -45.0);
+        assert_near(calibration.pulse_to_value(2500.0), 90.0);
+    }
+
+    #[test]
+    fn out_of_range_is_clamped() {
+        let calibration = Calibration::angular();
+        assert_near(calibration.value_to_pulse(-180.0), 500.0);
+        assert_near(calibration.value_to_pulse(180.0), 2500.0);
+        assert_near(calibration.pulse_to_value(100.0), -90.0);
+        assert_near(calibration.pulse_to_value(3000.0), 90.0);
+    }
+
+    #[test]
+    fn nan_goes_to_the_middle() {
+        let calibration = Calibration::angular();
+        assert_near(calibration.value_to_pulse(f32::NAN), 1500.0);
+        let reversed = Calibration::new(500.0, 1500.0, 2500.0, 90.0, -90.0).unwrap();
+        assert_near(reversed.value_to_pulse(f32::NAN), 1500.0);
+    }
+
+    #[test]
+    fn pulses_and_values_round_trip() {
+        let calibration = Calibration::from_points(&[
+            Point::new(600.0, 0.0),
+            Point::new(1000.0, 30.0),
+            Point::new(1800.0, 90.0),
+            Point::new(2400.0, 180.0),
+        ])
+        .unwrap();
+        assert_near(calibration.value_to_pulse(60.0), 1400.0);
+        assert_near(calibration.value_to_pulse(135.0), 2100.0);
+        for value in (0..=180).step_by(15) {
+            let value = value as f32;
+            assert_near(
+                calibration.pulse_to_value(calibration.value_to_pulse(value)),
+                value,
+            );
+        }
+    }
+
+    #[test]
+    fn values_may_decrease() {
+        let calibration = Calibration::new(500.0, 1500.0, 2500.0, 90.0, -90.0).unwrap();
+        assert_near(calibration.value_to_pulse(90.0), 500.0);
+        assert_near(calibration.value_to_pulse(45.0), 1000.0);
+        assert_near(calibration.value_to_pulse(-90.0), 2500.0);
+        assert_near(calibration.value_to_pulse(-120.0), 2500.0);
+        assert_near(calibration.pulse_to_value(2000.0), -45.0);
+        assert_near(calibration.mid_pulse(), 1500.0);
+    }
+
+    #[test]
+    fn new_puts_the_middle_value_at_the_middle_pulse() {
+        let calibration = Calibration::new(800.0, 1258.0, 2515.0, 0.0, 180.0).unwrap();
+        assert_near(calibration.value_to_pulse(0.0), 800.0);
+        assert_near(calibration.value_to_pulse(90.0), 1258.0);
+        assert_near(calibration.value_to_pulse(180.0), 2515.0);
+        assert_near(calibration.mid_value(), 90.0);
+    }
+
+    #[test]
+    fn invalid_points_are_rejected() {
+        assert_eq!(
+            Calibration::new(1500.0, 1000.0, 2000.0, -90.0, 90.0),
+            Err(CalibrationError::PulseOrder)
+        );
+        assert_eq!(
+            Calibration::new(1000.0, 1000.0, 2000.0, -90.0, 90.0),
+            Err(CalibrationError::PulseOrder)
+        );
+        assert_eq!(
+            Calibration::new(1000.0, f32::NAN, 2000.0, -90.0, 90.0),
+            Err(CalibrationError::PulseOrder)
+        );
+        assert_eq!(
+            Calibration::new(1000.0, 1500.0, 2000.0, 90.0, 90.0),
+            Err(CalibrationError::ValueOrder)
+        );
+        assert_eq!(
+            Calibration::from_points(&[
+                Point::new(500.0, 0.0),
+                Point::new(1500.0, 1.0),
+                Point::new(2500.0, 0.5),
+            ]),
+            Err(CalibrationError::ValueOrder)
+        );
+        assert_eq!(
+            Calibration::from_points(&[Point::new(500.0, 0.0)]),
+            Err(CalibrationError::PointCount)
+        );
+        assert_eq!(
+            Calibration::from_points(&[Point::new(500.0, 0.0); MAX_POINTS + 1]),
+            Err(CalibrationError::PointCount)
+        );
+    }
+
+    #[test]
+    fn pulses_to_duty_cycles() {
+        // 50Hz with a PWM counting up to 19_999, so a count per microsecond
+        assert_eq!(pulse_to_duty(1500.0, 20_000.0, 19_999), 1500);
+        assert_eq!(pulse_to_duty(0.0, 20_000.0, 19_999), 0);
+        assert_eq!(pulse_to_duty(20_000.0, 20_000.0, 19_999), 20_000);
+        assert_eq!(pulse_to_duty(30_000.0, 20_000.0, 19_999), 20_000);
+        assert_eq!(pulse_to_duty(f32::NAN, 20_000.0, 19_999), 0);
+        // Rounded to the nearest count
+        assert_eq!(pulse_to_duty(1000.0, 20_000.0, 999), 50);
+        assert_eq!(pulse_to_duty(1010.0, 20_000.0, 999), 51);
+        assert_eq!(
+            Calibration::angular().value_to_duty(0.0, 20_000.0, 19_999),
+            1500
+        );
+    }
+}